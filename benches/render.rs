@@ -0,0 +1,130 @@
+// Times the book 1 final scene at a reduced size, traced recursively and
+// as a wavefront and with each acceleration structure, closest hit queries
+// against a large scene, plus a few of the hot vector operations. Run `cargo bench`
+// and `cargo bench --features scalar-math` to compare the SSE backend
+// against the portable one.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ray_tracer::bvh::BvhNode;
+use ray_tracer::camera::Camera;
+use ray_tracer::color::Color;
+use ray_tracer::hittable::{Hittable, HittableList};
+use ray_tracer::interval::Interval;
+use ray_tracer::material::Lambertian;
+use ray_tracer::ray::Ray;
+use ray_tracer::scenes::random_scene;
+use ray_tracer::sphere::Sphere;
+use ray_tracer::utils::Float;
+use ray_tracer::vec3::{cross, dot, unit_vector, Point3, Vec3};
+use ray_tracer::wide_bvh::{Bvh4, Bvh8};
+
+const RUNS: usize = 5;
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort();
+    times[times.len() / 2]
+}
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // warm up caches and the allocator before timing
+    f();
+
+    let times = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+
+    println!("{:<24} {:>10.2?} (median of {})", name, median(times), RUNS);
+}
+
+fn sphere_cloud(count: usize) -> HittableList {
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let mut list = HittableList::default();
+    for _ in 0..count {
+        let center = Point3::from(Vec3::random_bounded(-100.0, 100.0));
+        list.add(Arc::new(Sphere::new(center, 0.5, material.clone())));
+    }
+    list
+}
+
+fn main() {
+    let world = random_scene();
+    let mut camera = Camera::default();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 160.0;
+    camera.samples_per_pixel = 8;
+    camera.max_depth = 20;
+
+    camera.vfov = 20.0;
+    camera.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    camera.lookat = Point3::origin();
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.6;
+    camera.focus_dist = 10.0;
+
+    bench("render random_scene", || {
+        black_box(camera.render_pixels(&world));
+    });
+
+    let bvh = BvhNode::new(&world);
+    bench("render bvh", || {
+        black_box(camera.render_pixels(&bvh));
+    });
+    let bvh4 = Bvh4::new(&world);
+    bench("render bvh4", || {
+        black_box(camera.render_pixels(&bvh4));
+    });
+    let bvh8 = Bvh8::new(&world);
+    bench("render bvh8", || {
+        black_box(camera.render_pixels(&bvh8));
+    });
+
+    camera.wavefront = true;
+    bench("render wavefront", || {
+        black_box(camera.render_pixels(&world));
+    });
+    bench("render wavefront bvh4", || {
+        black_box(camera.render_pixels(&bvh4));
+    });
+
+    // closest hits only, on a scene large enough for traversal to dominate
+    let spheres = sphere_cloud(100_000);
+    let rays: Vec<Ray> = (0..100_000)
+        .map(|_| {
+            let origin = Point3::from(Vec3::random_bounded(-120.0, 120.0));
+            Ray::new(origin, Vec3::random_bounded(-1.0, 1.0))
+        })
+        .collect();
+    let accels: [(&str, Box<dyn Hittable>); 3] = [
+        ("closest hit bvh", Box::new(BvhNode::new(&spheres))),
+        ("closest hit bvh4", Box::new(Bvh4::new(&spheres))),
+        ("closest hit bvh8", Box::new(Bvh8::new(&spheres))),
+    ];
+    for (name, accel) in accels.iter() {
+        bench(name, || {
+            for r in rays.iter() {
+                black_box(accel.hit(*r, Interval::new(0.0, Float::INFINITY)));
+            }
+        });
+    }
+
+    let vectors: Vec<Vec3> = (0..1_000_000)
+        .map(|_| Vec3::random_bounded(-1.0, 1.0))
+        .collect();
+    bench("vec3 dot/cross/unit", || {
+        let mut acc = Vec3::zero();
+        for pair in vectors.windows(2) {
+            let (u, v) = (black_box(pair[0]), black_box(pair[1]));
+            acc += unit_vector(cross(u, v)) * dot(u, v);
+        }
+        black_box(acc);
+    });
+}
@@ -0,0 +1,149 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::utils::Float;
+use crate::vec3::Point3;
+
+// axis-aligned bounding box, one interval per axis
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Aabb {
+        Aabb { x, y, z }
+    }
+
+    // box with a and b as opposite corners, in any order
+    pub fn from_points(a: Point3, b: Point3) -> Aabb {
+        let (min, max) = (a.min(b), a.max(b));
+        Aabb {
+            x: Interval::new(min.x(), max.x()),
+            y: Interval::new(min.y(), max.y()),
+            z: Interval::new(min.z(), max.z()),
+        }
+    }
+
+    // smallest box containing both a and b
+    pub fn enclosing(a: Aabb, b: Aabb) -> Aabb {
+        Aabb {
+            x: Interval::enclosing(a.x, b.x),
+            y: Interval::enclosing(a.y, b.y),
+            z: Interval::enclosing(a.z, b.z),
+        }
+    }
+
+    pub fn axis(&self, n: usize) -> Interval {
+        match n {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.size() < 0.0 || self.y.size() < 0.0 || self.z.size() < 0.0
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x > y && x > z {
+            0
+        } else if y > z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn surface_area(&self) -> Float {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    // slab test, whether the ray passes through the box within ray_t
+    pub fn hit(&self, r: Ray, ray_t: Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    // the part of ray_t for which the ray is inside the box
+    pub fn clip(&self, r: Ray, mut ray_t: Interval) -> Option<Interval> {
+        let origin = r.origin().to_array();
+        let direction = r.direction().to_array();
+
+        for axis in 0..3 {
+            let slab = self.axis(axis);
+            let inv_d = 1.0 / direction[axis];
+            let t0 = (slab.min - origin[axis]) * inv_d;
+            let t1 = (slab.max - origin[axis]) * inv_d;
+            let (t0, t1) = if inv_d < 0.0 { (t1, t0) } else { (t0, t1) };
+
+            ray_t.min = ray_t.min.max(t0);
+            ray_t.max = ray_t.max.min(t1);
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Aabb {
+        Aabb::EMPTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn slab_test() {
+        let bbox = Aabb::from_points(Point3::new(1.0, 1.0, 1.0), Point3::new(-1.0, -1.0, -1.0));
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bbox.hit(r, ray_t));
+        assert!(!bbox.hit(r, Interval::new(0.0, 3.0)));
+
+        let r = Ray::new(Point3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!bbox.hit(r, ray_t));
+
+        // pointing away
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!bbox.hit(r, ray_t));
+    }
+
+    #[test]
+    fn enclosing_and_area() {
+        let a = Aabb::from_points(Point3::origin(), Point3::new(1.0, 1.0, 1.0));
+        let b = Aabb::from_points(Point3::new(2.0, 0.0, 0.0), Point3::new(3.0, 1.0, 1.0));
+        let both = Aabb::enclosing(a, b);
+
+        assert_eq!(both.longest_axis(), 0);
+        assert_eq!(both.surface_area(), 14.0);
+        assert_eq!(Aabb::enclosing(Aabb::EMPTY, a).surface_area(), 6.0);
+        assert_eq!(Aabb::EMPTY.surface_area(), 0.0);
+    }
+}
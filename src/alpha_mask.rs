@@ -0,0 +1,127 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utils::Float;
+use crate::vec3::Point3;
+
+use std::sync::Arc;
+
+// wraps a material with an opacity texture (read from the first channel)
+// so primitives skip the parts of the surface that are cut away
+pub struct AlphaMask {
+    inner: Arc<dyn Scatter>,
+    opacity: Arc<dyn Texture>,
+    // None passes rays through stochastically in proportion to opacity
+    threshold: Option<Float>,
+}
+
+impl AlphaMask {
+    // hard cutout, surface only exists where opacity >= threshold
+    pub fn cutout(
+        inner: Arc<dyn Scatter>,
+        opacity: Arc<dyn Texture>,
+        threshold: Float,
+    ) -> AlphaMask {
+        AlphaMask {
+            inner,
+            opacity,
+            threshold: Some(threshold),
+        }
+    }
+
+    // partial transparency, the surface is hit with probability = opacity
+    pub fn stochastic(inner: Arc<dyn Scatter>, opacity: Arc<dyn Texture>) -> AlphaMask {
+        AlphaMask {
+            inner,
+            opacity,
+            threshold: None,
+        }
+    }
+}
+
+impl Scatter for AlphaMask {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.inner.scatter(r_in, rec)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.inner.emitted(u, v, p)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.inner.is_dispersive()
+    }
+
+    fn alpha(&self, u: Float, v: Float, p: Point3) -> Float {
+        let alpha = self.opacity.value(u, v, p).r().clamp(0.0, 1.0) * self.inner.alpha(u, v, p);
+        match self.threshold {
+            Some(threshold) if alpha >= threshold => 1.0,
+            Some(_) => 0.0,
+            None => alpha,
+        }
+    }
+
+    fn has_alpha(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::tests::gray;
+    use crate::quad::Quad;
+    use crate::texture::SolidColor;
+    use crate::vec3::Vec3;
+
+    fn opacity(value: Float) -> Arc<dyn Texture> {
+        Arc::new(SolidColor::from_value(value))
+    }
+
+    #[test]
+    fn cutout_threshold() {
+        let p = Point3::origin();
+        assert_eq!(
+            AlphaMask::cutout(gray(), opacity(0.6), 0.5).alpha(0.0, 0.0, p),
+            1.0
+        );
+        assert_eq!(
+            AlphaMask::cutout(gray(), opacity(0.4), 0.5).alpha(0.0, 0.0, p),
+            0.0
+        );
+        // out of range opacities are clamped
+        assert_eq!(
+            AlphaMask::stochastic(gray(), opacity(1.5)).alpha(0.0, 0.0, p),
+            1.0
+        );
+
+        // the inner material's alpha is applied before the threshold
+        let inner = Arc::new(AlphaMask::stochastic(gray(), opacity(0.5)));
+        let mask = AlphaMask::cutout(inner, opacity(0.8), 0.5);
+        assert_eq!(mask.alpha(0.0, 0.0, p), 0.0);
+    }
+
+    #[test]
+    fn stochastic_hits_in_proportion() {
+        let mask = Arc::new(AlphaMask::stochastic(gray(), opacity(0.25)));
+        assert_eq!(mask.alpha(0.0, 0.0, Point3::origin()), 0.25);
+
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            mask,
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let n = 10000;
+        let hits = (0..n)
+            .filter(|_| quad.hit(r, Interval::new(0.0, Float::INFINITY)).is_some())
+            .count();
+        let fraction = hits as Float / n as Float;
+        assert!((fraction - 0.25).abs() < 0.03, "{fraction}");
+    }
+}
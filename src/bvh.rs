@@ -0,0 +1,186 @@
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+use std::sync::Arc;
+
+// Binary tree from the top down build. BvhNode turns it into nested
+// hittables, the wide bvh collapses it into nodes with more children.
+pub(crate) enum BuildNode {
+    Leaf(Arc<dyn Hittable>),
+    Interior {
+        bbox: Aabb,
+        children: Box<[BuildNode; 2]>,
+    },
+}
+
+impl BuildNode {
+    // splits the objects at the median centroid along the longest axis
+    // of the centroids' bounds, objects must not be empty
+    pub(crate) fn new(objects: &mut [Arc<dyn Hittable>]) -> BuildNode {
+        if objects.len() == 1 {
+            return BuildNode::Leaf(objects[0].clone());
+        }
+
+        let bbox = objects
+            .iter()
+            .fold(Aabb::EMPTY, |b, o| Aabb::enclosing(b, o.bounding_box()));
+        let centroids = objects.iter().fold(Aabb::EMPTY, |b, o| {
+            let c = o.bounding_box().centroid();
+            Aabb::enclosing(b, Aabb::from_points(c, c))
+        });
+        let axis = centroids.longest_axis();
+        objects.sort_by(|a, b| {
+            let a = a.bounding_box().centroid().to_array()[axis];
+            let b = b.bounding_box().centroid().to_array()[axis];
+            a.total_cmp(&b)
+        });
+
+        let (left, right) = objects.split_at_mut(objects.len() / 2);
+        BuildNode::Interior {
+            bbox,
+            children: Box::new([BuildNode::new(left), BuildNode::new(right)]),
+        }
+    }
+
+    pub(crate) fn bounding_box(&self) -> Aabb {
+        match self {
+            BuildNode::Leaf(object) => object.bounding_box(),
+            BuildNode::Interior { bbox, .. } => *bbox,
+        }
+    }
+}
+
+// bounding volume hierarchy, every node has two children that are either
+// nodes themselves or the objects of the list
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+    has_instances: bool,
+}
+
+impl BvhNode {
+    pub fn new(list: &HittableList) -> BvhNode {
+        let mut objects = list.objects().to_vec();
+        if objects.is_empty() {
+            let empty: Arc<dyn Hittable> = Arc::new(HittableList::default());
+            return BvhNode {
+                left: empty.clone(),
+                right: empty,
+                bbox: Aabb::EMPTY,
+                has_instances: false,
+            };
+        }
+
+        match BuildNode::new(&mut objects) {
+            BuildNode::Leaf(object) => BvhNode {
+                left: object.clone(),
+                bbox: object.bounding_box(),
+                has_instances: object.has_instances(),
+                right: object,
+            },
+            BuildNode::Interior { bbox, children } => BvhNode::from_children(bbox, *children),
+        }
+    }
+
+    fn from_children(bbox: Aabb, children: [BuildNode; 2]) -> BvhNode {
+        let [left, right] = children.map(|child| -> Arc<dyn Hittable> {
+            match child {
+                BuildNode::Leaf(object) => object,
+                BuildNode::Interior { bbox, children } => {
+                    Arc::new(BvhNode::from_children(bbox, *children))
+                }
+            }
+        });
+        BvhNode {
+            has_instances: left.has_instances() || right.has_instances(),
+            left,
+            right,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        if !self.bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        let hit_left = self.left.intersect(r, ray_t);
+        let closest = hit_left.map_or(ray_t.max, |hit| hit.t);
+        let hit_right = self.right.intersect(r, Interval::new(ray_t.min, closest));
+
+        hit_right.or(hit_left)
+    }
+
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        self.bbox.hit(r, ray_t) && (self.left.occluded(r, ray_t) || self.right.occluded(r, ray_t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn has_instances(&self) -> bool {
+        self.has_instances
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+    use crate::sphere::Sphere;
+    use crate::utils::{random_double_bounded, Float};
+    use crate::vec3::{Point3, Vec3};
+
+    pub(crate) fn random_spheres(count: usize) -> HittableList {
+        let material = gray();
+        let mut list = HittableList::default();
+        for _ in 0..count {
+            let center = Point3::from(Vec3::random_bounded(-10.0, 10.0));
+            let radius = random_double_bounded(0.1, 1.0);
+            list.add(Arc::new(Sphere::new(center, radius, material.clone())));
+        }
+        list
+    }
+
+    // compares the closest hits of `accel` against testing every object
+    pub(crate) fn assert_same_hits(list: &HittableList, accel: &dyn Hittable) {
+        for _ in 0..1000 {
+            let origin = Point3::from(Vec3::random_bounded(-15.0, 15.0));
+            let r = Ray::new(origin, Vec3::random_bounded(-1.0, 1.0));
+            let ray_t = Interval::new(0.0, Float::INFINITY);
+
+            let expected = list.intersect(r, ray_t).map(|hit| hit.t);
+            let actual = accel.intersect(r, ray_t).map(|hit| hit.t);
+            assert_eq!(expected, actual);
+            assert_eq!(expected.is_some(), accel.occluded(r, ray_t));
+        }
+    }
+
+    #[test]
+    fn matches_list() {
+        let list = random_spheres(200);
+        let bvh = BvhNode::new(&list);
+
+        assert_same_hits(&list, &bvh);
+    }
+
+    #[test]
+    fn single_object() {
+        let list = random_spheres(1);
+        let bvh = BvhNode::new(&list);
+
+        assert_same_hits(&list, &bvh);
+        assert!(BvhNode::new(&HittableList::default())
+            .hit(
+                Ray::new(Point3::origin(), Vec3::new(1.0, 0.0, 0.0)),
+                Interval::default()
+            )
+            .is_none());
+    }
+}
@@ -2,17 +2,57 @@ use crate::color::{write_color, Color};
 use crate::hittable::Hittable;
 use crate::interval::Interval;
 use crate::ray::Ray;
-use crate::utils::{self, degrees_to_radians};
+use crate::spectrum::{self, SampledSpectrum, SampledWavelengths};
+use crate::texture::Texture;
+use crate::transform::Transform;
+use crate::utils::{self, degrees_to_radians, Float, PI};
 use crate::vec3::{cross, random_in_unit_disk, unit_vector, Point3, Vec3};
+use crate::wavefront;
 
-#[derive(Default)]
+use std::sync::Arc;
+
+// what rays that leave the scene see
+#[derive(Clone, Default)]
+pub enum Background {
+    // white at the horizon to blue overhead
+    #[default]
+    Sky,
+    Solid(Color),
+    // a texture over the sphere of directions in latitude-longitude
+    // layout, +y up, u going around from -z and v from the bottom. The
+    // transform takes world directions into the texture's space.
+    Environment(Arc<dyn Texture>, Box<Transform>),
+}
+
+impl Background {
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        match self {
+            Background::Sky => {
+                // linear interpolation (lerp) between white and blue
+                // for the background gradient
+                let unit_direction = unit_vector(direction);
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+            Background::Environment(texture, transform) => {
+                let d = unit_vector(transform.vector(direction));
+                let u = (d.x().atan2(-d.z()) / (2.0 * PI)).rem_euclid(1.0);
+                let v = 1.0 - d.y().clamp(-1.0, 1.0).acos() / PI;
+                texture.value(u, v, Point3::from(d))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Camera {
     // image width / image height
-    pub aspect_ratio: f32,
-    pub image_width: f32,
+    pub aspect_ratio: Float,
+    pub image_width: Float,
     pub samples_per_pixel: i32,
     pub max_depth: i32,
-    image_height: f32,
+    image_height: Float,
     // point where all rays originate from, eye point
     center: Point3,
     pixel00_loc: Point3,
@@ -20,29 +60,58 @@ pub struct Camera {
     pixel_delta_v: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
-    pub vfov: f32,
+    pub vfov: Float,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
-    pub defocus_angle: f32,
-    pub focus_dist: f32,
+    pub defocus_angle: Float,
+    pub focus_dist: Float,
+    // trace hero wavelengths instead of rgb, needed for dispersion
+    pub spectral: bool,
+    // trace tiles breadth first in batches, see wavefront.rs. Ignored
+    // in spectral mode
+    pub wavefront: bool,
+    pub background: Background,
 }
 
 impl Camera {
     pub fn render(&mut self, world: &dyn Hittable) {
-        self.initialize();
+        let pixels = self.render_pixels(world);
         println!("P3\n{} {}\n255", self.image_width, self.image_height);
 
+        for pixel_color in pixels {
+            write_color(pixel_color, self.samples_per_pixel);
+        }
+    }
+
+    // renders the image without writing it out, row by row from the top.
+    // Each pixel is the sum of its samples, not yet divided by their count.
+    pub fn render_pixels(&mut self, world: &dyn Hittable) -> Vec<Color> {
+        self.initialize();
+        if self.wavefront && !self.spectral {
+            return wavefront::render(self, world);
+        }
+
+        let mut pixels = Vec::new();
         for row in 0..self.image_height as i32 {
             for col in 0..self.image_width as i32 {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                let mut pixel_color = Color::black();
                 for _ in 0..self.samples_per_pixel {
                     let r = self.get_ray(col, row);
-                    pixel_color += self.ray_color(r, self.max_depth, world);
+                    pixel_color += if self.spectral {
+                        self.sample_spectral(r, world)
+                    } else {
+                        self.ray_color(r, self.max_depth, world)
+                    };
                 }
-                write_color(&mut pixel_color, self.samples_per_pixel);
+                pixels.push(pixel_color);
             }
         }
+        pixels
+    }
+
+    pub(crate) fn image_height(&self) -> Float {
+        self.image_height
     }
 
     fn initialize(&mut self) {
@@ -56,8 +125,8 @@ impl Camera {
         let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width = viewport_height * (self.image_width / self.image_height);
 
-        let w = unit_vector(&mut (self.lookfrom - self.lookat));
-        let u = unit_vector(&mut cross(self.vup, w));
+        let w = unit_vector(self.lookfrom - self.lookat);
+        let u = unit_vector(cross(self.vup, w));
         let v = cross(w, u);
         // Calculate the vectors across the horizontal and
         // down the vertical viewport edges
@@ -79,27 +148,62 @@ impl Camera {
 
     fn ray_color(&self, r: Ray, depth: i32, world: &dyn Hittable) -> Color {
         if depth <= 0 {
-            return Color::origin();
+            return Color::black();
         }
-        if let Some(rec) = world.hit(r, Interval::new(0.001, std::f32::INFINITY)) {
+        if let Some(rec) = world.hit(r, Interval::new(0.0, Float::INFINITY)) {
+            let emitted = rec.material.emitted(rec.u, rec.v, rec.p);
             if let Some((attenuation, scattered)) = rec.material.scatter(&r, &rec) {
-                attenuation * self.ray_color(scattered, depth - 1, world)
+                emitted + attenuation * self.ray_color(scattered, depth - 1, world)
             } else {
-                Color::origin()
+                emitted
             }
         } else {
-            // linear interpolation (lerp) between white and blue
-            // for the background gradient
-            let mut unit_direction = unit_vector(&mut r.direction());
-            let a = 0.5 * (unit_direction.y() + 1.0);
-            (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            self.background.radiance(r.direction())
         }
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
+    // one camera sample in spectral mode, converted back to rgb
+    fn sample_spectral(&self, r: Ray, world: &dyn Hittable) -> Color {
+        let mut lambda = SampledWavelengths::sample_uniform(utils::random_double());
+        let r = r.with_wavelength(lambda.hero());
+        let radiance = self.ray_color_spectral(r, &mut lambda, self.max_depth, world);
+
+        spectrum::to_rgb(&radiance, &lambda)
+    }
+
+    fn ray_color_spectral(
+        &self,
+        r: Ray,
+        lambda: &mut SampledWavelengths,
+        depth: i32,
+        world: &dyn Hittable,
+    ) -> SampledSpectrum {
+        if depth <= 0 {
+            return SampledSpectrum::new(0.0);
+        }
+        let Some(rec) = world.hit(r, Interval::new(0.0, Float::INFINITY)) else {
+            return SampledSpectrum::from_rgb(self.background.radiance(r.direction()), lambda);
+        };
+
+        let emitted = rec.material.emitted(rec.u, rec.v, rec.p);
+        let Some((attenuation, scattered)) = rec.material.scatter(&r, &rec) else {
+            return SampledSpectrum::from_rgb(emitted, lambda);
+        };
+        if rec.material.is_dispersive() {
+            lambda.terminate_secondary();
+        }
+
+        let scattered = scattered.with_wavelength(lambda.hero());
+        let incoming = self.ray_color_spectral(scattered, lambda, depth - 1, world);
+
+        SampledSpectrum::from_rgb(emitted, lambda)
+            + SampledSpectrum::from_rgb(attenuation, lambda) * incoming
+    }
+
+    pub(crate) fn get_ray(&self, i: i32, j: i32) -> Ray {
         let pixel_center = self.pixel00_loc
-            + (self.pixel_delta_u * (i as f32))
-            + ((j as f32) * self.pixel_delta_v);
+            + (self.pixel_delta_u * (i as Float))
+            + ((j as Float) * self.pixel_delta_v);
         let pixel_sample = pixel_center + self.pixel_sample_square();
 
         let ray_origin = if self.defocus_angle <= 0.0 {
@@ -113,7 +217,7 @@ impl Camera {
     }
 
     fn defocus_disk_sample(&self) -> Point3 {
-        let mut p = random_in_unit_disk();
+        let p = random_in_unit_disk();
         self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
     }
 
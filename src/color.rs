@@ -1,18 +1,73 @@
+use std::fmt;
+use std::ops;
+
 use crate::interval::Interval;
-use crate::vec3::Vec3;
+use crate::simd::Float4;
+use crate::utils::{random_double, random_double_bounded, Float};
+use crate::vec3::{tuple3, tuple3_assign_op, tuple3_op};
+
+// linear rgb
+tuple3!(Color);
+
+tuple3_op!(Add, add, +, Color, Color, Color);
+tuple3_op!(Sub, sub, -, Color, Color, Color);
+tuple3_op!(Mul, mul, *, Color, Color, Color);
+tuple3_assign_op!(AddAssign, add_assign, +, Color, Color);
+tuple3_assign_op!(MulAssign, mul_assign, *, Color, Color);
+
+impl Color {
+    pub fn black() -> Color {
+        Color::default()
+    }
+
+    #[inline]
+    pub fn r(&self) -> Float {
+        self.e.x()
+    }
+
+    #[inline]
+    pub fn g(&self) -> Float {
+        self.e.y()
+    }
+
+    #[inline]
+    pub fn b(&self) -> Float {
+        self.e.z()
+    }
 
-pub type Color = Vec3;
+    pub fn random() -> Color {
+        Color::new(random_double(), random_double(), random_double())
+    }
 
-pub fn linear_to_gamma(linear_component: f32) -> f32 {
+    pub fn random_bounded(min: Float, max: Float) -> Color {
+        Color::new(
+            random_double_bounded(min, max),
+            random_double_bounded(min, max),
+            random_double_bounded(min, max),
+        )
+    }
+}
+
+pub fn linear_to_gamma(linear_component: Float) -> Float {
     linear_component.sqrt()
 }
 
-pub fn write_color(pixel: &mut Color, samples_per_pixel: i32) {
-    let scale = 1.0 / (samples_per_pixel as f32);
+// the inverse, for colors stored ready for display
+pub fn gamma_to_linear(gamma_component: Float) -> Float {
+    gamma_component * gamma_component
+}
+
+// relative luminance of a linear rgb color
+pub fn luminance(color: Color) -> Float {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+pub fn write_color(pixel: Color, samples_per_pixel: i32) {
+    let scale = 1.0 / (samples_per_pixel as Float);
 
-    let mut r = pixel.x() * scale;
-    let mut g = pixel.y() * scale;
-    let mut b = pixel.z() * scale;
+    let mut r = pixel.r() * scale;
+    let mut g = pixel.g() * scale;
+    let mut b = pixel.b() * scale;
 
     r = linear_to_gamma(r);
     g = linear_to_gamma(g);
@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::polynomial::solve_quadratic;
+use crate::ray::Ray;
+use crate::utils::{azimuth, degrees_to_radians, gamma, Float, PI};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Open cone around the y axis through `base`, `radius` wide at the base and
+// narrowing to its apex `height` above it. Add a disk to close it. u goes
+// around the axis from +x, v from the base to the apex.
+pub struct Cone {
+    base: Point3,
+    radius: Float,
+    height: Float,
+    phi_max: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Cone {
+    pub fn new(base: Point3, radius: Float, height: Float, material: Arc<dyn Scatter>) -> Cone {
+        Cone {
+            base,
+            radius,
+            height,
+            phi_max: 2.0 * PI,
+            material,
+        }
+    }
+
+    // only the part swept from +x towards +z up to `degrees`
+    pub fn with_phi_max(self, degrees: Float) -> Cone {
+        Cone {
+            phi_max: degrees_to_radians(degrees.clamp(0.0, 360.0)),
+            ..self
+        }
+    }
+
+    fn on_surface(&self, local: Vec3) -> bool {
+        (0.0..=self.height).contains(&local.y())
+            && (self.phi_max >= 2.0 * PI || azimuth(local.x(), local.z()) <= self.phi_max)
+    }
+}
+
+impl Hittable for Cone {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let [ox, oy, oz] = (r.origin() - self.base).to_array();
+        let [dx, dy, dz] = r.direction().to_array();
+
+        // x^2 + z^2 = k^2 (h - y)^2, both nappes of the double cone
+        let k2 = (self.radius / self.height).powi(2);
+        let to_apex = self.height - oy;
+        let a = dx * dx + dz * dz - k2 * dy * dy;
+        let half_b = ox * dx + oz * dz + k2 * to_apex * dy;
+        let c = ox * ox + oz * oz - k2 * to_apex * to_apex;
+        let (t0, t1) = solve_quadratic(a, half_b, c)?;
+
+        [t0, t1]
+            .into_iter()
+            .filter(|&t| ray_t.surrounds(t) && self.on_surface(r.at(t) - self.base))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corner = Vec3::new(self.radius, 0.0, self.radius);
+        Aabb::from_points(
+            self.base - corner,
+            self.base + corner + Vec3::new(0.0, self.height, 0.0),
+        )
+    }
+}
+
+impl Primitive for Cone {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let p = r.at(hit.t);
+        let [x, y, z] = (p - self.base).to_array();
+        let phi = azimuth(x, z);
+        let (sin_phi, cos_phi) = phi.sin_cos();
+
+        // written with phi rather than x and z so they hold at the apex too
+        let dpdu = self.phi_max * Vec3::new(-z, 0.0, x);
+        let dpdv = Vec3::new(-self.radius * cos_phi, self.height, -self.radius * sin_phi);
+        let outward = unit_vector(Vec3::new(
+            self.height * cos_phi,
+            self.radius,
+            self.height * sin_phi,
+        ));
+
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            // the point isn't reprojected, allow for the quadratic's error
+            p_error: gamma(7) * Vec3::from(p).abs(),
+            material: &*self.material,
+            u: phi / self.phi_max,
+            v: y / self.height,
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    fn cone() -> Cone {
+        Cone::new(Point3::origin(), 1.0, 2.0, gray())
+    }
+
+    #[test]
+    fn side() {
+        let cone = cone();
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // halfway up the radius is 0.5
+        let r = Ray::new(Point3::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = cone.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.5).abs() < 1.0e-5);
+        assert!((rec.u).abs() < 1.0e-5 && (rec.v - 0.5).abs() < 1.0e-5);
+        let expected = unit_vector(Vec3::new(2.0, 1.0, 0.0));
+        assert!((Vec3::from(rec.normal) - expected).length() < 1.0e-5);
+
+        // through the open base, the inside is seen
+        let r = Ray::new(Point3::new(0.2, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = cone.hit(r, ray_t).unwrap();
+        assert!((rec.t - 6.6).abs() < 1.0e-4);
+        assert!(!rec.front_face);
+
+        // the upper nappe of the double cone isn't part of it
+        let r = Ray::new(Point3::new(5.0, 3.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(cone.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let quarter = cone().with_phi_max(90.0);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = quarter.hit(r, ray_t).unwrap();
+        assert!((rec.t - 5.5).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+
+        // both crossings are on the removed side of the axis
+        let r = Ray::new(Point3::new(-0.1, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(quarter.hit(r, ray_t).is_none());
+    }
+}
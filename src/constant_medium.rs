@@ -0,0 +1,127 @@
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::hittable::{Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::{Isotropic, Scatter};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utils::{random_double, Float};
+use crate::vec3::{Normal3, Vec3};
+
+use std::sync::Arc;
+
+// Smoke or fog of uniform density filling a closed boundary. A ray
+// passing through scatters at a random depth, the denser the medium the
+// sooner, and the phase function picks where it goes from there.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: Float,
+    phase_function: Arc<dyn Scatter>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: Float, albedo: Color) -> ConstantMedium {
+        ConstantMedium::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn from_texture(
+        boundary: Arc<dyn Hittable>,
+        density: Float,
+        albedo: Arc<dyn Texture>,
+    ) -> ConstantMedium {
+        let phase_function = Arc::new(Isotropic::from_texture(albedo));
+        ConstantMedium::with_phase_function(boundary, density, phase_function)
+    }
+
+    fn with_phase_function(
+        boundary: Arc<dyn Hittable>,
+        density: Float,
+        phase_function: Arc<dyn Scatter>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        // where the whole line enters and leaves the boundary, which
+        // also covers rays starting inside
+        let enter = self.boundary.intersect(r, Interval::UNIVERSE)?.t;
+        let exit = self
+            .boundary
+            .intersect(r, Interval::new(enter + 1.0e-4, Float::INFINITY))?
+            .t;
+
+        let t_min = enter.max(ray_t.min).max(0.0);
+        let t_max = exit.min(ray_t.max);
+        if t_min >= t_max {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (t_max - t_min) * ray_length;
+        // 1 - u keeps the draw in (0, 1], so the log stays finite
+        let hit_distance = self.neg_inv_density * (1.0 - random_double()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+        Some(Hit::new(t_min + hit_distance / ray_length, self))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+impl Primitive for ConstantMedium {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        // a point in a volume has no surface, so the normal and the
+        // tangents are arbitrary
+        HitRecord {
+            t: hit.t,
+            p: r.at(hit.t),
+            p_error: Vec3::zero(),
+            normal: Normal3::new(1.0, 0.0, 0.0),
+            material: &*self.phase_function,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 1.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 1.0),
+            front_face: true,
+            vertex_color: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn scatters_inside() {
+        let material = gray();
+        let boundary = Arc::new(Sphere::new(Point3::origin(), 1.0, material));
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // dense enough that every ray scatters, always inside the sphere
+        let fog = ConstantMedium::new(boundary.clone(), 1.0e6, Color::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = fog.hit(r, ray_t).unwrap();
+        assert!(rec.t >= 4.0 && rec.t < 4.01);
+        let inside = Ray::new(Point3::origin(), Vec3::new(0.0, 1.0, 0.0));
+        assert!(fog.hit(inside, ray_t).unwrap().t < 0.01);
+
+        // too thin to ever scatter, and missing the boundary
+        let haze = ConstantMedium::new(boundary, 1.0e-9, Color::new(1.0, 1.0, 1.0));
+        assert!(haze.hit(r, ray_t).is_none());
+        let r = Ray::new(Point3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(fog.hit(r, ray_t).is_none());
+    }
+}
@@ -0,0 +1,266 @@
+// Constructive solid geometry. Closed primitives report every interval of
+// a ray's line that lies inside them, and csg nodes combine those
+// intervals. Surfaces keep the material of the solid they came from.
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+// part of a ray's line inside a solid, from entering to leaving it
+#[derive(Clone, Copy)]
+pub struct Span<'a> {
+    pub enter: Hit<'a>,
+    pub exit: Hit<'a>,
+}
+
+pub trait Solid: Hittable {
+    // Every interval of the whole line of r, not just the part in front
+    // of the origin, that is inside the solid. Sorted and disjoint.
+    // Alpha masks are ignored since they would open the surface.
+    fn spans(&self, r: Ray) -> Vec<Span<'_>>;
+
+    // false for a shape with a gap in it, like a partial sweep, which
+    // has no inside to combine
+    fn is_closed(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgError {
+    // a partial sweep, or a mesh with holes or with edges shared by more
+    // than two triangles, has no well defined inside
+    NotClosed,
+}
+
+impl fmt::Display for CsgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsgError::NotClosed => write!(f, "csg needs closed solids"),
+        }
+    }
+}
+
+impl Error for CsgError {}
+
+// the first span boundary within ray_t
+pub fn first_boundary<'a>(spans: &[Span<'a>], ray_t: Interval) -> Option<Hit<'a>> {
+    spans
+        .iter()
+        .flat_map(|span| [span.enter, span.exit])
+        .find(|hit| ray_t.surrounds(hit.t))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    // the first solid with the second one cut away
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+pub struct Csg {
+    op: CsgOp,
+    a: Arc<dyn Solid>,
+    b: Arc<dyn Solid>,
+    bbox: Aabb,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Result<Csg, CsgError> {
+        if !a.is_closed() || !b.is_closed() {
+            return Err(CsgError::NotClosed);
+        }
+        let (box_a, box_b) = (a.bounding_box(), b.bounding_box());
+        let bbox = match op {
+            CsgOp::Union => Aabb::enclosing(box_a, box_b),
+            CsgOp::Intersection => Aabb::new(
+                overlap(box_a.x, box_b.x),
+                overlap(box_a.y, box_b.y),
+                overlap(box_a.z, box_b.z),
+            ),
+            CsgOp::Difference => box_a,
+        };
+        Ok(Csg { op, a, b, bbox })
+    }
+
+    pub fn union(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Result<Csg, CsgError> {
+        Csg::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Result<Csg, CsgError> {
+        Csg::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Result<Csg, CsgError> {
+        Csg::new(CsgOp::Difference, a, b)
+    }
+}
+
+// span boundaries tagged with whether they belong to the first solid and
+// whether the line enters it there
+fn boundaries(spans: Vec<Span<'_>>, from_a: bool) -> impl Iterator<Item = (Hit<'_>, bool, bool)> {
+    spans
+        .into_iter()
+        .flat_map(move |s| [(s.enter, from_a, true), (s.exit, from_a, false)])
+}
+
+fn overlap(a: Interval, b: Interval) -> Interval {
+    Interval::new(a.min.max(b.min), a.max.min(b.max))
+}
+
+impl Solid for Csg {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        if !self.bbox.hit(r, Interval::UNIVERSE) {
+            return Vec::new();
+        }
+
+        // boundaries of both solids in order along the line
+        let mut events: Vec<_> = boundaries(self.a.spans(r), true)
+            .chain(boundaries(self.b.spans(r), false))
+            .collect();
+        events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+        let (mut in_a, mut in_b, mut inside) = (false, false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for (hit, from_a, entering) in events {
+            if from_a {
+                in_a = entering;
+            } else {
+                in_b = entering;
+            }
+            if self.op.contains(in_a, in_b) == inside {
+                continue;
+            }
+            inside = !inside;
+
+            // leaving a child where the result is entered, or the other
+            // way around, turns that child's surface inside out
+            let hit = Hit {
+                flipped: hit.flipped ^ (entering != inside),
+                ..hit
+            };
+            match enter.take() {
+                None => enter = Some(hit),
+                Some(enter) => spans.push(Span { enter, exit: hit }),
+            }
+        }
+        spans
+    }
+}
+
+impl Hittable for Csg {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        first_boundary(&self.spans(r), ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Lambertian, Scatter};
+    use crate::sphere::Sphere;
+    use crate::utils::Float;
+    use crate::vec3::{Point3, Vec3};
+
+    fn sphere(x: Float, material: &Arc<dyn Scatter>) -> Arc<dyn Solid> {
+        Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 1.0, material.clone()))
+    }
+
+    fn materials() -> (Arc<dyn Scatter>, Arc<dyn Scatter>) {
+        (
+            Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1))),
+            Arc::new(Lambertian::new(Color::new(0.1, 0.1, 0.8))),
+        )
+    }
+
+    // ray along the x axis, from the left
+    fn ray() -> Ray {
+        Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    fn assert_spans(solid: &dyn Solid, expected: &[(Float, Float)]) {
+        let spans = solid.spans(ray());
+        assert_eq!(spans.len(), expected.len());
+        for (span, (enter, exit)) in spans.iter().zip(expected) {
+            assert!((span.enter.t - enter).abs() < 1.0e-5);
+            assert!((span.exit.t - exit).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn operations() {
+        let (red, blue) = materials();
+        let (a, b) = (sphere(0.0, &red), sphere(1.0, &blue));
+
+        let union = Csg::union(a.clone(), b.clone()).unwrap();
+        assert_spans(&union, &[(4.0, 7.0)]);
+        let intersection = Csg::intersection(a.clone(), b.clone()).unwrap();
+        assert_spans(&intersection, &[(5.0, 6.0)]);
+        let difference = Csg::difference(a.clone(), b.clone()).unwrap();
+        assert_spans(&difference, &[(4.0, 5.0)]);
+
+        // b splits a into two pieces
+        let thin = Arc::new(Sphere::new(Point3::origin(), 0.5, blue.clone()));
+        let split = Csg::difference(a, thin).unwrap();
+        assert_spans(&split, &[(4.0, 4.5), (5.5, 6.0)]);
+    }
+
+    #[test]
+    fn cut_surfaces_face_outwards() {
+        let (red, blue) = materials();
+        // the left sphere with the right one cut away, seen from the right
+        let difference = Csg::difference(sphere(0.0, &red), sphere(1.0, &blue)).unwrap();
+        let r = Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+
+        let rec = difference
+            .hit(r, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        // entering the result through the inside of the removed sphere
+        assert!((rec.t - 5.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((Vec3::from(rec.normal) - Vec3::new(1.0, 0.0, 0.0)).length() < 1.0e-5);
+        assert!(std::ptr::addr_eq(rec.material, &*blue));
+
+        // from inside the result the next boundary is its exit
+        let inside = Ray::new(Point3::new(-0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = difference
+            .hit(inside, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 0.5).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn nested() {
+        let (red, blue) = materials();
+        let lens = Arc::new(Csg::intersection(sphere(0.0, &red), sphere(1.0, &blue)).unwrap());
+        let rest = Csg::difference(sphere(0.5, &red), lens).unwrap();
+
+        assert_spans(&rest, &[(4.5, 5.0), (6.0, 6.5)]);
+        let r = ray();
+        assert!(rest.occluded(r, Interval::new(0.0, Float::INFINITY)));
+        assert!(!rest.occluded(r, Interval::new(0.0, 4.0)));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::utils::{gamma, Float};
+use crate::vec3::{Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// axis aligned box, closed so it can take part in csg
+pub struct Cuboid {
+    bbox: Aabb,
+    material: Arc<dyn Scatter>,
+}
+
+impl Cuboid {
+    // a and b are opposite corners, in any order
+    pub fn new(a: Point3, b: Point3, material: Arc<dyn Scatter>) -> Cuboid {
+        Cuboid {
+            bbox: Aabb::from_points(a, b),
+            material,
+        }
+    }
+
+    // where the line of r enters and leaves the box
+    fn slabs(&self, r: Ray) -> Option<(Float, Float)> {
+        let origin = r.origin().to_array();
+        let direction = r.direction().to_array();
+
+        let (mut t_enter, mut t_exit) = (-Float::INFINITY, Float::INFINITY);
+        for axis in 0..3 {
+            let slab = self.bbox.axis(axis);
+            if direction[axis] == 0.0 {
+                if !slab.contains(origin[axis]) {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (slab.min - origin[axis]) / direction[axis];
+            let t1 = (slab.max - origin[axis]) / direction[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        (t_enter <= t_exit).then_some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for Cuboid {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let (t0, t1) = self.slabs(r)?;
+        [t0, t1]
+            .into_iter()
+            .filter(|&t| ray_t.surrounds(t))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Primitive for Cuboid {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let mut p = r.at(hit.t).to_array();
+
+        // the face is the one whose plane the point is nearest to
+        let (mut axis, mut max_side, mut nearest) = (0, false, Float::INFINITY);
+        for (a, &coord) in p.iter().enumerate() {
+            let slab = self.bbox.axis(a);
+            for (side, plane) in [(false, slab.min), (true, slab.max)] {
+                let distance = (coord - plane).abs() / slab.size().max(Float::MIN_POSITIVE);
+                if distance < nearest {
+                    (axis, max_side, nearest) = (a, side, distance);
+                }
+            }
+        }
+        let slab = self.bbox.axis(axis);
+        p[axis] = if max_side { slab.max } else { slab.min };
+        let p = Point3::new(p[0], p[1], p[2]);
+
+        // the other two axes in cyclic order span the face
+        let (a_u, a_v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let unit = |a: usize| {
+            let mut v = [0.0; 3];
+            v[a] = 1.0;
+            Vec3::new(v[0], v[1], v[2])
+        };
+        let (slab_u, slab_v) = (self.bbox.axis(a_u), self.bbox.axis(a_v));
+        let coords = p.to_array();
+
+        let sign = if max_side { 1.0 } else { -1.0 };
+        let outward_normal = Normal3::from(sign * unit(axis));
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            // the face coordinate was snapped onto its plane
+            p_error: gamma(5) * Vec3::from(p).abs() * (Vec3::new(1.0, 1.0, 1.0) - unit(axis)),
+            material: &*self.material,
+            u: (coords[a_u] - slab_u.min) / slab_u.size(),
+            v: (coords[a_v] - slab_v.min) / slab_v.size(),
+            dpdu: slab_u.size() * unit(a_u),
+            dpdv: slab_v.size() * unit(a_v),
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+impl Solid for Cuboid {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        match self.slabs(r) {
+            Some((t0, t1)) => vec![Span {
+                enter: Hit::new(t0, self),
+                exit: Hit::new(t1, self),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    fn unit_cube() -> Cuboid {
+        let material = gray();
+        Cuboid::new(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            material,
+        )
+    }
+
+    #[test]
+    fn faces() {
+        let cube = unit_cube();
+        let r = Ray::new(Point3::new(0.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let rec = cube.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert_eq!(rec.t, 4.0);
+        assert!(rec.front_face);
+        assert_eq!(Vec3::from(rec.normal), Vec3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.75).abs() < 1.0e-6 && (rec.v - 0.5).abs() < 1.0e-6);
+
+        // from inside, leaving through the opposite face
+        let rec = cube.hit(r, Interval::new(4.5, Float::INFINITY)).unwrap();
+        assert_eq!(rec.t, 6.0);
+        assert!(!rec.front_face);
+        assert_eq!(Vec3::from(rec.normal), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn parallel_rays() {
+        let cube = unit_cube();
+        let outside = Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cube.spans(outside).is_empty());
+
+        let inside = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(cube.spans(inside).len(), 1);
+    }
+}
@@ -0,0 +1,475 @@
+// Thin cubic curves for hair, fur and grass, intersected as in pbrt: the
+// curve is moved into a frame where the ray runs down +z from the origin
+// and split in half until each piece is nearly straight, skipping pieces
+// whose bounds miss the ray. A hit is always on a ribbon facing the ray.
+
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::utils::Float;
+use crate::vec3::{cross, dot, unit_vector, Normal3, Point3, Vec3};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveKind {
+    // a flat strip always facing the ray, for grass blades seen from afar
+    Flat,
+    // still a strip, but shaded with normals that turn across it as if it
+    // were a tube, for hair and fur
+    Round,
+}
+
+pub struct Curve {
+    // bezier control points
+    cp: [Point3; 4],
+    // at the start and the end, in between it changes linearly
+    width: [Float; 2],
+    kind: CurveKind,
+    material: Arc<dyn Scatter>,
+    bbox: Aabb,
+}
+
+impl Curve {
+    pub fn bezier(
+        cp: [Point3; 4],
+        width: [Float; 2],
+        kind: CurveKind,
+        material: Arc<dyn Scatter>,
+    ) -> Curve {
+        let half = 0.5 * width[0].max(width[1]);
+        let pad = Vec3::new(half, half, half);
+        let bbox = cp.iter().fold(Aabb::EMPTY, |bbox, &p| {
+            Aabb::enclosing(bbox, Aabb::from_points(p - pad, p + pad))
+        });
+        Curve {
+            cp,
+            width,
+            kind,
+            material,
+            bbox,
+        }
+    }
+
+    // one bezier segment for each span of four uniform b-spline points,
+    // together a smooth strand through (near) all of them, with the width
+    // changing linearly along the strand
+    pub fn bspline(
+        points: &[Point3],
+        width: [Float; 2],
+        kind: CurveKind,
+        material: Arc<dyn Scatter>,
+    ) -> Vec<Curve> {
+        let segments = points.len().saturating_sub(3);
+        let width_at = |i: usize| {
+            let s = i as Float / segments as Float;
+            width[0] + s * (width[1] - width[0])
+        };
+        points
+            .windows(4)
+            .enumerate()
+            .map(|(i, p)| {
+                let [p0, p1, p2, p3] = [p[0], p[1], p[2], p[3]].map(Vec3::from);
+                let cp = [
+                    (p0 + 4.0 * p1 + p2) / 6.0,
+                    (2.0 * p1 + p2) / 3.0,
+                    (p1 + 2.0 * p2) / 3.0,
+                    (p1 + 4.0 * p2 + p3) / 6.0,
+                ]
+                .map(Point3::from);
+                Curve::bezier(cp, [width_at(i), width_at(i + 1)], kind, material.clone())
+            })
+            .collect()
+    }
+
+    fn width_at(&self, u: Float) -> Float {
+        self.width[0] + u * (self.width[1] - self.width[0])
+    }
+
+    // frame with the ray down +z from its origin and the curve's chord
+    // across x, which keeps the bounds of the pieces tight in y
+    fn ray_frame(&self, r: Ray) -> [Vec3; 3] {
+        let z = unit_vector(r.direction());
+        let across = cross(r.direction(), self.cp[3] - self.cp[0]);
+        let y = if across.length_squared() > 0.0 {
+            unit_vector(across)
+        } else {
+            Onb::build_from_w(z).v()
+        };
+        [cross(y, z), y, z]
+    }
+
+    // nearest hit on the curve's piece cp, covering u0..u1 of the whole
+    // curve, as the distance along the ray and the curve's u and v
+    #[allow(clippy::too_many_arguments)]
+    fn recursive_intersect(
+        &self,
+        r: Ray,
+        ray_t: Interval,
+        z_max: &mut Float,
+        cp: [Vec3; 4],
+        u0: Float,
+        u1: Float,
+        depth: u32,
+    ) -> Option<(Float, Float, Float)> {
+        let length = r.direction().length();
+
+        if depth > 0 {
+            let split = subdivide_bezier(cp);
+            let us = [u0, 0.5 * (u0 + u1), u1];
+            let mut nearest = None;
+            for seg in 0..2 {
+                let piece = [
+                    split[3 * seg],
+                    split[3 * seg + 1],
+                    split[3 * seg + 2],
+                    split[3 * seg + 3],
+                ];
+                let half = 0.5 * self.width_at(us[seg]).max(self.width_at(us[seg + 1]));
+                let (low, high) = piece.iter().fold((piece[0], piece[0]), |(low, high), &p| {
+                    (low.min(p), high.max(p))
+                });
+                // the ray is the segment from the origin to z_max on z
+                if high.x() + half < 0.0
+                    || low.x() - half > 0.0
+                    || high.y() + half < 0.0
+                    || low.y() - half > 0.0
+                    || high.z() + half < ray_t.min * length
+                    || low.z() - half > *z_max
+                {
+                    continue;
+                }
+                if let Some(hit) = self.recursive_intersect(
+                    r,
+                    ray_t,
+                    z_max,
+                    piece,
+                    us[seg],
+                    us[seg + 1],
+                    depth - 1,
+                ) {
+                    nearest = Some(hit);
+                }
+            }
+            return nearest;
+        }
+
+        // the ray has to pass between the lines through the end points
+        // perpendicular to the curve there
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if edge < 0.0 {
+            return None;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if edge < 0.0 {
+            return None;
+        }
+
+        // nearest point on the piece's chord, seen down the ray
+        let (chord_x, chord_y) = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let denom = chord_x * chord_x + chord_y * chord_y;
+        if denom == 0.0 {
+            return None;
+        }
+        let w = -(cp[0].x() * chord_x + cp[0].y() * chord_y) / denom;
+        let u = (u0 + w * (u1 - u0)).clamp(u0, u1);
+        let width = self.width_at(u);
+
+        let (pc, dpcdw) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        let dist_squared = pc.x() * pc.x() + pc.y() * pc.y();
+        if dist_squared > 0.25 * width * width || pc.z() > *z_max {
+            return None;
+        }
+        let t = pc.z() / length;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // v runs across the ribbon, 0.5 on the curve itself
+        let dist = dist_squared.sqrt();
+        let side = dpcdw.x() * -pc.y() + pc.x() * dpcdw.y();
+        let v = if side > 0.0 {
+            0.5 + dist / width
+        } else {
+            0.5 - dist / width
+        };
+
+        let hit = Hit {
+            u,
+            v,
+            ..Hit::new(t, self)
+        };
+        let visible = alpha_test(&*self.material, || {
+            let rec = self.interaction(r, &hit);
+            (rec.u, rec.v, rec.p)
+        });
+        if !visible {
+            return None;
+        }
+        *z_max = pc.z();
+        Some((t, u, v))
+    }
+}
+
+// point and tangent of a bezier curve at u
+fn eval_bezier(cp: [Vec3; 4], u: Float) -> (Vec3, Vec3) {
+    let lerp = |a: Vec3, b: Vec3| (1.0 - u) * a + u * b;
+    let cp1 = [lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3])];
+    let cp2 = [lerp(cp1[0], cp1[1]), lerp(cp1[1], cp1[2])];
+    let tangent = if (cp2[1] - cp2[0]).length_squared() > 0.0 {
+        3.0 * (cp2[1] - cp2[0])
+    } else {
+        // degenerate at an end where control points coincide
+        cp[3] - cp[0]
+    };
+    (lerp(cp2[0], cp2[1]), tangent)
+}
+
+// the two halves of a bezier curve, sharing the middle point
+fn subdivide_bezier(cp: [Vec3; 4]) -> [Vec3; 7] {
+    [
+        cp[0],
+        0.5 * (cp[0] + cp[1]),
+        0.25 * (cp[0] + 2.0 * cp[1] + cp[2]),
+        0.125 * (cp[0] + 3.0 * (cp[1] + cp[2]) + cp[3]),
+        0.25 * (cp[1] + 2.0 * cp[2] + cp[3]),
+        0.5 * (cp[2] + cp[3]),
+        cp[3],
+    ]
+}
+
+impl Hittable for Curve {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        if !self.bbox.hit(r, ray_t) {
+            return None;
+        }
+
+        let frame = self.ray_frame(r);
+        let cp = self.cp.map(|p| {
+            let d = p - r.origin();
+            Vec3::new(dot(d, frame[0]), dot(d, frame[1]), dot(d, frame[2]))
+        });
+
+        // enough splits for the pieces to be within a twentieth of the
+        // width of straight
+        let mut flatness: Float = 0.0;
+        for i in 0..2 {
+            let second = (cp[i] - 2.0 * cp[i + 1] + cp[i + 2]).abs();
+            flatness = flatness.max(second.x()).max(second.y()).max(second.z());
+        }
+        let eps = 0.05 * self.width[0].max(self.width[1]);
+        let depth = if eps > 0.0 {
+            ((Float::sqrt(2.0) * 6.0 * flatness / (8.0 * eps)).log2() / 2.0).clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+
+        let mut z_max = ray_t.max * r.direction().length();
+        let (t, u, v) = self.recursive_intersect(r, ray_t, &mut z_max, cp, 0.0, 1.0, depth)?;
+        Some(Hit {
+            u,
+            v,
+            ..Hit::new(t, self)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Primitive for Curve {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let cp = self.cp.map(Vec3::from);
+        let (_, dpdu) = eval_bezier(cp, hit.u);
+        let width = self.width_at(hit.u);
+
+        // across the ribbon, perpendicular to both the curve and the ray,
+        // with the normal of the flat ribbon facing the ray
+        let across = cross(dpdu, r.direction());
+        let across = if across.length_squared() > 0.0 {
+            unit_vector(across)
+        } else {
+            Onb::build_from_w(dpdu).u()
+        };
+        let mut dpdv = width * across;
+        if self.kind == CurveKind::Round {
+            // turns from facing one side to the other across the width
+            let theta = -90.0 + 180.0 * hit.v;
+            dpdv = Transform::rotate(theta, dpdu).vector(dpdv);
+        }
+
+        let outward_normal = Normal3::from(unit_vector(cross(dpdu, dpdv)));
+        let mut rec = HitRecord {
+            t: hit.t,
+            p: r.at(hit.t),
+            p_error: Vec3::new(2.0, 2.0, 2.0) * width,
+            material: &*self.material,
+            u: hit.u,
+            v: hit.v,
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("curve set line {}: {}", line + 1, message),
+    )
+}
+
+// Strands from a text file, one per line, `#` starts a comment:
+//
+//   bezier <width0> <width1> x y z ...    3n + 1 points, n segments
+//   bspline <width0> <width1> x y z ...   at least 4 points
+//
+// Put the curves in a bvh, strands are usually many and small.
+pub fn load_curve_set(
+    path: impl AsRef<Path>,
+    kind: CurveKind,
+    material: Arc<dyn Scatter>,
+) -> io::Result<Vec<Curve>> {
+    parse_curve_set(&fs::read_to_string(path)?, kind, material)
+}
+
+pub fn parse_curve_set(
+    text: &str,
+    kind: CurveKind,
+    material: Arc<dyn Scatter>,
+) -> io::Result<Vec<Curve>> {
+    let mut curves = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let Some(basis) = fields.next() else {
+            continue;
+        };
+        let numbers = fields
+            .map(|f| f.parse::<Float>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid(n, "bad number"))?;
+        if numbers.len() < 2 || (numbers.len() - 2) % 3 != 0 {
+            return Err(invalid(n, "expected two widths and x y z triples"));
+        }
+        let width = [numbers[0], numbers[1]];
+        let points: Vec<Point3> = numbers[2..]
+            .chunks_exact(3)
+            .map(|p| Point3::new(p[0], p[1], p[2]))
+            .collect();
+
+        match basis {
+            "bezier" => {
+                if points.len() < 4 || (points.len() - 1) % 3 != 0 {
+                    return Err(invalid(n, "a bezier strand needs 3n + 1 points"));
+                }
+                let segments = (points.len() - 1) / 3;
+                for (i, cp) in points.windows(4).step_by(3).enumerate() {
+                    let s = |i: usize| {
+                        let s = i as Float / segments as Float;
+                        width[0] + s * (width[1] - width[0])
+                    };
+                    let cp = [cp[0], cp[1], cp[2], cp[3]];
+                    curves.push(Curve::bezier(cp, [s(i), s(i + 1)], kind, material.clone()));
+                }
+            }
+            "bspline" => {
+                if points.len() < 4 {
+                    return Err(invalid(n, "a b-spline strand needs at least 4 points"));
+                }
+                curves.extend(Curve::bspline(&points, width, kind, material.clone()));
+            }
+            _ => return Err(invalid(n, "expected bezier or bspline")),
+        }
+    }
+    Ok(curves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    // an arch in the xy plane from (-2, 0) to (2, 0), its top at y = 1.5
+    fn arch(kind: CurveKind) -> Curve {
+        let cp = [
+            Point3::new(-2.0, 0.0, 0.0),
+            Point3::new(-1.0, 2.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+        ];
+        Curve::bezier(cp, [0.2, 0.2], kind, gray())
+    }
+
+    #[test]
+    fn ribbon_hits() {
+        let curve = arch(CurveKind::Flat);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // square on at the top of the arch
+        let r = Ray::new(Point3::new(0.0, 1.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = curve.hit(r, ray_t).unwrap();
+        assert!((rec.t - 5.0).abs() < 1.0e-3);
+        assert!((rec.u - 0.5).abs() < 1.0e-2);
+        assert!((rec.v - 0.5).abs() < 1.0e-2);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 0.0, -1.0)).length() < 1.0e-3);
+
+        // near the edge of the width, and just past it
+        let r = Ray::new(Point3::new(0.0, 1.58, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = curve.hit(r, ray_t).unwrap();
+        assert!((rec.v - 0.5).abs() > 0.3);
+        let r = Ray::new(Point3::new(0.0, 1.65, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(curve.hit(r, ray_t).is_none());
+
+        // under the arch
+        let r = Ray::new(Point3::new(0.0, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(curve.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn round_normals_turn_across() {
+        let curve = arch(CurveKind::Round);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let normal_at = |y: Float| {
+            let r = Ray::new(Point3::new(0.0, y, -5.0), Vec3::new(0.0, 0.0, 1.0));
+            Vec3::from(curve.hit(r, ray_t).unwrap().normal)
+        };
+        // facing the ray in the middle, tilted towards the edges
+        assert!(normal_at(1.5).z() < -0.99);
+        assert!(normal_at(1.58).y() > 0.5);
+        assert!(normal_at(1.42).y() < -0.5);
+    }
+
+    #[test]
+    fn curve_set() {
+        let text = "\
+            # two strands\n\
+            bezier 0.1 0.05 0 0 0  0 1 0  0 2 0  0 3 0  0 4 0  0 5 0  0 6 0\n\
+            bspline 0.1 0.1 1 0 0  1 1 0  1 2 0  1 3 0  1 4 0\n";
+        let curves = parse_curve_set(text, CurveKind::Flat, gray()).unwrap();
+        assert_eq!(curves.len(), 4);
+        assert!((curves[1].width[1] - 0.05).abs() < 1.0e-6);
+
+        // a b-spline through points on a line stays on it
+        let r = Ray::new(Point3::new(1.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+        assert!(curves[2..].iter().any(|c| c.hit(r, ray_t).is_some()));
+
+        assert!(parse_curve_set("bezier 0.1 0.1 0 0 0", CurveKind::Flat, gray()).is_err());
+        assert!(parse_curve_set("nurbs 0.1 0.1", CurveKind::Flat, gray()).is_err());
+    }
+}
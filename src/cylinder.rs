@@ -0,0 +1,255 @@
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::polynomial::solve_quadratic;
+use crate::ray::Ray;
+use crate::utils::{azimuth, degrees_to_radians, gamma, Float, PI};
+use crate::vec3::{Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Closed cylinder standing on the y axis through `base`, capped at both
+// ends. u goes around the axis from +x, v up the side and outwards from
+// the center on the caps.
+pub struct Cylinder {
+    base: Point3,
+    radius: Float,
+    height: Float,
+    // sweep around the axis in radians, less than a full turn cuts the
+    // cylinder open
+    phi_max: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, radius: Float, height: Float, material: Arc<dyn Scatter>) -> Cylinder {
+        Cylinder {
+            base,
+            radius,
+            height,
+            phi_max: 2.0 * PI,
+            material,
+        }
+    }
+
+    // only the part swept from +x towards +z up to `degrees`
+    pub fn with_phi_max(self, degrees: Float) -> Cylinder {
+        Cylinder {
+            phi_max: degrees_to_radians(degrees.clamp(0.0, 360.0)),
+            ..self
+        }
+    }
+
+    fn in_sweep(&self, p: Point3) -> bool {
+        let local = p - self.base;
+        self.phi_max >= 2.0 * PI || azimuth(local.x(), local.z()) <= self.phi_max
+    }
+
+    // where the line of r enters and leaves the cylinder
+    fn span(&self, r: Ray) -> Option<(Float, Float)> {
+        let [ox, oy, oz] = (r.origin() - self.base).to_array();
+        let [dx, dy, dz] = r.direction().to_array();
+
+        // infinite side
+        let a = dx * dx + dz * dz;
+        let c = ox * ox + oz * oz - self.radius * self.radius;
+        let (mut t_enter, mut t_exit) = if a == 0.0 {
+            if c > 0.0 {
+                return None;
+            }
+            (-Float::INFINITY, Float::INFINITY)
+        } else {
+            solve_quadratic(a, ox * dx + oz * dz, c)?
+        };
+
+        // between the caps
+        if dy == 0.0 {
+            if oy < 0.0 || oy > self.height {
+                return None;
+            }
+        } else {
+            let (t0, t1) = (-oy / dy, (self.height - oy) / dy);
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+
+        (t_enter <= t_exit).then_some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for Cylinder {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let (t0, t1) = self.span(r)?;
+        [t0, t1]
+            .into_iter()
+            .filter(|&t| ray_t.surrounds(t) && self.in_sweep(r.at(t)))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corner = Vec3::new(self.radius, 0.0, self.radius);
+        Aabb::from_points(
+            self.base - corner,
+            self.base + corner + Vec3::new(0.0, self.height, 0.0),
+        )
+    }
+}
+
+impl Primitive for Cylinder {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let [x, y, z] = (r.at(hit.t) - self.base).to_array();
+        let radial = (x * x + z * z).sqrt();
+        let u = azimuth(x, z) / self.phi_max;
+        // around the axis, the same on the side and the caps
+        let dpdu = self.phi_max * Vec3::new(-z, 0.0, x);
+
+        // whichever surface the point is nearest to, snapped onto it
+        let side = (radial - self.radius).abs();
+        let (bottom, top) = (y.abs(), (y - self.height).abs());
+        let (local, outward, v, dpdv) = if side <= bottom.min(top) {
+            let scale = self.radius / radial.max(Float::MIN_POSITIVE);
+            let local = Vec3::new(x * scale, y, z * scale);
+            let outward = Vec3::new(local.x(), 0.0, local.z()) / self.radius;
+            (
+                local,
+                outward,
+                y / self.height,
+                Vec3::new(0.0, self.height, 0.0),
+            )
+        } else {
+            let cap_y = if bottom < top { 0.0 } else { self.height };
+            let outward = Vec3::new(0.0, if bottom < top { -1.0 } else { 1.0 }, 0.0);
+            let dpdv = if radial > 0.0 {
+                (self.radius / radial) * Vec3::new(x, 0.0, z)
+            } else {
+                Vec3::new(self.radius, 0.0, 0.0)
+            };
+            (Vec3::new(x, cap_y, z), outward, radial / self.radius, dpdv)
+        };
+
+        let p = self.base + local;
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error: gamma(5) * local.abs() + gamma(1) * Vec3::from(p).abs(),
+            material: &*self.material,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+impl Solid for Cylinder {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        match self.span(r) {
+            Some((t0, t1)) => vec![Span {
+                enter: Hit::new(t0, self),
+                exit: Hit::new(t1, self),
+            }],
+            None => Vec::new(),
+        }
+    }
+
+    // the sweep leaves a gap with no surface across it
+    fn is_closed(&self) -> bool {
+        self.phi_max >= 2.0 * PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csg::{Csg, CsgError};
+    use crate::cuboid::Cuboid;
+    use crate::material::tests::gray;
+
+    fn cylinder() -> Cylinder {
+        Cylinder::new(Point3::origin(), 1.0, 2.0, gray())
+    }
+
+    #[test]
+    fn side_and_caps() {
+        let cylinder = cylinder();
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = cylinder.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!((Vec3::from(rec.normal) - Vec3::new(1.0, 0.0, 0.0)).length() < 1.0e-5);
+        assert!((rec.v - 0.5).abs() < 1.0e-5);
+
+        let r = Ray::new(Point3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = cylinder.hit(r, ray_t).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-5);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+        assert!((rec.v - 0.5).abs() < 1.0e-5);
+
+        // above the top, parallel to the axis but outside it
+        let r = Ray::new(Point3::new(1.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let half = cylinder().with_phi_max(180.0);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // the front half (z > 0) is there, the back half is open
+        let r = Ray::new(Point3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = half.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!((rec.u - 0.5).abs() < 1.0e-5);
+
+        // from behind, the inside of the front half is seen
+        let r = Ray::new(Point3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = half.hit(r, ray_t).unwrap();
+        assert!((rec.t - 6.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn drilled_block() {
+        let material = gray();
+        let block = Arc::new(Cuboid::new(
+            Point3::new(-2.0, 0.5, -2.0),
+            Point3::new(2.0, 1.5, 2.0),
+            material,
+        ));
+        let drilled = Csg::difference(block.clone(), Arc::new(cylinder())).unwrap();
+        // half a cylinder is open, so there's no hole to drill with it
+        let half = Arc::new(cylinder().with_phi_max(180.0));
+        assert_eq!(
+            Csg::difference(block, half).err(),
+            Some(CsgError::NotClosed)
+        );
+
+        // straight down the hole
+        let r = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(drilled.spans(r).is_empty());
+
+        // across the block and through the hole: inner wall faces the hole
+        let r = Ray::new(Point3::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let spans = drilled.spans(r);
+        assert_eq!(spans.len(), 2);
+        let rec = spans[0].exit.interaction(r);
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+        assert!((Vec3::from(rec.normal) - Vec3::new(1.0, 0.0, 0.0)).length() < 1.0e-5);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::utils::{azimuth, degrees_to_radians, gamma, Float, PI};
+use crate::vec3::{Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Flat disk facing +y centered on `center`, with an optional hole to make
+// it an annulus. u goes around the center from +x, v inwards from the rim.
+pub struct Disk {
+    center: Point3,
+    radius: Float,
+    inner_radius: Float,
+    phi_max: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Disk {
+    pub fn new(center: Point3, radius: Float, material: Arc<dyn Scatter>) -> Disk {
+        Disk {
+            center,
+            radius,
+            inner_radius: 0.0,
+            phi_max: 2.0 * PI,
+            material,
+        }
+    }
+
+    pub fn with_inner_radius(self, inner_radius: Float) -> Disk {
+        Disk {
+            inner_radius: inner_radius.clamp(0.0, self.radius),
+            ..self
+        }
+    }
+
+    // only the part swept from +x towards +z up to `degrees`
+    pub fn with_phi_max(self, degrees: Float) -> Disk {
+        Disk {
+            phi_max: degrees_to_radians(degrees.clamp(0.0, 360.0)),
+            ..self
+        }
+    }
+
+    fn on_surface(&self, local: Vec3) -> bool {
+        let dist_squared = local.x() * local.x() + local.z() * local.z();
+        dist_squared <= self.radius * self.radius
+            && dist_squared >= self.inner_radius * self.inner_radius
+            && (self.phi_max >= 2.0 * PI || azimuth(local.x(), local.z()) <= self.phi_max)
+    }
+}
+
+impl Hittable for Disk {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let dy = r.direction().y();
+        if dy == 0.0 {
+            return None;
+        }
+        let t = (self.center.y() - r.origin().y()) / dy;
+        if !ray_t.surrounds(t) || !self.on_surface(r.at(t) - self.center) {
+            return None;
+        }
+
+        let hit = Hit::new(t, self);
+        alpha_test(&*self.material, || {
+            let rec = self.interaction(r, &hit);
+            (rec.u, rec.v, rec.p)
+        })
+        .then_some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // padded so the slab test doesn't miss a box with no thickness
+        let corner = Vec3::new(self.radius, 1.0e-4, self.radius);
+        Aabb::from_points(self.center - corner, self.center + corner)
+    }
+}
+
+impl Primitive for Disk {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let [x, _, z] = (r.at(hit.t) - self.center).to_array();
+        let dist = (x * x + z * z).sqrt();
+        let phi = azimuth(x, z);
+        let (sin_phi, cos_phi) = phi.sin_cos();
+
+        // snapped onto the plane
+        let local = Vec3::new(x, 0.0, z);
+        let p = self.center + local;
+        let outward_normal = Normal3::from(Vec3::new(0.0, 1.0, 0.0));
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error: gamma(5) * Vec3::from(p).abs() * Vec3::new(1.0, 0.0, 1.0),
+            material: &*self.material,
+            u: phi / self.phi_max,
+            v: (self.radius - dist) / (self.radius - self.inner_radius),
+            dpdu: self.phi_max * Vec3::new(-z, 0.0, x),
+            dpdv: (self.inner_radius - self.radius) * Vec3::new(cos_phi, 0.0, sin_phi),
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    fn annulus() -> Disk {
+        Disk::new(Point3::new(0.0, 1.0, 0.0), 2.0, gray()).with_inner_radius(1.0)
+    }
+
+    #[test]
+    fn annulus_hits() {
+        let disk = annulus();
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(1.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = disk.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((rec.v - 0.5).abs() < 1.0e-5);
+        assert!(disk.bounding_box().hit(r, ray_t));
+
+        // from below the back is seen
+        let r = Ray::new(Point3::new(0.0, -5.0, 1.5), Vec3::new(0.0, 1.0, 0.0));
+        let rec = disk.hit(r, ray_t).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.u - 0.25).abs() < 1.0e-5);
+
+        // through the hole and past the rim
+        let r = Ray::new(Point3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(r, ray_t).is_none());
+        let r = Ray::new(Point3::new(2.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let half = annulus().with_phi_max(180.0);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(0.0, 5.0, 1.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(half.hit(r, ray_t).is_some());
+        let r = Ray::new(Point3::new(0.0, 5.0, -1.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(half.hit(r, ray_t).is_none());
+    }
+}
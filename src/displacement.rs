@@ -0,0 +1,308 @@
+// Displacement mapping at load time. The mesh is first tessellated until
+// its edges are short enough to carry the detail, then every vertex moves
+// along its normal by the texture's height and the normals are rebuilt.
+// Edges are split at their midpoints and whether an edge splits depends
+// on nothing but the edge, so neighbouring triangles always agree and no
+// cracks open up.
+
+use crate::mesh::{weld, TriangleMesh};
+use crate::texture::Texture;
+use crate::utils::Float;
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// rounds of splitting at most, each can quadruple the triangle count
+const MAX_LEVELS: u32 = 10;
+
+pub struct Displacement {
+    // height from the first channel, see SolidColor::from_value
+    texture: Arc<dyn Texture>,
+    scale: Float,
+    max_edge: Float,
+    eye: Option<(Point3, Float)>,
+}
+
+impl Displacement {
+    pub fn new(texture: Arc<dyn Texture>, scale: Float) -> Displacement {
+        Displacement {
+            texture,
+            scale,
+            max_edge: Float::INFINITY,
+            eye: None,
+        }
+    }
+
+    // split edges until none is longer than this
+    pub fn with_max_edge(self, length: Float) -> Displacement {
+        Displacement {
+            max_edge: length,
+            ..self
+        }
+    }
+
+    // max_edge then holds at `distance` from the eye and grows in
+    // proportion further away, so the detail goes where it can be seen
+    pub fn with_eye(self, eye: Point3, distance: Float) -> Displacement {
+        Displacement {
+            eye: Some((eye, distance)),
+            ..self
+        }
+    }
+
+    fn splits(&self, a: Point3, b: Point3) -> bool {
+        let limit = match self.eye {
+            Some((eye, distance)) => {
+                let mid = Point3::from(0.5 * (Vec3::from(a) + Vec3::from(b)));
+                self.max_edge * (mid - eye).length() / distance
+            }
+            None => self.max_edge,
+        };
+        (b - a).length() > limit
+    }
+
+    // the mesh split finely enough, still undisplaced. Normals, uvs and
+    // colors are interpolated along, smooth normals are computed first
+    // if it has none.
+    pub fn tessellate(&self, mesh: TriangleMesh) -> TriangleMesh {
+        let mut mesh = if mesh.normals.is_empty() {
+            mesh.with_smooth_normals()
+        } else {
+            mesh
+        };
+
+        for _ in 0..MAX_LEVELS {
+            let mut midpoints: HashMap<[u32; 2], u32> = HashMap::new();
+            let triangles = std::mem::take(&mut mesh.indices);
+            for &triangle in &triangles {
+                let mut m = [None; 3];
+                for (k, m) in m.iter_mut().enumerate() {
+                    let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                    if self.splits(mesh.positions[a as usize], mesh.positions[b as usize]) {
+                        let key = [a.min(b), a.max(b)];
+                        *m = Some(
+                            *midpoints
+                                .entry(key)
+                                .or_insert_with(|| split(&mut mesh, a, b)),
+                        );
+                    }
+                }
+                refine(triangle, m, &mut mesh.indices);
+            }
+            if midpoints.is_empty() {
+                break;
+            }
+        }
+        mesh
+    }
+
+    // tessellated, displaced along the normals and given new normals.
+    // Vertices split at a hard edge or a uv seam share a position but not
+    // a normal or a height, so each position moves once, by the average of
+    // its vertices, or the surface would tear open along the split.
+    pub fn apply(&self, mesh: TriangleMesh) -> TriangleMesh {
+        let mut mesh = self.tessellate(mesh);
+        let (welded, count) = weld(&mesh.positions);
+        let mut normals = vec![Vec3::zero(); count];
+        let mut heights = vec![(0.0, 0); count];
+        for (i, &w) in welded.iter().enumerate() {
+            let [u, v] = mesh.uvs.get(i).copied().unwrap_or([0.0, 0.0]);
+            let height = self.texture.value(u, v, mesh.positions[i]).r();
+            normals[w as usize] += Vec3::from(mesh.normals[i]);
+            let (sum, n) = &mut heights[w as usize];
+            *sum += height;
+            *n += 1;
+        }
+        for (i, &w) in welded.iter().enumerate() {
+            let (sum, n) = heights[w as usize];
+            let normal = normals[w as usize];
+            // opposite normals cancel out on a sheet folded flat
+            let normal = if normal.near_zero() {
+                Vec3::from(mesh.normals[i])
+            } else {
+                unit_vector(normal)
+            };
+            mesh.positions[i] += self.scale * sum / n as Float * normal;
+        }
+        mesh.with_smooth_normals()
+    }
+}
+
+// new vertex halfway along a b, index of it
+fn split(mesh: &mut TriangleMesh, a: u32, b: u32) -> u32 {
+    let (a, b) = (a as usize, b as usize);
+    let half = |x: Vec3, y: Vec3| 0.5 * (x + y);
+    let p = half(Vec3::from(mesh.positions[a]), Vec3::from(mesh.positions[b]));
+    mesh.positions.push(Point3::from(p));
+    let n = half(Vec3::from(mesh.normals[a]), Vec3::from(mesh.normals[b]));
+    mesh.normals.push(Normal3::from(if n.near_zero() {
+        Vec3::from(mesh.normals[a])
+    } else {
+        unit_vector(n)
+    }));
+    if !mesh.uvs.is_empty() {
+        let (ua, ub) = (mesh.uvs[a], mesh.uvs[b]);
+        mesh.uvs
+            .push([0.5 * (ua[0] + ub[0]), 0.5 * (ua[1] + ub[1])]);
+    }
+    if !mesh.colors.is_empty() {
+        let color = 0.5 * (mesh.colors[a] + mesh.colors[b]);
+        mesh.colors.push(color);
+    }
+    (mesh.positions.len() - 1) as u32
+}
+
+// a triangle with the midpoints of the edges that split, edge k going
+// from corner k to k + 1, into as many triangles as that takes
+fn refine(t: [u32; 3], m: [Option<u32>; 3], out: &mut Vec<[u32; 3]>) {
+    match m {
+        [None, None, None] => out.push(t),
+        [Some(m0), Some(m1), Some(m2)] => {
+            out.push([t[0], m0, m2]);
+            out.push([t[1], m1, m0]);
+            out.push([t[2], m2, m1]);
+            out.push([m0, m1, m2]);
+        }
+        _ => {
+            // turn it so the splits start at edge 0
+            let k = (0..3)
+                .find(|&k| m[k].is_some() && m[(k + 2) % 3].is_none())
+                .unwrap();
+            let [a, b, c] = [t[k], t[(k + 1) % 3], t[(k + 2) % 3]];
+            let m0 = m[k].unwrap();
+            match m[(k + 1) % 3] {
+                None => {
+                    out.push([a, m0, c]);
+                    out.push([m0, b, c]);
+                }
+                Some(m1) => {
+                    out.push([m0, b, m1]);
+                    out.push([a, m0, m1]);
+                    out.push([a, m1, c]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::SolidColor;
+
+    // unit square in the xz plane facing up
+    fn square() -> TriangleMesh {
+        TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 1.0),
+                Point3::new(0.0, 0.0, 1.0),
+            ],
+            vec![[0, 2, 1], [0, 3, 2]],
+        )
+        .with_uvs(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]])
+    }
+
+    fn edges(mesh: &TriangleMesh) -> impl Iterator<Item = Float> + '_ {
+        mesh.indices.iter().flat_map(move |t| {
+            (0..3).map(move |k| {
+                (mesh.positions[t[(k + 1) % 3] as usize] - mesh.positions[t[k] as usize]).length()
+            })
+        })
+    }
+
+    #[test]
+    fn tessellates_without_cracks() {
+        let flat = Arc::new(SolidColor::from_value(0.0));
+        let mesh = Displacement::new(flat, 1.0)
+            .with_max_edge(0.2)
+            .tessellate(square());
+        assert!(edges(&mesh).all(|length| length <= 0.2));
+
+        // every inner edge is shared by exactly two triangles
+        let mut count: HashMap<[u32; 2], u32> = HashMap::new();
+        for t in &mesh.indices {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *count.entry([a.min(b), a.max(b)]).or_default() += 1;
+            }
+        }
+        for ([a, b], n) in count {
+            let (pa, pb) = (mesh.positions[a as usize], mesh.positions[b as usize]);
+            let on_border = [0.0, 1.0].iter().any(|&side| {
+                (pa.x() == side && pb.x() == side) || (pa.z() == side && pb.z() == side)
+            });
+            assert_eq!(n, if on_border { 1 } else { 2 });
+        }
+
+        // far from the eye the edges may be longer
+        let flat = Arc::new(SolidColor::from_value(0.0));
+        let far = Displacement::new(flat, 1.0)
+            .with_max_edge(0.2)
+            .with_eye(Point3::new(0.0, 10.0, 0.0), 1.0)
+            .tessellate(square());
+        assert_eq!(far.indices.len(), 2);
+    }
+
+    #[test]
+    fn displaces_along_normals() {
+        let height = Arc::new(SolidColor::from_value(0.5));
+        let mesh = Displacement::new(height, 2.0)
+            .with_max_edge(0.5)
+            .apply(square());
+        assert!(mesh.indices.len() > 2);
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((p.y() - 1.0).abs() < 1.0e-5);
+            assert!((n.y() - 1.0).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn split_vertices_stay_together() {
+        // a unit cube with four vertices of its own on every face, so
+        // each corner is three vertices with three different normals
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for axis in 0..3 {
+            for side in [0.0, 1.0] {
+                let corner = |a: Float, b: Float| {
+                    let mut p = [0.0; 3];
+                    p[axis] = side;
+                    p[(axis + 1) % 3] = a;
+                    p[(axis + 2) % 3] = b;
+                    Point3::new(p[0], p[1], p[2])
+                };
+                let first = positions.len() as u32;
+                positions.extend([
+                    corner(0.0, 0.0),
+                    corner(1.0, 0.0),
+                    corner(1.0, 1.0),
+                    corner(0.0, 1.0),
+                ]);
+                let [a, b, c, d] = [first, first + 1, first + 2, first + 3];
+                // wound to face out
+                if side == 0.0 {
+                    indices.extend([[a, c, b], [a, d, c]]);
+                } else {
+                    indices.extend([[a, b, c], [a, c, d]]);
+                }
+            }
+        }
+        let cube = TriangleMesh::new(positions, indices);
+        assert!(cube.is_closed());
+
+        let height = Arc::new(SolidColor::from_value(0.1));
+        let mesh = Displacement::new(height, 1.0)
+            .with_max_edge(0.5)
+            .apply(cube);
+        assert!(mesh.is_closed());
+        // the corners move out along the diagonal
+        let far = Vec3::new(1.0, 1.0, 1.0) * (1.0 + 0.1 / Float::sqrt(3.0));
+        assert!(mesh
+            .positions
+            .iter()
+            .any(|&p| (Vec3::from(p) - far).length() < 1.0e-5));
+    }
+}
@@ -0,0 +1,824 @@
+// glTF 2.0 scenes, .gltf with its buffers and images beside it or in data
+// uris, and .glb. The node hierarchy becomes instances of one bvh per mesh
+// primitive, metallic-roughness materials become Principled ones with
+// their textures, and perspective cameras fill in the Camera fields that
+// place it. Parts of a file that can't be used are skipped with a warning
+// rather than failing the whole load.
+
+use crate::alpha_mask::AlphaMask;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable::Hittable;
+use crate::image::{Image, ImageTexture};
+use crate::instance::Instance;
+use crate::material::{Principled, Scatter};
+use crate::mesh::TriangleMesh;
+use crate::normal_map::NormalMap;
+use crate::texture::{SolidColor, Texture};
+use crate::transform::Transform;
+use crate::utils::Float;
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct Gltf {
+    // world space instances of the default scene, for a Tlas
+    pub instances: Vec<Instance>,
+    // only placed and given a field of view (and aspect ratio when the
+    // file has one), image size and sampling are left to the caller
+    pub cameras: Vec<Camera>,
+    pub warnings: Vec<String>,
+}
+
+pub fn load_gltf(path: impl AsRef<Path>) -> io::Result<Gltf> {
+    let path = path.as_ref();
+    parse_gltf(&fs::read(path)?, path.parent().unwrap_or(Path::new(".")))
+}
+
+// relative uris are looked up in `base`
+pub fn parse_gltf(bytes: &[u8], base: &Path) -> io::Result<Gltf> {
+    let (text, binary) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        (bytes, None)
+    };
+    // serde_json stops at 128 levels of nesting, so a hostile file can't
+    // exhaust the stack
+    let json: Value =
+        serde_json::from_slice(text).map_err(|error| invalid(&format!("gltf json: {error}")))?;
+    let version = json
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(Value::as_str);
+    if !version.is_some_and(|version| version.starts_with("2.")) {
+        return Err(invalid("only gltf 2.0 is supported"));
+    }
+
+    let mut document = Document {
+        buffers: Vec::new(),
+        images: Vec::new(),
+        base,
+        warnings: Vec::new(),
+        json: &json,
+    };
+    for (i, buffer) in list(&json, "buffers").iter().enumerate() {
+        let data = match buffer.get("uri").and_then(Value::as_str) {
+            Some(uri) => document.resolve(uri)?,
+            None if i == 0 => binary
+                .clone()
+                .ok_or_else(|| invalid("gltf buffer without data"))?,
+            None => return Err(invalid("gltf buffer without data")),
+        };
+        document.buffers.push(data);
+    }
+    document.images = vec![None; list(&json, "images").len()];
+
+    let materials: Vec<Arc<dyn Scatter>> = list(&json, "materials")
+        .iter()
+        .map(|material| document.material(material))
+        .collect();
+    let fallback: Arc<dyn Scatter> = Arc::new(default_material());
+    let meshes: Vec<Vec<Arc<dyn Hittable>>> = list(&json, "meshes")
+        .iter()
+        .enumerate()
+        .map(|(m, mesh)| {
+            let mut blases = Vec::new();
+            for (p, primitive) in list(mesh, "primitives").iter().enumerate() {
+                let material = primitive
+                    .get("material")
+                    .and_then(as_usize)
+                    .and_then(|i| materials.get(i))
+                    .unwrap_or(&fallback);
+                match document.primitive(primitive) {
+                    Ok(triangles) => {
+                        let list = triangles.triangles(material.clone());
+                        blases.push(Arc::new(Bvh4::new(&list)) as Arc<dyn Hittable>);
+                    }
+                    Err(error) => document
+                        .warnings
+                        .push(format!("mesh {m} primitive {p} skipped: {error}")),
+                }
+            }
+            blases
+        })
+        .collect();
+
+    let mut gltf = Gltf {
+        instances: Vec::new(),
+        cameras: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let nodes = list(&json, "nodes");
+    let scene = json.get("scene").and_then(as_usize).unwrap_or(0);
+    let roots: Vec<usize> = match list(&json, "scenes").get(scene) {
+        Some(scene) => list(scene, "nodes").iter().filter_map(as_usize).collect(),
+        // no scenes, every node that isn't a child is a root
+        None => (0..nodes.len())
+            .filter(|&i| {
+                !nodes.iter().any(|node| {
+                    list(node, "children")
+                        .iter()
+                        .any(|c| as_usize(c) == Some(i))
+                })
+            })
+            .collect(),
+    };
+
+    let mut stack: Vec<(usize, Transform, usize)> = roots
+        .into_iter()
+        .map(|root| (root, Transform::identity(), 0))
+        .collect();
+    while let Some((index, parent, depth)) = stack.pop() {
+        let Some(node) = nodes.get(index) else {
+            document
+                .warnings
+                .push(format!("node {index} doesn't exist"));
+            continue;
+        };
+        // a well formed hierarchy can't be deeper than the node count
+        if depth > nodes.len() {
+            return Err(invalid("gltf node hierarchy has a cycle"));
+        }
+        let Some(local) = node_transform(node) else {
+            document
+                .warnings
+                .push(format!("node {index} skipped: singular transform"));
+            continue;
+        };
+        let world = parent * local;
+
+        if let Some(mesh) = node.get("mesh").and_then(as_usize) {
+            for blas in meshes.get(mesh).into_iter().flatten() {
+                gltf.instances.push(Instance::new(blas.clone(), world));
+            }
+        }
+        if let Some(camera) = node.get("camera").and_then(as_usize) {
+            match camera_at(list(&json, "cameras").get(camera), world) {
+                Some(camera) => gltf.cameras.push(camera),
+                None => document.warnings.push(format!(
+                    "camera {camera} skipped: only perspective cameras are read"
+                )),
+            }
+        }
+        for child in list(node, "children").iter().filter_map(as_usize) {
+            stack.push((child, world, depth + 1));
+        }
+    }
+
+    gltf.warnings = document.warnings;
+    Ok(gltf)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[allow(clippy::unnecessary_cast)] // Float may already be f64
+fn float(x: f64) -> Float {
+    x as Float
+}
+
+// an array member, empty when missing
+fn list<'a>(json: &'a Value, key: &str) -> &'a [Value] {
+    json.get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+// an index or a count
+fn as_usize(json: &Value) -> Option<usize> {
+    json.as_u64().and_then(|x| usize::try_from(x).ok())
+}
+
+// an array of numbers, e.g. a vector or a matrix
+fn as_f64s(json: &Value) -> Option<Vec<f64>> {
+    json.as_array()?.iter().map(Value::as_f64).collect()
+}
+
+fn number(json: &Value, key: &str, default: f64) -> f64 {
+    json.get(key).and_then(Value::as_f64).unwrap_or(default)
+}
+
+// glb: a 12 byte header, then the json chunk and maybe a binary one
+fn split_glb(bytes: &[u8]) -> io::Result<(&[u8], Option<Vec<u8>>)> {
+    let word = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("glb ends early"))
+    };
+    if word(4)? != 2 {
+        return Err(invalid("only glb version 2 is supported"));
+    }
+    let mut pos = 12;
+    let (mut text, mut binary) = (None, None);
+    while pos + 8 <= bytes.len().min(word(8)?) {
+        let length = word(pos)?;
+        let data = bytes
+            .get(pos + 8..pos + 8 + length)
+            .ok_or_else(|| invalid("glb chunk runs past the end"))?;
+        match &bytes[pos + 4..pos + 8] {
+            b"JSON" => text = Some(data),
+            b"BIN\0" => binary = Some(data.to_vec()),
+            _ => {}
+        }
+        pos += 8 + length;
+    }
+    Ok((text.ok_or_else(|| invalid("glb without json"))?, binary))
+}
+
+// the 4x4 matrix, or translation * rotation * scale
+fn node_transform(node: &Value) -> Option<Transform> {
+    if let Some(m) = node.get("matrix").and_then(as_f64s) {
+        if m.len() != 16 {
+            return None;
+        }
+        // stored column by column
+        let rows = [0, 1, 2, 3].map(|i| [0, 1, 2, 3].map(|j| float(m[4 * j + i])));
+        return Transform::from_matrix(rows);
+    }
+
+    let vector = |key: &str, default: [f64; 3]| {
+        let v = node
+            .get(key)
+            .and_then(as_f64s)
+            .filter(|v| v.len() == 3)
+            .map_or(default, |v| [v[0], v[1], v[2]]);
+        v.map(float)
+    };
+    let [tx, ty, tz] = vector("translation", [0.0; 3]);
+    let [sx, sy, sz] = vector("scale", [1.0; 3]);
+    if sx == 0.0 || sy == 0.0 || sz == 0.0 {
+        return None;
+    }
+
+    let rotation = match node.get("rotation").and_then(as_f64s) {
+        Some(q) if q.len() == 4 => {
+            let length = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if length == 0.0 {
+                return None;
+            }
+            let [x, y, z, w] = [q[0], q[1], q[2], q[3]].map(|c| float(c / length));
+            Transform::from_matrix([
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - z * w),
+                    2.0 * (x * z + y * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * y + z * w),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - x * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * z - y * w),
+                    2.0 * (y * z + x * w),
+                    1.0 - 2.0 * (x * x + y * y),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ])?
+        }
+        _ => Transform::identity(),
+    };
+    Some(Transform::translate(Vec3::new(tx, ty, tz)) * rotation * Transform::scale(sx, sy, sz))
+}
+
+// gltf cameras look down their -z with +y up
+fn camera_at(camera: Option<&Value>, world: Transform) -> Option<Camera> {
+    let perspective = camera?.get("perspective")?;
+    let mut camera = Camera::default();
+    camera.vfov = float(number(perspective, "yfov", 0.8).to_degrees());
+    if let Some(aspect_ratio) = perspective.get("aspectRatio").and_then(Value::as_f64) {
+        camera.aspect_ratio = float(aspect_ratio);
+    }
+    camera.lookfrom = world.point(Point3::origin());
+    camera.lookat = world.point(Point3::new(0.0, 0.0, -1.0));
+    camera.vup = unit_vector(world.vector(Vec3::new(0.0, 1.0, 0.0)));
+    camera.focus_dist = (camera.lookat - camera.lookfrom).length();
+    Some(camera)
+}
+
+// gltf's defaults, a white rough metal
+fn default_material() -> Principled {
+    let value = |v: Float| -> Arc<dyn Texture> { Arc::new(SolidColor::from_value(v)) };
+    Principled {
+        base_color: value(1.0),
+        metallic: value(1.0),
+        roughness: value(1.0),
+        ..Principled::default()
+    }
+}
+
+// elements in an accessor that has no view, which would otherwise let a
+// tiny file ask for any amount of memory
+const MAX_ZEROED: usize = 1 << 20;
+
+struct Document<'a> {
+    json: &'a Value,
+    buffers: Vec<Vec<u8>>,
+    // decoded on first use, None inside once it failed
+    images: Vec<Option<Option<Arc<Image>>>>,
+    base: &'a Path,
+    warnings: Vec<String>,
+}
+
+impl Document<'_> {
+    fn resolve(&self, uri: &str) -> io::Result<Vec<u8>> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (_, encoded) = data
+                .split_once(";base64,")
+                .ok_or_else(|| invalid("only base64 data uris are supported"))?;
+            return base64(encoded);
+        }
+        fs::read(self.base.join(percent_decode(uri)))
+    }
+
+    // the bytes of a buffer view
+    fn view(&self, index: usize) -> io::Result<(&[u8], Option<usize>)> {
+        let view = list(self.json, "bufferViews")
+            .get(index)
+            .ok_or_else(|| invalid("gltf buffer view doesn't exist"))?;
+        let buffer = view
+            .get("buffer")
+            .and_then(as_usize)
+            .and_then(|i| self.buffers.get(i))
+            .ok_or_else(|| invalid("gltf buffer doesn't exist"))?;
+        let offset = number(view, "byteOffset", 0.0) as usize;
+        let length = number(view, "byteLength", 0.0) as usize;
+        let bytes = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid("gltf buffer view runs past its buffer"))?;
+        Ok((bytes, view.get("byteStride").and_then(as_usize)))
+    }
+
+    // the elements of an accessor, each `components` values long
+    fn accessor(&self, index: usize, components: usize) -> io::Result<Vec<f64>> {
+        let accessor = list(self.json, "accessors")
+            .get(index)
+            .ok_or_else(|| invalid("gltf accessor doesn't exist"))?;
+        if accessor.get("sparse").is_some() {
+            return Err(invalid("sparse accessors aren't supported"));
+        }
+        let width = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(invalid("unexpected gltf accessor type")),
+        };
+        if width < components {
+            return Err(invalid("gltf accessor has too few components"));
+        }
+        let kind = accessor.get("componentType").and_then(as_usize);
+        let size = match kind {
+            Some(5120 | 5121) => 1,
+            Some(5122 | 5123) => 2,
+            Some(5125 | 5126) => 4,
+            _ => return Err(invalid("unknown gltf component type")),
+        };
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let count = number(accessor, "count", 0.0) as usize;
+        let offset = number(accessor, "byteOffset", 0.0) as usize;
+
+        let Some(view) = accessor.get("bufferView").and_then(as_usize) else {
+            // no view means all zeros, and nothing to bound the count by
+            if count > MAX_ZEROED {
+                return Err(invalid("gltf accessor without a view is too long"));
+            }
+            return Ok(vec![0.0; count * components]);
+        };
+        let (bytes, stride) = self.view(view)?;
+        let stride = stride.unwrap_or(width * size);
+        // the last element has to fit before anything is allocated
+        let end = count.checked_sub(1).map_or(Some(0), |last| {
+            last.checked_mul(stride)?
+                .checked_add(offset)?
+                .checked_add(width * size)
+        });
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(invalid("gltf accessor runs past its view"));
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for c in 0..components {
+                let at = offset + element * stride + c * size;
+                let b = bytes
+                    .get(at..at + size)
+                    .ok_or_else(|| invalid("gltf accessor runs past its view"))?;
+                let (value, scale) = match kind {
+                    Some(5120) => (b[0] as i8 as f64, 127.0),
+                    Some(5121) => (b[0] as f64, 255.0),
+                    Some(5122) => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+                    Some(5123) => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+                    Some(5125) => (u32::from_le_bytes(b.try_into().unwrap()) as f64, 1.0),
+                    _ => (f32::from_le_bytes(b.try_into().unwrap()) as f64, 1.0),
+                };
+                values.push(if normalized {
+                    (value / scale).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok(values)
+    }
+
+    fn primitive(&self, primitive: &Value) -> io::Result<TriangleMesh> {
+        let attributes = primitive
+            .get("attributes")
+            .ok_or_else(|| invalid("primitive without attributes"))?;
+        let attribute = |name: &str| attributes.get(name).and_then(as_usize);
+
+        let positions: Vec<Point3> = self
+            .accessor(
+                attribute("POSITION").ok_or_else(|| invalid("primitive without positions"))?,
+                3,
+            )?
+            .chunks_exact(3)
+            .map(|p| Point3::new(float(p[0]), float(p[1]), float(p[2])))
+            .collect();
+        let n = positions.len() as u32;
+        let corners: Vec<u32> = match primitive.get("indices").and_then(as_usize) {
+            Some(indices) => self
+                .accessor(indices, 1)?
+                .into_iter()
+                .map(|i| i as u32)
+                .collect(),
+            None => (0..n).collect(),
+        };
+        if corners.iter().any(|&i| i >= n) {
+            return Err(invalid("index out of range"));
+        }
+        let indices: Vec<[u32; 3]> = match number(primitive, "mode", 4.0) as u32 {
+            4 => corners
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+            // every other strip triangle is wound the other way
+            5 => corners
+                .windows(3)
+                .enumerate()
+                .map(|(i, t)| {
+                    if i % 2 == 0 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+            6 => (1..corners.len().saturating_sub(1))
+                .map(|i| [corners[0], corners[i], corners[i + 1]])
+                .collect(),
+            _ => return Err(invalid("only triangles are supported")),
+        };
+
+        let mut mesh = TriangleMesh::new(positions, indices);
+        if let Some(normals) = attribute("NORMAL") {
+            let normals = self.accessor(normals, 3)?;
+            if normals.len() != 3 * mesh.positions.len() {
+                return Err(invalid("gltf NORMAL doesn't match POSITION"));
+            }
+            mesh = mesh.with_normals(
+                normals
+                    .chunks_exact(3)
+                    .map(|n| Normal3::from(Vec3::new(float(n[0]), float(n[1]), float(n[2]))))
+                    .collect(),
+            );
+        }
+        if let Some(uvs) = attribute("TEXCOORD_0") {
+            let uvs = self.accessor(uvs, 2)?;
+            if uvs.len() != 2 * mesh.positions.len() {
+                return Err(invalid("gltf TEXCOORD_0 doesn't match POSITION"));
+            }
+            // gltf's v runs down the image
+            mesh = mesh.with_uvs(
+                uvs.chunks_exact(2)
+                    .map(|uv| [float(uv[0]), float(1.0 - uv[1])])
+                    .collect(),
+            );
+        }
+        Ok(mesh)
+    }
+
+    fn image(&mut self, index: usize) -> Option<Arc<Image>> {
+        if let Some(Some(cached)) = self.images.get(index) {
+            return cached.clone();
+        }
+        let image = list(self.json, "images").get(index)?;
+        let bytes = match (
+            image.get("uri").and_then(Value::as_str),
+            image.get("bufferView").and_then(as_usize),
+        ) {
+            (Some(uri), _) => self.resolve(uri),
+            (None, Some(view)) => self.view(view).map(|(bytes, _)| bytes.to_vec()),
+            (None, None) => Err(invalid("image without data")),
+        };
+        let decoded = match bytes.and_then(|bytes| Image::decode(&bytes)) {
+            Ok(image) => Some(Arc::new(image)),
+            Err(error) => {
+                self.warnings.push(format!(
+                    "image {index} not used, its factors stand alone: {error}"
+                ));
+                None
+            }
+        };
+        self.images[index] = Some(decoded.clone());
+        decoded
+    }
+
+    // the image behind a texture info like baseColorTexture
+    fn texture(&mut self, info: Option<&Value>) -> Option<ImageTexture> {
+        let info = info?;
+        let texture = info
+            .get("index")
+            .and_then(as_usize)
+            .and_then(|i| list(self.json, "textures").get(i))?;
+        if info.get("texCoord").and_then(as_usize).unwrap_or(0) != 0 {
+            self.warnings
+                .push("only the first texture coordinate set is read".to_string());
+        }
+        let source = texture.get("source").and_then(as_usize)?;
+        self.image(source).map(ImageTexture::new)
+    }
+
+    fn material(&mut self, material: &Value) -> Arc<dyn Scatter> {
+        let none = Value::Null;
+        let pbr = material.get("pbrMetallicRoughness").unwrap_or(&none);
+        let factor = |json: &Value, key: &str, default: [f64; 4]| {
+            let v = json.get(key).and_then(as_f64s).unwrap_or_default();
+            [0, 1, 2, 3].map(|i| float(v.get(i).copied().unwrap_or(default[i])))
+        };
+        let [r, g, b, alpha] = factor(pbr, "baseColorFactor", [1.0; 4]);
+        let metallic = float(number(pbr, "metallicFactor", 1.0));
+        let roughness = float(number(pbr, "roughnessFactor", 1.0));
+        let strength = material
+            .get("extensions")
+            .and_then(|extensions| extensions.get("KHR_materials_emissive_strength"))
+            .map_or(1.0, |extension| {
+                float(number(extension, "emissiveStrength", 1.0))
+            });
+        let [er, eg, eb, _] = factor(material, "emissiveFactor", [0.0; 4]);
+
+        let gray = |v: Float| Color::new(v, v, v);
+        let scaled = |texture: Option<ImageTexture>, scale: Color| -> Arc<dyn Texture> {
+            match texture {
+                Some(texture) => Arc::new(texture.with_scale(scale)),
+                None => Arc::new(SolidColor::new(scale)),
+            }
+        };
+        let base = self.texture(pbr.get("baseColorTexture"));
+        let opacity = base.clone().map(|t| t.with_channel(3));
+        let mr = self.texture(pbr.get("metallicRoughnessTexture"));
+        let emissive = self.texture(material.get("emissiveTexture"));
+
+        // roughness is in green and metalness in blue
+        let principled = Principled {
+            base_color: scaled(base.map(ImageTexture::with_srgb), Color::new(r, g, b)),
+            roughness: scaled(mr.clone().map(|t| t.with_channel(1)), gray(roughness)),
+            metallic: scaled(mr.map(|t| t.with_channel(2)), gray(metallic)),
+            emission: scaled(
+                emissive.map(ImageTexture::with_srgb),
+                strength * Color::new(er, eg, eb),
+            ),
+            ..Principled::default()
+        };
+        let mut scatter: Arc<dyn Scatter> = Arc::new(principled);
+
+        if let Some(normals) = self.texture(material.get("normalTexture")) {
+            scatter = Arc::new(NormalMap::new(scatter, Arc::new(normals)));
+        }
+        match material.get("alphaMode").and_then(Value::as_str) {
+            Some("MASK") => {
+                let cutoff = float(number(material, "alphaCutoff", 0.5));
+                scatter = Arc::new(AlphaMask::cutout(
+                    scatter,
+                    scaled(opacity, gray(alpha)),
+                    cutoff,
+                ));
+            }
+            Some("BLEND") => {
+                scatter = Arc::new(AlphaMask::stochastic(scatter, scaled(opacity, gray(alpha))));
+            }
+            _ => {}
+        }
+        scatter
+    }
+}
+
+fn base64(text: &str) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|&c| c != b'=' && !c.is_ascii_whitespace())
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(invalid("bad base64")),
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Tlas;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    // a triangle's positions then its u16 indices
+    fn buffer() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in [0u16, 1, 2] {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        bytes
+    }
+
+    // the triangle scaled up inside a moved parent, a camera turned to
+    // look down -x, and a material with a texture that can't be found
+    fn json(buffer_uri: Option<String>) -> String {
+        let uri = buffer_uri.map_or(String::new(), |uri| format!(r#""uri": "{uri}","#));
+        format!(
+            r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0, 2]}}],
+  "nodes": [
+    {{"translation": [0, 0, -5], "children": [1]}},
+    {{"mesh": 0, "scale": [2, 2, 2]}},
+    {{"camera": 0, "translation": [0, 1, 3], "rotation": [0, 0.70710678, 0, 0.70710678]}}
+  ],
+  "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.5, "aspectRatio": 1.5, "znear": 0.1}}}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+  "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [0.5, 0.5, 0.5, 1], "metallicFactor": 0,
+                  "baseColorTexture": {{"index": 0}}}}}}],
+  "textures": [{{"source": 0}}],
+  "images": [{{"uri": "missing%20file.png"}}],
+  "buffers": [{{{uri} "byteLength": 42}}],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+  ],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+  ]
+}}"#
+        )
+    }
+
+    fn check(gltf: Gltf) {
+        assert_eq!(gltf.instances.len(), 1);
+        assert_eq!(gltf.warnings.len(), 1);
+
+        let world = Tlas::new(gltf.instances);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+        let r = Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = world.hit(r, ray_t).unwrap();
+        assert!((rec.t - 5.0).abs() < 1.0e-4);
+        let r = Ray::new(Point3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(world.hit(r, ray_t).is_none());
+
+        let camera = &gltf.cameras[0];
+        assert!((camera.vfov - float(0.5f64.to_degrees())).abs() < 1.0e-4);
+        assert_eq!(camera.aspect_ratio, 1.5);
+        assert!((camera.lookfrom - Point3::new(0.0, 1.0, 3.0)).length() < 1.0e-5);
+        assert!((camera.lookat - Point3::new(-1.0, 1.0, 3.0)).length() < 1.0e-5);
+        assert!((camera.vup - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+    }
+
+    #[test]
+    fn gltf_with_data_uri() {
+        const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in buffer().chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+            for k in 0..chunk.len() + 1 {
+                encoded.push(DIGITS[(n >> (18 - 6 * k) & 63) as usize] as char);
+            }
+        }
+        assert_eq!(base64(&encoded).unwrap(), buffer());
+
+        let uri = format!("data:application/octet-stream;base64,{encoded}");
+        let text = json(Some(uri));
+        check(parse_gltf(text.as_bytes(), Path::new(".")).unwrap());
+
+        assert!(parse_gltf(br#"{"asset": {"version": "1.0"}}"#, Path::new(".")).is_err());
+        // nesting deep enough to overflow a recursive parser's stack
+        let deep = "[".repeat(200_000);
+        assert!(parse_gltf(deep.as_bytes(), Path::new(".")).is_err());
+    }
+
+    // a binary container around the json and buffer()
+    fn glb(json: String) -> Vec<u8> {
+        let mut text = json.into_bytes();
+        text.resize(text.len().next_multiple_of(4), b' ');
+        let mut binary = buffer();
+        binary.resize(binary.len().next_multiple_of(4), 0);
+
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + text.len() + 8 + binary.len()) as u32).to_le_bytes());
+        for (kind, data) in [(b"JSON", &text), (b"BIN\0", &binary)] {
+            glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            glb.extend_from_slice(kind);
+            glb.extend_from_slice(data);
+        }
+        glb
+    }
+
+    #[test]
+    fn glb_container() {
+        check(parse_gltf(&glb(json(None)), Path::new(".")).unwrap());
+    }
+
+    #[test]
+    fn malformed_accessors() {
+        // a bad primitive is skipped with a warning rather than failing
+        // the whole file
+        let skipped = |text: String| {
+            let gltf = parse_gltf(&glb(text), Path::new(".")).unwrap();
+            gltf.warnings.iter().any(|w| w.contains("skipped"))
+        };
+        let with = |from: &str, to: &str| json(None).replacen(from, to, 1);
+
+        // counts and lengths the views can't back
+        let positions = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        let huge = r#"{"bufferView": 0, "componentType": 5126, "count": 1e12, "type": "VEC3"}"#;
+        assert!(skipped(with(positions, huge)));
+        let zeros = r#"{"componentType": 5126, "count": 1e12, "type": "VEC3"}"#;
+        assert!(skipped(with(positions, zeros)));
+        assert!(skipped(with(
+            r#""byteLength": 6}"#,
+            r#""byteLength": 1e300}"#
+        )));
+
+        // per-vertex attributes with fewer elements than the positions
+        let indices = r#"{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}"#;
+        for (attribute, kind) in [("NORMAL", "VEC3"), ("TEXCOORD_0", "VEC2")] {
+            let short = format!(
+                r#"{indices}, {{"bufferView": 0, "componentType": 5126, "count": 2, "type": "{kind}"}}"#
+            );
+            let text = with(indices, &short).replacen(
+                r#""POSITION": 0"#,
+                &format!(r#""POSITION": 0, "{attribute}": 2"#),
+                1,
+            );
+            assert!(skipped(text));
+        }
+        assert!(!skipped(json(None)));
+    }
+}
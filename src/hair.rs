@@ -0,0 +1,481 @@
+// Hair fiber scattering after d'Eon et al. and Chiang et al., following
+// pbrt. Light reflects off the fiber (R), passes through it (TT) or
+// reflects once inside (TRT), higher orders are lumped together. Each
+// lobe is a longitudinal term M, shifted by the tilt of the cuticle
+// scales, times an azimuthal term N, times the attenuation A from
+// Fresnel and absorption. Meant for Round curves: u runs along the fiber
+// and v across it.
+
+use crate::color::{luminance, Color};
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::utils::{degrees_to_radians, random_double, Float, PI};
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+// lobes evaluated individually: R, TT and TRT, then the rest
+const P_MAX: usize = 3;
+
+pub struct Hair {
+    // absorption inside the fiber, per unit of its diameter
+    sigma_a: Color,
+    eta: Float,
+    // longitudinal and azimuthal roughness, 0..1
+    beta_m: Float,
+    beta_n: Float,
+    // tilt of the cuticle scales in degrees
+    alpha: Float,
+}
+
+impl Hair {
+    pub fn new(sigma_a: Color) -> Hair {
+        Hair {
+            sigma_a,
+            eta: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        }
+    }
+
+    // absorption from the concentrations of the two melanin pigments,
+    // eumelanin from about 0 (blond) to 8 (black) and pheomelanin for red
+    pub fn from_melanin(eumelanin: Float, pheomelanin: Float) -> Hair {
+        let eu = Color::new(0.419, 0.697, 1.37);
+        let pheo = Color::new(0.187, 0.4, 1.05);
+        Hair::new(eumelanin * eu + pheomelanin * pheo)
+    }
+
+    // absorption that gives roughly `color` after multiple scattering in
+    // a head of hair with azimuthal roughness beta_n
+    pub fn from_color(color: Color, beta_n: Float) -> Hair {
+        let b = beta_n;
+        let denom = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+            + 5.574 * b.powi(4)
+            + 0.245 * b.powi(5);
+        let [r, g, bl] = color
+            .to_array()
+            .map(|c| (c.max(1.0e-4).ln() / denom).powi(2));
+        Hair {
+            beta_n,
+            ..Hair::new(Color::new(r, g, bl))
+        }
+    }
+
+    pub fn with_roughness(self, beta_m: Float, beta_n: Float) -> Hair {
+        Hair {
+            beta_m: beta_m.clamp(0.01, 1.0),
+            beta_n: beta_n.clamp(0.01, 1.0),
+            ..self
+        }
+    }
+
+    pub fn with_ior(self, eta: Float) -> Hair {
+        Hair { eta, ..self }
+    }
+
+    pub fn with_scale_angle(self, degrees: Float) -> Hair {
+        Hair {
+            alpha: degrees,
+            ..self
+        }
+    }
+
+    fn lobes(&self, h: Float) -> Lobes {
+        let bm = self.beta_m;
+        let v0 = (0.726 * bm + 0.812 * bm * bm + 3.7 * bm.powi(20)).powi(2);
+        let bn = self.beta_n;
+        let sqrt_pi_over_8 = 0.626_657_07;
+        let s = sqrt_pi_over_8 * (0.265 * bn + 1.194 * bn * bn + 5.372 * bn.powi(22));
+
+        // the scales tilt R by -2 alpha, TT by alpha and TRT by 4 alpha
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = degrees_to_radians(self.alpha).sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]);
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Lobes {
+            h,
+            gamma_o: safe_asin(h),
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // per lobe attenuation seen from a direction at cos_theta_o to the
+    // fiber's normal plane
+    fn attenuation(&self, lobes: &Lobes, sin_theta_o: Float, cos_theta_o: Float) -> [Color; 4] {
+        // the refracted path, through the fiber's cross section
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let sin_gamma_t = lobes.h / self.eta_p(sin_theta_o, cos_theta_o);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let path = 2.0 * cos_gamma_t / cos_theta_t;
+        let [r, g, b] = self.sigma_a.to_array().map(|s| (-s * path).exp());
+        let transmittance = Color::new(r, g, b);
+
+        let cos_gamma_o = safe_sqrt(1.0 - lobes.h * lobes.h);
+        let f = fr_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let ap0 = Color::new(f, f, f);
+        let ap1 = (1.0 - f) * (1.0 - f) * transmittance;
+        let ap2 = f * ap1 * transmittance;
+        let [r, g, b] = (f * ap2 * transmittance).to_array();
+        let [dr, dg, db] = (white - f * transmittance).to_array();
+        let ap3 = Color::new(r / dr, g / dg, b / db);
+        [ap0, ap1, ap2, ap3]
+    }
+
+    // the probability of sampling each lobe, by its share of the light
+    fn lobe_pdf(&self, lobes: &Lobes, sin_theta_o: Float, cos_theta_o: Float) -> [Float; 4] {
+        let ap = self
+            .attenuation(lobes, sin_theta_o, cos_theta_o)
+            .map(luminance);
+        let sum: Float = ap.iter().sum();
+        if sum <= 0.0 {
+            return [1.0, 0.0, 0.0, 0.0];
+        }
+        ap.map(|a| a / sum)
+    }
+
+    fn eta_p(&self, sin_theta_o: Float, cos_theta_o: Float) -> Float {
+        (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o
+    }
+
+    // f times |cos theta_i| for directions in the fiber's frame: x along
+    // it, z the surface normal
+    fn f_cos(&self, lobes: &Lobes, wo: Vec3, wi: Vec3) -> Color {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+
+        let sin_gamma_t = lobes.h / self.eta_p(sin_theta_o, cos_theta_o);
+        let gamma_t = safe_asin(sin_gamma_t);
+        let ap = self.attenuation(lobes, sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut sum = Color::black();
+        for (p, &a) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = lobes.tilt(p, sin_theta_o, cos_theta_o);
+            let m = mp(cos_theta_i, cos_op, sin_theta_i, sin_op, lobes.v[p]);
+            sum += m * np(phi, p, lobes.s, lobes.gamma_o, gamma_t) * a;
+        }
+        let m = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            lobes.v[P_MAX],
+        );
+        sum += m / (2.0 * PI) * ap[P_MAX];
+        sum
+    }
+
+    fn pdf(&self, lobes: &Lobes, wo: Vec3, wi: Vec3) -> Float {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+
+        let sin_gamma_t = lobes.h / self.eta_p(sin_theta_o, cos_theta_o);
+        let gamma_t = safe_asin(sin_gamma_t);
+        let lobe_pdf = self.lobe_pdf(lobes, sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, &weight) in lobe_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_op, cos_op) = lobes.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(cos_theta_i, cos_op, sin_theta_i, sin_op, lobes.v[p])
+                * weight
+                * np(phi, p, lobes.s, lobes.gamma_o, gamma_t);
+        }
+        pdf += mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            lobes.v[P_MAX],
+        ) * lobe_pdf[P_MAX]
+            / (2.0 * PI);
+        pdf
+    }
+
+    // a direction from the lobes' distributions and its weight f cos / pdf,
+    // in the fiber's frame, for uniform random numbers u
+    fn sample(&self, lobes: &Lobes, wo: Vec3, u: [Float; 4]) -> Option<(Color, Vec3)> {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+
+        // pick a lobe
+        let lobe_pdf = self.lobe_pdf(lobes, sin_theta_o, cos_theta_o);
+        let mut pick = u[0];
+        let mut p = 0;
+        while p < P_MAX && pick >= lobe_pdf[p] {
+            pick -= lobe_pdf[p];
+            p += 1;
+        }
+
+        // longitudinal angle around the lobe's tilted direction
+        let (sin_op, cos_op) = lobes.tilt(p, sin_theta_o, cos_theta_o);
+        let v = lobes.v[p];
+        let u1 = u[1].max(1.0e-5);
+        let cos_theta = 1.0 + v * (u1 + (1.0 - u1) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * u[2]).cos();
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        // azimuthal angle
+        let sin_gamma_t = lobes.h / self.eta_p(sin_theta_o, cos_theta_o);
+        let gamma_t = safe_asin(sin_gamma_t);
+        let dphi = if p < P_MAX {
+            big_phi(p, lobes.gamma_o, gamma_t) + sample_trimmed_logistic(u[3], lobes.s, -PI, PI)
+        } else {
+            2.0 * PI * u[3]
+        };
+        let phi_i = phi_o + dphi;
+        let wi = Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+
+        let pdf = self.pdf(lobes, wo, wi);
+        if pdf.is_nan() || pdf <= 0.0 {
+            return None;
+        }
+        Some((self.f_cos(lobes, wo, wi) / pdf, wi))
+    }
+}
+
+// what the lobes share for one hit, h is where across the fiber, -1..1
+struct Lobes {
+    h: Float,
+    gamma_o: Float,
+    // longitudinal variance per lobe
+    v: [Float; P_MAX + 1],
+    // azimuthal logistic scale
+    s: Float,
+    sin_2k_alpha: [Float; 3],
+    cos_2k_alpha: [Float; 3],
+}
+
+impl Lobes {
+    // theta_o rotated by the scale tilt of lobe p
+    fn tilt(&self, p: usize, sin_theta_o: Float, cos_theta_o: Float) -> (Float, Float) {
+        let (sin, cos) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin, cos.abs())
+    }
+}
+
+// sin and cos of the angle to the fiber's normal plane, and the angle
+// around the fiber
+fn angles(w: Vec3) -> (Float, Float, Float) {
+    let sin_theta = w.x();
+    (
+        sin_theta,
+        safe_sqrt(1.0 - sin_theta * sin_theta),
+        w.z().atan2(w.y()),
+    )
+}
+
+fn safe_sqrt(x: Float) -> Float {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: Float) -> Float {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+// unpolarized fresnel reflectance going into a dielectric from air
+fn fr_dielectric(cos_i: Float, eta: Float) -> Float {
+    let cos_i = cos_i.clamp(-1.0, 1.0);
+    let (eta_i, eta_t, cos_i) = if cos_i > 0.0 {
+        (1.0, eta, cos_i)
+    } else {
+        (eta, 1.0, -cos_i)
+    };
+    let sin_t = eta_i / eta_t * safe_sqrt(1.0 - cos_i * cos_i);
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+    let parallel = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let perpendicular = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// modified bessel function of the first kind, order zero
+fn i0(x: Float) -> Float {
+    let mut sum = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial: Float = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as Float;
+        }
+        sum += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    sum
+}
+
+fn log_i0(x: Float) -> Float {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+// longitudinal scattering
+fn mp(
+    cos_theta_i: Float,
+    cos_theta_o: Float,
+    sin_theta_i: Float,
+    sin_theta_o: Float,
+    v: Float,
+) -> Float {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // in logs, the terms overflow on their own
+        (log_i0(a) - b - 1.0 / v - v.ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// azimuthal direction leaving the fiber after p internal paths
+fn big_phi(p: usize, gamma_o: Float, gamma_t: Float) -> Float {
+    let p = p as Float;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: Float, s: Float) -> Float {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: Float, s: Float) -> Float {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: Float, s: Float, a: Float, b: Float) -> Float {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: Float, s: Float, a: Float, b: Float) -> Float {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// azimuthal scattering
+fn np(phi: Float, p: usize, s: Float, gamma_o: Float, gamma_t: Float) -> Float {
+    let mut dphi = phi - big_phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+impl Scatter for Hair {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        // x along the fiber, z the normal facing the ray
+        let x = unit_vector(rec.dpdu);
+        let n = Vec3::from(rec.normal);
+        let z = unit_vector(n - dot(n, x) * x);
+        let y = cross(z, x);
+        let to_local = |w: Vec3| Vec3::new(dot(w, x), dot(w, y), dot(w, z));
+
+        let wo = to_local(-unit_vector(r_in.direction()));
+        let lobes = self.lobes((-1.0 + 2.0 * rec.v).clamp(-1.0, 1.0));
+        let u = [
+            random_double(),
+            random_double(),
+            random_double(),
+            random_double(),
+        ];
+        let (weight, wi) = self.sample(&lobes, wo, u)?;
+
+        let direction = wi.x() * x + wi.y() * y + wi.z() * z;
+        Some((weight, rec.spawn_ray(direction)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_direction() -> Vec3 {
+        loop {
+            let v = Vec3::random_bounded(-1.0, 1.0);
+            if v.length_squared() <= 1.0 && v.length_squared() > 1.0e-4 {
+                return unit_vector(v);
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace() {
+        // without absorption no light is lost, and sampling matches f so
+        // closely that every weight is about one
+        for beta in [0.2, 0.5, 0.9] {
+            let hair = Hair::new(Color::black()).with_roughness(beta, beta);
+            let mut sum = 0.0;
+            let n = 20_000;
+            for _ in 0..n {
+                let lobes = hair.lobes(2.0 * random_double() - 1.0);
+                let wo = random_direction();
+                let u = [
+                    random_double(),
+                    random_double(),
+                    random_double(),
+                    random_double(),
+                ];
+                if let Some((weight, _)) = hair.sample(&lobes, wo, u) {
+                    sum += luminance(weight);
+                }
+            }
+            let average = sum / n as Float;
+            assert!((average - 1.0).abs() < 0.05, "beta {beta}: {average}");
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let hair = Hair::from_melanin(1.3, 0.0).with_roughness(0.4, 0.4);
+        let lobes = hair.lobes(0.3);
+        let wo = unit_vector(Vec3::new(0.3, 0.5, 0.8));
+
+        // uniform sphere sampling, pdf 1 / 4 pi
+        let n = 100_000;
+        let sum: Float = (0..n)
+            .map(|_| hair.pdf(&lobes, wo, random_direction()) * 4.0 * PI)
+            .sum();
+        let integral = sum / n as Float;
+        assert!((integral - 1.0).abs() < 0.05, "{integral}");
+    }
+}
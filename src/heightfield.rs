@@ -0,0 +1,519 @@
+// Terrain from a regular grid of heights. Only the heights are stored, each
+// grid cell is split into two triangles on the fly and rays walk the cells
+// they cross in order (a 2d dda), so the first hit found is the nearest.
+
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::mesh::hit_triangle;
+use crate::ray::Ray;
+use crate::utils::{gamma, Float};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct Heightfield {
+    // row by row along z, nx samples per row, scaled to 0..1
+    heights: Vec<Float>,
+    nx: usize,
+    nz: usize,
+    // the low x, low z corner at height 0
+    corner: Point3,
+    // extent along x and z, and the height of a sample of 1
+    size: Vec3,
+    material: Arc<dyn Scatter>,
+    bbox: Aabb,
+}
+
+impl Heightfield {
+    pub fn new(
+        heights: Vec<Float>,
+        nx: usize,
+        nz: usize,
+        corner: Point3,
+        size: Vec3,
+        material: Arc<dyn Scatter>,
+    ) -> Heightfield {
+        assert!(
+            nx >= 2 && nz >= 2,
+            "a heightfield needs at least 2x2 samples"
+        );
+        assert_eq!(heights.len(), nx * nz);
+
+        let (low, high) = heights
+            .iter()
+            .fold((Float::INFINITY, -Float::INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+        let bbox = Aabb::from_points(
+            corner + Vec3::new(0.0, low * size.y(), 0.0),
+            corner + Vec3::new(size.x(), high * size.y(), size.z()),
+        );
+        // padded like a quad's, so a flat field still has a box to clip to
+        let y = bbox.axis(1);
+        let y = if y.size() < 1.0e-4 {
+            y.expand(1.0e-4)
+        } else {
+            y
+        };
+        let bbox = Aabb::new(bbox.axis(0), y, bbox.axis(2));
+        Heightfield {
+            heights,
+            nx,
+            nz,
+            corner,
+            size,
+            material,
+            bbox,
+        }
+    }
+
+    // a grayscale pgm image, either plain (P2) or raw (P5) with 8 or 16
+    // bits per sample, with white as the full height
+    pub fn from_pgm(
+        path: impl AsRef<Path>,
+        corner: Point3,
+        size: Vec3,
+        material: Arc<dyn Scatter>,
+    ) -> io::Result<Heightfield> {
+        let (heights, nx, nz) = parse_pgm(&fs::read(path)?)?;
+        if nx < 2 || nz < 2 {
+            return Err(invalid("a heightfield needs at least 2x2 samples"));
+        }
+        Ok(Heightfield::new(heights, nx, nz, corner, size, material))
+    }
+
+    fn cell_size(&self) -> (Float, Float) {
+        (
+            self.size.x() / (self.nx - 1) as Float,
+            self.size.z() / (self.nz - 1) as Float,
+        )
+    }
+
+    fn height(&self, i: usize, j: usize) -> Float {
+        self.heights[j * self.nx + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        let (cell_x, cell_z) = self.cell_size();
+        self.corner
+            + Vec3::new(
+                i as Float * cell_x,
+                self.height(i, j) * self.size.y(),
+                j as Float * cell_z,
+            )
+    }
+
+    // from central differences of the heights, one sided at the borders
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (cell_x, cell_z) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let dx = (self.height(i1, j) - self.height(i0, j)) * self.size.y()
+            / ((i1 - i0) as Float * cell_x);
+        let dz = (self.height(i, j1) - self.height(i, j0)) * self.size.y()
+            / ((j1 - j0) as Float * cell_z);
+        unit_vector(Vec3::new(-dx, 1.0, -dz))
+    }
+
+    // nearest visible hit on the two triangles of cell (i, j)
+    fn hit_cell(&self, r: Ray, ray_t: Interval, i: usize, j: usize) -> Option<Hit<'_>> {
+        let (p00, p10) = (self.vertex(i, j), self.vertex(i + 1, j));
+        let (p01, p11) = (self.vertex(i, j + 1), self.vertex(i + 1, j + 1));
+
+        let mut candidates = [
+            hit_triangle(r, p00, p10, p11).map(|(t, _, _)| t),
+            hit_triangle(r, p00, p11, p01).map(|(t, _, _)| t),
+        ];
+        candidates.sort_by(|a, b| {
+            a.unwrap_or(Float::INFINITY)
+                .total_cmp(&b.unwrap_or(Float::INFINITY))
+        });
+        candidates
+            .into_iter()
+            .flatten()
+            .filter(|&t| ray_t.surrounds(t))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// whitespace separated header fields of a pgm, # starts a comment
+struct PgmHeader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl PgmHeader<'_> {
+    fn token(&mut self) -> io::Result<&[u8]> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid("pgm ends early")),
+            }
+        }
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        std::str::from_utf8(self.token()?)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid("bad number in pgm"))
+    }
+}
+
+// samples of a pgm image scaled to 0..1, with its width and height
+pub fn parse_pgm(bytes: &[u8]) -> io::Result<(Vec<Float>, usize, usize)> {
+    let magic = match bytes.get(..2) {
+        Some(b"P2") => 2,
+        Some(b"P5") => 5,
+        _ => return Err(invalid("not a P2 or P5 pgm")),
+    };
+    let mut header = PgmHeader { bytes, pos: 2 };
+    let (width, height, max) = (header.number()?, header.number()?, header.number()?);
+    if max == 0 || max > 65535 {
+        return Err(invalid("pgm max value out of range"));
+    }
+    let count = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid("pgm is too large"))?;
+    let scale = 1.0 / max as Float;
+
+    let samples: Vec<Float> = if magic == 2 {
+        (0..count)
+            .map(|_| header.number().map(|n| n as Float * scale))
+            .collect::<io::Result<_>>()?
+    } else {
+        // a single whitespace byte ends the header, then big endian
+        // samples two bytes wide if they don't fit in one
+        let data = &bytes[(header.pos + 1).min(bytes.len())..];
+        let wide = max > 255;
+        let needed = if wide { 2 * count } else { count };
+        if data.len() < needed {
+            return Err(invalid("pgm ends early"));
+        }
+        if wide {
+            data.chunks_exact(2)
+                .take(count)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as Float * scale)
+                .collect()
+        } else {
+            data[..count].iter().map(|&b| b as Float * scale).collect()
+        }
+    };
+    Ok((samples, width, height))
+}
+
+impl Hittable for Heightfield {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let span = self.bbox.clip(r, ray_t)?;
+        let (cell_x, cell_z) = self.cell_size();
+        let (cells_x, cells_z) = (self.nx - 1, self.nz - 1);
+
+        // grid coordinates of where the ray enters the box
+        let start = r.at(span.min) - self.corner;
+        let (gx, gz) = (start.x() / cell_x, start.z() / cell_z);
+        let mut i = (gx.max(0.0) as usize).min(cells_x - 1);
+        let mut j = (gz.max(0.0) as usize).min(cells_z - 1);
+
+        let [dx, dy, dz] = r.direction().to_array();
+        let oy = r.origin().y();
+        // distance to the next cell boundary and between boundaries
+        let axis = |g: Float, index: usize, d: Float, cell: Float| {
+            if d > 0.0 {
+                let next = (index + 1) as Float - g;
+                (span.min + next * cell / d, cell / d)
+            } else if d < 0.0 {
+                let next = g - index as Float;
+                (span.min + next * cell / -d, cell / -d)
+            } else {
+                (Float::INFINITY, Float::INFINITY)
+            }
+        };
+        let (mut next_x, delta_x) = axis(gx, i, dx, cell_x);
+        let (mut next_z, delta_z) = axis(gz, j, dz, cell_z);
+
+        let mut t_enter = span.min;
+        loop {
+            let t_exit = next_x.min(next_z).min(span.max);
+
+            // skip the cell if the ray passes wholly above or below it
+            let corners = [
+                self.height(i, j),
+                self.height(i + 1, j),
+                self.height(i, j + 1),
+                self.height(i + 1, j + 1),
+            ];
+            let low = corners.iter().fold(Float::INFINITY, |a, &b| a.min(b));
+            let high = corners.iter().fold(-Float::INFINITY, |a, &b| a.max(b));
+            let (y0, y1) = (oy + t_enter * dy, oy + t_exit * dy);
+            let base = self.corner.y();
+            let (ray_low, ray_high) = (y0.min(y1), y0.max(y1));
+            let slack = gamma(3) * (ray_low.abs() + ray_high.abs());
+            if ray_low - slack <= base + high * self.size.y()
+                && ray_high + slack >= base + low * self.size.y()
+            {
+                if let Some(hit) = self.hit_cell(r, ray_t, i, j) {
+                    return Some(hit);
+                }
+            }
+
+            if t_exit >= span.max {
+                return None;
+            }
+            t_enter = t_exit;
+            if next_x < next_z {
+                if dx > 0.0 && i + 1 < cells_x {
+                    i += 1;
+                } else if dx < 0.0 && i > 0 {
+                    i -= 1;
+                } else {
+                    return None;
+                }
+                next_x += delta_x;
+            } else {
+                if dz > 0.0 && j + 1 < cells_z {
+                    j += 1;
+                } else if dz < 0.0 && j > 0 {
+                    j -= 1;
+                } else {
+                    return None;
+                }
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Primitive for Heightfield {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let (cell_x, cell_z) = self.cell_size();
+        let local = r.at(hit.t) - self.corner;
+        let (gx, gz) = (local.x() / cell_x, local.z() / cell_z);
+        let i = (gx.max(0.0) as usize).min(self.nx - 2);
+        let j = (gz.max(0.0) as usize).min(self.nz - 2);
+        let (fx, fz) = (
+            (gx - i as Float).clamp(0.0, 1.0),
+            (gz - j as Float).clamp(0.0, 1.0),
+        );
+
+        // weights of the corners of the triangle the point is in, seen
+        // from above the split runs from (i, j) to (i + 1, j + 1)
+        let weights = if fx >= fz {
+            [1.0 - fx, fx - fz, 0.0, fz]
+        } else {
+            [1.0 - fz, 0.0, fz - fx, fx]
+        };
+        let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+        let weighted = || corners.iter().zip(weights);
+
+        // snapped onto the triangle, and its slopes along x and z
+        let height: Float = weighted()
+            .map(|(&(ci, cj), w)| w * self.height(ci, cj))
+            .sum();
+        let p = Point3::new(
+            self.corner.x() + local.x(),
+            self.corner.y() + height * self.size.y(),
+            self.corner.z() + local.z(),
+        );
+        let (p00, p11) = (self.vertex(i, j), self.vertex(i + 1, j + 1));
+        let (dpdu, dpdv) = if fx >= fz {
+            let p10 = self.vertex(i + 1, j);
+            (p10 - p00, p11 - p10)
+        } else {
+            let p01 = self.vertex(i, j + 1);
+            (p11 - p01, p01 - p00)
+        };
+        // per unit of u and v across the whole field
+        let dpdu = dpdu * (self.nx - 1) as Float;
+        let dpdv = dpdv * (self.nz - 1) as Float;
+
+        // smooth, blended from the corners' normals
+        let normal = weighted().fold(Vec3::zero(), |sum, (&(ci, cj), w)| {
+            sum + w * self.vertex_normal(ci, cj)
+        });
+        let outward_normal = Normal3::from(unit_vector(normal));
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error: gamma(7) * Vec3::from(p).abs(),
+            material: &*self.material,
+            u: local.x() / self.size.x(),
+            v: local.z() / self.size.z(),
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+    use crate::utils::random_double;
+
+    // a single bump in the middle of an otherwise flat 9x9 grid
+    fn bump() -> Heightfield {
+        let mut heights = vec![0.0; 81];
+        heights[4 * 9 + 4] = 1.0;
+        Heightfield::new(
+            heights,
+            9,
+            9,
+            Point3::origin(),
+            Vec3::new(8.0, 2.0, 8.0),
+            gray(),
+        )
+    }
+
+    #[test]
+    fn flat_and_bump() {
+        let field = bump();
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(1.5, 5.0, 6.5), Vec3::new(0.0, -1.0, 0.0));
+        let rec = field.hit(r, ray_t).unwrap();
+        assert!((rec.t - 5.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((rec.u - 1.5 / 8.0).abs() < 1.0e-5 && (rec.v - 6.5 / 8.0).abs() < 1.0e-5);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+
+        // straight onto the peak
+        let r = Ray::new(Point3::new(4.0, 5.0, 4.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = field.hit(r, ray_t).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-5);
+
+        // low across the field, the bump is in the way
+        let r = Ray::new(Point3::new(-1.0, 0.5, 4.25), Vec3::new(1.0, 0.0, 0.0));
+        let rec = field.hit(r, ray_t).unwrap();
+        assert!((rec.p.x() - 3.5).abs() < 1.0e-4);
+        assert!(rec.normal.x() < 0.0);
+    }
+
+    #[test]
+    fn constant_height() {
+        let field = Heightfield::new(
+            vec![0.5; 9],
+            3,
+            3,
+            Point3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 2.0, 2.0),
+            gray(),
+        );
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(0.25, 3.0, -0.5), Vec3::new(0.0, -1.0, 0.0));
+        let rec = field.hit(r, ray_t).unwrap();
+        assert!((rec.t - 2.0).abs() < 1.0e-5);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+        let r = Ray::new(Point3::new(-2.0, 2.0, 0.0), Vec3::new(1.0, -0.5, 0.3));
+        assert!((field.hit(r, ray_t).unwrap().p.y() - 1.0).abs() < 1.0e-5);
+        let r = Ray::new(Point3::new(-2.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(field.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn matches_every_triangle() {
+        let heights = (0..64).map(|_| random_double()).collect();
+        let field = Heightfield::new(
+            heights,
+            8,
+            8,
+            Point3::new(-2.0, 0.0, -2.0),
+            Vec3::new(4.0, 1.0, 4.0),
+            gray(),
+        );
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        for _ in 0..500 {
+            let origin = Point3::new(
+                4.0 * random_double() - 2.0,
+                3.0,
+                4.0 * random_double() - 2.0,
+            );
+            let direction = Vec3::random_bounded(-1.0, 1.0) - Vec3::new(0.0, 1.0, 0.0);
+            let r = Ray::new(origin, direction);
+
+            let mut nearest = None::<Float>;
+            for j in 0..7 {
+                for i in 0..7 {
+                    let (p00, p10) = (field.vertex(i, j), field.vertex(i + 1, j));
+                    let (p01, p11) = (field.vertex(i, j + 1), field.vertex(i + 1, j + 1));
+                    for t in [
+                        hit_triangle(r, p00, p10, p11).map(|(t, _, _)| t),
+                        hit_triangle(r, p00, p11, p01).map(|(t, _, _)| t),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .filter(|&t| t > 0.0)
+                    {
+                        nearest = Some(nearest.map_or(t, |n| n.min(t)));
+                    }
+                }
+            }
+
+            let hit = field.intersect(r, ray_t).map(|hit| hit.t);
+            match (hit, nearest) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1.0e-4, "{a} != {b}"),
+                (a, b) => assert_eq!(a.is_some(), b.is_some()),
+            }
+        }
+    }
+
+    #[test]
+    fn pgm() {
+        let plain = b"P2\n# a comment\n3 2\n4\n0 1 2\n3 4 4\n";
+        let (samples, width, height) = parse_pgm(plain).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(samples, vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0]);
+
+        let mut raw = b"P5 2 2 65535\n".to_vec();
+        for sample in [0u16, 65535, 32768, 1] {
+            raw.extend(sample.to_be_bytes());
+        }
+        let (samples, _, _) = parse_pgm(&raw).unwrap();
+        assert_eq!(samples[1], 1.0);
+        assert!((samples[2] - 0.5).abs() < 1.0e-4);
+
+        assert!(parse_pgm(b"P5 2 2 255\n\x00").is_err());
+        assert!(parse_pgm(b"P5 4294967296 4294967296 255\n\x00").is_err());
+        assert!(parse_pgm(b"P6 1 1 255\n\x00\x00\x00").is_err());
+    }
+}
@@ -1,29 +1,148 @@
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::instance::Instance;
 use crate::interval::Interval;
 use crate::material::Scatter;
-use crate::ray::Ray;
-use crate::vec3::{dot, Point3, Vec3};
+use crate::ray::{offset_ray_origin, Ray};
+use crate::utils::{random_double, Float};
+use crate::vec3::{dot, Normal3, Point3, Vec3};
 
 use std::sync::Arc;
 
-pub struct HitRecord {
+// Surface interaction at the closest hit, everything materials need to
+// scatter. Only built once the closest hit is known, see Hit.
+#[derive(Clone)]
+pub struct HitRecord<'a> {
     pub p: Point3,
-    pub normal: Vec3,
-    pub material: Arc<dyn Scatter>,
-    pub t: f32,
+    // conservative absolute error bound on each coordinate of p
+    pub p_error: Vec3,
+    // shading normal, always facing against the incoming ray
+    pub normal: Normal3,
+    pub material: &'a dyn Scatter,
+    pub t: Float,
+    // surface coordinates for texture lookups
+    pub u: Float,
+    pub v: Float,
+    // partial derivatives of the surface point, the tangent frame
+    // that normal and bump maps are expressed in
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
+    // interpolated from the corners on meshes with vertex colors
+    pub vertex_color: Option<Color>,
 }
 
 #[derive(Default)]
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
+    has_instances: bool,
+}
+
+// A hit found while searching for the closest one: the distance and the
+// primitive that was hit, which builds the HitRecord on request.
+#[derive(Clone, Copy)]
+pub struct Hit<'a> {
+    pub t: Float,
+    pub primitive: &'a dyn Primitive,
+    // set when the primitive was reached through an instance, whose
+    // transform and material apply on top of it. Instances can't nest.
+    pub instance: Option<&'a Instance>,
+    // parametric coordinates the primitive found on the way, if any
+    pub u: Float,
+    pub v: Float,
+    // the surface's inside and outside are swapped, as for the part of
+    // a csg difference that the subtracted solid leaves behind
+    pub flipped: bool,
+}
+
+impl<'a> Hit<'a> {
+    pub fn new(t: Float, primitive: &'a dyn Primitive) -> Hit<'a> {
+        Hit {
+            t,
+            primitive,
+            instance: None,
+            u: 0.0,
+            v: 0.0,
+            flipped: false,
+        }
+    }
+
+    // the surface interaction for this hit of r
+    pub fn interaction(&self, r: Ray) -> HitRecord<'a> {
+        let mut rec = match self.instance {
+            Some(instance) => instance.interaction_at(r, self),
+            None => self.primitive.interaction(r, self),
+        };
+        // the normal already faces against the ray either way
+        rec.front_face ^= self.flipped;
+        rec
+    }
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, r: Ray, interval: Interval) -> Option<HitRecord>;
+    // closest hit within ray_t, doing no more work than needed to find it
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>>;
+
+    // whether anything is hit within ray_t, for shadow rays. Any hit
+    // will do, so implementations can stop at the first one.
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        self.intersect(r, ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb;
+
+    // whether hits can come back through an instance, which another
+    // instance can't hold
+    fn has_instances(&self) -> bool {
+        false
+    }
+
+    // closest hit within ray_t together with its surface interaction
+    fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        self.intersect(r, ray_t).map(|hit| hit.interaction(r))
+    }
 }
 
-impl HitRecord {
-    pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
+// A hittable whose hits are on itself rather than on children, so it is
+// the one asked to build their surface interactions. Aggregates only
+// implement Hittable and hand out hits on their primitives.
+pub trait Primitive: Hittable {
+    // surface interaction for a hit this primitive returned from intersect
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_>;
+}
+
+// Whether a candidate hit on `material` is kept, partially opaque
+// surfaces are kept with probability equal to their alpha. The surface
+// coordinates are only worked out for materials that have an alpha.
+pub fn alpha_test(
+    material: &dyn Scatter,
+    surface: impl FnOnce() -> (Float, Float, Point3),
+) -> bool {
+    if !material.has_alpha() {
+        return true;
+    }
+
+    let (u, v, p) = surface();
+    let alpha = material.alpha(u, v, p);
+    if alpha >= 1.0 {
+        true
+    } else if alpha <= 0.0 {
+        false
+    } else {
+        random_double() < alpha
+    }
+}
+
+impl HitRecord<'_> {
+    // ray leaving the surface in `direction`, with its origin offset
+    // by the hit point's error bounds instead of a fixed epsilon
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let origin = offset_ray_origin(self.p, self.p_error, self.normal, direction);
+        Ray::new(origin, direction)
+    }
+
+    pub fn set_face_normal(&mut self, r: Ray, outward_normal: Normal3) {
         self.front_face = dot(r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face {
             outward_normal
@@ -34,22 +153,42 @@ impl HitRecord {
 }
 
 impl HittableList {
-    pub fn add(&mut self, object: Box<dyn Hittable>) {
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.bbox = Aabb::enclosing(self.bbox, object.bounding_box());
+        self.has_instances |= object.has_instances();
         self.objects.push(object);
     }
+
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitRecord> {
-        let mut tmp_rec = None;
+    fn intersect(&self, ray: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let mut closest = None;
         let mut closest_so_far = ray_t.max;
 
         for object in self.objects.iter() {
-            if let Some(rec) = object.hit(ray, Interval::new(ray_t.min, closest_so_far)) {
-                closest_so_far = rec.t;
-                tmp_rec = Some(rec);
+            if let Some(hit) = object.intersect(ray, Interval::new(ray_t.min, closest_so_far)) {
+                closest_so_far = hit.t;
+                closest = Some(hit);
             }
         }
-        tmp_rec
+        closest
+    }
+
+    fn occluded(&self, ray: Ray, ray_t: Interval) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.occluded(ray, ray_t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn has_instances(&self) -> bool {
+        self.has_instances
     }
 }
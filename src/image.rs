@@ -0,0 +1,397 @@
+// Images for textures, PNG and JPEG through the png and zune-jpeg crates.
+// Pixels are rgba in 0..1 as stored, top row first.
+
+use crate::color::{gamma_to_linear, Color};
+use crate::texture::Texture;
+use crate::utils::Float;
+use crate::vec3::Point3;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use zune_jpeg::zune_core::bytestream::ZCursor;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[Float; 4]>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<[Float; 4]>) -> Image {
+        assert_eq!(pixels.len(), width * height);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Image> {
+        Image::decode(&fs::read(path)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Image> {
+        if bytes.starts_with(PNG_SIGNATURE) {
+            decode_png(bytes)
+        } else if bytes.starts_with(JPEG_SIGNATURE) {
+            decode_jpeg(bytes)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unsupported image format, only png and jpeg are read",
+            ))
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [Float; 4] {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// An image wrapped around uv space, repeating outside 0..1 and filtered
+// bilinearly. v runs up the image like everywhere else here.
+#[derive(Clone)]
+pub struct ImageTexture {
+    image: Arc<Image>,
+    srgb: bool,
+    channel: Option<usize>,
+    scale: Color,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> ImageTexture {
+        ImageTexture {
+            image,
+            srgb: false,
+            channel: None,
+            scale: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // stored with display gamma, as color images usually are. Alpha is
+    // always linear.
+    pub fn with_srgb(self) -> ImageTexture {
+        ImageTexture { srgb: true, ..self }
+    }
+
+    // a single channel (3 is alpha) in all three, for the scalar
+    // parameters that read the first channel
+    pub fn with_channel(self, channel: usize) -> ImageTexture {
+        ImageTexture {
+            channel: Some(channel.min(3)),
+            ..self
+        }
+    }
+
+    // multiplies every lookup
+    pub fn with_scale(self, scale: Color) -> ImageTexture {
+        ImageTexture { scale, ..self }
+    }
+
+    fn lookup(&self, u: Float, v: Float) -> [Float; 4] {
+        let (width, height) = (self.image.width, self.image.height);
+        let x = u.rem_euclid(1.0) * width as Float - 0.5;
+        let y = (1.0 - v).rem_euclid(1.0) * height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |i: Float, n: usize| (i as isize).rem_euclid(n as isize) as usize;
+
+        let mut out = [0.0; 4];
+        for (dx, dy, weight) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let pixel = self.image.pixel(
+                wrap(x0 + dx as Float, width),
+                wrap(y0 + dy as Float, height),
+            );
+            for (o, p) in out.iter_mut().zip(pixel) {
+                *o += weight * p;
+            }
+        }
+        out
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _p: Point3) -> Color {
+        let mut rgba = self.lookup(u, v);
+        if self.srgb {
+            for c in &mut rgba[..3] {
+                *c = gamma_to_linear(*c);
+            }
+        }
+        let color = match self.channel {
+            Some(channel) => Color::new(rgba[channel], rgba[channel], rgba[channel]),
+            None => Color::new(rgba[0], rgba[1], rgba[2]),
+        };
+        self.scale * color
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SIGNATURE: &[u8] = &[0xff, 0xd8, 0xff];
+
+fn decode_png(bytes: &[u8]) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(io::Cursor::new(bytes));
+    // palettes, palette alpha and bit depths under 8 come out as plain
+    // gray or rgb, with alpha when there was any
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let error = |error: png::DecodingError| invalid(&format!("png: {error}"));
+    let mut reader = decoder.read_info().map_err(error)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| invalid("png too large"))?;
+    let mut buffer = vec![0; size];
+    let info = reader.next_frame(&mut buffer).map_err(error)?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    let samples: Vec<Float> = match info.bit_depth {
+        png::BitDepth::Sixteen => buffer
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as Float / 65535.0)
+            .collect(),
+        _ => buffer.iter().map(|&b| b as Float / 255.0).collect(),
+    };
+    let pixels = samples
+        .chunks_exact(info.color_type.samples())
+        .map(|s| match *s {
+            [gray] => [gray, gray, gray, 1.0],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [r, g, b] => [r, g, b, 1.0],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok(Image::new(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+fn decode_jpeg(bytes: &[u8]) -> io::Result<Image> {
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGB);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(bytes), options);
+    let rgb = decoder
+        .decode()
+        .map_err(|error| invalid(&format!("jpeg: {error}")))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| invalid("jpeg without a frame"))?;
+    let pixels = rgb
+        .chunks_exact(3)
+        .map(|rgb| {
+            let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| c as Float / 255.0);
+            [r, g, b, 1.0]
+        })
+        .collect();
+    Ok(Image::new(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a png of the given layout, with an optional palette and its alpha
+    fn png(
+        width: u32,
+        height: u32,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        palette: Option<(&[u8], &[u8])>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if let Some((palette, alpha)) = palette {
+            encoder.set_palette(palette);
+            encoder.set_trns(alpha);
+        }
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn decodes_png() {
+        use png::{BitDepth, ColorType};
+
+        let rgb = png(
+            2,
+            1,
+            ColorType::Rgb,
+            BitDepth::Eight,
+            None,
+            &[10, 20, 30, 40, 50, 60],
+        );
+        let image = Image::decode(&rgb).unwrap();
+        let bytes = |x, y| image.pixel(x, y).map(|c| (c * 255.0).round() as u8);
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(bytes(0, 0), [10, 20, 30, 255]);
+        assert_eq!(bytes(1, 0), [40, 50, 60, 255]);
+
+        // 4x1 with a 2 bit palette and palette alpha
+        let palette: &[u8] = &[255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let alpha: &[u8] = &[255, 0];
+        let indexed = png(
+            4,
+            1,
+            ColorType::Indexed,
+            BitDepth::Two,
+            Some((palette, alpha)),
+            &[0b00_01_10_00],
+        );
+        let image = Image::decode(&indexed).unwrap();
+        assert_eq!(image.pixel(1, 0), [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(image.pixel(2, 0), [0.0, 0.0, 1.0, 1.0]);
+
+        // 16 bit gray with alpha
+        let gray = png(
+            1,
+            1,
+            ColorType::GrayscaleAlpha,
+            BitDepth::Sixteen,
+            None,
+            &[255, 255, 0, 0],
+        );
+        assert_eq!(
+            Image::decode(&gray).unwrap().pixel(0, 0),
+            [1.0, 1.0, 1.0, 0.0]
+        );
+
+        assert!(Image::decode(b"GIF89a").is_err());
+        // cut off in the middle of the image data
+        let whole = png(
+            64,
+            64,
+            ColorType::Rgb,
+            BitDepth::Eight,
+            None,
+            &[7; 64 * 64 * 3],
+        );
+        assert!(Image::decode(&whole[..whole.len() / 2]).is_err());
+    }
+
+    // a baseline grayscale jpeg of flat 8x8 blocks side by side, each
+    // given by its dc coefficient. Quantization is all ones and one
+    // huffman table with 4 bit codes for sizes 0 to 11 serves for dc and
+    // ac, so a block is its dc difference and an end of block.
+    fn jpeg(dc: &[i32]) -> Vec<u8> {
+        let segment = |out: &mut Vec<u8>, marker: u8, data: &[u8]| {
+            out.extend_from_slice(&[0xff, marker]);
+            out.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+            out.extend_from_slice(data);
+        };
+        let mut out = vec![0xff, 0xd8];
+        segment(&mut out, 0xdb, &[[0].as_slice(), &[1; 64]].concat());
+        let mut counts = [0; 16];
+        counts[3] = 12;
+        let symbols: Vec<u8> = (0..12).collect();
+        for class in [0x00, 0x10] {
+            segment(&mut out, 0xc4, &[&[class], &counts[..], &symbols].concat());
+        }
+        let width = 8 * dc.len() as u16;
+        let mut frame = vec![8, 0, 8];
+        frame.extend_from_slice(&width.to_be_bytes());
+        frame.extend_from_slice(&[1, 1, 0x11, 0]);
+        segment(&mut out, 0xc0, &frame);
+        segment(&mut out, 0xda, &[1, 1, 0x00, 0, 63, 0]);
+
+        let mut bits = Vec::new();
+        let put = |bits: &mut Vec<bool>, value: u32, n: u32| {
+            bits.extend((0..n).rev().map(|i| (value >> i) & 1 == 1));
+        };
+        let mut prediction = 0;
+        for &value in dc {
+            let difference = value - prediction;
+            prediction = value;
+            let size = 32 - difference.unsigned_abs().leading_zeros();
+            put(&mut bits, size, 4);
+            let magnitude = if difference < 0 {
+                difference + (1 << size) - 1
+            } else {
+                difference
+            };
+            put(&mut bits, magnitude as u32, size);
+            // end of block
+            put(&mut bits, 0, 4);
+        }
+        // padded with ones and with zeros stuffed after 0xff
+        bits.resize(bits.len().div_ceil(8) * 8, true);
+        for byte in bits.chunks(8) {
+            let byte = byte.iter().fold(0u8, |b, &bit| (b << 1) | bit as u8);
+            out.push(byte);
+            if byte == 0xff {
+                out.push(0);
+            }
+        }
+        out.extend_from_slice(&[0xff, 0xd9]);
+        out
+    }
+
+    #[test]
+    fn decodes_jpeg() {
+        // flat blocks come out at 128 + dc / 8
+        let image = Image::decode(&jpeg(&[8 * (200 - 128), 8 * (60 - 128)])).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+        let gray = |x, y| image.pixel(x, y).map(|c| (c * 255.0).round() as u8);
+        assert_eq!(gray(0, 0), [200, 200, 200, 255]);
+        assert_eq!(gray(7, 7), [200, 200, 200, 255]);
+        assert_eq!(gray(8, 3), [60, 60, 60, 255]);
+
+        let whole = jpeg(&[0]);
+        assert!(Image::decode(&whole[..20]).is_err());
+    }
+
+    #[test]
+    fn texture_lookups() {
+        // black and white columns
+        let image = Arc::new(Image::new(
+            2,
+            1,
+            vec![[0.0, 0.0, 0.0, 1.0], [1.0, 0.5, 0.25, 0.5]],
+        ));
+        let p = Point3::origin();
+        let texture = ImageTexture::new(image.clone());
+        assert_eq!(texture.value(0.75, 0.5, p), Color::new(1.0, 0.5, 0.25));
+        // halfway between the texel centers, and wrapped around
+        assert_eq!(texture.value(0.5, 0.5, p), Color::new(0.5, 0.25, 0.125));
+        assert_eq!(texture.value(1.75, -3.5, p), Color::new(1.0, 0.5, 0.25));
+
+        let srgb = ImageTexture::new(image.clone()).with_srgb();
+        assert_eq!(srgb.value(0.75, 0.5, p), Color::new(1.0, 0.25, 0.0625));
+        let alpha = ImageTexture::new(image)
+            .with_channel(3)
+            .with_scale(Color::new(2.0, 2.0, 2.0));
+        assert_eq!(alpha.value(0.75, 0.5, p), Color::new(1.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,205 @@
+// Instancing. Geometry is built once into a bottom level structure (any
+// hittable, usually a Bvh4 over a mesh) and shared between instances
+// through an Arc, each instance only adds a transform and optionally its
+// own material. A Tlas is a Bvh4 over the instances' world space bounds.
+
+use crate::aabb::Aabb;
+use crate::hittable::{Hit, HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::{unit_vector, Normal3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use std::sync::Arc;
+
+pub struct Instance {
+    blas: Arc<dyn Hittable>,
+    // object to world
+    transform: Transform,
+    material: Option<Arc<dyn Scatter>>,
+    bbox: Aabb,
+}
+
+impl Instance {
+    // panics if blas holds instances itself, instances can't nest
+    pub fn new(blas: Arc<dyn Hittable>, transform: Transform) -> Instance {
+        assert!(!blas.has_instances(), "instances can't nest");
+        Instance {
+            bbox: transform.bounding_box(blas.bounding_box()),
+            blas,
+            transform,
+            material: None,
+        }
+    }
+
+    // replaces the material of every hit on this instance. Alpha masking
+    // is still decided by the shared geometry's own material.
+    pub fn with_material(self, material: Arc<dyn Scatter>) -> Instance {
+        Instance {
+            material: Some(material),
+            ..self
+        }
+    }
+}
+
+impl Instance {
+    // the ray in object space, the direction isn't normalized so t means
+    // the same in both spaces
+    fn to_object(&self, r: Ray) -> Ray {
+        let to_object = self.transform.inverse();
+        let local = Ray::new(to_object.point(r.origin()), to_object.vector(r.direction()));
+        match r.wavelength() {
+            Some(wavelength) => local.with_wavelength(wavelength),
+            None => local,
+        }
+    }
+
+    // surface interaction of a hit on the shared geometry, moved to world
+    // space and with the material override applied
+    pub(crate) fn interaction_at<'a>(&'a self, r: Ray, hit: &Hit<'a>) -> HitRecord<'a> {
+        let mut rec = hit.primitive.interaction(self.to_object(r), hit);
+
+        (rec.p, rec.p_error) = self.transform.point_with_error(rec.p, rec.p_error);
+        // still faces against the ray, the inverse transpose keeps the sign
+        // of its dot product with the transformed direction
+        let normal = Vec3::from(self.transform.normal(rec.normal));
+        rec.normal = Normal3::from(unit_vector(normal));
+        rec.dpdu = self.transform.vector(rec.dpdu);
+        rec.dpdv = self.transform.vector(rec.dpdv);
+        if let Some(material) = &self.material {
+            rec.material = &**material;
+        }
+        rec
+    }
+}
+
+impl Hittable for Instance {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let hit = self.blas.intersect(self.to_object(r), ray_t)?;
+        Some(Hit {
+            instance: Some(self),
+            ..hit
+        })
+    }
+
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        self.blas.occluded(self.to_object(r), ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn has_instances(&self) -> bool {
+        true
+    }
+}
+
+// top level acceleration structure over instances
+pub struct Tlas {
+    bvh: Bvh4,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Tlas {
+        let mut list = HittableList::default();
+        for instance in instances {
+            list.add(Arc::new(instance));
+        }
+        Tlas {
+            bvh: Bvh4::new(&list),
+        }
+    }
+}
+
+impl Hittable for Tlas {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        self.bvh.intersect(r, ray_t)
+    }
+
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        self.bvh.occluded(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    fn has_instances(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{tests::gray, Metal};
+    use crate::sphere::Sphere;
+    use crate::utils::Float;
+    use crate::vec3::Point3;
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::origin(), 1.0, gray()))
+    }
+
+    #[test]
+    fn transformed_hit() {
+        let transform =
+            Transform::translate(Vec3::new(0.0, 0.0, 5.0)) * Transform::scale(1.0, 1.0, 2.0);
+        let instance = Instance::new(unit_sphere(), transform);
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, 1.0));
+
+        let rec = instance
+            .hit(r, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-4);
+        assert!((rec.p - Point3::new(0.0, 0.0, 3.0)).length() < 1.0e-4);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 0.0, -1.0)).length() < 1.0e-4);
+        assert!(rec.front_face);
+
+        let bbox = instance.bounding_box();
+        assert!((bbox.z.min - 3.0).abs() < 1.0e-4 && (bbox.z.max - 7.0).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn tlas_shares_geometry() {
+        let blas = unit_sphere();
+        let metal: Arc<dyn Scatter> = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0));
+
+        let instances = (0..100)
+            .map(|i| {
+                let offset = Vec3::new(3.0 * i as Float, 0.0, 0.0);
+                let instance = Instance::new(blas.clone(), Transform::translate(offset));
+                if i == 42 {
+                    instance.with_material(metal.clone())
+                } else {
+                    instance
+                }
+            })
+            .collect();
+        let tlas = Tlas::new(instances);
+        assert_eq!(Arc::strong_count(&blas), 101);
+
+        let r = Ray::new(Point3::new(126.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = tlas.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-4);
+        assert!(std::ptr::addr_eq(rec.material, &*metal));
+
+        let r = Ray::new(Point3::new(124.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(tlas.hit(r, Interval::new(0.0, Float::INFINITY)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "instances can't nest")]
+    fn nested_instances_rejected() {
+        let inner = Instance::new(unit_sphere(), Transform::scale(2.0, 2.0, 2.0));
+        let tlas = Tlas::new(vec![inner]);
+        Instance::new(
+            Arc::new(tlas),
+            Transform::translate(Vec3::new(1.0, 0.0, 0.0)),
+        );
+    }
+}
@@ -1,18 +1,52 @@
+use crate::utils::Float;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Interval {
-    pub min: f32,
-    pub max: f32,
+    pub min: Float,
+    pub max: Float,
 }
 
 impl Interval {
-    pub fn new(min: f32, max: f32) -> Interval {
+    pub const EMPTY: Interval = Interval {
+        min: Float::INFINITY,
+        max: -Float::INFINITY,
+    };
+    pub const UNIVERSE: Interval = Interval {
+        min: -Float::INFINITY,
+        max: Float::INFINITY,
+    };
+
+    pub fn new(min: Float, max: Float) -> Interval {
         Interval { min, max }
     }
 
-    pub fn surrounds(&self, x: f32) -> bool {
+    // smallest interval containing both a and b
+    pub fn enclosing(a: Interval, b: Interval) -> Interval {
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> Float {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, x: Float) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    // grows the interval by delta, half on each side
+    pub fn expand(&self, delta: Float) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
+
+    pub fn surrounds(&self, x: Float) -> bool {
         self.min < x && x < self.max
     }
 
-    pub fn clamp(&self, x: f32) -> f32 {
+    pub fn clamp(&self, x: Float) -> Float {
         if x < self.min {
             return self.min;
         } else if x > self.max {
@@ -24,9 +58,6 @@ impl Interval {
 
 impl Default for Interval {
     fn default() -> Interval {
-        Interval {
-            min: -std::f32::INFINITY,
-            max: std::f32::INFINITY,
-        }
+        Interval::UNIVERSE
     }
 }
@@ -0,0 +1,49 @@
+pub mod aabb;
+pub mod alpha_mask;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod cone;
+pub mod constant_medium;
+pub mod csg;
+pub mod cuboid;
+pub mod curve;
+pub mod cylinder;
+pub mod disk;
+pub mod displacement;
+pub mod gltf;
+pub mod hair;
+pub mod heightfield;
+pub mod hittable;
+pub mod image;
+pub mod instance;
+pub mod interval;
+pub mod material;
+pub mod mesh;
+pub mod mitsuba;
+pub mod normal_map;
+pub mod obj;
+pub mod onb;
+pub mod paraboloid;
+pub mod pbrt;
+pub mod perlin;
+pub mod ply;
+pub mod polynomial;
+pub mod quad;
+pub mod ray;
+pub mod scenes;
+pub mod script;
+pub mod sdf;
+pub mod simd;
+pub mod spectrum;
+pub mod sphere;
+pub mod stl;
+pub mod subdivision;
+pub mod texture;
+pub mod torus;
+pub mod transform;
+pub mod utils;
+pub mod vec3;
+pub mod wavefront;
+pub mod wide_bvh;
+pub mod xml;
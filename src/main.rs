@@ -1,98 +1,147 @@
-mod camera;
-mod color;
-mod hittable;
-mod interval;
-mod material;
-mod ray;
-mod sphere;
-mod utils;
-mod vec3;
-
-use camera::Camera;
-use color::Color;
-use hittable::HittableList;
-use material::{Dielectric, Lambertian, Metal};
-use sphere::Sphere;
-use utils::random_double_bounded;
-use vec3::{Point3, Vec3};
-
-use rand::prelude::*;
+use ray_tracer::camera::Camera;
+use ray_tracer::gltf::load_gltf;
+use ray_tracer::hittable::{Hittable, HittableList};
+use ray_tracer::instance::Tlas;
+use ray_tracer::material::Principled;
+use ray_tracer::mitsuba::load_mitsuba;
+use ray_tracer::obj::load_obj_scene;
+use ray_tracer::pbrt::load_pbrt;
+use ray_tracer::scenes::{preset, Scene, PRESETS};
+use ray_tracer::script::load_script;
+use ray_tracer::vec3::{Point3, Vec3};
+use ray_tracer::wide_bvh::Bvh4;
+
+use std::io;
+use std::path::Path;
+use std::process;
 use std::sync::Arc;
 
-fn random_scene() -> HittableList {
-    let mut world: HittableList = HittableList::default();
-
-    let mut rng = rand::thread_rng();
-    let ground_mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let ground_sphere = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
-
-    world.add(Box::new(ground_sphere));
-
-    for a in -11..=11 {
-        for b in -11..=11 {
-            let choose_mat: f64 = rng.gen();
-            let center = Point3::new(
-                (a as f32) + random_double_bounded(0.0, 0.9),
-                0.2,
-                (b as f32) + random_double_bounded(0.0, 0.9),
-            );
-
-            if choose_mat < 0.8 {
-                // Diffuse
-                let albedo = Color::random_bounded(0.0, 1.0) * Color::random_bounded(0.0, 1.0);
-                let sphere_mat = Arc::new(Lambertian::new(albedo));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
-
-                world.add(Box::new(sphere));
-            } else if choose_mat < 0.95 {
-                // Metal
-                let albedo = Color::random_bounded(0.4, 1.0);
-                let fuzz = random_double_bounded(0.0, 0.5);
-                let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
+// Renders a preset or a scene file to stdout as a ppm:
+//
+//     ray-tracer cornell_box > image.ppm
+//     ray-tracer model.gltf > image.ppm
+//     ray-tracer model.obj > image.ppm
+//     ray-tracer scene.pbrt > image.ppm
+//     ray-tracer scene.xml > image.ppm
+//     ray-tracer scenes/random_scene.rhai > image.ppm
+//
+// Files are read by their extension, .xml files are mitsuba scenes and
+// .rhai files scene scripts. Without an argument it's the random_scene
+// preset.
+fn main() {
+    let name = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "random_scene".to_string());
+
+    let scene = match preset(&name) {
+        Some(scene) => scene,
+        None if Path::new(&name).is_file() => load(Path::new(&name)).unwrap_or_else(|error| {
+            eprintln!("{name}: {error}");
+            process::exit(1);
+        }),
+        None => {
+            let presets: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            eprintln!("{name}: no such preset or scene file");
+            eprintln!("presets: {}", presets.join(", "));
+            process::exit(1);
+        }
+    };
+    for warning in &scene.warnings {
+        eprintln!("warning: {warning}");
+    }
 
-                world.add(Box::new(sphere));
-            } else {
-                // Glass
-                let sphere_mat = Arc::new(Dielectric::new(1.5));
-                let sphere = Sphere::new(center, 0.2, sphere_mat);
+    let mut camera = scene.camera;
+    camera.render(&Bvh4::new(&scene.world));
+}
 
-                world.add(Box::new(sphere));
+fn load(path: &Path) -> io::Result<Scene> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("pbrt") => {
+            let pbrt = load_pbrt(path)?;
+            Ok(Scene {
+                world: pbrt.world,
+                camera: pbrt.camera,
+                warnings: pbrt.warnings,
+            })
+        }
+        Some("xml") => {
+            let mitsuba = load_mitsuba(path)?;
+            Ok(Scene {
+                world: mitsuba.world,
+                camera: mitsuba.camera,
+                warnings: mitsuba.warnings,
+            })
+        }
+        Some("obj") => {
+            let mut obj = load_obj_scene(path, Arc::new(Principled::default()))?;
+            let warnings = std::mem::take(&mut obj.warnings);
+            let world = obj.world();
+            let mut camera = framing(&world);
+            camera.aspect_ratio = 16.0 / 9.0;
+            camera.image_width = 400.0;
+            camera.samples_per_pixel = 100;
+            camera.max_depth = 50;
+            Ok(Scene {
+                world,
+                camera,
+                warnings,
+            })
+        }
+        Some("gltf" | "glb") => {
+            let gltf = load_gltf(path)?;
+            let mut world = HittableList::default();
+            world.add(Arc::new(Tlas::new(gltf.instances)));
+            let mut camera = gltf
+                .cameras
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| framing(&world));
+            // gltf leaves the image and sampling to us
+            if camera.aspect_ratio <= 0.0 {
+                camera.aspect_ratio = 16.0 / 9.0;
             }
+            camera.image_width = 400.0;
+            camera.samples_per_pixel = 100;
+            camera.max_depth = 50;
+            Ok(Scene {
+                world,
+                camera,
+                warnings: gltf.warnings,
+            })
+        }
+        Some("rhai") => {
+            let script = load_script(path)?;
+            Ok(Scene {
+                world: script.world,
+                camera: script.camera,
+                warnings: Vec::new(),
+            })
         }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unknown scene file type, expected .pbrt, .xml, .obj, .gltf, .glb or .rhai",
+        )),
     }
-
-    let mat1 = Arc::new(Dielectric::new(1.5));
-    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-
-    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
-    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
-    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
-
-    world.add(Box::new(sphere1));
-    world.add(Box::new(sphere2));
-    world.add(Box::new(sphere3));
-
-    return world;
 }
 
-fn main() {
-    let world = random_scene();
-    let mut camera = Camera::default();
-
-    camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = 400.0;
-    camera.samples_per_pixel = 100;
-    camera.max_depth = 50;
+// for a file without a camera, looking down -z at the whole scene
+fn framing(world: &HittableList) -> Camera {
+    let bbox = world.bounding_box();
+    let min = Point3::new(bbox.x.min, bbox.y.min, bbox.z.min);
+    let max = Point3::new(bbox.x.max, bbox.y.max, bbox.z.max);
+    let center = bbox.centroid();
+    let distance = 1.5 * (max - min).length().max(1.0e-3);
 
-    camera.vfov = 20.0;
-    camera.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    camera.lookat = Point3::origin();
+    let mut camera = Camera::default();
+    camera.vfov = 40.0;
+    camera.lookat = center;
+    // far enough back for the bounding sphere to fit the field of view
+    camera.lookfrom = center + Vec3::new(0.0, 0.0, distance);
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    camera.defocus_angle = 0.6;
-    camera.focus_dist = 10.0;
-
-    camera.render(&world);
+    camera.focus_dist = distance;
+    camera
 }
@@ -1,24 +1,199 @@
 use rand::prelude::*;
 
-use crate::color::Color;
+use crate::color::{luminance, Color};
 use crate::hittable::HitRecord;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vec3::{dot, random_in_unit_sphere, unit_vector};
+use crate::spectrum::rgb_to_spectrum;
+use crate::texture::{SolidColor, Texture};
+use crate::utils::{random_double, Float, PI};
+use crate::vec3::{dot, random_cosine_direction, random_in_unit_sphere, unit_vector, Point3, Vec3};
+
+use std::sync::Arc;
 
 pub trait Scatter: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    fn emitted(&self, _u: Float, _v: Float, _p: Point3) -> Color {
+        Color::black()
+    }
+
+    // true when scattering depends on the ray's wavelength, either
+    // through dispersion or through a wavelength dependent weight
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // opacity of the surface at a hit point, primitives ignore
+    // hits that fail `hittable::alpha_test` as if nothing was there
+    fn alpha(&self, _u: Float, _v: Float, _p: Point3) -> Float {
+        1.0
+    }
+
+    // false when alpha is 1 everywhere, so primitives can skip working
+    // out the surface coordinates of hits that may not be the closest
+    fn has_alpha(&self) -> bool {
+        false
+    }
+}
+
+// index of refraction, optionally varying with wavelength
+#[derive(Debug, Clone, Copy)]
+pub enum Ior {
+    Constant(Float),
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: Float, b: Float },
+    // n^2 = 1 + sum(b * lambda^2 / (lambda^2 - c)), lambda in micrometers
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Ior {
+    // sodium D line, used when a ray carries no wavelength
+    const REFERENCE_WAVELENGTH: Float = 589.3;
+
+    // Schott N-BK7 crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+
+    pub fn at(&self, wavelength: Option<Float>) -> Float {
+        let lambda_um = wavelength.unwrap_or(Self::REFERENCE_WAVELENGTH) / 1000.0;
+        let l2 = lambda_um * lambda_um;
+
+        match *self {
+            Ior::Constant(ir) => ir,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: Float = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+// thin transparent layer coating a surface, like a soap bubble's
+// wall or an anti-reflective lens coating
+#[derive(Debug, Clone, Copy)]
+pub struct ThinFilm {
+    // nanometers
+    pub thickness: Float,
+    pub ior: Float,
+}
+
+impl ThinFilm {
+    // wavelengths standing in for the r, g and b channels outside spectral mode
+    const RGB_WAVELENGTHS: [Float; 3] = [650.0, 532.0, 450.0];
+
+    pub fn new(thickness: Float, ior: Float) -> ThinFilm {
+        ThinFilm { thickness, ior }
+    }
+
+    // Airy reflectance of the film sitting between the incident medium
+    // (eta_i) and a dielectric substrate (eta_t), averaged over polarizations
+    fn reflectance(&self, cos_i: Float, eta_i: Float, eta_t: Float, wavelength: Float) -> Float {
+        let sin2_i = (1.0 - cos_i * cos_i).max(0.0);
+        let Some(cos_f) = refracted_cosine(sin2_i, eta_i / self.ior) else {
+            return 1.0;
+        };
+        let Some(cos_t) = refracted_cosine(sin2_i, eta_i / eta_t) else {
+            return 1.0;
+        };
+
+        let (rs12, rp12) = fresnel_amplitudes(eta_i, self.ior, cos_i, cos_f);
+        let (rs23, rp23) = fresnel_amplitudes(self.ior, eta_t, cos_f, cos_t);
+        let phase = self.phase(cos_f, wavelength);
+
+        0.5 * (airy(rs12, rs23, phase) + airy(rp12, rp23, phase))
+    }
+
+    // a conductor has no real index, so its amplitude is approximated
+    // from its reflectance with the half wave shift of a metal
+    fn conductor_reflectance(&self, cos_i: Float, albedo: Float, wavelength: Float) -> Float {
+        let sin2_i = (1.0 - cos_i * cos_i).max(0.0);
+        let Some(cos_f) = refracted_cosine(sin2_i, 1.0 / self.ior) else {
+            return 1.0;
+        };
+
+        let (rs12, rp12) = fresnel_amplitudes(1.0, self.ior, cos_i, cos_f);
+        let r23 = -albedo.clamp(0.0, 1.0).sqrt();
+        let phase = self.phase(cos_f, wavelength);
+
+        0.5 * (airy(rs12, r23, phase) + airy(rp12, r23, phase))
+    }
+
+    // phase difference between the two surfaces of the film
+    fn phase(&self, cos_f: Float, wavelength: Float) -> Float {
+        4.0 * PI * self.ior * self.thickness * cos_f / wavelength
+    }
+
+    // evaluates a per wavelength reflectance either at the ray's
+    // wavelength (as a gray color) or once per rgb channel
+    fn per_channel(wavelength: Option<Float>, f: impl Fn(usize, Float) -> Float) -> Color {
+        match wavelength {
+            Some(lambda) => {
+                let r = f(0, lambda);
+                Color::new(r, r, r)
+            }
+            None => {
+                let [r, g, b] = Self::RGB_WAVELENGTHS;
+                Color::new(f(0, r), f(1, g), f(2, b))
+            }
+        }
+    }
+}
+
+fn refracted_cosine(sin2_i: Float, eta_ratio: Float) -> Option<Float> {
+    let sin2_t = eta_ratio * eta_ratio * sin2_i;
+    if sin2_t >= 1.0 {
+        None
+    } else {
+        Some((1.0 - sin2_t).sqrt())
+    }
+}
+
+// s and p polarized Fresnel amplitude coefficients
+fn fresnel_amplitudes(eta_i: Float, eta_t: Float, cos_i: Float, cos_t: Float) -> (Float, Float) {
+    let rs = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    let rp = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    (rs, rp)
+}
+
+// reflectance of the multiple bounces inside a film
+fn airy(r12: Float, r23: Float, phase: Float) -> Float {
+    let cross = 2.0 * r12 * r23 * phase.cos();
+    let numerator = r12 * r12 + r23 * r23 + cross;
+    let denominator = 1.0 + r12 * r12 * r23 * r23 + cross;
+    (numerator / denominator).clamp(0.0, 1.0)
 }
 
 pub struct Dielectric {
-    ir: f32,
+    ir: Ior,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
-    pub fn new(ir: f32) -> Dielectric {
-        Dielectric { ir }
+    pub fn new(ir: Float) -> Dielectric {
+        Dielectric {
+            ir: Ior::Constant(ir),
+            film: None,
+        }
     }
 
-    fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
+    // glass whose index varies with wavelength, only disperses
+    // light when the camera renders in spectral mode
+    pub fn dispersive(ir: Ior) -> Dielectric {
+        Dielectric { ir, film: None }
+    }
+
+    pub fn with_thin_film(self, film: ThinFilm) -> Dielectric {
+        Dielectric {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    fn reflectance(cosine: Float, ref_idx: Float) -> Float {
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
@@ -26,75 +201,637 @@ impl Dielectric {
 
 impl Scatter for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.ir.at(r_in.wavelength());
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
-        let unit_direction = unit_vector(&mut r_in.direction());
-        let cos_theta = dot((-1.0) * unit_direction, rec.normal).min(1.0);
+        let unit_direction = unit_vector(r_in.direction());
+        let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
 
         let mut rng = rand::thread_rng();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let will_reflect = rng.gen::<f32>() < Self::reflectance(cos_theta, refraction_ratio);
+        let reflected = rec.spawn_ray(unit_direction.reflect(rec.normal));
+        let refracted = || rec.spawn_ray(unit_direction.refract(rec.normal, refraction_ratio));
 
-        let direction = if cannot_refract || will_reflect {
-            unit_direction.reflect(rec.normal)
-        } else {
-            unit_direction.refract(rec.normal, refraction_ratio)
+        if cannot_refract {
+            return Some((Color::new(1.0, 1.0, 1.0), reflected));
+        }
+
+        let Some(film) = self.film else {
+            let will_reflect = rng.gen::<Float>() < Self::reflectance(cos_theta, refraction_ratio);
+            let scattered = if will_reflect { reflected } else { refracted() };
+            return Some((Color::new(1.0, 1.0, 1.0), scattered));
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        // the film tints reflection and transmission differently, so pick
+        // between them by the average and reweight each channel
+        let (eta_i, eta_t) = if rec.front_face { (1.0, ir) } else { (ir, 1.0) };
+        let reflectance = ThinFilm::per_channel(r_in.wavelength(), |_, lambda| {
+            film.reflectance(cos_theta, eta_i, eta_t, lambda)
+        });
+        let reflect_prob = ((reflectance.r() + reflectance.g() + reflectance.b()) / 3.0)
+            .clamp(1.0e-4, 1.0 - 1.0e-4);
+
+        if rng.gen::<Float>() < reflect_prob {
+            Some((reflectance / reflect_prob, reflected))
+        } else {
+            let transmittance = Color::new(1.0, 1.0, 1.0) - reflectance;
+            Some((transmittance / (1.0 - reflect_prob), refracted()))
+        }
+    }
 
-        Some((Color::new(1.0, 1.0, 1.0), scattered))
+    fn is_dispersive(&self) -> bool {
+        !matches!(self.ir, Ior::Constant(_)) || self.film.is_some()
     }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture>) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
 impl Scatter for Lambertian {
     fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + unit_vector(&mut random_in_unit_sphere());
+        let mut scatter_direction = Vec3::from(rec.normal) + unit_vector(random_in_unit_sphere());
         if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+            scatter_direction = Vec3::from(rec.normal);
         }
-        let scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = rec.spawn_ray(scatter_direction);
 
-        Some((self.albedo, scattered))
+        Some((self.albedo.value_at(rec), scattered))
     }
 }
 
+// emits on both sides and doesn't scatter
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight::from_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn from_texture(emit: Arc<dyn Texture>) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+}
+
+// phase function of participating media, scatters the same in every
+// direction, see ConstantMedium
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Scatter for Isotropic {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let scattered = Ray::new(rec.p, unit_vector(random_in_unit_sphere()));
+        Some((self.albedo.value_at(rec), scattered))
+    }
+}
+
+// rgb approximations of the complex ior of common metals, eta then k
+const METALS: [(&str, [Float; 3], [Float; 3]); 4] = [
+    ("Ag", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+    ("Al", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("Au", [0.143, 0.375, 1.442], [3.983, 2.386, 1.603]),
+    ("Cu", [0.200, 0.924, 1.102], [3.913, 2.453, 2.142]),
+];
+
+// eta and k of a metal by its chemical symbol
+pub fn metal_ior(symbol: &str) -> Option<(Color, Color)> {
+    let (_, eta, k) = METALS.iter().find(|(m, _, _)| *m == symbol)?;
+    let color = |[r, g, b]: [Float; 3]| Color::new(r, g, b);
+    Some((color(*eta), color(*k)))
+}
+
+// reflectance of a conductor at normal incidence, what Metal and a
+// metallic Principled take as their color
+pub fn conductor_f0(eta: Color, k: Color) -> Color {
+    let (eta, k) = (eta.to_array(), k.to_array());
+    let [r, g, b] = std::array::from_fn(|i| {
+        let (n, k) = (eta[i], k[i]);
+        ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k)
+    });
+    Color::new(r, g, b)
+}
+
 pub struct Metal {
     albedo: Color,
-    fuzz: f32,
+    fuzz: Float,
+    film: Option<ThinFilm>,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f32) -> Metal {
-        Metal { albedo, fuzz }
+    pub fn new(albedo: Color, fuzz: Float) -> Metal {
+        Metal {
+            albedo,
+            fuzz,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(self, film: ThinFilm) -> Metal {
+        Metal {
+            film: Some(film),
+            ..self
+        }
     }
 }
 
 impl Scatter for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut reflected = r_in.direction().reflect(rec.normal);
+        let reflected = r_in.direction().reflect(rec.normal);
         let normalized = reflected / reflected.length();
-        let scattered = Ray::new(rec.p, normalized + self.fuzz * random_in_unit_sphere());
+        let scattered = rec.spawn_ray(normalized + self.fuzz * random_in_unit_sphere());
+
+        if dot(scattered.direction(), rec.normal) <= 0.0 {
+            return None;
+        }
+
+        let attenuation = match self.film {
+            None => self.albedo,
+            Some(film) => {
+                let cos_theta = dot(-unit_vector(r_in.direction()), rec.normal).clamp(0.0, 1.0);
+                let channels = self.albedo.to_array();
+                ThinFilm::per_channel(r_in.wavelength(), |channel, lambda| {
+                    let albedo = match r_in.wavelength() {
+                        Some(_) => rgb_to_spectrum(self.albedo, lambda),
+                        None => channels[channel],
+                    };
+                    film.conductor_reflectance(cos_theta, albedo, lambda)
+                })
+            }
+        };
 
-        if dot(scattered.direction(), rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
+        Some((attenuation, scattered))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.film.is_some()
+    }
+}
+
+// Disney style "principled" material. Every parameter is a texture so it
+// can be driven per point, scalar parameters read the texture's first channel.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // scales the dielectric reflectance, 0.5 maps to a 4% F0
+    pub specular: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+    pub ior: Float,
+}
+
+// parameters sampled at a single hit point
+struct PrincipledParams {
+    base_color: Color,
+    metallic: Float,
+    alpha: Float,
+    specular: Float,
+    sheen: Float,
+    clearcoat: Float,
+    clearcoat_alpha: Float,
+    transmission: Float,
+}
+
+// probability of picking each lobe, in the order
+// diffuse, specular, clearcoat, transmission
+struct LobeWeights([Float; 4]);
+
+impl LobeWeights {
+    // the lobe whose share of the unit interval `choice` falls in, None
+    // if no lobe has any weight
+    fn pick(&self, choice: Float) -> Option<usize> {
+        let mut cdf = 0.0;
+        for (i, w) in self.0.iter().enumerate() {
+            cdf += w;
+            if choice < cdf {
+                return Some(i);
+            }
+        }
+        // rounding can leave the sum just short of one
+        self.0.iter().rposition(|&w| w > 0.0)
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled {
+            base_color: Arc::new(SolidColor::new(base_color)),
+            ..Default::default()
+        }
+    }
+
+    fn params(&self, rec: &HitRecord) -> PrincipledParams {
+        let scalar = |t: &Arc<dyn Texture>| t.value_at(rec).r().clamp(0.0, 1.0);
+
+        let roughness = scalar(&self.roughness);
+        let clearcoat_gloss = scalar(&self.clearcoat_gloss);
+
+        PrincipledParams {
+            base_color: self.base_color.value_at(rec),
+            metallic: scalar(&self.metallic),
+            alpha: (roughness * roughness).max(1.0e-3),
+            specular: scalar(&self.specular),
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * clearcoat_gloss,
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    fn lobe_weights(params: &PrincipledParams, cos_o: Float) -> LobeWeights {
+        let dielectric = (1.0 - params.metallic) * (1.0 - params.transmission);
+        let f0 = Self::specular_f0(params);
+
+        let diffuse = dielectric * luminance(params.base_color).max(params.sheen);
+        let specular = luminance(schlick_color(f0, cos_o));
+        let clearcoat = 0.25 * params.clearcoat * schlick(0.04, cos_o);
+        let transmission = (1.0 - params.metallic) * params.transmission;
+
+        let total = diffuse + specular + clearcoat + transmission;
+        if total <= 0.0 {
+            return LobeWeights([0.0; 4]);
+        }
+
+        LobeWeights([
+            diffuse / total,
+            specular / total,
+            clearcoat / total,
+            transmission / total,
+        ])
+    }
+
+    fn specular_f0(params: &PrincipledParams) -> Color {
+        let dielectric_f0 = 0.08 * params.specular * Color::new(1.0, 1.0, 1.0);
+        (1.0 - params.metallic) * dielectric_f0 + params.metallic * params.base_color
+    }
+
+    // f(wo, wi) * cos(theta_i) summed over the non-delta lobes,
+    // along with the combined pdf of sampling wi from them
+    fn evaluate(
+        params: &PrincipledParams,
+        weights: &LobeWeights,
+        wo: Vec3,
+        wi: Vec3,
+    ) -> (Color, Float) {
+        let cos_o = wo.z();
+        let cos_i = wi.z();
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return (Color::black(), 0.0);
+        }
+
+        let h = unit_vector(wo + wi);
+        let cos_h = h.z();
+        let cos_d = dot(wi, h);
+
+        let mut f = Color::black();
+        let mut pdf = 0.0;
+
+        // diffuse and sheen
+        let dielectric = (1.0 - params.metallic) * (1.0 - params.transmission);
+        if dielectric > 0.0 {
+            let sheen = params.sheen * (1.0 - cos_d).powi(5);
+            let diffuse = params.base_color / PI + sheen * Color::new(1.0, 1.0, 1.0);
+            f += dielectric * diffuse;
+        }
+        pdf += weights.0[0] * cos_i / PI;
+
+        // primary specular
+        let d = ggx_d(cos_h, params.alpha);
+        let g = smith_g1(cos_o, params.alpha) * smith_g1(cos_i, params.alpha);
+        let fresnel = schlick_color(Self::specular_f0(params), cos_d);
+        f += (d * g / (4.0 * cos_o * cos_i)) * fresnel;
+        pdf += weights.0[1] * d * cos_h / (4.0 * cos_d);
+
+        // clearcoat
+        if params.clearcoat > 0.0 {
+            let dr = gtr1_d(cos_h, params.clearcoat_alpha);
+            let gr = smith_g1(cos_o, 0.25) * smith_g1(cos_i, 0.25);
+            let fr = schlick(0.04, cos_d);
+            let coat = 0.25 * params.clearcoat * dr * gr * fr / (4.0 * cos_o * cos_i);
+            f += coat * Color::new(1.0, 1.0, 1.0);
+            pdf += weights.0[2] * dr * cos_h / (4.0 * cos_d);
+        }
+
+        (f * cos_i, pdf)
+    }
+
+    // smooth-ish dielectric transmission, the microfacet normal is drawn
+    // from the roughness distribution and then treated like a perfect interface
+    fn sample_transmission(
+        &self,
+        params: &PrincipledParams,
+        onb: &Onb,
+        wo: Vec3,
+        front_face: bool,
+    ) -> (Color, Vec3) {
+        let mut h = sample_ggx(params.alpha);
+        let refraction_ratio = if front_face { 1.0 / self.ior } else { self.ior };
+
+        let cos_theta = dot(wo, h).min(1.0);
+        if cos_theta <= 0.0 {
+            h = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let cos_theta = dot(wo, h).clamp(0.0, 1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let r0 = ((1.0 - refraction_ratio) / (1.0 + refraction_ratio)).powi(2);
+        let will_reflect = random_double() < schlick(r0, cos_theta);
+
+        let world_h = onb.local(h);
+        let world_wo = onb.local(wo);
+        if cannot_refract || will_reflect {
+            (Color::new(1.0, 1.0, 1.0), (-world_wo).reflect(world_h))
         } else {
-            None
+            let tint = params.base_color.to_array().map(Float::sqrt);
+            let tint = Color::new(tint[0], tint[1], tint[2]);
+            (tint, (-world_wo).refract(world_h, refraction_ratio))
+        }
+    }
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        let value = |v: Float| -> Arc<dyn Texture> { Arc::new(SolidColor::from_value(v)) };
+
+        Principled {
+            base_color: value(0.8),
+            metallic: value(0.0),
+            roughness: value(0.5),
+            specular: value(0.5),
+            sheen: value(0.0),
+            clearcoat: value(0.0),
+            clearcoat_gloss: value(1.0),
+            transmission: value(0.0),
+            emission: value(0.0),
+            ior: 1.5,
+        }
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let params = self.params(rec);
+        let onb = Onb::build_from_w(rec.normal);
+        let wo = onb.to_local(-unit_vector(r_in.direction()));
+
+        let weights = Self::lobe_weights(&params, wo.z().max(0.0));
+        let lobe = weights.pick(random_double())?;
+
+        // transmission is the only delta lobe, so it gets its own weight
+        // and doesn't take part in the combined pdf below
+        if lobe == 3 {
+            let (tint, direction) = self.sample_transmission(&params, &onb, wo, rec.front_face);
+            let attenuation = (1.0 - params.metallic) * params.transmission * tint / weights.0[3];
+            return Some((attenuation, rec.spawn_ray(direction)));
         }
+
+        let wi = match lobe {
+            0 => random_cosine_direction(),
+            1 => sample_ggx(params.alpha).reflect_about(wo),
+            _ => sample_gtr1(params.clearcoat_alpha).reflect_about(wo),
+        };
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let (f_cos, pdf) = Self::evaluate(&params, &weights, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((f_cos / pdf, rec.spawn_ray(onb.local(wi))))
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.emission.value(u, v, p)
+    }
+}
+
+fn schlick(f0: Float, cosine: Float) -> Float {
+    f0 + (1.0 - f0) * (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick_color(f0: Color, cosine: Float) -> Color {
+    let weight = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+    f0 + weight * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+fn ggx_d(cos_h: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    a2 / (PI * t * t)
+}
+
+// Berry distribution used by the clearcoat lobe
+fn gtr1_d(cos_h: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+fn smith_g1(cos_theta: Float, alpha: Float) -> Float {
+    let a2 = alpha * alpha;
+    let cos2 = cos_theta * cos_theta;
+    2.0 * cos_theta / (cos_theta + (a2 + cos2 - a2 * cos2).sqrt())
+}
+
+// microfacet normal about +z distributed as D(h) * cos(theta_h)
+fn sample_ggx(alpha: Float) -> Vec3 {
+    let r1 = random_double();
+    let r2 = random_double();
+
+    let phi = 2.0 * PI * r1;
+    let cos_theta = ((1.0 - r2) / (1.0 + (alpha * alpha - 1.0) * r2)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+fn sample_gtr1(alpha: Float) -> Vec3 {
+    let r1 = random_double();
+    let r2 = random_double();
+
+    let a2 = alpha * alpha;
+    let phi = 2.0 * PI * r1;
+    let cos_theta = ((1.0 - a2.powf(1.0 - r2)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::vec3::Normal3;
+
+    // the plain gray diffuse material tests put on their geometry
+    pub(crate) fn gray() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // a hit at the origin on a surface facing +z
+    fn record(material: &dyn Scatter) -> HitRecord<'_> {
+        HitRecord {
+            p: Point3::origin(),
+            p_error: Vec3::zero(),
+            normal: Normal3::new(0.0, 0.0, 1.0),
+            material,
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            front_face: true,
+            vertex_color: None,
+        }
+    }
+
+    fn random_hemisphere() -> Vec3 {
+        let d = unit_vector(random_in_unit_sphere());
+        Vec3::new(d.x(), d.y(), d.z().abs())
+    }
+
+    fn value(v: Float) -> Arc<dyn Texture> {
+        Arc::new(SolidColor::from_value(v))
+    }
+
+    #[test]
+    fn principled_white_furnace() {
+        // a smooth white metal reflects everything, and sampling matches
+        // f closely enough that every weight is about one
+        for roughness in [0.05, 0.2] {
+            let metal = Principled {
+                base_color: value(1.0),
+                metallic: value(1.0),
+                roughness: value(roughness),
+                ..Principled::default()
+            };
+            let rec = record(&metal);
+            let n = 20_000;
+            let mut sum = 0.0;
+            for _ in 0..n {
+                let wo = random_hemisphere();
+                let r_in = Ray::new(Point3::from(wo), -wo);
+                if let Some((weight, _)) = metal.scatter(&r_in, &rec) {
+                    sum += luminance(weight);
+                }
+            }
+            let average = sum / n as Float;
+            assert!(
+                (average - 1.0).abs() < 0.05,
+                "roughness {roughness}: {average}"
+            );
+        }
+    }
+
+    #[test]
+    fn lobe_choice_past_the_last_step() {
+        // weights that sum to just under one with the transmission lobe
+        // empty fall back to the last lobe that has weight
+        let weights = LobeWeights([0.25, 0.5, 0.2499999, 0.0]);
+        assert_eq!(weights.pick(0.1), Some(0));
+        assert_eq!(weights.pick(0.99999994), Some(2));
+        assert_eq!(LobeWeights([0.0; 4]).pick(0.5), None);
+    }
+
+    #[test]
+    fn principled_pdf_matches_sampling() {
+        // the reflected energy estimated from the material's own samples
+        // agrees with plain uniform sampling of f only if pdf is the
+        // density scatter really draws from
+        let material = Principled {
+            base_color: Arc::new(SolidColor::new(Color::new(0.6, 0.5, 0.4))),
+            metallic: value(0.3),
+            roughness: value(0.5),
+            sheen: value(0.3),
+            clearcoat: value(0.8),
+            clearcoat_gloss: value(0.0),
+            ..Principled::default()
+        };
+        let rec = record(&material);
+        let params = material.params(&rec);
+        let wo = unit_vector(Vec3::new(0.3, -0.2, 0.8));
+        let weights = Principled::lobe_weights(&params, wo.z());
+
+        let n = 200_000;
+        let r_in = Ray::new(Point3::from(wo), -wo);
+        let sampled: Float = (0..n)
+            .filter_map(|_| material.scatter(&r_in, &rec))
+            .map(|(weight, _)| luminance(weight))
+            .sum::<Float>()
+            / n as Float;
+        let (uniform, integral) = (0..n)
+            .map(|_| {
+                let (f_cos, pdf) = Principled::evaluate(&params, &weights, wo, random_hemisphere());
+                (luminance(f_cos), pdf)
+            })
+            .fold((0.0, 0.0), |(a, b), (f, pdf)| (a + f, b + pdf));
+        let uniform = uniform * 2.0 * PI / n as Float;
+        let integral = integral * 2.0 * PI / n as Float;
+
+        assert!(
+            (sampled - uniform).abs() < 0.03 * uniform,
+            "{sampled} vs {uniform}"
+        );
+        // what's left of the unit pdf went below the horizon
+        assert!(integral <= 1.02 && integral > 0.9, "{integral}");
+    }
+
+    #[test]
+    fn zero_thickness_film_matches_bare_interface() {
+        let film = ThinFilm::new(0.0, 1.8);
+        let with_film = film.reflectance(1.0, 1.0, 1.5, 550.0);
+        let bare = ((1.0 - 1.5) / (1.0 + 1.5) as Float).powi(2);
+
+        assert!((with_film - bare).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn quarter_wave_coating_is_anti_reflective() {
+        // n = sqrt(1.5) at a quarter wavelength cancels reflection entirely
+        let ior = (1.5 as Float).sqrt();
+        let film = ThinFilm::new(550.0 / (4.0 * ior), ior);
+
+        assert!(film.reflectance(1.0, 1.0, 1.5, 550.0) < 1.0e-5);
+        assert!(film.reflectance(1.0, 1.0, 1.5, 450.0) > 1.0e-4);
     }
 }
@@ -0,0 +1,631 @@
+// Triangle meshes. The vertex data lives once in a TriangleMesh and every
+// triangle is its own small hittable pointing into it, so a mesh goes into
+// a bvh like any other list of primitives.
+
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::csg::{CsgError, Solid, Span};
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, HittableList, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utils::{gamma, Float};
+use crate::vec3::{cross, dot, unit_vector, Normal3, Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    // one per position, or empty for flat shading
+    pub normals: Vec<Normal3>,
+    // one per position, or empty for the same mapping on every triangle
+    pub uvs: Vec<[Float; 2]>,
+    // one per position or empty, interpolated into the hit record
+    pub colors: Vec<Color>,
+    // counter-clockwise seen from the outside
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[u32; 3]>) -> TriangleMesh {
+        TriangleMesh {
+            positions,
+            indices,
+            ..TriangleMesh::default()
+        }
+    }
+
+    pub fn with_normals(self, normals: Vec<Normal3>) -> TriangleMesh {
+        assert_eq!(normals.len(), self.positions.len());
+        TriangleMesh { normals, ..self }
+    }
+
+    pub fn with_uvs(self, uvs: Vec<[Float; 2]>) -> TriangleMesh {
+        assert_eq!(uvs.len(), self.positions.len());
+        TriangleMesh { uvs, ..self }
+    }
+
+    pub fn with_colors(self, colors: Vec<Color>) -> TriangleMesh {
+        assert_eq!(colors.len(), self.positions.len());
+        TriangleMesh { colors, ..self }
+    }
+
+    // smooth vertex normals, the area weighted average of the normals of
+    // the triangles around each vertex
+    pub fn with_smooth_normals(self) -> TriangleMesh {
+        let mut sums = vec![Vec3::zero(); self.positions.len()];
+        for &[i0, i1, i2] in &self.indices {
+            let [p0, p1, p2] = [i0, i1, i2].map(|i| self.positions[i as usize]);
+            // twice the area along the normal
+            let n = cross(p1 - p0, p2 - p0);
+            for i in [i0, i1, i2] {
+                sums[i as usize] += n;
+            }
+        }
+        let normals = sums
+            .into_iter()
+            .map(|n| {
+                Normal3::from(if n.near_zero() {
+                    Vec3::new(0.0, 1.0, 0.0)
+                } else {
+                    unit_vector(n)
+                })
+            })
+            .collect();
+        TriangleMesh { normals, ..self }
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        self.positions.iter().fold(Aabb::EMPTY, |bbox, &p| {
+            Aabb::enclosing(bbox, Aabb::from_points(p, p))
+        })
+    }
+
+    // every edge is shared by exactly two triangles running along it in
+    // opposite directions, counting vertices at the same position as one
+    pub fn is_closed(&self) -> bool {
+        let (vertex, _) = weld(&self.positions);
+        let mut edges: HashMap<[u32; 2], u32> = HashMap::new();
+        for triangle in &self.indices {
+            let t = triangle.map(|i| vertex[i as usize]);
+            for k in 0..3 {
+                *edges.entry([t[k], t[(k + 1) % 3]]).or_default() += 1;
+            }
+        }
+        edges
+            .iter()
+            .all(|(&[a, b], &count)| count == 1 && edges.get(&[b, a]) == Some(&1))
+    }
+
+    // the vertex colors as a texture, if there are any
+    pub fn color_texture(&self) -> Option<VertexColors> {
+        (!self.colors.is_empty()).then(|| VertexColors::new(Color::black()))
+    }
+
+    // every triangle as a hittable of its own, to build a bvh over
+    pub fn triangles(self, material: Arc<dyn Scatter>) -> HittableList {
+        let mut list = HittableList::default();
+        for triangle in self.split(material) {
+            list.add(triangle);
+        }
+        list
+    }
+
+    fn split(self, material: Arc<dyn Scatter>) -> Vec<Arc<Triangle>> {
+        let shared = Arc::new(Shared {
+            mesh: self,
+            material,
+        });
+        (0..shared.mesh.indices.len())
+            .map(|index| {
+                Arc::new(Triangle {
+                    shared: shared.clone(),
+                    index,
+                })
+            })
+            .collect()
+    }
+}
+
+struct Shared {
+    mesh: TriangleMesh,
+    material: Arc<dyn Scatter>,
+}
+
+pub struct Triangle {
+    shared: Arc<Shared>,
+    index: usize,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [usize; 3] {
+        self.shared.mesh.indices[self.index].map(|i| i as usize)
+    }
+
+    fn positions(&self) -> [Point3; 3] {
+        self.vertices().map(|i| self.shared.mesh.positions[i])
+    }
+}
+
+impl Hittable for Triangle {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let [p0, p1, p2] = self.positions();
+        let (t, b1, b2) = hit_triangle(r, p0, p1, p2)?;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let hit = Hit {
+            u: b1,
+            v: b2,
+            ..Hit::new(t, self)
+        };
+        alpha_test(&*self.shared.material, || {
+            let rec = self.interaction(r, &hit);
+            (rec.u, rec.v, rec.p)
+        })
+        .then_some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [p0, p1, p2] = self.positions();
+        // pad so triangles in an axis plane don't give a flat box
+        let bbox = Aabb::enclosing(Aabb::from_points(p0, p1), Aabb::from_points(p2, p2));
+        let pad = Vec3::new(1.0e-4, 1.0e-4, 1.0e-4);
+        Aabb::enclosing(bbox, Aabb::from_points(p0 - pad, p0 + pad))
+    }
+}
+
+impl Primitive for Triangle {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let mesh = &self.shared.mesh;
+        let vertices = self.vertices();
+        let [p0, p1, p2] = self.positions();
+        let (b1, b2) = (hit.u, hit.v);
+        let b0 = 1.0 - b1 - b2;
+
+        // barycentric interpolation is far more accurate than r.at(t)
+        let terms = [
+            b0 * Vec3::from(p0),
+            b1 * Vec3::from(p1),
+            b2 * Vec3::from(p2),
+        ];
+        let p = Point3::from(terms[0] + terms[1] + terms[2]);
+        let p_error = gamma(7) * (terms[0].abs() + terms[1].abs() + terms[2].abs());
+
+        let [uv0, uv1, uv2] = if mesh.uvs.is_empty() {
+            [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]
+        } else {
+            vertices.map(|i| mesh.uvs[i])
+        };
+        let u = b0 * uv0[0] + b1 * uv1[0] + b2 * uv2[0];
+        let v = b0 * uv0[1] + b1 * uv1[1] + b2 * uv2[1];
+
+        let geometric = unit_vector(cross(p1 - p0, p2 - p0));
+        let outward = if mesh.normals.is_empty() {
+            geometric
+        } else {
+            let [n0, n1, n2] = vertices.map(|i| Vec3::from(mesh.normals[i]));
+            let n = b0 * n0 + b1 * n1 + b2 * n2;
+            if n.near_zero() {
+                geometric
+            } else {
+                unit_vector(n)
+            }
+        };
+
+        // solve for the tangents from the uv deltas along the edges
+        let (du02, dv02) = (uv0[0] - uv2[0], uv0[1] - uv2[1]);
+        let (du12, dv12) = (uv1[0] - uv2[0], uv1[1] - uv2[1]);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let determinant = du02 * dv12 - dv02 * du12;
+        let (dpdu, dpdv) = if determinant.abs() < 1.0e-9 {
+            let frame = Onb::build_from_w(outward);
+            (frame.u(), frame.v())
+        } else {
+            let inv = 1.0 / determinant;
+            (
+                (dv12 * dp02 - dv02 * dp12) * inv,
+                (du02 * dp12 - du12 * dp02) * inv,
+            )
+        };
+
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error,
+            material: &*self.shared.material,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: (!mesh.colors.is_empty()).then(|| {
+                let [c0, c1, c2] = vertices.map(|i| mesh.colors[i]);
+                b0 * c0 + b1 * c1 + b2 * c2
+            }),
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+// A closed mesh as a csg solid. Every crossing of the line is found by
+// asking the bvh for the next hit past the one before, and the winding of
+// the triangle hit says whether the line enters or leaves there.
+pub struct ClosedMesh {
+    bvh: Bvh4,
+    // the outward facing normal of each triangle, by its address
+    normals: HashMap<usize, Vec3>,
+}
+
+impl ClosedMesh {
+    pub fn new(mesh: TriangleMesh, material: Arc<dyn Scatter>) -> Result<ClosedMesh, CsgError> {
+        if !mesh.is_closed() {
+            return Err(CsgError::NotClosed);
+        }
+        let mut list = HittableList::default();
+        let mut normals = HashMap::new();
+        for triangle in mesh.split(material) {
+            let [p0, p1, p2] = triangle.positions();
+            normals.insert(address(&*triangle), cross(p1 - p0, p2 - p0));
+            list.add(triangle);
+        }
+        Ok(ClosedMesh {
+            bvh: Bvh4::new(&list),
+            normals,
+        })
+    }
+}
+
+fn address(primitive: &dyn Primitive) -> usize {
+    primitive as *const dyn Primitive as *const () as usize
+}
+
+impl Solid for ClosedMesh {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        let mut spans = Vec::new();
+        let mut enter = None;
+        // how many layers deep the line is, in case it crosses a seam
+        // the other way or two overlapping parts
+        let mut depth = 0;
+        let mut previous: Option<(Float, bool)> = None;
+        let mut t = -Float::INFINITY;
+        while let Some(hit) = self.bvh.intersect(r, Interval::new(t, Float::INFINITY)) {
+            t = hit.t;
+            let entering = dot(self.normals[&address(hit.primitive)], r.direction()) < 0.0;
+            // a line through an edge hits both triangles there
+            let seam = previous.is_some_and(|(last, was_entering)| {
+                was_entering == entering && t - last <= 1.0e-5 * t.abs().max(1.0)
+            });
+            previous = Some((t, entering));
+            if seam {
+                continue;
+            }
+
+            if entering {
+                depth += 1;
+                if depth == 1 {
+                    enter = Some(hit);
+                }
+            } else if depth > 0 {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some(enter) = enter.take() {
+                        spans.push(Span { enter, exit: hit });
+                    }
+                }
+            }
+        }
+        spans
+    }
+}
+
+impl Hittable for ClosedMesh {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        self.bvh.intersect(r, ray_t)
+    }
+
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        self.bvh.occluded(r, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+// one index per distinct position for every vertex, and how many there are
+pub(crate) fn weld(positions: &[Point3]) -> (Vec<u32>, usize) {
+    let mut welded: HashMap<[u64; 3], u32> = HashMap::new();
+    let vertex = positions
+        .iter()
+        .map(|p| {
+            #[allow(clippy::unnecessary_cast)] // Float may already be f64
+            let key = p.to_array().map(|x| (x as f64).to_bits());
+            let next = welded.len() as u32;
+            *welded.entry(key).or_insert(next)
+        })
+        .collect();
+    (vertex, welded.len())
+}
+
+// Möller-Trumbore, the distance along r and the barycentrics of p1 and p2
+pub(crate) fn hit_triangle(
+    r: Ray,
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+) -> Option<(Float, Float, Float)> {
+    let (e1, e2) = (p1 - p0, p2 - p0);
+    let pvec = cross(r.direction(), e2);
+    let det = dot(e1, pvec);
+    if det == 0.0 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.origin() - p0;
+    let b1 = dot(tvec, pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = cross(tvec, e1);
+    let b2 = dot(r.direction(), qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    Some((dot(e2, qvec) * inv_det, b1, b2))
+}
+
+// Vertex colors as a texture. Triangles interpolate their corner colors
+// into the hit record, so this only reads them back out, and surfaces
+// without vertex colors get the fallback.
+pub struct VertexColors {
+    fallback: Color,
+}
+
+impl VertexColors {
+    pub fn new(fallback: Color) -> VertexColors {
+        VertexColors { fallback }
+    }
+}
+
+impl Texture for VertexColors {
+    fn value(&self, _u: Float, _v: Float, _p: Point3) -> Color {
+        self.fallback
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color.unwrap_or(self.fallback)
+    }
+}
+
+// Polygons sharing vertices, the input to subdivision. Faces list their
+// vertices counter-clockwise seen from the outside, edges can be marked
+// as creases that subdivision keeps sharp.
+#[derive(Clone, Default)]
+pub struct PolyMesh {
+    pub positions: Vec<Point3>,
+    pub faces: Vec<Vec<u32>>,
+    // one per position, or empty
+    pub uvs: Vec<[Float; 2]>,
+    // edges by their two vertices in either order, with their sharpness:
+    // the number of subdivision levels they stay sharp for
+    pub creases: Vec<([u32; 2], Float)>,
+}
+
+impl PolyMesh {
+    pub fn new(positions: Vec<Point3>, faces: Vec<Vec<u32>>) -> PolyMesh {
+        PolyMesh {
+            positions,
+            faces,
+            uvs: Vec::new(),
+            creases: Vec::new(),
+        }
+    }
+
+    pub fn with_uvs(self, uvs: Vec<[Float; 2]>) -> PolyMesh {
+        assert_eq!(uvs.len(), self.positions.len());
+        PolyMesh { uvs, ..self }
+    }
+
+    // Float::INFINITY for an edge that stays sharp at any level
+    pub fn with_crease(mut self, a: u32, b: u32, sharpness: Float) -> PolyMesh {
+        self.creases.push(([a, b], sharpness));
+        self
+    }
+
+    // fan triangulated, with normals averaged between the faces around a
+    // vertex except across creases, where the vertex is split instead
+    pub fn to_triangle_mesh(&self) -> TriangleMesh {
+        let triangles: Vec<[u32; 3]> = self
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(|i| [face[0], face[i], face[i + 1]]))
+            .collect();
+
+        let creased: HashSet<[u32; 2]> = self
+            .creases
+            .iter()
+            .filter(|&&(_, sharpness)| sharpness > 0.0)
+            .map(|&([a, b], _)| [a.min(b), a.max(b)])
+            .collect();
+
+        // corners are (triangle, vertex) pairs, joined into smoothing
+        // groups across every uncreased edge two triangles share
+        let mut group: Vec<usize> = (0..3 * triangles.len()).collect();
+        fn find(group: &mut [usize], mut i: usize) -> usize {
+            while group[i] != i {
+                group[i] = group[group[i]];
+                i = group[i];
+            }
+            i
+        }
+        let mut edges: HashMap<[u32; 2], Vec<usize>> = HashMap::new();
+        for (t, tri) in triangles.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                edges.entry([a.min(b), a.max(b)]).or_default().push(t);
+            }
+        }
+        let corner =
+            |t: usize, vertex: u32| 3 * t + triangles[t].iter().position(|&i| i == vertex).unwrap();
+        for (edge, sides) in &edges {
+            if creased.contains(edge) {
+                continue;
+            }
+            for pair in sides.windows(2) {
+                for vertex in *edge {
+                    let r0 = find(&mut group, corner(pair[0], vertex));
+                    let r1 = find(&mut group, corner(pair[1], vertex));
+                    group[r0] = r1;
+                }
+            }
+        }
+
+        // one output vertex per smoothing group
+        let mut sums: Vec<Vec3> = Vec::new();
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut slot = HashMap::new();
+        let mut indices = Vec::with_capacity(triangles.len());
+        for (t, tri) in triangles.iter().enumerate() {
+            let [p0, p1, p2] = tri.map(|i| self.positions[i as usize]);
+            let n = cross(p1 - p0, p2 - p0);
+            let mut out = [0; 3];
+            for k in 0..3 {
+                let root = find(&mut group, 3 * t + k);
+                let index = *slot.entry(root).or_insert_with(|| {
+                    positions.push(self.positions[tri[k] as usize]);
+                    if !self.uvs.is_empty() {
+                        uvs.push(self.uvs[tri[k] as usize]);
+                    }
+                    sums.push(Vec3::zero());
+                    positions.len() - 1
+                });
+                sums[index] += n;
+                out[k] = index as u32;
+            }
+            indices.push(out);
+        }
+        let normals = sums
+            .into_iter()
+            .map(|n| {
+                Normal3::from(if n.near_zero() {
+                    Vec3::new(0.0, 1.0, 0.0)
+                } else {
+                    unit_vector(n)
+                })
+            })
+            .collect();
+
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            indices,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csg::Csg;
+    use crate::material::tests::gray;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn triangle_hits() {
+        let mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        )
+        .with_uvs(vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        let list = mesh.triangles(gray());
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(0.25, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = list.hit(r, ray_t).unwrap();
+        assert!((rec.t - 2.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((rec.u - 0.25).abs() < 1.0e-5 && (rec.v - 0.5).abs() < 1.0e-5);
+        assert!((rec.dpdu - Vec3::new(1.0, 0.0, 0.0)).length() < 1.0e-5);
+        assert!((rec.dpdv - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+
+        let r = Ray::new(Point3::new(0.75, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(list.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn creases_split_normals() {
+        // two quads folded along the x axis
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(1.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        let faces = vec![vec![0, 1, 2, 3], vec![5, 4, 1, 0]];
+        let smooth = PolyMesh::new(positions.clone(), faces.clone()).to_triangle_mesh();
+        assert_eq!(smooth.positions.len(), 6);
+
+        let sharp = PolyMesh::new(positions, faces)
+            .with_crease(0, 1, Float::INFINITY)
+            .to_triangle_mesh();
+        // the two fold vertices are split in two
+        assert_eq!(sharp.positions.len(), 8);
+        for (p, n) in sharp.positions.iter().zip(&sharp.normals) {
+            if p.y() == 0.0 && p.z() == 0.0 {
+                let n = Vec3::from(*n);
+                assert!(n.z().abs() > 0.999 || n.y().abs() > 0.999);
+            }
+        }
+    }
+
+    #[test]
+    fn closed_mesh_in_csg() {
+        let cube = crate::subdivision::tests::cube().to_triangle_mesh();
+        let solid = Arc::new(ClosedMesh::new(cube.clone(), gray()).unwrap());
+        let ball = Arc::new(Sphere::new(Point3::origin(), 0.5, gray()));
+        let hollow = Csg::difference(solid.clone(), ball).unwrap();
+
+        // through the middle of a face, on the diagonal the two triangles
+        // of the face share
+        let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let spans: Vec<_> = solid
+            .spans(r)
+            .iter()
+            .map(|s| (s.enter.t, s.exit.t))
+            .collect();
+        assert_eq!(spans, vec![(4.0, 6.0)]);
+        let spans: Vec<_> = hollow
+            .spans(r)
+            .iter()
+            .map(|s| (s.enter.t, s.exit.t))
+            .collect();
+        assert_eq!(spans, vec![(4.0, 4.5), (5.5, 6.0)]);
+        let r = Ray::new(Point3::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(solid.spans(r).is_empty());
+
+        let mut open = cube;
+        open.indices.pop();
+        assert_eq!(
+            ClosedMesh::new(open, gray()).err(),
+            Some(CsgError::NotClosed)
+        );
+    }
+}
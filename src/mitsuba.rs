@@ -0,0 +1,843 @@
+// Mitsuba scene files, version 3 and the older camelCase ones. Read are
+// the perspective and thin lens sensors with their film and sampler,
+// obj (with their mtl materials unless given a bsdf), ply, sphere,
+// rectangle and cube shapes, diffuse, conductor, roughconductor,
+// dielectric and roughdielectric bsdfs (twosided ones unwrapped), area
+// emitters, and constant and png or jpeg envmap emitters as the
+// background. bsdfs can be shared by id, and <default> values fill in
+// $name references. Other plugins, point emitters among them, are skipped
+// with a warning, textures are not read.
+
+use crate::camera::{Background, Camera};
+use crate::color::Color;
+use crate::cuboid::Cuboid;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::{Image, ImageTexture};
+use crate::instance::Instance;
+use crate::material::{conductor_f0, metal_ior, Dielectric, Lambertian, Principled, Scatter};
+use crate::mesh::TriangleMesh;
+use crate::obj::{load_obj, load_obj_scene};
+use crate::ply::load_ply;
+use crate::spectrum::blackbody_rgb;
+use crate::sphere::Sphere;
+use crate::texture::{SolidColor, Texture};
+use crate::transform::Transform;
+use crate::utils::{degrees_to_radians, Float};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+use crate::xml::Element;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Mitsuba's named indices of refraction
+const IORS: [(&str, Float); 23] = [
+    ("vacuum", 1.0),
+    ("helium", 1.000_036),
+    ("hydrogen", 1.000_132),
+    ("air", 1.000_277),
+    ("carbon dioxide", 1.000_45),
+    ("water", 1.333),
+    ("acetone", 1.36),
+    ("ethanol", 1.361),
+    ("carbon tetrachloride", 1.461),
+    ("glycerol", 1.4729),
+    ("benzene", 1.501),
+    ("silicone oil", 1.520_45),
+    ("bromine", 1.661),
+    ("water ice", 1.31),
+    ("fused quartz", 1.458),
+    ("pyrex", 1.47),
+    ("acrylic glass", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("sodium chloride", 1.544),
+    ("amber", 1.55),
+    ("pet", 1.575),
+    ("diamond", 2.419),
+];
+
+// unlimited in Mitsuba, which this renderer can't do
+const DEFAULT_MAX_DEPTH: i32 = 50;
+
+pub struct Mitsuba {
+    // meshes come as one bvh each, the list still wants one around it
+    pub world: HittableList,
+    // everything the file sets, image size, samples and depth included
+    pub camera: Camera,
+    pub warnings: Vec<String>,
+}
+
+pub fn load_mitsuba(path: impl AsRef<Path>) -> io::Result<Mitsuba> {
+    let path = path.as_ref();
+    parse_mitsuba(
+        &fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new(".")),
+    )
+}
+
+// mesh and image files are looked up in `base`
+pub fn parse_mitsuba(text: &str, base: &Path) -> io::Result<Mitsuba> {
+    let mut root = Element::parse(text)?;
+    if root.name != "scene" {
+        return Err(invalid("not a mitsuba scene"));
+    }
+    let mut defaults: Vec<(String, String)> = root
+        .children
+        .iter()
+        .filter(|child| child.name == "default")
+        .filter_map(|default| {
+            let name = default.attribute("name")?;
+            Some((format!("${name}"), default.attribute("value")?.to_string()))
+        })
+        .collect();
+    // $resx has to go before $res
+    defaults.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    prepare(&mut root, &defaults);
+
+    let mut loader = Loader {
+        base,
+        bsdfs: HashMap::new(),
+        mirror: Transform::identity(),
+        background: Background::Solid(Color::black()),
+        world: HittableList::default(),
+        warnings: Vec::new(),
+    };
+    // whether the scene gets mirrored depends on the sensor, so it's first
+    let mut sensors = root.children.iter().filter(|child| child.name == "sensor");
+    let mut camera = loader.camera(sensors.next())?;
+    if sensors.next().is_some() {
+        loader.warn("only the first sensor is used".to_string());
+    }
+
+    for child in &root.children {
+        match child.name.as_str() {
+            "default" | "sensor" => {}
+            "integrator" => {
+                let max_depth = float(child, "max_depth", -1.0)?;
+                if max_depth >= 0.0 {
+                    camera.max_depth = max_depth as i32;
+                }
+            }
+            "shape" => loader.shape(child)?,
+            "bsdf" => {
+                loader.bsdf(child)?;
+            }
+            "emitter" => loader.emitter(child)?,
+            name => loader.warn(format!("<{name}> skipped: not supported")),
+        }
+    }
+
+    camera.background = loader.background;
+    Ok(Mitsuba {
+        world: loader.world,
+        camera,
+        warnings: loader.warnings,
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// fills in $name references and renames the camelCase properties of
+// older versions, toWorld becomes to_world and intIOR int_ior
+fn prepare(element: &mut Element, defaults: &[(String, String)]) {
+    for (key, value) in element.attributes.iter_mut() {
+        for (name, default) in defaults {
+            *value = value.replace(name, default);
+        }
+        if key == "name" {
+            *value = snake_case(value);
+        }
+    }
+    for child in element.children.iter_mut() {
+        prepare(child, defaults);
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut after_lowercase = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && after_lowercase {
+            out.push('_');
+        }
+        after_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+fn number(text: &str) -> io::Result<Float> {
+    text.trim()
+        .parse()
+        .map_err(|_| invalid(&format!("bad number {text}")))
+}
+
+// separated by commas, whitespace or both
+fn numbers(text: &str) -> io::Result<Vec<Float>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(number)
+        .collect()
+}
+
+fn value(element: &Element) -> io::Result<&str> {
+    element
+        .attribute("value")
+        .ok_or_else(|| invalid(&format!("<{}> without a value", element.name)))
+}
+
+fn triple(text: &str) -> io::Result<Vec3> {
+    match numbers(text)?[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(invalid(&format!("expected three numbers in {text}"))),
+    }
+}
+
+// a point or vector given by its value or its x, y and z
+fn vector(element: &Element, default: Float) -> io::Result<Vec3> {
+    if let Some(value) = element.attribute("value") {
+        return triple(value);
+    }
+    let axis = |name| element.attribute(name).map_or(Ok(default), number);
+    Ok(Vec3::new(axis("x")?, axis("y")?, axis("z")?))
+}
+
+// the child that sets property `name`
+fn property<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    element
+        .children
+        .iter()
+        .find(|child| child.attribute("name") == Some(name))
+}
+
+fn child<'a>(element: &'a Element, tag: &str) -> Option<&'a Element> {
+    element.children.iter().find(|child| child.name == tag)
+}
+
+fn float(element: &Element, name: &str, default: Float) -> io::Result<Float> {
+    match property(element, name) {
+        Some(p) if p.name == "float" || p.name == "integer" => number(value(p)?),
+        Some(p) => Err(invalid(&format!("{name} given as <{}>", p.name))),
+        None => Ok(default),
+    }
+}
+
+fn string<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+    property(element, name)
+        .filter(|p| p.name == "string")
+        .and_then(|p| p.attribute("value"))
+}
+
+fn boolean(element: &Element, name: &str) -> bool {
+    property(element, name)
+        .filter(|p| p.name == "boolean")
+        .and_then(|p| p.attribute("value"))
+        == Some("true")
+}
+
+fn to_world(element: &Element) -> io::Result<Transform> {
+    property(element, "to_world").map_or(Ok(Transform::identity()), transform)
+}
+
+// every operation applies after the ones before it
+fn transform(element: &Element) -> io::Result<Transform> {
+    let mut t = Transform::identity();
+    for op in &element.children {
+        let attribute = |name, default| op.attribute(name).map_or(Ok(default), number);
+        let step = match op.name.as_str() {
+            "translate" => Transform::translate(vector(op, 0.0)?),
+            "scale" => match op.attribute("value").map(numbers).transpose()?.as_deref() {
+                Some(&[s]) => Transform::scale(s, s, s),
+                Some(&[x, y, z]) => Transform::scale(x, y, z),
+                Some(_) => return Err(invalid("scale takes one or three numbers")),
+                None => {
+                    let [x, y, z] = ["x", "y", "z"].map(|axis| attribute(axis, 1.0));
+                    Transform::scale(x?, y?, z?)
+                }
+            },
+            "rotate" => Transform::rotate(attribute("angle", 0.0)?, vector(op, 0.0)?),
+            "matrix" => {
+                // row by row
+                let m: [Float; 16] = numbers(value(op)?)?
+                    .try_into()
+                    .map_err(|_| invalid("matrix takes 16 numbers"))?;
+                Transform::from_matrix(std::array::from_fn(|i| {
+                    std::array::from_fn(|j| m[4 * i + j])
+                }))
+                .ok_or_else(|| invalid("singular matrix"))?
+            }
+            "lookat" => {
+                let point = |name| {
+                    op.attribute(name)
+                        .ok_or_else(|| invalid(&format!("lookat without {name}")))
+                        .and_then(triple)
+                };
+                let up = op
+                    .attribute("up")
+                    .map_or(Ok(Vec3::new(0.0, 1.0, 0.0)), triple)?;
+                Transform::look_at(
+                    Point3::from(point("origin")?),
+                    Point3::from(point("target")?),
+                    up,
+                )
+                .ok_or_else(|| invalid("degenerate lookat"))?
+            }
+            name => return Err(invalid(&format!("unknown transform <{name}>"))),
+        };
+        t = step * t;
+    }
+    Ok(t)
+}
+
+struct Loader<'a> {
+    base: &'a Path,
+    bsdfs: HashMap<String, Arc<dyn Scatter>>,
+    // Mitsuba's world to this one, a reflection when the sensor has one
+    // that the camera here couldn't reproduce
+    mirror: Transform,
+    // black unless there's an environment emitter, as in Mitsuba
+    background: Background,
+    world: HittableList,
+    warnings: Vec<String>,
+}
+
+impl Loader<'_> {
+    // a message for every kind of problem, not every time it comes up
+    fn warn(&mut self, message: String) {
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    fn camera(&mut self, sensor: Option<&Element>) -> io::Result<Camera> {
+        let empty = Element::default();
+        let sensor = sensor.unwrap_or(&empty);
+        let kind = sensor.attribute("type").unwrap_or("perspective");
+        if kind != "perspective" && kind != "thinlens" {
+            self.warn(format!("\"{kind}\" sensor read as a perspective one"));
+        }
+
+        let mut camera = Camera::default();
+        let film = child(sensor, "film").unwrap_or(&empty);
+        let (width, height) = (float(film, "width", 768.0)?, float(film, "height", 576.0)?);
+        camera.image_width = width;
+        camera.aspect_ratio = width / height;
+        let sampler = child(sensor, "sampler").unwrap_or(&empty);
+        camera.samples_per_pixel = float(sampler, "sample_count", 4.0)? as i32;
+        camera.max_depth = DEFAULT_MAX_DEPTH;
+
+        // a focal length is for 35mm film and measured across the diagonal
+        let (fov, axis) = match property(sensor, "fov") {
+            Some(_) => (
+                float(sensor, "fov", 90.0)?,
+                string(sensor, "fov_axis").unwrap_or("x"),
+            ),
+            None => {
+                let focal_length = string(sensor, "focal_length").unwrap_or("50mm");
+                let millimeters = number(focal_length.trim_end_matches("mm"))?;
+                let diagonal = (36.0 as Float).hypot(24.0);
+                (
+                    (diagonal / (2.0 * millimeters)).atan().to_degrees() * 2.0,
+                    "diagonal",
+                )
+            }
+        };
+        let tangent = degrees_to_radians(fov / 2.0).tan();
+        let aspect = camera.aspect_ratio;
+        let vertical = match axis {
+            "x" => tangent / aspect,
+            "y" => tangent,
+            "diagonal" => tangent / aspect.hypot(1.0),
+            "smaller" if aspect >= 1.0 => tangent,
+            "smaller" => tangent / aspect,
+            "larger" if aspect >= 1.0 => tangent / aspect,
+            "larger" => tangent,
+            _ => return Err(invalid(&format!("unknown fov axis {axis}"))),
+        };
+        camera.vfov = 2.0 * vertical.atan().to_degrees();
+
+        // the sensor looks down +z with +y up and +x to the left, the way
+        // the camera here does, unless its transform has a reflection
+        let to_world = to_world(sensor)?;
+        if to_world.swaps_handedness() {
+            self.mirror = Transform::scale(-1.0, 1.0, 1.0);
+        }
+        let to_world = self.mirror * to_world;
+        camera.lookfrom = to_world.point(Point3::origin());
+        camera.lookat = camera.lookfrom + unit_vector(to_world.vector(Vec3::new(0.0, 0.0, 1.0)));
+        camera.vup = to_world.vector(Vec3::new(0.0, 1.0, 0.0));
+
+        camera.focus_dist = 1.0;
+        let aperture = float(sensor, "aperture_radius", 0.0)?;
+        let focus_distance = float(sensor, "focus_distance", 0.0)?;
+        if aperture > 0.0 && focus_distance > 0.0 {
+            camera.focus_dist = focus_distance;
+            camera.defocus_angle = 2.0 * (aperture / focus_distance).atan().to_degrees();
+        }
+        Ok(camera)
+    }
+
+    // an rgb, spectrum, blackbody or number property, None when it's
+    // missing or given as a texture
+    fn color(&mut self, element: &Element, name: &str) -> io::Result<Option<Color>> {
+        let Some(p) = property(element, name) else {
+            return Ok(None);
+        };
+        let gray = |v: Float| Some(Color::new(v, v, v));
+        match p.name.as_str() {
+            "spectrum" if p.attribute("type") == Some("blackbody") => {
+                Ok(Some(blackbody_rgb(float(p, "temperature", 6504.0)?)))
+            }
+            "blackbody" => {
+                let kelvin = p.attribute("temperature").unwrap_or("6504");
+                let scale = p.attribute("scale").map_or(Ok(1.0), number)?;
+                Ok(Some(
+                    scale * blackbody_rgb(number(kelvin.trim_end_matches('K'))?),
+                ))
+            }
+            "spectrum" if value(p)?.contains(':') => {
+                // wavelength:value pairs
+                self.warn(format!("sampled spectrum \"{name}\" read as its average"));
+                let values = value(p)?
+                    .split(',')
+                    .map(|pair| number(pair.split(':').nth(1).unwrap_or("")))
+                    .collect::<io::Result<Vec<Float>>>()?;
+                Ok(gray(values.iter().sum::<Float>() / values.len() as Float))
+            }
+            "rgb" | "spectrum" | "float" => match numbers(value(p)?)?[..] {
+                [v] => Ok(gray(v)),
+                [r, g, b] => Ok(Some(Color::new(r, g, b))),
+                _ => Err(invalid(&format!("bad color for {name}"))),
+            },
+            tag => {
+                self.warn(format!("<{tag}> \"{name}\" skipped: using its default"));
+                Ok(None)
+            }
+        }
+    }
+
+    fn ior(&mut self, element: &Element, name: &str, default: Float) -> io::Result<Float> {
+        match string(element, name) {
+            Some(material) => match IORS.iter().find(|(m, _)| *m == material) {
+                Some(&(_, ior)) => Ok(ior),
+                None => {
+                    self.warn(format!("ior \"{material}\" unknown, using {default}"));
+                    Ok(default)
+                }
+            },
+            None => float(element, name, default),
+        }
+    }
+
+    // the alpha of a rough bsdf, anisotropic ones are averaged
+    fn alpha(element: &Element) -> io::Result<Float> {
+        let alpha = float(element, "alpha", 0.1)?;
+        Ok(0.5 * (float(element, "alpha_u", alpha)? + float(element, "alpha_v", alpha)?))
+    }
+
+    // a bsdf inside a shape or a twosided, given in place or by reference
+    fn nested_bsdf(&mut self, element: &Element) -> io::Result<Option<Arc<dyn Scatter>>> {
+        for child in &element.children {
+            match child.name.as_str() {
+                "bsdf" => return self.bsdf(child).map(Some),
+                "ref" if matches!(child.attribute("name"), None | Some("bsdf")) => {
+                    let id = child.attribute("id").unwrap_or_default();
+                    match self.bsdfs.get(id) {
+                        Some(bsdf) => return Ok(Some(bsdf.clone())),
+                        None => self.warn(format!("bsdf \"{id}\" doesn't exist")),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn bsdf(&mut self, element: &Element) -> io::Result<Arc<dyn Scatter>> {
+        let default =
+            || -> Arc<dyn Scatter> { Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))) };
+        let kind = element.attribute("type").unwrap_or_default();
+        // Principled squares its roughness to get the alpha
+        let scatter: Arc<dyn Scatter> = match kind {
+            "twosided" | "mask" | "bumpmap" | "normalmap" => {
+                if kind != "twosided" {
+                    self.warn(format!("\"{kind}\" bsdf read as the bsdf inside it"));
+                }
+                self.nested_bsdf(element)?.unwrap_or_else(default)
+            }
+            "diffuse" => {
+                let reflectance = self.color(element, "reflectance")?;
+                Arc::new(Lambertian::new(
+                    reflectance.unwrap_or(Color::new(0.5, 0.5, 0.5)),
+                ))
+            }
+            "conductor" | "roughconductor" => {
+                let f0 = match string(element, "material") {
+                    // a perfect mirror, Mitsuba's default
+                    Some("none") => Color::new(1.0, 1.0, 1.0),
+                    Some(symbol) => match metal_ior(symbol) {
+                        Some((eta, k)) => conductor_f0(eta, k),
+                        None => {
+                            self.warn(format!("metal \"{symbol}\" unknown, using a mirror"));
+                            Color::new(1.0, 1.0, 1.0)
+                        }
+                    },
+                    None => {
+                        let eta = self.color(element, "eta")?.unwrap_or(Color::black());
+                        let k = self.color(element, "k")?.unwrap_or(Color::black());
+                        conductor_f0(eta, k)
+                    }
+                };
+                let tint = self
+                    .color(element, "specular_reflectance")?
+                    .unwrap_or(Color::new(1.0, 1.0, 1.0));
+                let alpha = match kind {
+                    "roughconductor" => Self::alpha(element)?,
+                    _ => 0.0,
+                };
+                Arc::new(Principled {
+                    metallic: Arc::new(SolidColor::from_value(1.0)),
+                    roughness: Arc::new(SolidColor::from_value(alpha.sqrt())),
+                    ..Principled::new(tint * f0)
+                })
+            }
+            "dielectric" | "roughdielectric" => {
+                let eta = self.ior(element, "int_ior", 1.5046)?
+                    / self.ior(element, "ext_ior", 1.000_277)?;
+                match kind {
+                    "roughdielectric" => Arc::new(Principled {
+                        transmission: Arc::new(SolidColor::from_value(1.0)),
+                        roughness: Arc::new(SolidColor::from_value(Self::alpha(element)?.sqrt())),
+                        ior: eta,
+                        ..Principled::new(Color::new(1.0, 1.0, 1.0))
+                    }),
+                    _ => Arc::new(Dielectric::new(eta)),
+                }
+            }
+            _ => {
+                self.warn(format!("\"{kind}\" bsdf read as diffuse"));
+                default()
+            }
+        };
+        if let Some(id) = element.attribute("id") {
+            self.bsdfs.insert(id.to_string(), scatter.clone());
+        }
+        Ok(scatter)
+    }
+
+    fn emitter(&mut self, element: &Element) -> io::Result<()> {
+        let kind = element.attribute("type").unwrap_or_default();
+        match kind {
+            "constant" => {
+                let radiance = self.color(element, "radiance")?;
+                self.background = Background::Solid(radiance.unwrap_or(Color::new(1.0, 1.0, 1.0)));
+            }
+            "envmap" => {
+                let filename = string(element, "filename")
+                    .ok_or_else(|| invalid("envmap without a filename"))?;
+                match Image::load(self.base.join(filename)) {
+                    Ok(image) => {
+                        let scale = float(element, "scale", 1.0)?;
+                        let texture = ImageTexture::new(Arc::new(image))
+                            .with_srgb()
+                            .with_scale(Color::new(scale, scale, scale));
+                        let to_world = self.mirror * to_world(element)?;
+                        self.background = Background::Environment(
+                            Arc::new(texture),
+                            Box::new(to_world.inverse()),
+                        );
+                    }
+                    Err(error) => self.warn(format!("{filename} skipped: {error}")),
+                }
+            }
+            // a point light can only be hit by sampling it, which nothing
+            // here does
+            "point" => self.warn("\"point\" emitter skipped: no light sampling".to_string()),
+            _ => self.warn(format!("\"{kind}\" emitter skipped: not supported")),
+        }
+        Ok(())
+    }
+
+    fn shape(&mut self, element: &Element) -> io::Result<()> {
+        let kind = element.attribute("type").unwrap_or_default();
+        let nested = self.nested_bsdf(element)?;
+        // whether the shape says what it's made of, an obj that doesn't
+        // keeps the materials from its mtl library
+        let mut assigned = nested.is_some();
+        let mut material =
+            nested.unwrap_or_else(|| Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        // area lights are always two sided here
+        for emitter in element
+            .children
+            .iter()
+            .filter(|child| child.name == "emitter")
+        {
+            match emitter.attribute("type") {
+                Some("area") => {
+                    let radiance = self.color(emitter, "radiance")?;
+                    let emission: Arc<dyn Texture> = Arc::new(SolidColor::new(
+                        radiance.unwrap_or(Color::new(1.0, 1.0, 1.0)),
+                    ));
+                    material = Arc::new(Principled {
+                        emission,
+                        ..Principled::new(Color::black())
+                    });
+                    assigned = true;
+                }
+                other => self.warn(format!(
+                    "\"{}\" emitter on a shape skipped: not supported",
+                    other.unwrap_or_default()
+                )),
+            }
+        }
+        let to_world = self.mirror * to_world(element)?;
+        let flip_normals = boolean(element, "flip_normals");
+
+        let object: Arc<dyn Hittable> = match kind {
+            "obj" | "ply" => {
+                let filename = string(element, "filename")
+                    .ok_or_else(|| invalid(&format!("{kind} shape without a filename")))?;
+                let path = self.base.join(filename);
+                let loaded = match kind {
+                    "obj" if !assigned => {
+                        load_obj_scene(path, material).map(|obj| (obj.parts, obj.warnings))
+                    }
+                    "obj" => load_obj(path).map(|mesh| (vec![(mesh, material)], Vec::new())),
+                    _ => load_ply(path).map(|mesh| (vec![(mesh, material)], Vec::new())),
+                };
+                let (parts, warnings) = match loaded {
+                    Ok(loaded) => loaded,
+                    Err(error) => {
+                        self.warn(format!("{filename} skipped: {error}"));
+                        return Ok(());
+                    }
+                };
+                for warning in warnings {
+                    self.warn(format!("{filename}: {warning}"));
+                }
+                let face_normals = boolean(element, "face_normals");
+                let mut list = HittableList::default();
+                for (mut mesh, material) in parts {
+                    if face_normals {
+                        mesh.normals.clear();
+                    }
+                    list.add(self.mesh(mesh, material, to_world, flip_normals));
+                }
+                match list.objects() {
+                    [only] => only.clone(),
+                    _ => Arc::new(list),
+                }
+            }
+            "rectangle" => self.mesh(rectangle(), material, to_world, flip_normals),
+            "cube" => {
+                let (a, b) = (Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+                Arc::new(Instance::new(
+                    Arc::new(Cuboid::new(a, b, material)),
+                    to_world,
+                ))
+            }
+            "sphere" => {
+                let center = property(element, "center")
+                    .map(|center| vector(center, 0.0))
+                    .transpose()?
+                    .map_or(Point3::origin(), Point3::from);
+                let sphere = Sphere::new(center, float(element, "radius", 1.0)?, material);
+                Arc::new(Instance::new(Arc::new(sphere), to_world))
+            }
+            _ => {
+                self.warn(format!("\"{kind}\" shapes skipped: not supported"));
+                return Ok(());
+            }
+        };
+        self.world.add(object);
+        Ok(())
+    }
+
+    fn mesh(
+        &self,
+        mut mesh: TriangleMesh,
+        material: Arc<dyn Scatter>,
+        to_world: Transform,
+        flip_normals: bool,
+    ) -> Arc<dyn Hittable> {
+        for p in mesh.positions.iter_mut() {
+            *p = to_world.point(*p);
+        }
+        for n in mesh.normals.iter_mut() {
+            let [x, y, z] = to_world.normal(*n).to_array();
+            *n = if flip_normals {
+                Normal3::new(-x, -y, -z)
+            } else {
+                Normal3::new(x, y, z)
+            };
+        }
+        // Mitsuba faces triangles by their winding in world space too, only
+        // the mirror added here turns them around
+        if self.mirror.swaps_handedness() != flip_normals {
+            for [_, b, c] in mesh.indices.iter_mut() {
+                std::mem::swap(b, c);
+            }
+        }
+        Arc::new(Bvh4::new(&mesh.triangles(material)))
+    }
+}
+
+// [-1, 1] square in the xy plane facing +z
+fn rectangle() -> TriangleMesh {
+    let corners = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+    TriangleMesh::new(
+        corners.map(|[x, y]| Point3::new(x, y, 0.0)).to_vec(),
+        vec![[0, 1, 2], [0, 2, 3]],
+    )
+    .with_normals(vec![Normal3::new(0.0, 0.0, 1.0); 4])
+    .with_uvs(
+        corners
+            .map(|[x, y]| [0.5 * (x + 1.0), 0.5 * (y + 1.0)])
+            .to_vec(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    // an older camelCase scene with a lit rectangle, a shared bsdf, and
+    // plugins that aren't supported
+    const SCENE: &str = r#"<?xml version="1.0"?>
+<scene version="0.6.0">
+    <default name="spp" value="32"/>
+    <integrator type="path"><integer name="maxDepth" value="8"/></integrator>
+    <sensor type="perspective">
+        <float name="fov" value="40"/>
+        <string name="fovAxis" value="y"/>
+        <transform name="toWorld">
+            <lookat origin="0, 1, -5" target="0, 1, 0" up="0, 1, 0"/>
+        </transform>
+        <sampler type="independent"><integer name="sampleCount" value="$spp"/></sampler>
+        <film type="hdrfilm">
+            <integer name="width" value="320"/>
+            <integer name="height" value="240"/>
+        </film>
+    </sensor>
+    <bsdf type="twosided" id="gold">
+        <bsdf type="roughconductor"><string name="material" value="Au"/></bsdf>
+    </bsdf>
+    <shape type="sphere">
+        <point name="center" x="2" y="0" z="0"/>
+        <float name="radius" value="0.5"/>
+        <ref id="gold"/>
+    </shape>
+    <shape type="rectangle">
+        <transform name="toWorld">
+            <rotate x="1" angle="90"/>
+            <scale value="3"/>
+            <translate y="3"/>
+        </transform>
+        <emitter type="area"><rgb name="radiance" value="4, 4, 4"/></emitter>
+    </shape>
+    <shape type="obj"><string name="filename" value="missing.obj"/></shape>
+    <emitter type="point"><point name="position" x="0" y="2" z="0"/></emitter>
+    <emitter type="spot"/>
+</scene>
+"#;
+
+    #[test]
+    fn parses_scene() {
+        let mitsuba = parse_mitsuba(SCENE, Path::new(".")).unwrap();
+        assert_eq!(mitsuba.world.objects().len(), 2);
+        assert_eq!(mitsuba.warnings.len(), 3, "{:?}", mitsuba.warnings);
+        assert!(mitsuba
+            .warnings
+            .iter()
+            .any(|w| w.contains("no light sampling")));
+
+        let camera = &mitsuba.camera;
+        assert_eq!(camera.image_width, 320.0);
+        assert_eq!(camera.samples_per_pixel, 32);
+        assert_eq!(camera.max_depth, 8);
+        assert!((camera.vfov - 40.0).abs() < 1.0e-3);
+        assert_eq!(camera.lookfrom, Point3::new(0.0, 1.0, -5.0));
+        assert!(matches!(camera.background, Background::Solid(_)));
+
+        // Mitsuba is right handed like this renderer, nothing is mirrored
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+        let r = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = mitsuba.world.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.5).abs() < 1.0e-3);
+
+        // the rectangle was turned to face down, then moved up
+        let r = Ray::new(Point3::new(0.5, 0.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        let rec = mitsuba.world.hit(r, ray_t).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-3);
+        assert!(rec.front_face);
+        assert_eq!(rec.material.emitted(rec.u, rec.v, rec.p).r(), 4.0);
+    }
+
+    #[test]
+    fn obj_keeps_its_materials() {
+        let dir = std::env::temp_dir().join(format!("mitsuba-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("glow.mtl"), "newmtl glow\nKd 0 0 0\nKe 2 2 2\n").unwrap();
+        fs::write(
+            dir.join("quad.obj"),
+            "mtllib glow.mtl\nv -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nusemtl glow\nf 1 2 3 4\n",
+        )
+        .unwrap();
+        let scene = |bsdf: &str| {
+            format!(
+                r#"<scene version="3.0.0">
+                <shape type="obj"><string name="filename" value="quad.obj"/>{bsdf}</shape>
+            </scene>"#
+            )
+        };
+        let emitted = |scene: &str| {
+            let mitsuba = parse_mitsuba(scene, &dir).unwrap();
+            assert!(mitsuba.warnings.is_empty(), "{:?}", mitsuba.warnings);
+            let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let rec = mitsuba
+                .world
+                .hit(r, Interval::new(0.0, Float::INFINITY))
+                .unwrap();
+            rec.material.emitted(rec.u, rec.v, rec.p).r()
+        };
+        // the mtl's emission, unless the shape has a bsdf of its own
+        let lit = emitted(&scene(""));
+        let plain = emitted(&scene(r#"<bsdf type="diffuse"/>"#));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lit, 2.0);
+        assert_eq!(plain, 0.0);
+    }
+
+    #[test]
+    fn transforms_and_errors() {
+        let scene = r#"<scene version="3.0.0">
+            <shape type="cube">
+                <transform name="to_world">
+                    <matrix value="1 0 0 0  0 1 0 0  0 0 1 10  0 0 0 1"/>
+                </transform>
+            </shape>
+            <emitter type="constant"><rgb name="radiance" value="0.5"/></emitter>
+        </scene>"#;
+        let mitsuba = parse_mitsuba(scene, Path::new(".")).unwrap();
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, 1.0));
+        let rec = mitsuba
+            .world
+            .hit(r, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 9.0).abs() < 1.0e-3);
+        assert!(matches!(mitsuba.camera.background, Background::Solid(c) if c.g() == 0.5));
+
+        assert!(parse_mitsuba("<film/>", Path::new(".")).is_err());
+        let bad_transform = r#"<scene><shape type="cube">
+            <transform name="to_world"><skew value="1"/></transform></shape></scene>"#;
+        assert!(parse_mitsuba(bad_transform, Path::new(".")).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::utils::Float;
+use crate::vec3::{cross, dot, unit_vector, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// wraps a material and perturbs the shading normal with a tangent
+// space normal map before handing the hit to it
+pub struct NormalMap {
+    inner: Arc<dyn Scatter>,
+    map: Arc<dyn Texture>,
+}
+
+// wraps a material and perturbs the shading normal as if the
+// surface were displaced by a height texture
+pub struct BumpMap {
+    inner: Arc<dyn Scatter>,
+    height: Arc<dyn Texture>,
+    scale: Float,
+}
+
+impl NormalMap {
+    pub fn new(inner: Arc<dyn Scatter>, map: Arc<dyn Texture>) -> NormalMap {
+        NormalMap { inner, map }
+    }
+}
+
+impl BumpMap {
+    // step in uv used for the finite differences of the height
+    const DELTA: Float = 5.0e-4;
+
+    pub fn new(inner: Arc<dyn Scatter>, height: Arc<dyn Texture>, scale: Float) -> BumpMap {
+        BumpMap {
+            inner,
+            height,
+            scale,
+        }
+    }
+
+    fn displacement(&self, u: Float, v: Float, p: Point3) -> Float {
+        self.scale * self.height.value(u, v, p).r()
+    }
+}
+
+impl Scatter for NormalMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let outward = outward_normal(rec);
+        let tangent = unit_vector(rec.dpdu - dot(rec.dpdu, outward) * outward);
+        let bitangent = cross(outward, tangent);
+
+        // rgb in [0,1] encodes a tangent space direction in [-1,1]
+        let m = 2.0 * self.map.value_at(rec) - Color::new(1.0, 1.0, 1.0);
+        let shading = m.r() * tangent + m.g() * bitangent + m.b() * outward;
+        if shading.near_zero() {
+            return self.inner.scatter(r_in, rec);
+        }
+
+        scatter_with_shading_normal(&*self.inner, r_in, rec, shading)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.inner.emitted(u, v, p)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.inner.is_dispersive()
+    }
+
+    fn alpha(&self, u: Float, v: Float, p: Point3) -> Float {
+        self.inner.alpha(u, v, p)
+    }
+
+    fn has_alpha(&self) -> bool {
+        self.inner.has_alpha()
+    }
+}
+
+impl Scatter for BumpMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let outward = outward_normal(rec);
+        let d = self.displacement(rec.u, rec.v, rec.p);
+        let d_u = self.displacement(rec.u + Self::DELTA, rec.v, rec.p + Self::DELTA * rec.dpdu);
+        let d_v = self.displacement(rec.u, rec.v + Self::DELTA, rec.p + Self::DELTA * rec.dpdv);
+
+        // displaced partial derivatives, ignoring the change of the normal itself
+        let dpdu = rec.dpdu + ((d_u - d) / Self::DELTA) * outward;
+        let dpdv = rec.dpdv + ((d_v - d) / Self::DELTA) * outward;
+
+        let mut shading = cross(dpdu, dpdv);
+        if shading.near_zero() {
+            return self.inner.scatter(r_in, rec);
+        }
+        if dot(shading, outward) < 0.0 {
+            shading = -shading;
+        }
+
+        scatter_with_shading_normal(&*self.inner, r_in, rec, shading)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Point3) -> Color {
+        self.inner.emitted(u, v, p)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.inner.is_dispersive()
+    }
+
+    fn alpha(&self, u: Float, v: Float, p: Point3) -> Float {
+        self.inner.alpha(u, v, p)
+    }
+
+    fn has_alpha(&self) -> bool {
+        self.inner.has_alpha()
+    }
+}
+
+fn outward_normal(rec: &HitRecord) -> Vec3 {
+    let normal = Vec3::from(rec.normal);
+    if rec.front_face {
+        normal
+    } else {
+        -normal
+    }
+}
+
+// scatters off `inner` using `shading` (outward facing) as the normal, while
+// keeping the result on the side of the geometric surface it was meant for
+fn scatter_with_shading_normal(
+    inner: &dyn Scatter,
+    r_in: &Ray,
+    rec: &HitRecord,
+    shading: Vec3,
+) -> Option<(Color, Ray)> {
+    let geometric = Vec3::from(rec.normal);
+    let mut shading = unit_vector(shading);
+    if !rec.front_face {
+        shading = -shading;
+    }
+
+    // a shading normal facing away from the viewer would
+    // let rays through the surface, fall back to the real one
+    if dot(shading, r_in.direction()) >= 0.0 {
+        shading = geometric;
+    }
+
+    let mut shaded = rec.clone();
+    shaded.normal = Normal3::from(shading);
+    let (attenuation, scattered) = inner.scatter(r_in, &shaded)?;
+
+    // the material decided between reflection and transmission against the
+    // shading normal, mirror the ray back if the geometry disagrees
+    let mut direction = scattered.direction();
+    let reflects = dot(direction, shading) > 0.0;
+    let above_geometry = dot(direction, geometric) > 0.0;
+    if reflects != above_geometry {
+        direction = direction - 2.0 * dot(direction, geometric) * geometric;
+    }
+
+    // respawn against the geometric normal, the origin offset has to
+    // follow the side of the real surface the ray ends up on
+    Some((attenuation, rec.spawn_ray(direction)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::{tests::gray, Metal};
+    use crate::quad::Quad;
+    use crate::texture::SolidColor;
+    use crate::utils::degrees_to_radians;
+
+    // a constant normal map over `inner`, and a quad in the xy plane to
+    // hit that faces +z with dpdu along +x
+    fn tilted(map: Color, inner: Arc<dyn Scatter>) -> (NormalMap, Quad) {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            inner.clone(),
+        );
+        (NormalMap::new(inner, Arc::new(SolidColor::new(map))), quad)
+    }
+
+    fn mirror() -> Arc<dyn Scatter> {
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0))
+    }
+
+    // encodes a tangent space normal tilted by `degrees` towards +u
+    fn slope(degrees: Float) -> Color {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        0.5 * (Color::new(sin, 0.0, cos) + Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn flat_map_changes_nothing() {
+        let (map, quad) = tilted(Color::new(0.5, 0.5, 1.0), mirror());
+        let r = Ray::new(Point3::new(-1.0, 0.5, 1.0), Vec3::new(1.0, -0.5, -1.0));
+        let rec = quad.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+
+        let (attenuation, plain) = rec.material.scatter(&r, &rec).unwrap();
+        let (mapped_attenuation, mapped) = map.scatter(&r, &rec).unwrap();
+        assert_eq!(attenuation.to_array(), mapped_attenuation.to_array());
+        assert!((plain.direction() - mapped.direction()).length() < 1.0e-5);
+    }
+
+    #[test]
+    fn slope_tilts_the_normal() {
+        let (map, quad) = tilted(slope(20.0), mirror());
+        let r = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = quad.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+
+        // straight down off a normal tilted by 20 degrees comes back at 40
+        let (_, scattered) = map.scatter(&r, &rec).unwrap();
+        let (sin, cos) = degrees_to_radians(40.0).sin_cos();
+        let expected = Vec3::new(sin, 0.0, cos);
+        assert!((unit_vector(scattered.direction()) - expected).length() < 1.0e-4);
+    }
+
+    #[test]
+    fn grazing_rays_stay_above_the_surface() {
+        // a mirror tilted by less than the grazing angle still faces the
+        // ray but reflects it under the geometry, which has to fold it back
+        for inner in [mirror(), gray()] {
+            for grazing in [1.0, 5.0, 10.0, 20.0] {
+                for tilt in [-80.0, -30.0, 0.5 * grazing, 0.9 * grazing, 30.0, 80.0] {
+                    let (map, quad) = tilted(slope(tilt), inner.clone());
+                    let (sin, cos) = degrees_to_radians(grazing).sin_cos();
+                    let r = Ray::new(Point3::new(-cos, 0.0, sin), Vec3::new(cos, 0.0, -sin));
+                    let rec = quad.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+                    for _ in 0..100 {
+                        let (_, scattered) = map.scatter(&r, &rec).unwrap();
+                        assert!(dot(scattered.direction(), Vec3::new(0.0, 0.0, 1.0)) >= 0.0);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,514 @@
+// Wavefront OBJ meshes. Polygons are fanned into triangles and every
+// distinct position, uv and normal combination becomes a vertex, unless
+// read as a PolyMesh. uvs and normals are only kept when every corner has
+// them. load_obj_scene also
+// reads the mtl libraries and splits the mesh by material, everything
+// else that isn't geometry is ignored.
+
+use crate::alpha_mask::AlphaMask;
+use crate::color::{luminance, Color};
+use crate::hittable::{Hittable, HittableList};
+use crate::image::{Image, ImageTexture};
+use crate::material::{Principled, Scatter};
+use crate::mesh::{PolyMesh, TriangleMesh};
+use crate::texture::{SolidColor, Texture};
+use crate::utils::Float;
+use crate::vec3::{Normal3, Point3};
+use crate::wide_bvh::Bvh4;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct Obj {
+    // the mesh split by material, one part for each material used
+    pub parts: Vec<(TriangleMesh, Arc<dyn Scatter>)>,
+    pub warnings: Vec<String>,
+}
+
+impl Obj {
+    // a bvh of triangles for each part
+    pub fn world(self) -> HittableList {
+        let mut world = HittableList::default();
+        for (mesh, material) in self.parts {
+            world.add(Arc::new(Bvh4::new(&mesh.triangles(material))) as Arc<dyn Hittable>);
+        }
+        world
+    }
+}
+
+pub fn load_obj(path: impl AsRef<Path>) -> io::Result<TriangleMesh> {
+    parse_obj(&fs::read_to_string(path)?)
+}
+
+pub fn parse_obj(text: &str) -> io::Result<TriangleMesh> {
+    Ok(parse(text)?.mesh)
+}
+
+pub fn load_obj_polygons(path: impl AsRef<Path>) -> io::Result<PolyMesh> {
+    parse_obj_polygons(&fs::read_to_string(path)?)
+}
+
+// the faces as they are, to subdivide. Only the positions are kept: obj
+// uvs belong to face corners rather than positions, and subdivision makes
+// its own normals.
+pub fn parse_obj_polygons(text: &str) -> io::Result<PolyMesh> {
+    let mut positions = Vec::new();
+    let mut faces = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let [x, y, z] = numbers(words)?;
+                positions.push(Point3::new(x, y, z));
+            }
+            Some("f") => {
+                let face = words
+                    .map(|corner| {
+                        let p = corner.split('/').next().unwrap_or_default();
+                        resolve(p, positions.len()).map(|p| p as u32)
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                if face.len() < 3 {
+                    return Err(invalid("obj face with fewer than three corners"));
+                }
+                faces.push(face);
+            }
+            _ => {}
+        }
+    }
+    Ok(PolyMesh::new(positions, faces))
+}
+
+// the obj with its materials, mtl libraries are found relative to the
+// obj's directory. Faces before any usemtl, or naming a material that
+// isn't defined, get `fallback`.
+pub fn load_obj_scene(path: impl AsRef<Path>, fallback: Arc<dyn Scatter>) -> io::Result<Obj> {
+    let path = path.as_ref();
+    let base = path.parent().unwrap_or(Path::new("."));
+    parse_obj_scene(&fs::read_to_string(path)?, base, fallback)
+}
+
+pub fn parse_obj_scene(text: &str, base: &Path, fallback: Arc<dyn Scatter>) -> io::Result<Obj> {
+    let parsed = parse(text)?;
+    let mut warnings = Vec::new();
+
+    let mut library = HashMap::new();
+    for file in &parsed.libraries {
+        match fs::read_to_string(base.join(file)) {
+            Ok(text) => library.extend(parse_mtl(&text)?),
+            Err(error) => warnings.push(format!("{file} skipped: {error}")),
+        }
+    }
+
+    let mut materials = vec![fallback.clone()];
+    for name in &parsed.materials {
+        materials.push(match library.get(name) {
+            Some(mtl) => mtl.material(base, &mut warnings),
+            None => {
+                warnings.push(format!("material {name} isn't defined, using the fallback"));
+                fallback.clone()
+            }
+        });
+    }
+
+    let mut groups = vec![Vec::new(); materials.len()];
+    for (&triangle, &material) in parsed.mesh.indices.iter().zip(&parsed.face_materials) {
+        groups[material.map_or(0, |m| m + 1)].push(triangle);
+    }
+    let parts = groups
+        .into_iter()
+        .zip(materials)
+        .filter(|(indices, _)| !indices.is_empty())
+        .map(|(indices, material)| (submesh(&parsed.mesh, indices), material))
+        .collect();
+    Ok(Obj { parts, warnings })
+}
+
+struct Parsed {
+    mesh: TriangleMesh,
+    // usemtl names in order of first use
+    materials: Vec<String>,
+    // per triangle, an index into materials, None before any usemtl
+    face_materials: Vec<Option<usize>>,
+    libraries: Vec<String>,
+}
+
+fn parse(text: &str) -> io::Result<Parsed> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+
+    let mut mesh = TriangleMesh::default();
+    let mut vertices: HashMap<[Option<usize>; 3], u32> = HashMap::new();
+    let (mut all_uvs, mut all_normals) = (true, true);
+    let (mut materials, mut face_materials, mut libraries) = (Vec::new(), Vec::new(), Vec::new());
+    let mut current = None;
+
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let [x, y, z] = numbers(words)?;
+                positions.push(Point3::new(x, y, z));
+            }
+            Some("vt") => {
+                // the v coordinate is optional
+                let mut uv = words.take(2).map(number);
+                let u = uv.next().ok_or_else(|| invalid("obj vt without u"))??;
+                uvs.push([u, uv.next().transpose()?.unwrap_or(0.0)]);
+            }
+            Some("vn") => {
+                let [x, y, z] = numbers(words)?;
+                normals.push(Normal3::new(x, y, z));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+                for corner in words {
+                    let mut parts = corner.split('/');
+                    let mut index = |count: usize| {
+                        parts
+                            .next()
+                            .filter(|part| !part.is_empty())
+                            .map(|part| resolve(part, count))
+                            .transpose()
+                    };
+                    let key = [
+                        index(positions.len())?,
+                        index(uvs.len())?,
+                        index(normals.len())?,
+                    ];
+                    let [p, uv, n] = key;
+                    let p = p.ok_or_else(|| invalid("obj face corner without a position"))?;
+                    all_uvs &= uv.is_some();
+                    all_normals &= n.is_some();
+
+                    let vertex = *vertices.entry(key).or_insert_with(|| {
+                        mesh.positions.push(positions[p]);
+                        mesh.uvs.push(uv.map_or([0.0, 0.0], |uv| uvs[uv]));
+                        mesh.normals
+                            .push(n.map_or(Normal3::new(0.0, 0.0, 0.0), |n| normals[n]));
+                        (mesh.positions.len() - 1) as u32
+                    });
+                    corners.push(vertex);
+                }
+                if corners.len() < 3 {
+                    return Err(invalid("obj face with fewer than three corners"));
+                }
+                for i in 1..corners.len() - 1 {
+                    mesh.indices.push([corners[0], corners[i], corners[i + 1]]);
+                    face_materials.push(current);
+                }
+            }
+            Some("mtllib") => libraries.extend(words.map(String::from)),
+            Some("usemtl") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                current = Some(match materials.iter().position(|m| *m == name) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(name);
+                        materials.len() - 1
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !all_uvs {
+        mesh.uvs.clear();
+    }
+    if !all_normals {
+        mesh.normals.clear();
+    }
+    Ok(Parsed {
+        mesh,
+        materials,
+        face_materials,
+        libraries,
+    })
+}
+
+// the vertices the triangles use, renumbered
+fn submesh(mesh: &TriangleMesh, indices: Vec<[u32; 3]>) -> TriangleMesh {
+    let mut out = TriangleMesh::default();
+    let mut renumbered: HashMap<u32, u32> = HashMap::new();
+    let mut vertex = |i: u32| {
+        *renumbered.entry(i).or_insert_with(|| {
+            let i = i as usize;
+            out.positions.push(mesh.positions[i]);
+            out.normals.extend(mesh.normals.get(i));
+            out.uvs.extend(mesh.uvs.get(i));
+            out.colors.extend(mesh.colors.get(i));
+            (out.positions.len() - 1) as u32
+        })
+    };
+    let indices = indices.into_iter().map(|t| t.map(&mut vertex)).collect();
+    TriangleMesh { indices, ..out }
+}
+
+// one material of an mtl library, with the classic and the pbr extension
+// statements it had
+#[derive(Clone, Default)]
+struct Mtl {
+    kd: Option<Color>,
+    map_kd: Option<String>,
+    ks: Option<Color>,
+    ns: Option<Float>,
+    pr: Option<Float>,
+    pm: Option<Float>,
+    ke: Option<Color>,
+    // dissolve, 1 is opaque
+    d: Option<Float>,
+    ni: Option<Float>,
+}
+
+impl Mtl {
+    // Kd or map_Kd is the base color and Pm the metallic. Pr is the
+    // roughness, or without it the Phong exponent Ns converted through
+    // the Beckmann alpha sqrt(2 / (Ns + 2)). The brightness of Ks scales
+    // the dielectric specular and Ke is emitted.
+    fn principled(&self, base: &Path, warnings: &mut Vec<String>) -> Principled {
+        let value = |v: Float| -> Arc<dyn Texture> { Arc::new(SolidColor::from_value(v)) };
+        let defaults = Principled::default();
+
+        let texture = self
+            .map_kd
+            .as_ref()
+            .and_then(|file| match Image::load(base.join(file)) {
+                Ok(image) => Some(Arc::new(ImageTexture::new(Arc::new(image)).with_srgb())),
+                Err(error) => {
+                    warnings.push(format!("{file} skipped: {error}"));
+                    None
+                }
+            });
+        let base_color: Arc<dyn Texture> = match (texture, self.kd) {
+            (Some(texture), _) => texture,
+            (None, Some(kd)) => Arc::new(SolidColor::new(kd)),
+            (None, None) => defaults.base_color,
+        };
+        let roughness = self
+            .pr
+            .or_else(|| self.ns.map(|ns| (2.0 / (ns.max(0.0) + 2.0)).powf(0.25)));
+        Principled {
+            base_color,
+            metallic: self.pm.map_or(defaults.metallic, value),
+            roughness: roughness.map_or(defaults.roughness, value),
+            specular: self
+                .ks
+                .map_or(defaults.specular, |ks| value(luminance(ks).clamp(0.0, 1.0))),
+            emission: self
+                .ke
+                .map_or(defaults.emission, |ke| Arc::new(SolidColor::new(ke))),
+            ior: self.ni.filter(|&ni| ni > 0.0).unwrap_or(defaults.ior),
+            ..defaults
+        }
+    }
+
+    // d below one dissolves the surface stochastically
+    fn material(&self, base: &Path, warnings: &mut Vec<String>) -> Arc<dyn Scatter> {
+        let principled = Arc::new(self.principled(base, warnings));
+        match self.d {
+            Some(d) if d < 1.0 => {
+                let opacity = Arc::new(SolidColor::from_value(d.max(0.0)));
+                Arc::new(AlphaMask::stochastic(principled, opacity))
+            }
+            _ => principled,
+        }
+    }
+}
+
+fn parse_mtl(text: &str) -> io::Result<HashMap<String, Mtl>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Mtl)> = None;
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            materials.extend(current.take());
+            current = Some((words.collect::<Vec<_>>().join(" "), Mtl::default()));
+            continue;
+        }
+        let Some((_, mtl)) = current.as_mut() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        let color = || numbers(args.iter().copied()).map(|[r, g, b]| Color::new(r, g, b));
+        let scalar = || {
+            let word = args
+                .first()
+                .ok_or_else(|| invalid("mtl statement without a value"))?;
+            number(word)
+        };
+        match keyword {
+            "Kd" => mtl.kd = Some(color()?),
+            "Ks" => mtl.ks = Some(color()?),
+            "Ke" => mtl.ke = Some(color()?),
+            "Ns" => mtl.ns = Some(scalar()?),
+            "Pr" => mtl.pr = Some(scalar()?),
+            "Pm" => mtl.pm = Some(scalar()?),
+            "Ni" => mtl.ni = Some(scalar()?),
+            "d" => mtl.d = Some(scalar()?),
+            "Tr" => mtl.d = Some(1.0 - scalar()?),
+            // the file name is last, after any options
+            "map_Kd" => mtl.map_kd = args.last().map(|file| file.to_string()),
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    Ok(materials)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn number(word: &str) -> io::Result<Float> {
+    word.parse().map_err(|_| invalid("bad obj number"))
+}
+
+fn numbers<'a>(mut words: impl Iterator<Item = &'a str>) -> io::Result<[Float; 3]> {
+    let mut out = [0.0; 3];
+    for x in out.iter_mut() {
+        let word = words
+            .next()
+            .ok_or_else(|| invalid("obj vector too short"))?;
+        *x = number(word)?;
+    }
+    Ok(out)
+}
+
+// indices count from 1, negative ones back from the latest element
+fn resolve(part: &str, count: usize) -> io::Result<usize> {
+    let index: i64 = part.parse().map_err(|_| invalid("bad obj index"))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => -1,
+    };
+    usize::try_from(resolved)
+        .ok()
+        .filter(|&i| i < count)
+        .ok_or_else(|| invalid("obj index out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_faces() {
+        let quad = "# a quad, the second half written with negative indices
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl white
+f 1/1/1 2/2/1 3/3/1
+f -4/-4/-1 -2/-2/-1 -1/-1/-1
+";
+        let mesh = parse_obj(quad).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.uvs[3], [0.0, 1.0]);
+        assert_eq!(mesh.normals.len(), 4);
+
+        // a polygon is fanned, and uvs missing on a corner are dropped
+        let pentagon = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 0 0\nvt 0 0\nf 1/1 2 3 4 5\n";
+        let mesh = parse_obj(pentagon).unwrap();
+        assert_eq!(mesh.indices.len(), 3);
+        assert!(mesh.uvs.is_empty() && mesh.normals.is_empty());
+
+        assert!(parse_obj("v 0 0 0\nf 1 2 3\n").is_err());
+        assert!(parse_obj("v 0 0\n").is_err());
+
+        // as polygons the faces stay whole and the vertices aren't split
+        let polygons = parse_obj_polygons(pentagon).unwrap();
+        assert_eq!(polygons.positions.len(), 5);
+        assert_eq!(polygons.faces, vec![vec![0, 1, 2, 3, 4]]);
+        let polygons = parse_obj_polygons(quad).unwrap();
+        assert_eq!(polygons.faces, vec![vec![0, 1, 2], vec![0, 2, 3]]);
+        assert!(parse_obj_polygons("v 0 0 0\nf 1 1\n").is_err());
+    }
+
+    #[test]
+    fn mtl_to_principled() {
+        let library = "
+newmtl gold metal
+Kd 1.0 0.8 0.3
+Pm 1
+Pr 0.2
+Ke 0 0 0.5
+
+newmtl plastic
+Kd 0.2 0.4 0.6
+Ks 1 1 1
+Ns 98
+d 0.25
+";
+        let materials = parse_mtl(library).unwrap();
+        let mut warnings = Vec::new();
+        let at = |t: &Arc<dyn Texture>| t.value(0.0, 0.0, Point3::origin());
+
+        let gold = materials["gold metal"].principled(Path::new("."), &mut warnings);
+        assert_eq!(at(&gold.base_color), Color::new(1.0, 0.8, 0.3));
+        assert_eq!(at(&gold.metallic).r(), 1.0);
+        assert_eq!(at(&gold.roughness).r(), 0.2);
+        assert_eq!(at(&gold.emission), Color::new(0.0, 0.0, 0.5));
+
+        // Ns 98 is a Beckmann alpha of sqrt(2 / 100), and alpha is the
+        // roughness squared
+        let plastic = &materials["plastic"];
+        let principled = plastic.principled(Path::new("."), &mut warnings);
+        assert!((at(&principled.roughness).r().powi(4) - 0.02).abs() < 1.0e-5);
+        assert!((at(&principled.specular).r() - 1.0).abs() < 1.0e-5);
+        assert_eq!(at(&principled.metallic).r(), 0.0);
+        assert!(plastic.material(Path::new("."), &mut warnings).has_alpha());
+        assert!(warnings.is_empty());
+
+        assert!(parse_mtl("newmtl a\nKd 1 1\n").is_err());
+    }
+
+    #[test]
+    fn splits_by_material() {
+        let obj = "mtllib missing.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+f 1 2 3
+usemtl red
+f 1 3 4
+usemtl blue
+f 1 2 4
+usemtl red
+f 2 3 4
+";
+        let fallback: Arc<dyn Scatter> = Arc::new(Principled::default());
+        let scene = parse_obj_scene(obj, Path::new("."), fallback.clone()).unwrap();
+        assert_eq!(scene.warnings.len(), 3);
+        assert!(scene.warnings[0].starts_with("missing.mtl skipped"));
+        // no usemtl, red and blue, all three without a library to find
+        // them in
+        assert_eq!(scene.parts.len(), 3);
+        assert!(scene.parts.iter().all(|(_, m)| Arc::ptr_eq(m, &fallback)));
+        assert_eq!(scene.parts[1].0.indices.len(), 2);
+        // each with its own bvh
+        assert_eq!(scene.world().objects().len(), 3);
+
+        let mesh = parse_obj(obj).unwrap();
+        let red = submesh(&mesh, vec![mesh.indices[1], mesh.indices[3]]);
+        assert_eq!(red.positions.len(), 4);
+        assert_eq!(red.indices, vec![[0, 1, 2], [3, 1, 2]]);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::vec3::{cross, dot, unit_vector, Direction, Vec3};
+
+// orthonormal basis built around a single direction,
+// used to move directions in and out of a surface's local frame
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn build_from_w(w: impl Direction) -> Onb {
+        let unit_w = unit_vector(w.to_vec3());
+        let a = if unit_w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(cross(unit_w, a));
+        let u = cross(unit_w, v);
+
+        Onb {
+            axis: [u, v, unit_w],
+        }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    // local coordinates -> world space
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u() + a.y() * self.v() + a.z() * self.w()
+    }
+
+    // world space -> local coordinates
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(a, self.u()), dot(a, self.v()), dot(a, self.w()))
+    }
+}
@@ -0,0 +1,170 @@
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::polynomial::solve_quadratic;
+use crate::ray::Ray;
+use crate::utils::{azimuth, degrees_to_radians, gamma, Float, PI};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Open bowl around the y axis with its lowest point at `base`, rising to
+// `radius` wide at `height` above it: y = height (x^2 + z^2) / radius^2.
+// u goes around the axis from +x, v from the bottom to the rim.
+pub struct Paraboloid {
+    base: Point3,
+    radius: Float,
+    height: Float,
+    phi_max: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Paraboloid {
+    pub fn new(
+        base: Point3,
+        radius: Float,
+        height: Float,
+        material: Arc<dyn Scatter>,
+    ) -> Paraboloid {
+        Paraboloid {
+            base,
+            radius,
+            height,
+            phi_max: 2.0 * PI,
+            material,
+        }
+    }
+
+    // only the part swept from +x towards +z up to `degrees`
+    pub fn with_phi_max(self, degrees: Float) -> Paraboloid {
+        Paraboloid {
+            phi_max: degrees_to_radians(degrees.clamp(0.0, 360.0)),
+            ..self
+        }
+    }
+
+    fn on_surface(&self, local: Vec3) -> bool {
+        (0.0..=self.height).contains(&local.y())
+            && (self.phi_max >= 2.0 * PI || azimuth(local.x(), local.z()) <= self.phi_max)
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let [ox, oy, oz] = (r.origin() - self.base).to_array();
+        let [dx, dy, dz] = r.direction().to_array();
+
+        // k (x^2 + z^2) - y = 0, a line along the axis crosses it once
+        let k = self.height / (self.radius * self.radius);
+        let a = k * (dx * dx + dz * dz);
+        let half_b = k * (ox * dx + oz * dz) - 0.5 * dy;
+        let c = k * (ox * ox + oz * oz) - oy;
+        let (t0, t1) = solve_quadratic(a, half_b, c)?;
+
+        [t0, t1]
+            .into_iter()
+            .filter(|&t| ray_t.surrounds(t) && self.on_surface(r.at(t) - self.base))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corner = Vec3::new(self.radius, 0.0, self.radius);
+        Aabb::from_points(
+            self.base - corner,
+            self.base + corner + Vec3::new(0.0, self.height, 0.0),
+        )
+    }
+}
+
+impl Primitive for Paraboloid {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let p = r.at(hit.t);
+        let [x, y, z] = (p - self.base).to_array();
+        let k = self.height / (self.radius * self.radius);
+
+        // p(u, v) = (r sqrt(v) cos phi, h v, r sqrt(v) sin phi), whose
+        // v derivative blows up at the bottom
+        let dpdv = if y > 0.0 {
+            Vec3::new(
+                self.height * x / (2.0 * y),
+                self.height,
+                self.height * z / (2.0 * y),
+            )
+        } else {
+            Vec3::new(0.0, self.height, 0.0)
+        };
+        // away from the axis, the outside of the bowl
+        let outward = unit_vector(Vec3::new(2.0 * k * x, -1.0, 2.0 * k * z));
+
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            // the point isn't reprojected, allow for the quadratic's error
+            p_error: gamma(7) * Vec3::from(p).abs(),
+            material: &*self.material,
+            u: azimuth(x, z) / self.phi_max,
+            v: y / self.height,
+            dpdu: self.phi_max * Vec3::new(-z, 0.0, x),
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    fn bowl() -> Paraboloid {
+        Paraboloid::new(Point3::origin(), 2.0, 4.0, gray())
+    }
+
+    #[test]
+    fn bowl_hits() {
+        let bowl = bowl();
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // down the axis into the bowl, the inside is seen at the bottom
+        let r = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = bowl.hit(r, ray_t).unwrap();
+        assert!((rec.t - 5.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 1.0, 0.0)).length() < 1.0e-5);
+
+        // from the side at y = 1, where the radius is 1
+        let r = Ray::new(Point3::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = bowl.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((rec.v - 0.25).abs() < 1.0e-5);
+
+        // above the rim
+        let r = Ray::new(Point3::new(5.0, 4.5, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(bowl.hit(r, ray_t).is_none());
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let half = bowl().with_phi_max(180.0);
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        // the z < 0 half is cut away, leaving the inside of the other half
+        let r = Ray::new(Point3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = half.hit(r, ray_t).unwrap();
+        assert!((rec.t - 6.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+    }
+}
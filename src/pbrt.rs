@@ -0,0 +1,913 @@
+// pbrt-v3 and pbrt-v4 scene files, the part of the format that simple
+// test scenes use: a perspective camera with its film, sampler and
+// integrator settings, spheres, triangle meshes and ply meshes, diffuse,
+// conductor and dielectric materials, diffuse area lights, uniform
+// infinite lights as the background, named materials, attribute and
+// transform blocks and includes. Everything else is skipped with a
+// warning. Textures aren't read, parameters given as a texture keep their
+// default.
+//
+// pbrt is left handed, the same camera placed here would see the scene
+// mirrored. So the scene is mirrored in x instead, unless the camera
+// transform already flips it, and the image comes out as pbrt renders it.
+
+use crate::camera::{Background, Camera};
+use crate::color::Color;
+use crate::hittable::{Hittable, HittableList};
+use crate::instance::Instance;
+use crate::material::{conductor_f0, metal_ior, Dielectric, Ior, Lambertian, Principled, Scatter};
+use crate::mesh::TriangleMesh;
+use crate::ply::load_ply;
+use crate::spectrum::blackbody_rgb;
+use crate::sphere::Sphere;
+use crate::texture::{SolidColor, Texture};
+use crate::transform::Transform;
+use crate::utils::{degrees_to_radians, Float};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub struct Pbrt {
+    // meshes come as one bvh each, the list still wants one around it
+    pub world: HittableList,
+    // everything the file sets, image size, samples and depth included
+    pub camera: Camera,
+    pub warnings: Vec<String>,
+}
+
+pub fn load_pbrt(path: impl AsRef<Path>) -> io::Result<Pbrt> {
+    let path = path.as_ref();
+    parse_pbrt(
+        &fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new(".")),
+    )
+}
+
+// includes and ply meshes are looked up in `base`
+pub fn parse_pbrt(text: &str, base: &Path) -> io::Result<Pbrt> {
+    let mut loader = Loader::new(base);
+    loader.run(text, 0)?;
+    Ok(loader.finish())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[allow(clippy::unnecessary_cast)] // Float may already be f64
+fn float(x: f64) -> Float {
+    x as Float
+}
+
+fn constant(value: Float) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::from_value(value))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    // directive names, and the bools of pbrt-v4
+    Word(String),
+    Str(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next().map(|(_, c)| c) {
+                        Some('"') => break,
+                        Some('\\') => match chars.next().map(|(_, c)| c) {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err(invalid("unterminated pbrt string")),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err(invalid("unterminated pbrt string")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|&(_, c)| !c.is_whitespace() && !"[]\"#".contains(c))
+                {
+                    end = i + c.len_utf8();
+                }
+                let word = &text[start..end];
+                tokens.push(if c.is_ascii_alphabetic() {
+                    Token::Word(word.to_string())
+                } else {
+                    Token::Number(
+                        word.parse()
+                            .map_err(|_| invalid(&format!("bad pbrt number {word}")))?,
+                    )
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Statement {
+    name: String,
+    args: Vec<Token>,
+}
+
+// every word other than a bool starts a directive, everything up to the
+// next one is its arguments
+fn statements(tokens: Vec<Token>) -> io::Result<Vec<Statement>> {
+    let mut statements: Vec<Statement> = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Word(name) if name != "true" && name != "false" => statements.push(Statement {
+                name,
+                args: Vec::new(),
+            }),
+            token => statements
+                .last_mut()
+                .ok_or_else(|| invalid("pbrt file doesn't start with a directive"))?
+                .args
+                .push(token),
+        }
+    }
+    Ok(statements)
+}
+
+// the numbers of a directive like Translate, brackets or not
+fn numbers<const N: usize>(args: &[Token]) -> io::Result<[Float; N]> {
+    let numbers = args
+        .iter()
+        .filter(|token| !matches!(token, Token::Open | Token::Close))
+        .map(|token| match token {
+            Token::Number(x) => Ok(float(*x)),
+            _ => Err(invalid("expected only numbers")),
+        })
+        .collect::<io::Result<Vec<Float>>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| invalid(&format!("expected {N} numbers")))
+}
+
+fn string(args: &[Token]) -> io::Result<String> {
+    match args {
+        [Token::Str(s)] => Ok(s.clone()),
+        _ => Err(invalid("expected a single string")),
+    }
+}
+
+// a type or name followed by a parameter list
+fn named(args: &[Token]) -> io::Result<(String, Params)> {
+    match args {
+        [Token::Str(s), rest @ ..] => Ok((s.clone(), Params::parse(rest)?)),
+        _ => Err(invalid("expected a string before the parameters")),
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+#[derive(Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn parse(args: &[Token]) -> io::Result<Params> {
+        let mut params = Vec::new();
+        let mut args = args.iter();
+        while let Some(token) = args.next() {
+            let Token::Str(declaration) = token else {
+                return Err(invalid("expected a pbrt parameter declaration"));
+            };
+            let words: Vec<&str> = declaration.split_whitespace().collect();
+            let [ty, name] = words[..] else {
+                return Err(invalid(&format!("bad pbrt parameter \"{declaration}\"")));
+            };
+
+            let value = |token: &Token| match token {
+                Token::Number(x) => Ok(Value::Number(*x)),
+                Token::Str(s) => Ok(Value::Str(s.clone())),
+                Token::Word(word) => Ok(Value::Bool(word == "true")),
+                _ => Err(invalid("nested brackets in a pbrt parameter")),
+            };
+            let mut values = Vec::new();
+            match args.next() {
+                Some(Token::Open) => loop {
+                    match args.next() {
+                        Some(Token::Close) => break,
+                        Some(token) => values.push(value(token)?),
+                        None => return Err(invalid("unclosed pbrt parameter list")),
+                    }
+                },
+                Some(token) => values.push(value(token)?),
+                None => {
+                    return Err(invalid(&format!(
+                        "pbrt parameter \"{declaration}\" without a value"
+                    )))
+                }
+            }
+
+            // pbrt-v3 names of the pbrt-v4 types
+            let ty = match ty {
+                "point" => "point3",
+                "vector" => "vector3",
+                "normal" => "normal3",
+                "color" => "rgb",
+                ty => ty,
+            };
+            params.push(Param {
+                ty: ty.to_string(),
+                name: name.to_string(),
+                values,
+            });
+        }
+        Ok(Params(params))
+    }
+
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        self.get(name)?
+            .values
+            .iter()
+            .map(|value| match value {
+                Value::Number(x) => Some(*x),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn float(&self, name: &str, default: Float) -> Float {
+        self.numbers(name)
+            .and_then(|numbers| numbers.first().copied())
+            .map_or(default, float)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.values.first()? {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    // pbrt-v3 writes bools as strings
+    fn bool(&self, name: &str, default: bool) -> bool {
+        match self.get(name).and_then(|param| param.values.first()) {
+            Some(Value::Bool(b)) => *b,
+            Some(Value::Str(s)) => s == "true",
+            _ => default,
+        }
+    }
+}
+
+// pbrt's named metal spectra, like metal-Au-eta
+fn named_spectrum(name: &str) -> Option<Color> {
+    let (metal, part) = name.strip_prefix("metal-")?.split_once('-')?;
+    let (eta, k) = metal_ior(metal)?;
+    match part {
+        "eta" => Some(eta),
+        "k" => Some(k),
+        _ => None,
+    }
+}
+
+// the roughness giving pbrt-v4's microfacet alpha here, where Principled
+// squares it. Anisotropic roughness is averaged.
+fn roughness(params: &Params, default: Float) -> Float {
+    let r = params.float("roughness", default);
+    let r = 0.5 * (params.float("uroughness", r) + params.float("vroughness", r));
+    let alpha = if params.bool("remaproughness", true) {
+        r.sqrt()
+    } else {
+        r
+    };
+    alpha.sqrt()
+}
+
+fn triangle_mesh(params: &Params) -> io::Result<TriangleMesh> {
+    let points = params
+        .numbers("P")
+        .ok_or_else(|| invalid("trianglemesh without P"))?;
+    if points.len() % 3 != 0 {
+        return Err(invalid("trianglemesh P isn't made of points"));
+    }
+    let positions: Vec<Point3> = points
+        .chunks_exact(3)
+        .map(|p| Point3::new(float(p[0]), float(p[1]), float(p[2])))
+        .collect();
+
+    let indices = match params.numbers("indices") {
+        Some(indices) => indices,
+        None if positions.len() == 3 => vec![0.0, 1.0, 2.0],
+        None => return Err(invalid("trianglemesh without indices")),
+    };
+    let valid = |i: &f64| *i >= 0.0 && (*i as usize) < positions.len();
+    if indices.len() % 3 != 0 || !indices.iter().all(valid) {
+        return Err(invalid("bad trianglemesh indices"));
+    }
+    let indices = indices
+        .chunks_exact(3)
+        .map(|t| [t[0] as u32, t[1] as u32, t[2] as u32])
+        .collect();
+    let mut mesh = TriangleMesh::new(positions, indices);
+
+    if let Some(normals) = params.numbers("N") {
+        if normals.len() != points.len() {
+            return Err(invalid("trianglemesh N doesn't match P"));
+        }
+        mesh.normals = normals
+            .chunks_exact(3)
+            .map(|n| Normal3::new(float(n[0]), float(n[1]), float(n[2])))
+            .collect();
+    }
+    // pbrt-v3 has them as "float uv" or "float st"
+    if let Some(uvs) = params.numbers("uv").or_else(|| params.numbers("st")) {
+        if uvs.len() != 2 * mesh.positions.len() {
+            return Err(invalid("trianglemesh uv doesn't match P"));
+        }
+        mesh.uvs = uvs
+            .chunks_exact(2)
+            .map(|uv| [float(uv[0]), float(uv[1])])
+            .collect();
+    }
+    Ok(mesh)
+}
+
+#[derive(Clone)]
+struct Attributes {
+    // object to pbrt's world, or world to camera before WorldBegin
+    ctm: Transform,
+    // None for pbrt's "interface" material, which isn't seen
+    material: Option<Arc<dyn Scatter>>,
+    emission: Option<Color>,
+    reverse_orientation: bool,
+}
+
+struct Loader<'a> {
+    base: &'a Path,
+    attributes: Attributes,
+    attribute_stack: Vec<Attributes>,
+    transform_stack: Vec<Transform>,
+    named_materials: HashMap<String, Option<Arc<dyn Scatter>>>,
+    coordinate_systems: HashMap<String, Transform>,
+    // pbrt's world to this one
+    mirror: Transform,
+    camera_from_world: Transform,
+    camera: Params,
+    film: Params,
+    sampler: Params,
+    integrator: Params,
+    // black unless there's an infinite light, as in pbrt
+    background: Background,
+    // shapes between ObjectBegin and ObjectEnd are only instanced, which
+    // isn't supported
+    in_object: bool,
+    world: HittableList,
+    warnings: Vec<String>,
+}
+
+impl Loader<'_> {
+    fn new(base: &Path) -> Loader<'_> {
+        Loader {
+            base,
+            attributes: Attributes {
+                ctm: Transform::identity(),
+                material: Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+                emission: None,
+                reverse_orientation: false,
+            },
+            attribute_stack: Vec::new(),
+            transform_stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            mirror: Transform::scale(-1.0, 1.0, 1.0),
+            camera_from_world: Transform::identity(),
+            camera: Params::default(),
+            film: Params::default(),
+            sampler: Params::default(),
+            integrator: Params::default(),
+            background: Background::Solid(Color::black()),
+            in_object: false,
+            world: HittableList::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // a message for every kind of problem, not every time it comes up
+    fn warn(&mut self, message: String) {
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    fn run(&mut self, text: &str, depth: usize) -> io::Result<()> {
+        for statement in statements(tokenize(text)?)? {
+            self.statement(statement, depth)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, t: Transform) {
+        self.attributes.ctm = self.attributes.ctm * t;
+    }
+
+    fn statement(&mut self, Statement { name, args }: Statement, depth: usize) -> io::Result<()> {
+        match name.as_str() {
+            "AttributeBegin" => self.attribute_stack.push(self.attributes.clone()),
+            "AttributeEnd" => {
+                self.attributes = self
+                    .attribute_stack
+                    .pop()
+                    .ok_or_else(|| invalid("AttributeEnd without AttributeBegin"))?;
+            }
+            "TransformBegin" => self.transform_stack.push(self.attributes.ctm),
+            "TransformEnd" => {
+                self.attributes.ctm = self
+                    .transform_stack
+                    .pop()
+                    .ok_or_else(|| invalid("TransformEnd without TransformBegin"))?;
+            }
+            "WorldBegin" => {
+                self.attributes.ctm = Transform::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Transform::identity());
+            }
+            "WorldEnd" => {}
+
+            "Identity" => self.attributes.ctm = Transform::identity(),
+            "Translate" => {
+                let [x, y, z] = numbers(&args)?;
+                self.apply(Transform::translate(Vec3::new(x, y, z)));
+            }
+            "Scale" => {
+                let [x, y, z] = numbers(&args)?;
+                self.apply(Transform::scale(x, y, z));
+            }
+            "Rotate" => {
+                let [degrees, x, y, z] = numbers(&args)?;
+                self.apply(Transform::rotate(degrees, Vec3::new(x, y, z)));
+            }
+            "LookAt" => {
+                let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = numbers(&args)?;
+                let eye = Point3::new(ex, ey, ez);
+                match Transform::look_at(eye, Point3::new(lx, ly, lz), Vec3::new(ux, uy, uz)) {
+                    Some(world_from_camera) => self.apply(world_from_camera.inverse()),
+                    None => self.warn("LookAt skipped: degenerate view".to_string()),
+                }
+            }
+            "Transform" | "ConcatTransform" => {
+                // the file has the matrix column by column
+                let m: [Float; 16] = numbers(&args)?;
+                let t = Transform::from_matrix(std::array::from_fn(|i| {
+                    std::array::from_fn(|j| m[4 * j + i])
+                }));
+                match t {
+                    Some(t) if name == "Transform" => self.attributes.ctm = t,
+                    Some(t) => self.apply(t),
+                    None => self.warn(format!("{name} skipped: singular matrix")),
+                }
+            }
+            "CoordinateSystem" => {
+                self.coordinate_systems
+                    .insert(string(&args)?, self.attributes.ctm);
+            }
+            "CoordSysTransform" => {
+                let system = string(&args)?;
+                match self.coordinate_systems.get(&system) {
+                    Some(&t) => self.attributes.ctm = t,
+                    None => self.warn(format!("coordinate system \"{system}\" doesn't exist")),
+                }
+            }
+            "ReverseOrientation" => {
+                self.attributes.reverse_orientation = !self.attributes.reverse_orientation;
+            }
+
+            "Camera" => {
+                let (kind, params) = named(&args)?;
+                if kind != "perspective" {
+                    self.warn(format!("\"{kind}\" camera read as a perspective one"));
+                }
+                self.camera_from_world = self.attributes.ctm;
+                self.coordinate_systems
+                    .insert("camera".to_string(), self.attributes.ctm.inverse());
+                self.mirror = if self.attributes.ctm.swaps_handedness() {
+                    Transform::identity()
+                } else {
+                    Transform::scale(-1.0, 1.0, 1.0)
+                };
+                self.camera = params;
+            }
+            "Film" => self.film = named(&args)?.1,
+            "Sampler" => self.sampler = named(&args)?.1,
+            "Integrator" => self.integrator = named(&args)?.1,
+            // only change how the image is computed, not what it shows
+            "PixelFilter" | "Accelerator" | "ColorSpace" | "Option" => {}
+
+            "Material" => {
+                let (kind, params) = named(&args)?;
+                self.attributes.material = self.material(&kind, &params);
+            }
+            "MakeNamedMaterial" => {
+                let (material, params) = named(&args)?;
+                let kind = params.string("type").unwrap_or("diffuse").to_string();
+                let scatter = self.material(&kind, &params);
+                self.named_materials.insert(material, scatter);
+            }
+            "NamedMaterial" => {
+                let material = string(&args)?;
+                match self.named_materials.get(&material) {
+                    Some(scatter) => self.attributes.material = scatter.clone(),
+                    None => self.warn(format!("material \"{material}\" doesn't exist")),
+                }
+            }
+            "AreaLightSource" => {
+                let (kind, params) = named(&args)?;
+                if kind != "diffuse" {
+                    self.warn(format!("\"{kind}\" area light skipped"));
+                    return Ok(());
+                }
+                let radiance = self
+                    .color(&params, "L")
+                    .unwrap_or(Color::new(1.0, 1.0, 1.0));
+                self.attributes.emission = Some(params.float("scale", 1.0) * radiance);
+            }
+            "LightSource" => {
+                let (kind, params) = named(&args)?;
+                match kind.as_str() {
+                    "infinite" if params.get("filename").is_none() => {
+                        let radiance = self
+                            .color(&params, "L")
+                            .unwrap_or(Color::new(1.0, 1.0, 1.0));
+                        self.background = Background::Solid(params.float("scale", 1.0) * radiance);
+                    }
+                    _ => self.warn(format!("\"{kind}\" light skipped: not supported")),
+                }
+            }
+            "Shape" => {
+                let (kind, params) = named(&args)?;
+                self.shape(&kind, &params)?;
+            }
+
+            "ObjectBegin" => {
+                self.warn(
+                    "object instancing isn't supported, instanced shapes skipped".to_string(),
+                );
+                self.attribute_stack.push(self.attributes.clone());
+                self.in_object = true;
+            }
+            "ObjectEnd" => {
+                self.attributes = self
+                    .attribute_stack
+                    .pop()
+                    .ok_or_else(|| invalid("ObjectEnd without ObjectBegin"))?;
+                self.in_object = false;
+            }
+            "ObjectInstance" => {}
+
+            "Include" | "Import" => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(invalid("pbrt includes nested too deeply"));
+                }
+                let text = fs::read_to_string(self.base.join(string(&args)?))?;
+                self.run(&text, depth + 1)?;
+            }
+            _ => self.warn(format!("{name} skipped: not supported")),
+        }
+        Ok(())
+    }
+
+    // an rgb, blackbody or constant parameter, None when it's missing or
+    // given some other way
+    fn color(&mut self, params: &Params, name: &str) -> Option<Color> {
+        let param = params.get(name)?;
+        let numbers: Vec<Float> = param
+            .values
+            .iter()
+            .filter_map(|value| match value {
+                Value::Number(x) => Some(float(*x)),
+                _ => None,
+            })
+            .collect();
+        match (param.ty.as_str(), &numbers[..]) {
+            ("rgb", &[r, g, b]) => Some(Color::new(r, g, b)),
+            ("blackbody", &[kelvin]) => Some(blackbody_rgb(kelvin)),
+            // pbrt-v3 scales it
+            ("blackbody", &[kelvin, scale]) => Some(scale * blackbody_rgb(kelvin)),
+            ("spectrum" | "float", &[value]) => Some(Color::new(value, value, value)),
+            (ty, _) => {
+                self.warn(format!("{ty} \"{name}\" skipped: using its default"));
+                None
+            }
+        }
+    }
+
+    // eta or k of a conductor, copper by default as in pbrt
+    fn metal_constant(&mut self, params: &Params, name: &str) -> Color {
+        let copper = named_spectrum(&format!("metal-Cu-{name}")).unwrap();
+        if let Some(spectrum) = params.string(name) {
+            return named_spectrum(spectrum).unwrap_or_else(|| {
+                self.warn(format!("spectrum \"{spectrum}\" unknown, using copper"));
+                copper
+            });
+        }
+        self.color(params, name).unwrap_or(copper)
+    }
+
+    fn material(&mut self, kind: &str, params: &Params) -> Option<Arc<dyn Scatter>> {
+        Some(match kind {
+            "diffuse" | "matte" => {
+                let reflectance = self
+                    .color(params, "reflectance")
+                    .or_else(|| self.color(params, "Kd"))
+                    .unwrap_or(Color::new(0.5, 0.5, 0.5));
+                Arc::new(Lambertian::new(reflectance))
+            }
+            "conductor" | "metal" => {
+                // reflectance at normal incidence from the complex ior
+                let f0 = match self.color(params, "reflectance") {
+                    Some(reflectance) => reflectance,
+                    None => {
+                        let eta = self.metal_constant(params, "eta");
+                        let k = self.metal_constant(params, "k");
+                        conductor_f0(eta, k)
+                    }
+                };
+                let default = if kind == "metal" { 0.01 } else { 0.0 };
+                Arc::new(Principled {
+                    metallic: constant(1.0),
+                    roughness: constant(roughness(params, default)),
+                    ..Principled::new(f0)
+                })
+            }
+            "dielectric" | "glass" => {
+                let roughness = roughness(params, 0.0);
+                let bk7 = params.string("eta") == Some("glass-BK7");
+                if let Some(spectrum) = params.string("eta").filter(|_| !bk7) {
+                    self.warn(format!(
+                        "spectrum \"{spectrum}\" unknown, using an ior of 1.5"
+                    ));
+                }
+                let eta = match bk7 {
+                    true => 1.5168,
+                    false => params.float("eta", params.float("index", 1.5)),
+                };
+                if roughness > 0.0 {
+                    Arc::new(Principled {
+                        transmission: constant(1.0),
+                        roughness: constant(roughness),
+                        ior: eta,
+                        ..Principled::new(Color::new(1.0, 1.0, 1.0))
+                    })
+                } else if bk7 {
+                    Arc::new(Dielectric::dispersive(Ior::BK7))
+                } else {
+                    Arc::new(Dielectric::new(eta))
+                }
+            }
+            "interface" | "none" | "" => return None,
+            _ => {
+                self.warn(format!("\"{kind}\" material read as diffuse"));
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+            }
+        })
+    }
+
+    fn shape(&mut self, kind: &str, params: &Params) -> io::Result<()> {
+        if self.in_object {
+            return Ok(());
+        }
+        // area lights are emitters whatever the material, and always two
+        // sided here
+        let material: Arc<dyn Scatter> = match (self.attributes.emission, &self.attributes.material)
+        {
+            (Some(emission), _) => Arc::new(Principled {
+                emission: Arc::new(SolidColor::new(emission)),
+                ..Principled::new(Color::black())
+            }),
+            (None, Some(material)) => material.clone(),
+            (None, None) => return Ok(()),
+        };
+        let to_world = self.mirror * self.attributes.ctm;
+
+        let object: Arc<dyn Hittable> = match kind {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"]
+                    .iter()
+                    .any(|name| params.get(name).is_some())
+                {
+                    self.warn("partial spheres read as whole ones".to_string());
+                }
+                let sphere = Sphere::new(Point3::origin(), params.float("radius", 1.0), material);
+                Arc::new(Instance::new(Arc::new(sphere), to_world))
+            }
+            "trianglemesh" => self.mesh(triangle_mesh(params)?, material, to_world),
+            "plymesh" => {
+                let filename = params
+                    .string("filename")
+                    .ok_or_else(|| invalid("plymesh without a filename"))?;
+                match load_ply(self.base.join(filename)) {
+                    Ok(mesh) => self.mesh(mesh, material, to_world),
+                    Err(error) => {
+                        self.warn(format!("{filename} skipped: {error}"));
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                self.warn(format!("\"{kind}\" shapes skipped: not supported"));
+                return Ok(());
+            }
+        };
+        self.world.add(object);
+        Ok(())
+    }
+
+    fn mesh(
+        &self,
+        mut mesh: TriangleMesh,
+        material: Arc<dyn Scatter>,
+        to_world: Transform,
+    ) -> Arc<dyn Hittable> {
+        for p in mesh.positions.iter_mut() {
+            *p = to_world.point(*p);
+        }
+        for n in mesh.normals.iter_mut() {
+            *n = to_world.normal(*n);
+        }
+        // pbrt faces triangles without normals by their winding, turned
+        // around by reversed orientation and transforms that swap
+        // handedness. The mirror is one of those, it flips the winding here
+        // but not in pbrt.
+        if to_world.swaps_handedness() != self.attributes.reverse_orientation {
+            for [_, b, c] in mesh.indices.iter_mut() {
+                std::mem::swap(b, c);
+            }
+        }
+        Arc::new(Bvh4::new(&mesh.triangles(material)))
+    }
+
+    fn finish(self) -> Pbrt {
+        let mut camera = Camera::default();
+        let width = self.film.float("xresolution", 1280.0);
+        let height = self.film.float("yresolution", 720.0);
+        camera.image_width = width;
+        camera.aspect_ratio = width / height;
+        camera.samples_per_pixel = self.sampler.float("pixelsamples", 16.0) as i32;
+        camera.max_depth = self.integrator.float("maxdepth", 5.0) as i32;
+
+        // pbrt's fov spans the shorter side of the image
+        let fov = self.camera.float("fov", 90.0);
+        camera.vfov = if width >= height {
+            fov
+        } else {
+            let half = degrees_to_radians(fov / 2.0).tan() / camera.aspect_ratio;
+            2.0 * half.atan().to_degrees()
+        };
+
+        // the camera looks down +z with +y up in its own space
+        let to_world = self.mirror * self.camera_from_world.inverse();
+        camera.lookfrom = to_world.point(Point3::origin());
+        camera.lookat = camera.lookfrom + unit_vector(to_world.vector(Vec3::new(0.0, 0.0, 1.0)));
+        camera.vup = to_world.vector(Vec3::new(0.0, 1.0, 0.0));
+
+        let lens_radius = self.camera.float("lensradius", 0.0);
+        let focal_distance = self.camera.float("focaldistance", 1.0e6);
+        camera.focus_dist = 1.0;
+        if lens_radius > 0.0 {
+            camera.focus_dist = focal_distance;
+            camera.defocus_angle = 2.0 * (lens_radius / focal_distance).atan().to_degrees();
+        }
+
+        camera.background = self.background;
+
+        Pbrt {
+            world: self.world,
+            camera,
+            warnings: self.warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    const SCENE: &str = r#"
+# a lit quad and a sphere off to the right
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 30 ]
+Film "rgb" "integer xresolution" 200 "integer yresolution" [ 400 ]
+Sampler "halton" "integer pixelsamples" 64
+WorldBegin
+LightSource "point" "rgb I" [ 1 1 1 ]
+AttributeBegin
+  AreaLightSource "diffuse" "blackbody L" 6500 "float scale" 2
+  Translate 0 0 3
+  Shape "trianglemesh" "point3 P" [ -1 -1 0  1 -1 0  1 1 0  -1 1 0 ]
+    "integer indices" [ 0 1 2  0 2 3 ]
+AttributeEnd
+MakeNamedMaterial "gold" "string type" "conductor"
+  "spectrum eta" "metal-Au-eta" "spectrum k" "metal-Au-k" "float roughness" 0.1
+AttributeBegin
+  NamedMaterial "gold"
+  Translate 2 0 0
+  Shape "sphere" "float radius" 0.5
+AttributeEnd
+Shape "cylinder"
+"#;
+
+    #[test]
+    fn parses_scene() {
+        let pbrt = parse_pbrt(SCENE, Path::new(".")).unwrap();
+        assert_eq!(pbrt.world.objects().len(), 2);
+        assert_eq!(pbrt.warnings.len(), 2, "{:?}", pbrt.warnings);
+
+        let camera = &pbrt.camera;
+        assert_eq!(camera.image_width, 200.0);
+        assert_eq!(camera.aspect_ratio, 0.5);
+        assert_eq!(camera.samples_per_pixel, 64);
+        // the fov was across the width
+        assert!(camera.vfov > 55.0 && camera.vfov < 60.0);
+        assert_eq!(camera.lookfrom, Point3::new(0.0, 0.0, -5.0));
+
+        // pbrt shows +x on the right looking down +z, here -x is, so the
+        // sphere is mirrored over
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+        let r = Ray::new(Point3::new(-2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = pbrt.world.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.5).abs() < 1.0e-3);
+        let r = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(pbrt.world.hit(r, ray_t).is_none());
+
+        // the light faces away from the camera as it does in pbrt, and glows
+        let r = Ray::new(Point3::new(0.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = pbrt.world.hit(r, ray_t).unwrap();
+        assert!((rec.t - 8.0).abs() < 1.0e-3);
+        assert!(!rec.front_face);
+        assert!(rec.material.emitted(rec.u, rec.v, rec.p).g() > 1.5);
+    }
+
+    #[test]
+    fn transforms_and_errors() {
+        // the matrix is given column by column, this one moves by (1, 2, 3)
+        let scene = r#"
+Transform [ 1 0 0 0  0 1 0 0  0 0 1 0  1 2 3 1 ]
+Camera "perspective"
+WorldBegin
+ConcatTransform [ 1 0 0 0  0 1 0 0  0 0 1 0  0 0 4 1 ]
+Shape "sphere"
+"#;
+        let pbrt = parse_pbrt(scene, Path::new(".")).unwrap();
+        // world to camera moved by (1, 2, 3), the camera sits at -(1, 2, 3)
+        // and then gets mirrored
+        assert_eq!(pbrt.camera.lookfrom, Point3::new(1.0, -2.0, -3.0));
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = pbrt
+            .world
+            .hit(r, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-3);
+
+        assert!(parse_pbrt("AttributeEnd", Path::new(".")).is_err());
+        assert!(parse_pbrt("Translate 1 2", Path::new(".")).is_err());
+        assert!(parse_pbrt("Shape \"sphere\" \"float radius\"", Path::new(".")).is_err());
+        assert!(parse_pbrt(
+            "Shape \"trianglemesh\" \"point3 P\" [ 0 0 0 ]",
+            Path::new(".")
+        )
+        .is_err());
+        assert!(parse_pbrt("\"sphere\"", Path::new(".")).is_err());
+    }
+}
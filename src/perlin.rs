@@ -0,0 +1,108 @@
+// Ken Perlin's gradient noise: random unit vectors at the corners of the
+// integer lattice, blended with a hermite curve so the noise is smooth
+// and zero at every lattice point.
+
+use crate::utils::Float;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+use rand::prelude::*;
+
+const POINT_COUNT: usize = 256;
+
+pub struct Perlin {
+    randvec: [Vec3; POINT_COUNT],
+    perm_x: [usize; POINT_COUNT],
+    perm_y: [usize; POINT_COUNT],
+    perm_z: [usize; POINT_COUNT],
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let mut rng = rand::thread_rng();
+        let mut permutation = || {
+            let mut perm = std::array::from_fn(|i| i);
+            perm.shuffle(&mut rng);
+            perm
+        };
+        let (perm_x, perm_y, perm_z) = (permutation(), permutation(), permutation());
+        Perlin {
+            randvec: std::array::from_fn(|_| unit_vector(Vec3::random_bounded(-1.0, 1.0))),
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // in [-1, 1]
+    pub fn noise(&self, p: Point3) -> Float {
+        let [x, y, z] = p.to_array();
+        let (u, v, w) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (i, j, k) = (x.floor() as i64, y.floor() as i64, z.floor() as i64);
+
+        // hermite smoothing so the blend has no creases at cell borders
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let corner = self.randvec[self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize]];
+                    let (fi, fj, fk) = (di as Float, dj as Float, dk as Float);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * dot(corner, weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // sum of `depth` octaves of noise, each twice the frequency and half
+    // the weight of the one before
+    pub fn turb(&self, p: Point3, depth: u32) -> Float {
+        let mut accum = 0.0;
+        let mut temp = Vec3::from(p);
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(Point3::from(temp));
+            weight *= 0.5;
+            temp *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_and_bounded() {
+        let perlin = Perlin::new();
+        // zero on the lattice, wherever the corner vectors point
+        assert!(perlin.noise(Point3::new(3.0, -2.0, 7.0)).abs() < 1.0e-5);
+
+        let mut previous = perlin.noise(Point3::new(0.5, 0.25, 0.125));
+        for i in 1..1000 {
+            let x = 0.5 + i as Float * 0.001;
+            let n = perlin.noise(Point3::new(x, 0.25, 0.125));
+            assert!(n.abs() <= 1.0);
+            assert!((n - previous).abs() < 0.01);
+            previous = n;
+        }
+        assert!(perlin.turb(Point3::new(1.3, 2.7, -0.4), 7) >= 0.0);
+    }
+}
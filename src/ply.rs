@@ -0,0 +1,488 @@
+// Stanford PLY meshes, ascii or binary in either byte order. Vertices may
+// carry normals, colors and texture coordinates, faces are polygons and
+// are fan triangulated unless read as a PolyMesh. Elements other than
+// vertices and faces are skipped.
+
+use crate::color::{gamma_to_linear, Color};
+use crate::mesh::{PolyMesh, TriangleMesh};
+use crate::utils::Float;
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub fn load_ply(path: impl AsRef<Path>) -> io::Result<TriangleMesh> {
+    parse_ply(&fs::read(path)?)
+}
+
+pub fn parse_ply(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    let ply = Ply::read(bytes)?;
+    let indices = ply
+        .faces
+        .iter()
+        .flat_map(|face| (1..face.len() - 1).map(|i| [face[0], face[i], face[i + 1]]))
+        .collect();
+    let mut mesh = TriangleMesh::new(ply.positions, indices);
+    mesh.normals = ply.normals;
+    mesh.uvs = ply.uvs;
+    mesh.colors = ply.colors;
+    Ok(mesh)
+}
+
+pub fn load_ply_polygons(path: impl AsRef<Path>) -> io::Result<PolyMesh> {
+    parse_ply_polygons(&fs::read(path)?)
+}
+
+// the faces as they are, to subdivide. Only the positions and uvs are
+// kept, subdivision makes its own normals.
+pub fn parse_ply_polygons(bytes: &[u8]) -> io::Result<PolyMesh> {
+    let ply = Ply::read(bytes)?;
+    let mesh = PolyMesh::new(ply.positions, ply.faces);
+    Ok(if ply.uvs.is_empty() {
+        mesh
+    } else {
+        mesh.with_uvs(ply.uvs)
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Scalar> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid("unknown ply property type")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // what an integer color channel is out of
+    fn full_scale(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Scalar,
+    // the type of the item count, for list properties
+    count: Option<Scalar>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+// the body after the header, read one value at a time
+struct Body<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Body<'_> {
+    fn read(&mut self, kind: Scalar) -> io::Result<f64> {
+        if let Format::Ascii = self.format {
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(u8::is_ascii_whitespace)
+            {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self
+                .bytes
+                .get(self.pos)
+                .is_some_and(|b| !b.is_ascii_whitespace())
+            {
+                self.pos += 1;
+            }
+            return std::str::from_utf8(&self.bytes[start..self.pos])
+                .ok()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid("bad or missing ply value"));
+        }
+
+        let size = kind.size();
+        let mut raw = [0; 8];
+        raw[..size].copy_from_slice(
+            self.bytes
+                .get(self.pos..self.pos + size)
+                .ok_or_else(|| invalid("ply body too short"))?,
+        );
+        self.pos += size;
+        if let Format::BigEndian = self.format {
+            raw[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = raw;
+        Ok(match kind {
+            Scalar::I8 => b0 as i8 as f64,
+            Scalar::U8 => b0 as f64,
+            Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F64 => f64::from_le_bytes(raw),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Ply {
+    positions: Vec<Point3>,
+    normals: Vec<Normal3>,
+    uvs: Vec<[Float; 2]>,
+    colors: Vec<Color>,
+    faces: Vec<Vec<u32>>,
+}
+
+impl Ply {
+    fn read(bytes: &[u8]) -> io::Result<Ply> {
+        const END: &[u8] = b"end_header";
+        let end = bytes
+            .windows(END.len())
+            .position(|window| window == END)
+            .ok_or_else(|| invalid("ply header has no end"))?;
+        let mut body_start = end + END.len();
+        while bytes
+            .get(body_start)
+            .is_some_and(|&b| b == b'\r' || b == b' ')
+        {
+            body_start += 1;
+        }
+        body_start += 1;
+        let header =
+            std::str::from_utf8(&bytes[..end]).map_err(|_| invalid("ply header isn't text"))?;
+
+        let mut lines = header.lines().map(str::trim);
+        if lines.next() != Some("ply") {
+            return Err(invalid("not a ply file"));
+        }
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", name, _version] => {
+                    format = Some(match *name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::LittleEndian,
+                        "binary_big_endian" => Format::BigEndian,
+                        _ => return Err(invalid("unknown ply format")),
+                    })
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| invalid("bad ply element count"))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count, kind, name] => elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("ply property outside an element"))?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        kind: Scalar::parse(kind)?,
+                        count: Some(Scalar::parse(count)?),
+                    }),
+                ["property", kind, name] => elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("ply property outside an element"))?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        kind: Scalar::parse(kind)?,
+                        count: None,
+                    }),
+                [] | ["comment", ..] | ["obj_info", ..] => {}
+                _ => return Err(invalid("bad ply header line")),
+            }
+        }
+
+        let mut body = Body {
+            bytes,
+            pos: body_start.min(bytes.len()),
+            format: format.ok_or_else(|| invalid("ply header has no format"))?,
+        };
+        let mut ply = Ply::default();
+        for element in &elements {
+            for _ in 0..element.count {
+                let mut values = Vec::with_capacity(element.properties.len());
+                let mut list = Vec::new();
+                for property in &element.properties {
+                    match property.count {
+                        Some(count) => {
+                            // only the vertex indices of faces are kept
+                            let keep =
+                                matches!(property.name.as_str(), "vertex_indices" | "vertex_index");
+                            for _ in 0..body.read(count)? as usize {
+                                let item = body.read(property.kind)?;
+                                if keep {
+                                    list.push(item);
+                                }
+                            }
+                            values.push(0.0);
+                        }
+                        None => values.push(body.read(property.kind)?),
+                    }
+                }
+                match element.name.as_str() {
+                    "vertex" => ply.vertex(element, &values)?,
+                    "face" => {
+                        if list.len() < 3 || list.iter().any(|&i| i < 0.0) {
+                            return Err(invalid("bad ply face"));
+                        }
+                        ply.faces.push(list.iter().map(|&i| i as u32).collect());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if ply
+            .faces
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= ply.positions.len())
+        {
+            return Err(invalid("ply face index out of range"));
+        }
+        Ok(ply)
+    }
+
+    // one vertex from its property values, in header order
+    fn vertex(&mut self, element: &Element, values: &[f64]) -> io::Result<()> {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        #[allow(clippy::unnecessary_cast)] // Float may already be f64
+        let get = |index: usize| values[index] as Float;
+
+        let [x, y, z] = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let (Some(x), Some(y), Some(z)) = (x, y, z) else {
+            return Err(invalid("ply vertex without a position"));
+        };
+        self.positions.push(Point3::new(get(x), get(y), get(z)));
+
+        if let (Some(x), Some(y), Some(z)) = (find(&["nx"]), find(&["ny"]), find(&["nz"])) {
+            let n = Vec3::new(get(x), get(y), get(z));
+            self.normals.push(Normal3::from(if n.near_zero() {
+                n
+            } else {
+                unit_vector(n)
+            }));
+        }
+        if let (Some(u), Some(v)) = (
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ) {
+            self.uvs.push([get(u), get(v)]);
+        }
+        if let (Some(r), Some(g), Some(b)) = (
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+        ) {
+            let channel = |index: usize| {
+                let scale = element.properties[index].kind.full_scale();
+                #[allow(clippy::unnecessary_cast)] // Float may already be f64
+                gamma_to_linear((values[index] / scale) as Float)
+            };
+            self.colors
+                .push(Color::new(channel(r), channel(g), channel(b)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::Principled;
+    use crate::ray::Ray;
+    use crate::texture::Texture;
+    use crate::vec3::Vec3;
+    use std::sync::Arc;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a square of two triangles
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+
+    // the same square, binary in the given byte order
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = ASCII
+            .replace("ascii", format)
+            .split("end_header\n")
+            .next()
+            .unwrap()
+            .to_string()
+            .into_bytes();
+        bytes.extend_from_slice(b"end_header\n");
+        let f = |x: f32| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let i = |x: i32| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        for (p, c) in [
+            ([0.0, 0.0, 0.0], [255, 0, 0]),
+            ([1.0, 0.0, 0.0], [0, 255, 0]),
+            ([1.0, 1.0, 0.0], [0, 0, 255]),
+            ([0.0, 1.0, 0.0], [255, 255, 255]),
+        ] {
+            for x in p {
+                bytes.extend_from_slice(&f(x));
+            }
+            bytes.extend_from_slice(&c);
+        }
+        bytes.push(4);
+        for index in 0..4 {
+            bytes.extend_from_slice(&i(index));
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        let ascii = parse_ply(ASCII.as_bytes()).unwrap();
+        assert_eq!(ascii.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(ascii.colors[1], Color::new(0.0, 1.0, 0.0));
+        assert!(ascii.normals.is_empty() && ascii.uvs.is_empty());
+
+        for big_endian in [false, true] {
+            let mesh = parse_ply(&binary(big_endian)).unwrap();
+            assert_eq!(mesh.positions, ascii.positions);
+            assert_eq!(mesh.colors, ascii.colors);
+            assert_eq!(mesh.indices, ascii.indices);
+        }
+
+        let polygons = parse_ply_polygons(ASCII.as_bytes()).unwrap();
+        assert_eq!(polygons.faces, vec![vec![0, 1, 2, 3]]);
+        assert!(polygons.uvs.is_empty());
+        let textured = ASCII
+            .replace(
+                "property uchar red",
+                "property float u\nproperty float v\nproperty uchar red",
+            )
+            .replace("0 0 0 255 0 0", "0 0 0 0 0 255 0 0")
+            .replace("1 0 0 0 255 0", "1 0 0 1 0 0 255 0")
+            .replace("1 1 0 0 0 255", "1 1 0 1 1 0 0 255")
+            .replace("0 1 0 255 255 255", "0 1 0 0 1 255 255 255");
+        let polygons = parse_ply_polygons(textured.as_bytes()).unwrap();
+        assert_eq!(polygons.uvs[2], [1.0, 1.0]);
+
+        let truncated = binary(false);
+        assert!(parse_ply(&truncated[..truncated.len() - 3]).is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\n").is_err());
+    }
+
+    #[test]
+    fn colors_as_texture() {
+        let mesh = parse_ply(ASCII.as_bytes()).unwrap();
+        let texture: Arc<dyn Texture> = Arc::new(mesh.color_texture().unwrap());
+        let material = Principled {
+            base_color: texture.clone(),
+            ..Principled::default()
+        };
+        let world = mesh.triangles(Arc::new(material));
+        let color_at = |x, y| {
+            let r = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let rec = world.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+            texture.value_at(&rec)
+        };
+        let close = |a: Color, b: Color| (a - b).to_array().iter().all(|x| x.abs() < 1.0e-5);
+
+        // a quarter red and blue and half green, in linear terms
+        let first = color_at(0.75, 0.25);
+        assert!(close(first, Color::new(0.25, 0.5, 0.25)));
+        // the second triangle, half of it from the white corner
+        let second = color_at(0.25, 0.75);
+        assert!(close(second, Color::new(0.75, 0.5, 0.75)));
+        // away from a mesh there's only the fallback
+        assert_eq!(texture.value(0.0, 0.0, Point3::origin()), Color::black());
+    }
+}
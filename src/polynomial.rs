@@ -0,0 +1,160 @@
+// Real roots of low degree polynomials for the analytic primitives. The
+// cubic and quartic work in f64 whatever Float is, tori need the range.
+
+use crate::utils::Float;
+
+use std::f64::consts::PI;
+
+// roots of a t^2 + 2 half_b t + c in increasing order, written to avoid
+// cancellation between -half_b and the square root
+pub fn solve_quadratic(a: Float, half_b: Float, c: Float) -> Option<(Float, Float)> {
+    if a == 0.0 {
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let q = if half_b > 0.0 {
+        -half_b - sqrtd
+    } else {
+        -half_b + sqrtd
+    };
+    if q == 0.0 {
+        // half_b and c are both zero
+        return Some((0.0, 0.0));
+    }
+    let (t0, t1) = (q / a, c / q);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// real roots of x^3 + a x^2 + b x + c, in no particular order
+fn solve_monic_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        (0..3)
+            .map(|k| scale * ((theta + 2.0 * PI * k as f64) / 3.0).cos() - shift)
+            .collect()
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let big_b = if big_a == 0.0 { 0.0 } else { q / big_a };
+        vec![big_a + big_b - shift]
+    }
+}
+
+// real roots of c[4] x^4 + c[3] x^3 + c[2] x^2 + c[1] x + c[0] in
+// increasing order, found with Ferrari's method and polished with a few
+// Newton steps on the original polynomial
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    if c[4] == 0.0 {
+        return Vec::new();
+    }
+    let (a, b, cc, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+
+    // depressed quartic y^4 + p y^2 + q y + r with x = y - a / 4
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut ys = Vec::with_capacity(4);
+    let mut push_quadratic = |b: f64, c: f64| {
+        let discriminant = b * b - 4.0 * c;
+        if discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            ys.push((-b - sqrtd) / 2.0);
+            ys.push((-b + sqrtd) / 2.0);
+        }
+    };
+
+    if q.abs() < 1.0e-12 * (1.0 + p.abs() + r.abs()) {
+        // biquadratic, z = y^2
+        let discriminant = p * p - 4.0 * r;
+        if discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for z in [(-p - sqrtd) / 2.0, (-p + sqrtd) / 2.0] {
+                if z >= 0.0 {
+                    ys.push(-z.sqrt());
+                    ys.push(z.sqrt());
+                }
+            }
+        }
+    } else {
+        // the largest root of the resolvent cubic is positive when q != 0
+        let m = solve_monic_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            push_quadratic(s, p / 2.0 + m - q / (2.0 * s));
+            push_quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+        }
+    }
+
+    let f = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let df = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    let mut roots: Vec<f64> = ys
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..4 {
+                let slope = df(x);
+                if slope == 0.0 {
+                    break;
+                }
+                x -= f(x) / slope;
+            }
+            x
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1.0e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        assert_eq!(solve_quadratic(1.0, -3.0, 8.0), Some((2.0, 4.0)));
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+        assert_eq!(solve_quadratic(0.0, 1.0, -4.0), Some((2.0, 2.0)));
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic([24.0, -50.0, 35.0, -10.0, 1.0]),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 - 1)(x^2 - 9), biquadratic
+        assert_roots(
+            solve_quartic([9.0, 0.0, -10.0, 0.0, 1.0]),
+            &[-3.0, -1.0, 1.0, 3.0],
+        );
+        // (x^2 + 1)(x - 2)(x + 5)
+        assert_roots(solve_quartic([-10.0, 3.0, -9.0, 3.0, 1.0]), &[-5.0, 2.0]);
+        // x^4 + 1
+        assert_roots(solve_quartic([1.0, 0.0, 0.0, 0.0, 1.0]), &[]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::utils::{gamma, Float};
+use crate::vec3::{cross, dot, unit_vector, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Parallelogram with corner q and edges u and v, facing cross(u, v). The
+// hit's u and v are how far along each edge the point is.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    // maps a point on the plane to its edge coordinates, see intersect
+    w: Vec3,
+    normal: Vec3,
+    // the plane is dot(normal, p) = d
+    d: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Scatter>) -> Quad {
+        let n = cross(u, v);
+        let normal = unit_vector(n);
+        Quad {
+            q,
+            u,
+            v,
+            w: n / dot(n, n),
+            normal,
+            d: dot(normal, Vec3::from(q)),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let denom = dot(self.normal, r.direction());
+        if denom.abs() < 1.0e-8 {
+            return None;
+        }
+        let t = (self.d - dot(self.normal, Vec3::from(r.origin()))) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let planar = r.at(t) - self.q;
+        let alpha = dot(self.w, cross(planar, self.v));
+        let beta = dot(self.w, cross(self.u, planar));
+        let unit = Interval::new(0.0, 1.0);
+        if !unit.contains(alpha) || !unit.contains(beta) {
+            return None;
+        }
+
+        let hit = Hit {
+            u: alpha,
+            v: beta,
+            ..Hit::new(t, self)
+        };
+        alpha_test(&*self.material, || {
+            let rec = self.interaction(r, &hit);
+            (rec.u, rec.v, rec.p)
+        })
+        .then_some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::enclosing(
+            Aabb::from_points(self.q, self.q + self.u + self.v),
+            Aabb::from_points(self.q + self.u, self.q + self.v),
+        );
+        // padded so the slab test doesn't miss a box with no thickness
+        let pad = |axis: Interval| {
+            if axis.size() < 1.0e-4 {
+                axis.expand(1.0e-4)
+            } else {
+                axis
+            }
+        };
+        Aabb::new(pad(bbox.axis(0)), pad(bbox.axis(1)), pad(bbox.axis(2)))
+    }
+}
+
+impl Primitive for Quad {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        // rebuilt from the edge coordinates, so it lies on the quad
+        let (along_u, along_v) = (hit.u * self.u, hit.v * self.v);
+        let p = self.q + along_u + along_v;
+        let outward_normal = Normal3::from(self.normal);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error: gamma(7) * (Vec3::from(self.q).abs() + along_u.abs() + along_v.abs()),
+            material: &*self.material,
+            u: hit.u,
+            v: hit.v,
+            dpdu: self.u,
+            dpdv: self.v,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    #[test]
+    fn hits_inside_edges() {
+        let material = gray();
+        // 2 x 4 in the z = 1 plane, facing +z
+        let quad = Quad::new(
+            Point3::new(-1.0, -2.0, 1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 4.0, 0.0),
+            material,
+        );
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::new(0.5, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = quad.hit(r, ray_t).unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+        assert!(rec.front_face);
+        assert!((rec.u - 0.75).abs() < 1.0e-5 && (rec.v - 0.75).abs() < 1.0e-5);
+        assert!(quad.bounding_box().hit(r, ray_t));
+
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!quad.hit(r, ray_t).unwrap().front_face);
+
+        // past an edge, and parallel to the plane
+        let r = Ray::new(Point3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(r, ray_t).is_none());
+        let r = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(quad.hit(r, ray_t).is_none());
+    }
+}
@@ -1,14 +1,32 @@
-use crate::vec3::{Point3, Vec3};
+use crate::utils::{next_float_down, next_float_up, Float};
+use crate::vec3::{dot, Direction, Point3, Vec3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    // hero wavelength in nanometers when rendering in spectral mode
+    wavelength: Option<Float>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(self, wavelength: Float) -> Ray {
+        Ray {
+            wavelength: Some(wavelength),
+            ..self
+        }
+    }
+
+    pub fn wavelength(&self) -> Option<Float> {
+        self.wavelength
     }
 
     pub fn origin(&self) -> Point3 {
@@ -19,11 +37,38 @@ impl Ray {
         self.direction
     }
 
-    pub fn at(&self, t: f32) -> Point3 {
+    pub fn at(&self, t: Float) -> Point3 {
         self.origin() + t * self.direction()
     }
 }
 
+// Moves a ray origin off a surface so the new ray can't hit it again.
+// p_error bounds the absolute error of each coordinate of p, and the point
+// is pushed along n just past that box, on the side `w` leaves towards.
+pub fn offset_ray_origin(p: Point3, p_error: Vec3, n: impl Direction, w: Vec3) -> Point3 {
+    let n = n.to_vec3();
+    let d = dot(n.abs(), p_error);
+    let mut offset = d * n;
+    if dot(w, n) < 0.0 {
+        offset = -offset;
+    }
+    let po = p + offset;
+
+    // round away from p so the offset isn't lost to rounding
+    let [px, py, pz] = po.to_array();
+    let [ox, oy, oz] = offset.to_array();
+    let round = |v: Float, o: Float| {
+        if o > 0.0 {
+            next_float_up(v)
+        } else if o < 0.0 {
+            next_float_down(v)
+        } else {
+            v
+        }
+    };
+    Point3::new(round(px, ox), round(py, oy), round(pz, oz))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,7 +90,7 @@ mod tests {
         let dir = vec3::Vec3::new(1.0, 2.0, 3.0);
         let foo = Ray::new(origin, dir);
 
-        let mut at = foo.at(2.0);
+        let at = foo.at(2.0);
 
         assert_eq!(at.x(), 3.0);
         assert_eq!(at.y(), 6.0);
@@ -0,0 +1,537 @@
+use crate::camera::{Background, Camera};
+use crate::color::Color;
+use crate::constant_medium::ConstantMedium;
+use crate::cuboid::Cuboid;
+use crate::hittable::HittableList;
+use crate::image::{Image, ImageTexture};
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatter};
+use crate::quad::Quad;
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, NoiseTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use crate::utils::{random_double_bounded, Float};
+use crate::vec3::{Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use rand::prelude::*;
+use std::sync::Arc;
+
+// a world together with the camera that frames it
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+    // what had to be left out, like a texture image that isn't there
+    pub warnings: Vec<String>,
+}
+
+type Build = fn() -> Scene;
+
+// the scenes of the first two books, by the names the binary takes
+pub const PRESETS: [(&str, Build); 9] = [
+    ("random_scene", random),
+    ("checkered_spheres", checkered_spheres),
+    ("earth", earth),
+    ("perlin_spheres", perlin_spheres),
+    ("quads", quads),
+    ("simple_light", simple_light),
+    ("cornell_box", cornell_box),
+    ("cornell_smoke", cornell_smoke),
+    ("final_scene", final_scene),
+];
+
+pub fn preset(name: &str) -> Option<Scene> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, build)| build())
+}
+
+// the first book's final scene
+pub fn random_scene() -> HittableList {
+    let mut world: HittableList = HittableList::default();
+
+    let mut rng = rand::thread_rng();
+    let ground_mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let ground_sphere = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
+
+    world.add(Arc::new(ground_sphere));
+
+    for a in -11..=11 {
+        for b in -11..=11 {
+            let choose_mat: f64 = rng.gen();
+            let center = Point3::new(
+                (a as Float) + random_double_bounded(0.0, 0.9),
+                0.2,
+                (b as Float) + random_double_bounded(0.0, 0.9),
+            );
+
+            if choose_mat < 0.8 {
+                // Diffuse
+                let albedo = Color::random_bounded(0.0, 1.0) * Color::random_bounded(0.0, 1.0);
+                let sphere_mat = Arc::new(Lambertian::new(albedo));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.add(Arc::new(sphere));
+            } else if choose_mat < 0.95 {
+                // Metal
+                let albedo = Color::random_bounded(0.4, 1.0);
+                let fuzz = random_double_bounded(0.0, 0.5);
+                let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.add(Arc::new(sphere));
+            } else {
+                // Glass
+                let sphere_mat = Arc::new(Dielectric::new(1.5));
+                let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                world.add(Arc::new(sphere));
+            }
+        }
+    }
+
+    let mat1 = Arc::new(Dielectric::new(1.5));
+    let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+
+    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
+    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
+    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
+
+    world.add(Arc::new(sphere1));
+    world.add(Arc::new(sphere2));
+    world.add(Arc::new(sphere3));
+
+    world
+}
+
+// the camera most of the second book's scenes start from
+fn camera(vfov: Float, lookfrom: Point3, lookat: Point3) -> Camera {
+    let mut camera = Camera::default();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 400.0;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.background = Background::Solid(Color::new(0.7, 0.8, 1.0));
+
+    camera.vfov = vfov;
+    camera.lookfrom = lookfrom;
+    camera.lookat = lookat;
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+    camera
+}
+
+fn scene(world: HittableList, camera: Camera) -> Scene {
+    Scene {
+        world,
+        camera,
+        warnings: Vec::new(),
+    }
+}
+
+fn random() -> Scene {
+    let mut camera = camera(20.0, Point3::new(13.0, 2.0, 3.0), Point3::origin());
+    camera.background = Background::Sky;
+    camera.defocus_angle = 0.6;
+    scene(random_scene(), camera)
+}
+
+fn checkered_spheres() -> Scene {
+    let mut world = HittableList::default();
+    let checker: Arc<dyn Texture> = Arc::new(CheckerTexture::from_colors(
+        0.32,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    for y in [-10.0, 10.0] {
+        let material = Arc::new(Lambertian::from_texture(checker.clone()));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, y, 0.0),
+            10.0,
+            material,
+        )));
+    }
+    scene(
+        world,
+        camera(20.0, Point3::new(13.0, 2.0, 3.0), Point3::origin()),
+    )
+}
+
+// The book's earthmap.jpg, or a png of it, read from the working
+// directory. A plain blue stands in when neither is there.
+fn earth_texture(warnings: &mut Vec<String>) -> Arc<dyn Texture> {
+    let image =
+        Image::load("earthmap.jpg").or_else(|error| Image::load("earthmap.png").map_err(|_| error));
+    match image {
+        Ok(image) => Arc::new(ImageTexture::new(Arc::new(image)).with_srgb()),
+        Err(error) => {
+            warnings.push(format!(
+                "earthmap.jpg not loaded, the earth is plain blue: {error}"
+            ));
+            Arc::new(SolidColor::new(Color::new(0.1, 0.3, 0.7)))
+        }
+    }
+}
+
+fn earth() -> Scene {
+    let mut warnings = Vec::new();
+    let surface = Arc::new(Lambertian::from_texture(earth_texture(&mut warnings)));
+    let mut world = HittableList::default();
+    world.add(Arc::new(Sphere::new(Point3::origin(), 2.0, surface)));
+    Scene {
+        warnings,
+        ..scene(
+            world,
+            camera(20.0, Point3::new(0.0, 0.0, 12.0), Point3::origin()),
+        )
+    }
+}
+
+fn perlin_spheres() -> Scene {
+    let mut world = HittableList::default();
+    let marble = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(4.0))));
+    let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, marble.clone());
+    world.add(Arc::new(ground));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        marble,
+    )));
+    scene(
+        world,
+        camera(20.0, Point3::new(13.0, 2.0, 3.0), Point3::origin()),
+    )
+}
+
+fn quads() -> Scene {
+    let mut world = HittableList::default();
+    // corner, two edges and color of each
+    let quads = [
+        (
+            [-3.0, -2.0, 5.0],
+            [0.0, 0.0, -4.0],
+            [0.0, 4.0, 0.0],
+            [1.0, 0.2, 0.2],
+        ),
+        (
+            [-2.0, -2.0, 0.0],
+            [4.0, 0.0, 0.0],
+            [0.0, 4.0, 0.0],
+            [0.2, 1.0, 0.2],
+        ),
+        (
+            [3.0, -2.0, 1.0],
+            [0.0, 0.0, 4.0],
+            [0.0, 4.0, 0.0],
+            [0.2, 0.2, 1.0],
+        ),
+        (
+            [-2.0, 3.0, 1.0],
+            [4.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0],
+            [1.0, 0.5, 0.0],
+        ),
+        (
+            [-2.0, -3.0, 5.0],
+            [4.0, 0.0, 0.0],
+            [0.0, 0.0, -4.0],
+            [0.2, 0.8, 0.8],
+        ),
+    ];
+    for ([qx, qy, qz], [ux, uy, uz], [vx, vy, vz], [r, g, b]) in quads {
+        world.add(Arc::new(Quad::new(
+            Point3::new(qx, qy, qz),
+            Vec3::new(ux, uy, uz),
+            Vec3::new(vx, vy, vz),
+            Arc::new(Lambertian::new(Color::new(r, g, b))),
+        )));
+    }
+
+    let mut camera = camera(80.0, Point3::new(0.0, 0.0, 9.0), Point3::origin());
+    camera.aspect_ratio = 1.0;
+    scene(world, camera)
+}
+
+fn simple_light() -> Scene {
+    let mut world = HittableList::default();
+    let marble = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(4.0))));
+    let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, marble.clone());
+    world.add(Arc::new(ground));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        marble,
+    )));
+
+    let light = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 7.0, 0.0),
+        2.0,
+        light.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(3.0, 1.0, -2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        light,
+    )));
+
+    let mut camera = camera(
+        20.0,
+        Point3::new(26.0, 3.0, 6.0),
+        Point3::new(0.0, 2.0, 0.0),
+    );
+    camera.background = Background::Solid(Color::black());
+    scene(world, camera)
+}
+
+// the five walls of the 555 wide Cornell box and its ceiling light
+fn cornell_walls(light: Quad) -> HittableList {
+    let mut world = HittableList::default();
+    let red: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    let walls = [
+        (
+            [555.0, 0.0, 0.0],
+            [0.0, 555.0, 0.0],
+            [0.0, 0.0, 555.0],
+            green,
+        ),
+        ([0.0, 0.0, 0.0], [0.0, 555.0, 0.0], [0.0, 0.0, 555.0], red),
+        (
+            [0.0, 0.0, 0.0],
+            [555.0, 0.0, 0.0],
+            [0.0, 0.0, 555.0],
+            white.clone(),
+        ),
+        (
+            [555.0, 555.0, 555.0],
+            [-555.0, 0.0, 0.0],
+            [0.0, 0.0, -555.0],
+            white.clone(),
+        ),
+        (
+            [0.0, 0.0, 555.0],
+            [555.0, 0.0, 0.0],
+            [0.0, 555.0, 0.0],
+            white,
+        ),
+    ];
+    for ([qx, qy, qz], [ux, uy, uz], [vx, vy, vz], material) in walls {
+        world.add(Arc::new(Quad::new(
+            Point3::new(qx, qy, qz),
+            Vec3::new(ux, uy, uz),
+            Vec3::new(vx, vy, vz),
+            material,
+        )));
+    }
+    world.add(Arc::new(light));
+    world
+}
+
+// the tall and the short box, turned and moved into place
+fn cornell_boxes() -> [Instance; 2] {
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let cuboid = |height, degrees, x, z| {
+        let cuboid = Cuboid::new(
+            Point3::origin(),
+            Point3::new(165.0, height, 165.0),
+            white.clone(),
+        );
+        let placement = Transform::translate(Vec3::new(x, 0.0, z))
+            * Transform::rotate(degrees, Vec3::new(0.0, 1.0, 0.0));
+        Instance::new(Arc::new(cuboid), placement)
+    };
+    [
+        cuboid(330.0, 15.0, 265.0, 295.0),
+        cuboid(165.0, -18.0, 130.0, 65.0),
+    ]
+}
+
+fn cornell_camera() -> Camera {
+    let mut camera = camera(
+        40.0,
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+    );
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 600.0;
+    camera.samples_per_pixel = 200;
+    camera.background = Background::Solid(Color::black());
+    camera
+}
+
+fn cornell_box() -> Scene {
+    let light = Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0))),
+    );
+    let mut world = cornell_walls(light);
+    for cuboid in cornell_boxes() {
+        world.add(Arc::new(cuboid));
+    }
+    scene(world, cornell_camera())
+}
+
+fn cornell_smoke() -> Scene {
+    // larger and dimmer than the plain box's light
+    let light = Quad::new(
+        Point3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0))),
+    );
+    let mut world = cornell_walls(light);
+    let [tall, short] = cornell_boxes();
+    world.add(Arc::new(ConstantMedium::new(
+        Arc::new(tall),
+        0.01,
+        Color::black(),
+    )));
+    let white = Color::new(1.0, 1.0, 1.0);
+    world.add(Arc::new(ConstantMedium::new(Arc::new(short), 0.01, white)));
+    scene(world, cornell_camera())
+}
+
+// the second book's final scene
+fn final_scene() -> Scene {
+    let mut world = HittableList::default();
+    let mut warnings = Vec::new();
+
+    // a floor of boxes of random heights
+    let ground = Arc::new(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as Float * w;
+            let z0 = -1000.0 + j as Float * w;
+            let y1 = random_double_bounded(1.0, 101.0);
+            let a = Point3::new(x0, 0.0, z0);
+            let b = Point3::new(x0 + w, y1, z0 + w);
+            world.add(Arc::new(Cuboid::new(a, b, ground.clone())));
+        }
+    }
+
+    let light = Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0)));
+    world.add(Arc::new(Quad::new(
+        Point3::new(123.0, 554.0, 147.0),
+        Vec3::new(300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 265.0),
+        light,
+    )));
+
+    // the book blurs this one moving 30 to the right, without motion
+    // blur here it stays where it starts
+    let orange = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.1)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(400.0, 400.0, 200.0),
+        50.0,
+        orange,
+    )));
+
+    let glass: Arc<dyn Scatter> = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(260.0, 150.0, 45.0),
+        50.0,
+        glass.clone(),
+    )));
+    let brushed = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 150.0, 145.0),
+        50.0,
+        brushed,
+    )));
+
+    // a glass ball filled with blue smoke, and a thin mist over everything
+    let boundary = Arc::new(Sphere::new(
+        Point3::new(360.0, 150.0, 145.0),
+        70.0,
+        glass.clone(),
+    ));
+    world.add(boundary.clone());
+    let blue = Color::new(0.2, 0.4, 0.9);
+    world.add(Arc::new(ConstantMedium::new(boundary, 0.2, blue)));
+    let boundary = Arc::new(Sphere::new(Point3::origin(), 5000.0, glass));
+    let white = Color::new(1.0, 1.0, 1.0);
+    world.add(Arc::new(ConstantMedium::new(boundary, 0.0001, white)));
+
+    let earth = Arc::new(Lambertian::from_texture(earth_texture(&mut warnings)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(400.0, 200.0, 400.0),
+        100.0,
+        earth,
+    )));
+    let marble = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(0.2))));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(220.0, 280.0, 300.0),
+        80.0,
+        marble,
+    )));
+
+    // a cube made of a thousand small spheres
+    let mut cluster = HittableList::default();
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    for _ in 0..1000 {
+        let center = Point3::from(Vec3::random_bounded(0.0, 165.0));
+        cluster.add(Arc::new(Sphere::new(center, 10.0, white.clone())));
+    }
+    let placement = Transform::translate(Vec3::new(-100.0, 270.0, 395.0))
+        * Transform::rotate(15.0, Vec3::new(0.0, 1.0, 0.0));
+    world.add(Arc::new(Instance::new(
+        Arc::new(Bvh4::new(&cluster)),
+        placement,
+    )));
+
+    // the book's quick settings, it also renders at 800 wide with 10000
+    // samples and a depth of 40
+    let mut camera = camera(
+        40.0,
+        Point3::new(478.0, 278.0, -600.0),
+        Point3::new(278.0, 278.0, 0.0),
+    );
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 400.0;
+    camera.samples_per_pixel = 250;
+    camera.max_depth = 4;
+    camera.background = Background::Solid(Color::black());
+    Scene {
+        warnings,
+        ..scene(world, camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    #[test]
+    fn presets_frame_their_scenes() {
+        for (name, _) in PRESETS {
+            let scene = preset(name).unwrap();
+            let camera = &scene.camera;
+            assert!(
+                camera.image_width > 0.0 && camera.samples_per_pixel > 0,
+                "{name}"
+            );
+
+            // something is in front of the camera
+            let r = Ray::new(camera.lookfrom, camera.lookat - camera.lookfrom);
+            let ray_t = Interval::new(0.0, Float::INFINITY);
+            assert!(scene.world.hit(r, ray_t).is_some(), "{name}");
+        }
+        assert!(preset("teapot").is_none());
+    }
+}
@@ -0,0 +1,641 @@
+// Scene scripts, for building scenes without recompiling. They're written
+// in Rhai (https://rhai.rs), which reads like Rust without types:
+//
+//     seed(42);
+//     let glass = dielectric(1.5);
+//     for i in 0..5 {
+//         add(sphere(point(i, 0.5 + random(), 0), 0.5, glass));
+//     }
+//     camera.lookfrom = point(2, 1, 10);
+//
+// On top of the language a script gets points, vectors and colors with
+// their arithmetic, textures, materials, spheres, cuboids, quads and meshes
+// read from files, subdivided or displaced, add() to put an object in
+// the scene, and a `camera` whose settings it assigns. random() draws
+// from a generator seed() sets, so a script builds the same scene every
+// time. Wherever a number is taken an integer will do.
+
+use crate::camera::{Background, Camera};
+use crate::color::Color;
+use crate::cuboid::Cuboid;
+use crate::displacement::Displacement;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::{Image, ImageTexture};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Principled, Scatter};
+use crate::mesh::{PolyMesh, TriangleMesh};
+use crate::obj::{load_obj, load_obj_polygons};
+use crate::ply::{load_ply, load_ply_polygons};
+use crate::quad::Quad;
+use crate::sphere::Sphere;
+use crate::stl::load_stl;
+use crate::subdivision::{Scheme, Subdivision};
+use crate::texture::{CheckerTexture, NoiseTexture, SolidColor, Texture};
+use crate::utils::Float;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+use crate::wide_bvh::Bvh4;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rhai::module_resolvers::FileModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, FLOAT, INT};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// scripts can come from anywhere, these keep one that nests or loops
+// without end from taking the stack or the machine with it
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_OPERATIONS: u64 = 100_000_000;
+// each level of subdivision quadruples the faces
+const MAX_SUBDIVISION_LEVELS: INT = 6;
+
+pub struct Script {
+    pub world: HittableList,
+    pub camera: Camera,
+}
+
+pub fn load_script(path: impl AsRef<Path>) -> io::Result<Script> {
+    let path = path.as_ref();
+    run_script(
+        &fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new(".")),
+    )
+}
+
+// image files and imported modules are looked up in `base`
+pub fn run_script(text: &str, base: &Path) -> io::Result<Script> {
+    let state = Rc::new(RefCell::new(State {
+        rng: StdRng::seed_from_u64(0),
+        world: HittableList::default(),
+    }));
+    let engine = engine(base, &state);
+
+    // the book's camera defaults, scripts set what they need
+    let mut camera = Camera::default();
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 100.0;
+    camera.samples_per_pixel = 10;
+    camera.max_depth = 10;
+    camera.vfov = 90.0;
+    camera.lookat = Point3::new(0.0, 0.0, -1.0);
+    camera.vup = Vec3::new(0.0, 1.0, 0.0);
+    camera.focus_dist = 10.0;
+
+    let mut scope = Scope::new();
+    scope.push("camera", camera);
+    engine
+        .run_with_scope(&mut scope, text)
+        .map_err(|error| invalid(error.to_string()))?;
+
+    let camera = scope
+        .get_value::<Camera>("camera")
+        .ok_or_else(|| invalid("camera was replaced by something else".to_string()))?;
+    let world = std::mem::take(&mut state.borrow_mut().world);
+    Ok(Script { world, camera })
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// what the script's built-ins share
+struct State {
+    rng: StdRng,
+    world: HittableList,
+}
+
+impl State {
+    fn random(&mut self, min: Float, max: Float) -> Float {
+        min + (max - min) * self.rng.gen::<Float>()
+    }
+}
+
+type Fallible<T> = Result<T, Box<EvalAltResult>>;
+
+// Rhai keeps integers and floats apart, scripts shouldn't have to
+fn number(value: Dynamic) -> Fallible<Float> {
+    match value.as_float() {
+        Ok(x) => Ok(x as Float),
+        Err(kind) => match value.as_int() {
+            Ok(n) => Ok(n as Float),
+            Err(_) => Err(format!("expected a number, got {kind}").into()),
+        },
+    }
+}
+
+// a texture, or a color for a constant one
+fn texture(value: Dynamic) -> Fallible<Arc<dyn Texture>> {
+    match value.try_cast_result::<Color>() {
+        Ok(c) => Ok(Arc::new(SolidColor::new(c))),
+        Err(value) => {
+            let kind = value.type_name();
+            value
+                .try_cast::<Arc<dyn Texture>>()
+                .ok_or_else(|| format!("expected a color or a texture, got {kind}").into())
+        }
+    }
+}
+
+// a texture, or a number for a constant one, for the scalar parameters
+// of a material
+fn scalar(value: Dynamic) -> Fallible<Arc<dyn Texture>> {
+    if value.is_float() || value.is_int() {
+        return Ok(Arc::new(SolidColor::from_value(number(value)?)));
+    }
+    let kind = value.type_name();
+    value
+        .try_cast::<Arc<dyn Texture>>()
+        .ok_or_else(|| format!("expected a number or a texture, got {kind}").into())
+}
+
+fn engine(base: &Path, state: &Rc<RefCell<State>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_operations(MAX_OPERATIONS)
+        .set_module_resolver(FileModuleResolver::new_with_path(base));
+    // stdout has the image
+    engine
+        .on_print(|text| eprintln!("{text}"))
+        .on_debug(|text, _, position| eprintln!("{position:?}: {text}"));
+
+    vectors(&mut engine);
+    materials(&mut engine, base);
+    meshes(&mut engine, base);
+    camera(&mut engine);
+
+    engine
+        .register_type_with_name::<Arc<dyn Hittable>>("Object")
+        .register_fn(
+            "sphere",
+            |center: Point3, radius: Dynamic, material: Arc<dyn Scatter>| -> Fallible<_> {
+                let sphere = Sphere::new(center, number(radius)?, material);
+                Ok(Arc::new(sphere) as Arc<dyn Hittable>)
+            },
+        )
+        .register_fn(
+            "cuboid",
+            |a: Point3, b: Point3, material: Arc<dyn Scatter>| {
+                Arc::new(Cuboid::new(a, b, material)) as Arc<dyn Hittable>
+            },
+        )
+        .register_fn(
+            "quad",
+            |q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Scatter>| {
+                Arc::new(Quad::new(q, u, v, material)) as Arc<dyn Hittable>
+            },
+        );
+    let shared = state.clone();
+    engine.register_fn("add", move |object: Arc<dyn Hittable>| {
+        shared.borrow_mut().world.add(object);
+    });
+
+    let shared = state.clone();
+    engine.register_fn("seed", move |seed: INT| {
+        shared.borrow_mut().rng = StdRng::seed_from_u64(seed as u64);
+    });
+    let shared = state.clone();
+    engine.register_fn("random", move || {
+        shared.borrow_mut().random(0.0, 1.0) as FLOAT
+    });
+    let shared = state.clone();
+    engine.register_fn(
+        "random",
+        move |min: Dynamic, max: Dynamic| -> Fallible<FLOAT> {
+            let (min, max) = (number(min)?, number(max)?);
+            Ok(shared.borrow_mut().random(min, max) as FLOAT)
+        },
+    );
+    let shared = state.clone();
+    engine.register_fn("random_color", move || {
+        let mut state = shared.borrow_mut();
+        let [r, g, b] = [(); 3].map(|_| state.random(0.0, 1.0));
+        Color::new(r, g, b)
+    });
+    let shared = state.clone();
+    engine.register_fn(
+        "random_color",
+        move |min: Dynamic, max: Dynamic| -> Fallible<Color> {
+            let (min, max) = (number(min)?, number(max)?);
+            let mut state = shared.borrow_mut();
+            let [r, g, b] = [(); 3].map(|_| state.random(min, max));
+            Ok(Color::new(r, g, b))
+        },
+    );
+    engine
+}
+
+// points, directions and colors, kept apart like they are in Rust
+fn vectors(engine: &mut Engine) {
+    macro_rules! tuple3 {
+        ($type:ty, $name:literal, $new:literal, [$x:ident, $y:ident, $z:ident]) => {
+            engine
+                .register_type_with_name::<$type>($name)
+                .register_fn(
+                    $new,
+                    |x: Dynamic, y: Dynamic, z: Dynamic| -> Fallible<$type> {
+                        Ok(<$type>::new(number(x)?, number(y)?, number(z)?))
+                    },
+                )
+                .register_get(stringify!($x), |v: &mut $type| v.$x() as FLOAT)
+                .register_get(stringify!($y), |v: &mut $type| v.$y() as FLOAT)
+                .register_get(stringify!($z), |v: &mut $type| v.$z() as FLOAT)
+                .register_fn("to_string", |v: &mut $type| format!("{}({v})", $new))
+                .register_fn("==", |a: $type, b: $type| a == b)
+                .register_fn("!=", |a: $type, b: $type| a != b);
+        };
+    }
+    tuple3!(Point3, "Point3", "point", [x, y, z]);
+    tuple3!(Vec3, "Vec3", "vec3", [x, y, z]);
+    tuple3!(Color, "Color", "color", [r, g, b]);
+
+    // scaling by a number on either side, or dividing by one
+    macro_rules! scale {
+        ($type:ty) => {
+            engine
+                .register_fn("*", |v: $type, t: Dynamic| -> Fallible<$type> {
+                    Ok(v * number(t)?)
+                })
+                .register_fn("*", |t: Dynamic, v: $type| -> Fallible<$type> {
+                    Ok(number(t)? * v)
+                })
+                .register_fn("/", |v: $type, t: Dynamic| -> Fallible<$type> {
+                    Ok(v / number(t)?)
+                });
+        };
+    }
+    scale!(Vec3);
+    scale!(Color);
+
+    engine
+        .register_fn("+", |a: Vec3, b: Vec3| a + b)
+        .register_fn("-", |a: Vec3, b: Vec3| a - b)
+        .register_fn("*", |a: Vec3, b: Vec3| a * b)
+        .register_fn("-", |v: Vec3| -v)
+        .register_fn("+", |p: Point3, v: Vec3| p + v)
+        .register_fn("-", |p: Point3, v: Vec3| p - v)
+        .register_fn("-", |p: Point3, q: Point3| p - q)
+        .register_fn("+", |a: Color, b: Color| a + b)
+        .register_fn("-", |a: Color, b: Color| a - b)
+        .register_fn("*", |a: Color, b: Color| a * b)
+        .register_fn("dot", |u: Vec3, v: Vec3| dot(u, v) as FLOAT)
+        .register_fn("cross", |u: Vec3, v: Vec3| cross(u, v))
+        .register_fn("length", |v: Vec3| v.length() as FLOAT)
+        .register_fn("unit", unit_vector);
+}
+
+fn materials(engine: &mut Engine, base: &Path) {
+    let base = base.to_path_buf();
+    engine
+        .register_type_with_name::<Arc<dyn Texture>>("Texture")
+        .register_fn("solid", |c: Color| {
+            Arc::new(SolidColor::new(c)) as Arc<dyn Texture>
+        })
+        .register_fn("image", move |path: &str| -> Fallible<Arc<dyn Texture>> {
+            let image = Image::load(base.join(path)).map_err(|error| format!("{path}: {error}"))?;
+            Ok(Arc::new(ImageTexture::new(Arc::new(image)).with_srgb()))
+        })
+        .register_fn(
+            "checker",
+            |scale: Dynamic, even: Dynamic, odd: Dynamic| -> Fallible<Arc<dyn Texture>> {
+                let checker = CheckerTexture::new(number(scale)?, texture(even)?, texture(odd)?);
+                Ok(Arc::new(checker))
+            },
+        )
+        .register_fn("noise", |scale: Dynamic| -> Fallible<Arc<dyn Texture>> {
+            Ok(Arc::new(NoiseTexture::new(number(scale)?)))
+        });
+
+    engine
+        .register_type_with_name::<Arc<dyn Scatter>>("Material")
+        .register_fn(
+            "lambertian",
+            |albedo: Dynamic| -> Fallible<Arc<dyn Scatter>> {
+                Ok(Arc::new(Lambertian::from_texture(texture(albedo)?)))
+            },
+        )
+        .register_fn(
+            "metal",
+            |c: Color, fuzz: Dynamic| -> Fallible<Arc<dyn Scatter>> {
+                Ok(Arc::new(Metal::new(c, number(fuzz)?)))
+            },
+        )
+        .register_fn("dielectric", |ior: Dynamic| -> Fallible<Arc<dyn Scatter>> {
+            Ok(Arc::new(Dielectric::new(number(ior)?)))
+        })
+        .register_fn("principled", |base: Color| {
+            Arc::new(Principled::new(base)) as Arc<dyn Scatter>
+        })
+        .register_fn("principled", |base: Arc<dyn Texture>| {
+            Arc::new(Principled {
+                base_color: base,
+                ..Principled::default()
+            }) as Arc<dyn Scatter>
+        })
+        // principled(#{ base_color: ..., roughness: ..., ... }), the
+        // parameters left out keep their defaults
+        .register_fn("principled", |params: Map| -> Fallible<Arc<dyn Scatter>> {
+            let mut material = Principled::default();
+            for (name, value) in params {
+                match name.as_str() {
+                    "base_color" => material.base_color = texture(value)?,
+                    "emission" => material.emission = texture(value)?,
+                    "metallic" => material.metallic = scalar(value)?,
+                    "roughness" => material.roughness = scalar(value)?,
+                    "specular" => material.specular = scalar(value)?,
+                    "sheen" => material.sheen = scalar(value)?,
+                    "clearcoat" => material.clearcoat = scalar(value)?,
+                    "clearcoat_gloss" => material.clearcoat_gloss = scalar(value)?,
+                    "transmission" => material.transmission = scalar(value)?,
+                    "ior" => material.ior = number(value)?,
+                    _ => return Err(format!("principled has no parameter {name}").into()),
+                }
+            }
+            Ok(Arc::new(material))
+        })
+        // emits on both sides and reflects nothing
+        .register_fn("light", |emit: Dynamic| -> Fallible<Arc<dyn Scatter>> {
+            Ok(Arc::new(DiffuseLight::from_texture(texture(emit)?)))
+        });
+}
+
+// Meshes from obj, ply and stl files. polygons() keeps an obj's or ply's
+// faces whole for subdivide(), displace() moves a mesh's surface by a
+// height texture, and triangles() turns a mesh into an object:
+//
+//     let cage = polygons("cube.obj");
+//     let smooth = subdivide(cage, "catmull-clark", 3);
+//     add(triangles(displace(smooth, image("bumps.png"), 0.1, 0.02), glass));
+fn meshes(engine: &mut Engine, base: &Path) {
+    let extension = |path: &Path| {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+    };
+    let read =
+        |error: io::Error, path: &str| -> Box<EvalAltResult> { format!("{path}: {error}").into() };
+
+    let dir = base.to_path_buf();
+    engine
+        .register_type_with_name::<TriangleMesh>("Mesh")
+        .register_fn("mesh", move |path: &str| -> Fallible<TriangleMesh> {
+            let file = dir.join(path);
+            match extension(&file).as_deref() {
+                Some("obj") => load_obj(file),
+                Some("ply") => load_ply(file),
+                Some("stl") => load_stl(file),
+                _ => return Err(format!("{path}: expected an obj, ply or stl mesh").into()),
+            }
+            .map_err(|error| read(error, path))
+        })
+        .register_fn(
+            "triangles",
+            |mesh: TriangleMesh, material: Arc<dyn Scatter>| {
+                Arc::new(Bvh4::new(&mesh.triangles(material))) as Arc<dyn Hittable>
+            },
+        );
+
+    let dir = base.to_path_buf();
+    engine
+        .register_type_with_name::<PolyMesh>("Polygons")
+        .register_fn("polygons", move |path: &str| -> Fallible<PolyMesh> {
+            let file = dir.join(path);
+            match extension(&file).as_deref() {
+                Some("obj") => load_obj_polygons(file),
+                Some("ply") => load_ply_polygons(file),
+                _ => return Err(format!("{path}: expected an obj or ply mesh").into()),
+            }
+            .map_err(|error| read(error, path))
+        })
+        .register_fn(
+            "subdivide",
+            |mesh: &mut PolyMesh, scheme: &str, levels: INT| -> Fallible<TriangleMesh> {
+                let scheme = match scheme {
+                    "catmull-clark" => Scheme::CatmullClark,
+                    "loop" => Scheme::Loop,
+                    _ => return Err(format!("no subdivision scheme {scheme:?}").into()),
+                };
+                if !(0..=MAX_SUBDIVISION_LEVELS).contains(&levels) {
+                    return Err(format!(
+                        "{levels} subdivision levels, expected 0 to {MAX_SUBDIVISION_LEVELS}"
+                    )
+                    .into());
+                }
+                Ok(Subdivision::new(scheme, levels as u32).apply(mesh))
+            },
+        );
+
+    // split until no edge is longer than max_edge, then move along the
+    // normals by the height times scale
+    engine.register_fn(
+        "displace",
+        |mesh: TriangleMesh,
+         height: Dynamic,
+         scale: Dynamic,
+         max_edge: Dynamic|
+         -> Fallible<TriangleMesh> {
+            let max_edge = number(max_edge)?;
+            if max_edge.is_nan() || max_edge <= 0.0 {
+                return Err(format!("max edge {max_edge}, expected more than 0").into());
+            }
+            let displacement = Displacement::new(scalar(height)?, number(scale)?);
+            Ok(displacement.with_max_edge(max_edge).apply(mesh))
+        },
+    );
+}
+
+// `camera.<setting> = value` for each public camera setting
+fn camera(engine: &mut Engine) {
+    macro_rules! numbers {
+        ($($field:ident),*) => {
+            $(engine.register_set(
+                stringify!($field),
+                |camera: &mut Camera, value: Dynamic| -> Fallible<()> {
+                    camera.$field = number(value)? as _;
+                    Ok(())
+                },
+            );)*
+        };
+    }
+    numbers!(
+        aspect_ratio,
+        image_width,
+        samples_per_pixel,
+        max_depth,
+        vfov,
+        defocus_angle,
+        focus_dist
+    );
+
+    engine
+        .register_type_with_name::<Camera>("Camera")
+        .register_set("lookfrom", |camera: &mut Camera, p: Point3| {
+            camera.lookfrom = p
+        })
+        .register_set("lookat", |camera: &mut Camera, p: Point3| camera.lookat = p)
+        .register_set("vup", |camera: &mut Camera, v: Vec3| camera.vup = v)
+        .register_set("spectral", |camera: &mut Camera, on: bool| {
+            camera.spectral = on
+        })
+        .register_set("wavefront", |camera: &mut Camera, on: bool| {
+            camera.wavefront = on
+        })
+        // a color, an environment texture, or "sky" for the gradient
+        .register_set("background", |camera: &mut Camera, c: Color| {
+            camera.background = Background::Solid(c)
+        })
+        .register_set(
+            "background",
+            |camera: &mut Camera, texture: Arc<dyn Texture>| {
+                camera.background = Background::Environment(texture, Box::default())
+            },
+        )
+        .register_set(
+            "background",
+            |camera: &mut Camera, name: &str| -> Fallible<()> {
+                match name {
+                    "sky" => camera.background = Background::Sky,
+                    _ => return Err(format!("no background named {name:?}").into()),
+                }
+                Ok(())
+            },
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+
+    #[test]
+    fn runs_random_scene() {
+        let text = include_str!("../scenes/random_scene.rhai");
+        let script = run_script(text, Path::new(".")).unwrap();
+        // the ground, 23 x 23 small spheres and the three big ones
+        assert_eq!(script.world.objects().len(), 1 + 23 * 23 + 3);
+        assert_eq!(script.camera.lookfrom, Point3::new(13.0, 2.0, 3.0));
+        assert_eq!(script.camera.samples_per_pixel, 100);
+        assert!((script.camera.aspect_ratio - 16.0 / 9.0).abs() < 1.0e-6);
+
+        // the same seed places the spheres the same way
+        let again = run_script(text, Path::new(".")).unwrap();
+        let r = Ray::new(Point3::new(0.0, 5.0, 0.5), Vec3::new(0.3, -1.0, 0.1));
+        let t = |world: &HittableList| world.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap().t;
+        assert_eq!(t(&script.world), t(&again.world));
+    }
+
+    #[test]
+    fn builtins() {
+        let text = r#"
+            let v = point(1, 2, 3) + vec3(1, 0, 0) * 2 - vec3(0, 0, 1);
+            camera.vfov = v.x * 6 + 1;
+            camera.max_depth = v.z;
+            camera.background = color(0.1, 0.2, 0.3) / 2;
+            print(`placing a cube at ${v}`);
+            let tiles = checker(0.5, color(1, 1, 1), noise(4));
+            add(cuboid(point(-1, -1, -1), point(1, 1, 1), lambertian(tiles)));
+            add(quad(point(0, 3, 0), vec3(1, 0, 0), vec3(0, 0, 1), light(color(4, 4, 4))));
+        "#;
+        let script = run_script(text, Path::new(".")).unwrap();
+        assert_eq!(script.camera.vfov, 19.0);
+        assert_eq!(script.camera.max_depth, 2);
+        assert!(matches!(script.camera.background, Background::Solid(c) if c.b() == 0.15));
+        assert_eq!(script.world.objects().len(), 2);
+
+        // every parameter of the principled material, textures included
+        let text = r#"
+            let lamp = principled(#{
+                base_color: color(1, 1, 1),
+                metallic: solid(color(0.5, 0.5, 0.5)),
+                roughness: 0.3,
+                clearcoat: 1,
+                emission: color(2, 3, 4),
+                ior: 1.45,
+            });
+            add(sphere(point(0, 0, 0), 1, lamp));
+        "#;
+        let script = run_script(text, Path::new(".")).unwrap();
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = script
+            .world
+            .hit(r, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert_eq!(
+            rec.material.emitted(rec.u, rec.v, rec.p),
+            Color::new(2.0, 3.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn meshes() {
+        let dir = std::env::temp_dir().join(format!("script-meshes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cube = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+                    v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+                    f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\nf 2 3 7 6\nf 3 4 8 7\nf 4 1 5 8\n";
+        fs::write(dir.join("cube.obj"), cube).unwrap();
+
+        // the z = 1 face, straight on
+        let t = |text: &str| {
+            let script = run_script(text, &dir).unwrap();
+            let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let rec = script
+                .world
+                .hit(r, Interval::new(0.0, Float::INFINITY))
+                .unwrap();
+            rec.t
+        };
+        let gray = "lambertian(color(0.5, 0.5, 0.5))";
+        let plain = t(&format!(r#"add(triangles(mesh("cube.obj"), {gray}));"#));
+        // smoothing pulls the faces in towards the center
+        let smooth = t(&format!(
+            r#"add(triangles(subdivide(polygons("cube.obj"), "catmull-clark", 3), {gray}));"#
+        ));
+        // a constant height pushes the faces out evenly
+        let pushed = t(&format!(
+            r#"add(triangles(displace(mesh("cube.obj"), 1, 0.5, 0.25), {gray}));"#
+        ));
+        let message = |text: &str| run_script(text, &dir).err().unwrap().to_string();
+        let errors = [
+            message(r#"subdivide(polygons("cube.obj"), "butterfly", 1)"#),
+            message(r#"subdivide(polygons("cube.obj"), "loop", 7)"#),
+            message(r#"mesh("cube.fbx")"#),
+            message(r#"mesh("missing.ply")"#),
+            message(r#"displace(mesh("cube.obj"), 1, 0.5, 0)"#),
+        ];
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!((plain - 4.0).abs() < 1.0e-4);
+        assert!(smooth > 4.1 && smooth < 5.0, "{smooth}");
+        assert!(errors[0].contains("no subdivision scheme \"butterfly\""));
+        assert!(errors[1].contains("expected 0 to 6"));
+        assert!(errors[2].contains("expected an obj, ply or stl mesh"));
+        assert!((pushed - 3.5).abs() < 1.0e-3, "{pushed}");
+        assert!(errors[3].contains("missing.ply"));
+        assert!(errors[4].contains("expected more than 0"));
+    }
+
+    #[test]
+    fn errors_and_limits() {
+        let message = |text: &str| run_script(text, Path::new(".")).err().unwrap().to_string();
+        assert!(message("let a = 1;\nlet b = c;").contains("line 2"));
+        assert!(message(r#"sphere(point(0, 0, 0), "big", dielectric(1.5))"#)
+            .contains("expected a number, got string"));
+        assert!(message("camera.zoom = 2;").contains("zoom"));
+        assert!(message(r#"principled(#{ roughness: "rough" })"#)
+            .contains("expected a number or a texture, got string"));
+        assert!(message("principled(#{ shine: 1 })").contains("no parameter shine"));
+        assert!(message("let camera = 1;").contains("camera was replaced"));
+        // loops that never end, and nesting that would take the stack
+        assert!(message("while true {}").contains("Too many operations"));
+        let deep = format!("let x = {}1{};", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(message(&deep).contains("maximum complexity"));
+        assert!(message("fn f(n) { f(n + 1) } f(0);").contains("Stack overflow"));
+    }
+}
@@ -0,0 +1,503 @@
+// Shapes given by signed distance functions, negative inside, and drawn by
+// sphere tracing: stepping along the ray by the distance to the nearest
+// surface, which can't overshoot it. Distance functions are built from
+// the primitives and combinators below and wrapped in a DistanceField to
+// go into a scene.
+
+use crate::aabb::Aabb;
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::utils::{Float, PI};
+use crate::vec3::{unit_vector, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: Point3) -> Float;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // How much faster than the distance to the surface the function can
+    // change. Distorting combinators are no longer exact distances, the
+    // marcher divides its steps by this to stay on the safe side.
+    fn lipschitz(&self) -> Float {
+        1.0
+    }
+}
+
+pub struct Sphere {
+    center: Point3,
+    radius: Float,
+}
+
+impl Sphere {
+    pub fn new(center: Point3, radius: Float) -> Sphere {
+        Sphere { center, radius }
+    }
+}
+
+impl Sdf for Sphere {
+    fn distance(&self, p: Point3) -> Float {
+        (p - self.center).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let corner = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - corner, self.center + corner)
+    }
+}
+
+// axis aligned box given by its center and half its size along each axis
+pub struct Cuboid {
+    center: Point3,
+    half_size: Vec3,
+}
+
+impl Cuboid {
+    pub fn new(center: Point3, half_size: Vec3) -> Cuboid {
+        Cuboid { center, half_size }
+    }
+}
+
+fn box_distance(local: Vec3, half_size: Vec3) -> Float {
+    let q = local.abs() - half_size;
+    let outside = q.max(Vec3::zero()).length();
+    let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+    outside + inside
+}
+
+impl Sdf for Cuboid {
+    fn distance(&self, p: Point3) -> Float {
+        box_distance(p - self.center, self.half_size)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.center - self.half_size, self.center + self.half_size)
+    }
+}
+
+// box whose edges and corners are rounded off with `radius`, within the
+// same half size
+pub struct RoundedBox {
+    center: Point3,
+    half_size: Vec3,
+    radius: Float,
+}
+
+impl RoundedBox {
+    pub fn new(center: Point3, half_size: Vec3, radius: Float) -> RoundedBox {
+        let smallest = half_size.x().min(half_size.y()).min(half_size.z());
+        RoundedBox {
+            center,
+            half_size,
+            radius: radius.clamp(0.0, smallest),
+        }
+    }
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, p: Point3) -> Float {
+        let inner = self.half_size - Vec3::new(self.radius, self.radius, self.radius);
+        box_distance(p - self.center, inner) - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.center - self.half_size, self.center + self.half_size)
+    }
+}
+
+// torus around the y axis, like the analytic one
+pub struct Torus {
+    center: Point3,
+    major_radius: Float,
+    minor_radius: Float,
+}
+
+impl Torus {
+    pub fn new(center: Point3, major_radius: Float, minor_radius: Float) -> Torus {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: Point3) -> Float {
+        let [x, y, z] = (p - self.center).to_array();
+        let ring = (x * x + z * z).sqrt() - self.major_radius;
+        (ring * ring + y * y).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = self.major_radius + self.minor_radius;
+        let corner = Vec3::new(extent, self.minor_radius, extent);
+        Aabb::from_points(self.center - corner, self.center + corner)
+    }
+}
+
+// union of two shapes blended over about `k` where they meet
+pub struct SmoothUnion {
+    a: Arc<dyn Sdf>,
+    b: Arc<dyn Sdf>,
+    k: Float,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: Float) -> SmoothUnion {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Point3) -> Float {
+        let (d1, d2) = (self.a.distance(p), self.b.distance(p));
+        if self.k <= 0.0 {
+            return d1.min(d2);
+        }
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // the blend only fills in the creases, which stay inside the
+        // boxes of the two shapes up to k
+        let bbox = Aabb::enclosing(self.a.bounding_box(), self.b.bounding_box());
+        let k = 2.0 * self.k.max(0.0);
+        Aabb::new(bbox.x.expand(k), bbox.y.expand(k), bbox.z.expand(k))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.a.lipschitz().max(self.b.lipschitz())
+    }
+}
+
+// `b` carved out of `a`, the cut edge rounded over about `k`
+pub struct SmoothSubtraction {
+    a: Arc<dyn Sdf>,
+    b: Arc<dyn Sdf>,
+    k: Float,
+}
+
+impl SmoothSubtraction {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: Float) -> SmoothSubtraction {
+        SmoothSubtraction { a, b, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: Point3) -> Float {
+        let (d1, d2) = (self.a.distance(p), -self.b.distance(p));
+        if self.k <= 0.0 {
+            return d1.max(d2);
+        }
+        let h = (0.5 - 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h + self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.a.bounding_box()
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.a.lipschitz().max(self.b.lipschitz())
+    }
+}
+
+// Copies of a shape built around the origin, `spacing` apart and `count`
+// of them on either side of the original along each axis. The shape
+// should fit within one spacing for the distance to stay exact.
+pub struct Repeat {
+    sdf: Arc<dyn Sdf>,
+    spacing: Vec3,
+    count: [u32; 3],
+}
+
+impl Repeat {
+    pub fn new(sdf: Arc<dyn Sdf>, spacing: Vec3, count: [u32; 3]) -> Repeat {
+        Repeat {
+            sdf,
+            spacing,
+            count,
+        }
+    }
+
+    fn extent(&self) -> Vec3 {
+        let [x, y, z] = self.count.map(|n| n as Float);
+        self.spacing * Vec3::new(x, y, z)
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: Point3) -> Float {
+        let p = p.to_array();
+        let spacing = self.spacing.to_array();
+        let mut local = [0.0; 3];
+        for axis in 0..3 {
+            let limit = self.count[axis] as Float;
+            let cell = if spacing[axis] > 0.0 {
+                (p[axis] / spacing[axis]).round().clamp(-limit, limit)
+            } else {
+                0.0
+            };
+            local[axis] = p[axis] - spacing[axis] * cell;
+        }
+        self.sdf.distance(Point3::new(local[0], local[1], local[2]))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.sdf.bounding_box();
+        let extent = self.extent();
+        Aabb::new(
+            Interval::new(bbox.x.min - extent.x(), bbox.x.max + extent.x()),
+            Interval::new(bbox.y.min - extent.y(), bbox.y.max + extent.y()),
+            Interval::new(bbox.z.min - extent.z(), bbox.z.max + extent.z()),
+        )
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.sdf.lipschitz()
+    }
+}
+
+// a shape twisted around the y axis by `rate` radians per unit of height
+pub struct Twist {
+    sdf: Arc<dyn Sdf>,
+    rate: Float,
+    // farthest the shape reaches from the y axis
+    reach: Float,
+}
+
+impl Twist {
+    pub fn new(sdf: Arc<dyn Sdf>, rate: Float) -> Twist {
+        let bbox = sdf.bounding_box();
+        let x = bbox.x.min.abs().max(bbox.x.max.abs());
+        let z = bbox.z.min.abs().max(bbox.z.max.abs());
+        Twist {
+            sdf,
+            rate,
+            reach: (x * x + z * z).sqrt(),
+        }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: Point3) -> Float {
+        let (sin, cos) = (-self.rate * p.y()).sin_cos();
+        let twisted = Point3::new(cos * p.x() - sin * p.z(), p.y(), sin * p.x() + cos * p.z());
+        self.sdf.distance(twisted)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let y = self.sdf.bounding_box().y;
+        let around = Interval::new(-self.reach, self.reach);
+        Aabb::new(around, y, around)
+    }
+
+    fn lipschitz(&self) -> Float {
+        // the rotation shears points at the reach by rate * reach per
+        // unit of height
+        let shear = self.rate * self.reach;
+        self.sdf.lipschitz() * (1.0 + shear * shear).sqrt()
+    }
+}
+
+// A distance function placed in the scene. There is no natural surface
+// parameterization, u and v map the normal's direction like a sphere's.
+pub struct DistanceField {
+    sdf: Arc<dyn Sdf>,
+    material: Arc<dyn Scatter>,
+    bbox: Aabb,
+    // how close to the surface counts as on it, also the step for the
+    // normal's central differences
+    epsilon: Float,
+    max_steps: u32,
+}
+
+impl DistanceField {
+    pub fn new(sdf: Arc<dyn Sdf>, material: Arc<dyn Scatter>) -> DistanceField {
+        let bbox = sdf.bounding_box();
+        let size = bbox.x.size().max(bbox.y.size()).max(bbox.z.size());
+        let epsilon = 1.0e-4 * size.max(Float::MIN_POSITIVE);
+        DistanceField {
+            sdf,
+            material,
+            bbox: Aabb::EMPTY,
+            epsilon,
+            max_steps: 256,
+        }
+        .with_epsilon(epsilon)
+    }
+
+    pub fn with_epsilon(self, epsilon: Float) -> DistanceField {
+        // room to start marching from just outside the surface
+        let pad = 8.0 * epsilon;
+        let bbox = self.sdf.bounding_box();
+        DistanceField {
+            epsilon,
+            bbox: Aabb::new(bbox.x.expand(pad), bbox.y.expand(pad), bbox.z.expand(pad)),
+            ..self
+        }
+    }
+
+    pub fn with_max_steps(self, max_steps: u32) -> DistanceField {
+        DistanceField { max_steps, ..self }
+    }
+
+    fn normal(&self, p: Point3) -> Vec3 {
+        let h = self.epsilon;
+        let along = |offset: Vec3| self.sdf.distance(p + offset) - self.sdf.distance(p - offset);
+        unit_vector(Vec3::new(
+            along(Vec3::new(h, 0.0, 0.0)),
+            along(Vec3::new(0.0, h, 0.0)),
+            along(Vec3::new(0.0, 0.0, h)),
+        ))
+    }
+}
+
+impl Hittable for DistanceField {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let span = self.bbox.clip(r, ray_t)?;
+        // distances are in world units, t is in lengths of the direction
+        let speed = r.direction().length() * self.sdf.lipschitz();
+
+        let mut t = span.min;
+        // still next to a surface whose hit the alpha test cut away
+        let mut skipping = false;
+        for _ in 0..self.max_steps {
+            if t > span.max {
+                break;
+            }
+            // the magnitude lets rays that start inside march out too
+            let distance = self.sdf.distance(r.at(t)).abs();
+            if distance < self.epsilon {
+                if !skipping && ray_t.surrounds(t) {
+                    let hit = Hit::new(t, self);
+                    let visible = alpha_test(&*self.material, || {
+                        let rec = self.interaction(r, &hit);
+                        (rec.u, rec.v, rec.p)
+                    });
+                    if visible {
+                        return Some(hit);
+                    }
+                    skipping = true;
+                }
+            } else {
+                skipping = false;
+            }
+            t += distance.max(self.epsilon) / speed;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Primitive for DistanceField {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let p = r.at(hit.t);
+        let outward = self.normal(p);
+
+        let theta = (-outward.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-outward.z()).atan2(outward.x()) + PI;
+        let onb = Onb::build_from_w(outward);
+
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            // the march stops anywhere within epsilon of the surface, so
+            // spawned rays have to clear twice that
+            p_error: Vec3::new(2.0, 2.0, 2.0) * self.epsilon,
+            material: &*self.material,
+            u: phi / (2.0 * PI),
+            v: theta / PI,
+            dpdu: onb.u(),
+            dpdv: onb.v(),
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    #[test]
+    fn distances() {
+        let cube = Cuboid::new(Point3::origin(), Vec3::new(1.0, 1.0, 1.0));
+        assert!((cube.distance(Point3::new(3.0, 0.0, 0.0)) - 2.0).abs() < 1.0e-6);
+        assert!((cube.distance(Point3::new(0.5, 0.0, 0.0)) + 0.5).abs() < 1.0e-6);
+        assert!((cube.distance(Point3::new(2.0, 2.0, 1.0)) - Float::sqrt(2.0)).abs() < 1.0e-6);
+
+        // the faces stay put, the corners are cut off
+        let rounded = RoundedBox::new(Point3::origin(), Vec3::new(1.0, 1.0, 1.0), 0.5);
+        assert!((rounded.distance(Point3::new(1.5, 0.0, 0.0)) - 0.5).abs() < 1.0e-6);
+        assert!(rounded.distance(Point3::new(1.0, 1.0, 1.0)) > 0.3);
+
+        let torus = Torus::new(Point3::origin(), 2.0, 0.5);
+        assert!((torus.distance(Point3::new(0.0, 0.0, 2.0)) + 0.5).abs() < 1.0e-6);
+        assert!((torus.distance(Point3::origin()) - 1.5).abs() < 1.0e-6);
+
+        // a blended union is never farther than either shape
+        let a: Arc<dyn Sdf> = Arc::new(Sphere::new(Point3::new(-1.0, 0.0, 0.0), 1.0));
+        let b: Arc<dyn Sdf> = Arc::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0));
+        let blend = SmoothUnion::new(a.clone(), b.clone(), 0.5);
+        let p = Point3::new(0.0, 1.2, 0.0);
+        assert!(blend.distance(p) < a.distance(p).min(b.distance(p)));
+
+        let carved = SmoothSubtraction::new(a, b, 0.0);
+        assert!(carved.distance(Point3::new(-1.5, 0.0, 0.0)) < 0.0);
+        assert!(carved.distance(Point3::new(0.5, 0.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn repeat_and_twist() {
+        let ball: Arc<dyn Sdf> = Arc::new(Sphere::new(Point3::origin(), 0.5));
+        let row = Repeat::new(ball.clone(), Vec3::new(2.0, 0.0, 0.0), [2, 0, 0]);
+        assert!(row.distance(Point3::new(4.0, 0.0, 0.0)) < 0.0);
+        // beyond the last copy
+        assert!((row.distance(Point3::new(7.0, 0.0, 0.0)) - 2.5).abs() < 1.0e-5);
+        let bbox = row.bounding_box();
+        assert!((bbox.x.min + 4.5).abs() < 1.0e-6 && (bbox.x.max - 4.5).abs() < 1.0e-6);
+
+        let bar: Arc<dyn Sdf> = Arc::new(Cuboid::new(Point3::origin(), Vec3::new(1.0, 2.0, 0.2)));
+        let twisted = Twist::new(bar, PI / 4.0);
+        // at y = 2 the bar has turned by 90 degrees
+        assert!(twisted.distance(Point3::new(0.0, 1.9, 0.9)) < 0.0);
+        assert!(twisted.distance(Point3::new(0.9, 1.9, 0.0)) > 0.0);
+        assert!(twisted.lipschitz() > 1.0);
+    }
+
+    #[test]
+    fn traced_sphere() {
+        let ball = Arc::new(Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5));
+        let field = DistanceField::new(ball, gray());
+        let ray_t = Interval::new(0.0, Float::INFINITY);
+
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, -2.0));
+        let rec = field.hit(r, ray_t).unwrap();
+        assert!((rec.t - 0.75).abs() < 1.0e-3);
+        assert!(rec.front_face);
+        assert!((Vec3::from(rec.normal) - Vec3::new(0.0, 0.0, 1.0)).length() < 1.0e-3);
+
+        // a ray spawned through the surface finds the far side
+        let rec = field.hit(rec.spawn_ray(r.direction()), ray_t).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.p.z() + 2.5).abs() < 1.0e-3);
+
+        let r = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(field.hit(r, ray_t).is_none());
+    }
+}
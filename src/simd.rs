@@ -0,0 +1,270 @@
+// Four lane vector backing the 3d math types. For f32 on x86_64 it maps onto
+// SSE registers (part of the baseline, so no runtime detection is needed),
+// for f64, other targets or with the `scalar-math` feature it falls back to
+// plain arrays.
+
+pub use imp::Float4;
+
+impl std::fmt::Debug for Float4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.to_array().fmt(f)
+    }
+}
+
+impl Default for Float4 {
+    fn default() -> Float4 {
+        Float4::splat(0.0)
+    }
+}
+
+#[cfg(all(
+    target_arch = "x86_64",
+    not(feature = "scalar-math"),
+    not(feature = "f64")
+))]
+mod imp {
+    use std::arch::x86_64::*;
+    use std::ops;
+
+    // yzx lane order, used by the cross product
+    const YZXW: i32 = 0b11_00_10_01;
+    // lane 1 and lane 2 broadcast into lane 0
+    const YYYY: i32 = 0b01_01_01_01;
+    const ZZZZ: i32 = 0b10_10_10_10;
+
+    #[derive(Clone, Copy)]
+    #[repr(transparent)]
+    pub struct Float4(__m128);
+
+    // SAFETY (all blocks below): sse and sse2 are enabled on every x86_64
+    // target, and none of the intrinsics touch memory except through
+    // references to properly sized arrays.
+    impl Float4 {
+        #[inline]
+        pub fn new(a: f32, b: f32, c: f32, d: f32) -> Float4 {
+            unsafe { Float4(_mm_set_ps(d, c, b, a)) }
+        }
+
+        #[inline]
+        pub fn splat(v: f32) -> Float4 {
+            unsafe { Float4(_mm_set1_ps(v)) }
+        }
+
+        #[inline]
+        pub fn to_array(self) -> [f32; 4] {
+            let mut out = [0.0; 4];
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0) };
+            out
+        }
+
+        // single lanes, read straight from the register
+        #[inline]
+        pub fn x(self) -> f32 {
+            unsafe { _mm_cvtss_f32(self.0) }
+        }
+
+        #[inline]
+        pub fn y(self) -> f32 {
+            unsafe { _mm_cvtss_f32(_mm_shuffle_ps::<YYYY>(self.0, self.0)) }
+        }
+
+        #[inline]
+        pub fn z(self) -> f32 {
+            unsafe { _mm_cvtss_f32(_mm_shuffle_ps::<ZZZZ>(self.0, self.0)) }
+        }
+
+        #[inline]
+        pub fn min(self, o: Float4) -> Float4 {
+            unsafe { Float4(_mm_min_ps(self.0, o.0)) }
+        }
+
+        #[inline]
+        pub fn max(self, o: Float4) -> Float4 {
+            unsafe { Float4(_mm_max_ps(self.0, o.0)) }
+        }
+
+        // sum of the first three lanes
+        #[inline]
+        pub fn sum3(self) -> f32 {
+            self.x() + self.y() + self.z()
+        }
+
+        // cross product of the first three lanes, the fourth stays zero
+        #[inline]
+        pub fn cross3(self, o: Float4) -> Float4 {
+            unsafe {
+                let a_yzx = _mm_shuffle_ps::<YZXW>(self.0, self.0);
+                let b_yzx = _mm_shuffle_ps::<YZXW>(o.0, o.0);
+                let c = _mm_sub_ps(_mm_mul_ps(self.0, b_yzx), _mm_mul_ps(a_yzx, o.0));
+                Float4(_mm_shuffle_ps::<YZXW>(c, c))
+            }
+        }
+
+        // bit i is set when lane i of self is <= lane i of o
+        #[inline]
+        pub fn le_mask(self, o: Float4) -> u32 {
+            unsafe { _mm_movemask_ps(_mm_cmple_ps(self.0, o.0)) as u32 }
+        }
+    }
+
+    impl PartialEq for Float4 {
+        #[inline]
+        fn eq(&self, o: &Float4) -> bool {
+            unsafe { _mm_movemask_ps(_mm_cmpeq_ps(self.0, o.0)) == 0b1111 }
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $fn:ident, $intrinsic:ident) => {
+            impl ops::$trait for Float4 {
+                type Output = Float4;
+
+                #[inline]
+                fn $fn(self, o: Float4) -> Float4 {
+                    unsafe { Float4($intrinsic(self.0, o.0)) }
+                }
+            }
+        };
+    }
+
+    binary_op!(Add, add, _mm_add_ps);
+    binary_op!(Sub, sub, _mm_sub_ps);
+    binary_op!(Mul, mul, _mm_mul_ps);
+    binary_op!(Div, div, _mm_div_ps);
+}
+
+#[cfg(not(all(
+    target_arch = "x86_64",
+    not(feature = "scalar-math"),
+    not(feature = "f64")
+)))]
+mod imp {
+    use crate::utils::Float;
+    use std::ops;
+
+    #[derive(Clone, Copy)]
+    #[repr(C, align(16))]
+    pub struct Float4([Float; 4]);
+
+    impl Float4 {
+        #[inline]
+        pub fn new(a: Float, b: Float, c: Float, d: Float) -> Float4 {
+            Float4([a, b, c, d])
+        }
+
+        #[inline]
+        pub fn splat(v: Float) -> Float4 {
+            Float4([v; 4])
+        }
+
+        #[inline]
+        pub fn to_array(self) -> [Float; 4] {
+            self.0
+        }
+
+        #[inline]
+        pub fn x(self) -> Float {
+            self.0[0]
+        }
+
+        #[inline]
+        pub fn y(self) -> Float {
+            self.0[1]
+        }
+
+        #[inline]
+        pub fn z(self) -> Float {
+            self.0[2]
+        }
+
+        #[inline]
+        fn zip(self, o: Float4, f: impl Fn(Float, Float) -> Float) -> Float4 {
+            let (a, b) = (self.0, o.0);
+            Float4([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])])
+        }
+
+        #[inline]
+        pub fn min(self, o: Float4) -> Float4 {
+            self.zip(o, Float::min)
+        }
+
+        #[inline]
+        pub fn max(self, o: Float4) -> Float4 {
+            self.zip(o, Float::max)
+        }
+
+        #[inline]
+        pub fn sum3(self) -> Float {
+            self.0[0] + self.0[1] + self.0[2]
+        }
+
+        #[inline]
+        pub fn cross3(self, o: Float4) -> Float4 {
+            let (a, b) = (self.0, o.0);
+            Float4([
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+                0.0,
+            ])
+        }
+
+        #[inline]
+        pub fn le_mask(self, o: Float4) -> u32 {
+            (0..4).fold(0, |mask, i| mask | ((self.0[i] <= o.0[i]) as u32) << i)
+        }
+    }
+
+    impl PartialEq for Float4 {
+        #[inline]
+        fn eq(&self, o: &Float4) -> bool {
+            self.0 == o.0
+        }
+    }
+
+    macro_rules! binary_op {
+        ($trait:ident, $fn:ident, $op:tt) => {
+            impl ops::$trait for Float4 {
+                type Output = Float4;
+
+                #[inline]
+                fn $fn(self, o: Float4) -> Float4 {
+                    self.zip(o, |a, b| a $op b)
+                }
+            }
+        };
+    }
+
+    binary_op!(Add, add, +);
+    binary_op!(Sub, sub, -);
+    binary_op!(Mul, mul, *);
+    binary_op!(Div, div, /);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Float;
+
+    #[test]
+    fn arithmetic() {
+        let a = Float4::new(1.0, 2.0, 3.0, 0.0);
+        let b = Float4::new(4.0, 5.0, 6.0, 0.0);
+
+        assert_eq!((a + b).to_array(), [5.0, 7.0, 9.0, 0.0]);
+        assert_eq!((b - a).to_array(), [3.0, 3.0, 3.0, 0.0]);
+        assert_eq!((a * b).to_array(), [4.0, 10.0, 18.0, 0.0]);
+        assert_eq!((a * b).sum3(), 32.0);
+        assert_eq!((b.x(), b.y(), b.z()), (4.0, 5.0, 6.0));
+        assert_eq!(a.le_mask(Float4::new(1.0, 1.0, 4.0, Float::NAN)), 0b0101);
+    }
+
+    #[test]
+    fn cross() {
+        let x = Float4::new(1.0, 0.0, 0.0, 0.0);
+        let y = Float4::new(0.0, 1.0, 0.0, 0.0);
+
+        assert_eq!(x.cross3(y).to_array(), [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(y.cross3(x).to_array(), [0.0, 0.0, -1.0, 0.0]);
+    }
+}
@@ -0,0 +1,369 @@
+use std::ops;
+use std::sync::OnceLock;
+
+use crate::color::Color;
+use crate::utils::Float;
+
+// visible range traced in spectral mode, in nanometers
+pub const LAMBDA_MIN: Float = 360.0;
+pub const LAMBDA_MAX: Float = 830.0;
+
+// number of wavelengths carried by every camera sample
+pub const N_SAMPLES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampledSpectrum {
+    values: [Float; N_SAMPLES],
+}
+
+// hero wavelength sampling: the first wavelength is drawn uniformly and
+// the rest are evenly rotated across the visible range from it
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    lambda: [Float; N_SAMPLES],
+    pdf: [Float; N_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(value: Float) -> SampledSpectrum {
+        SampledSpectrum {
+            values: [value; N_SAMPLES],
+        }
+    }
+
+    pub fn from_fn(f: impl Fn(usize) -> Float) -> SampledSpectrum {
+        SampledSpectrum {
+            values: std::array::from_fn(f),
+        }
+    }
+
+    // upsample an rgb reflectance or radiance at the given wavelengths
+    pub fn from_rgb(color: Color, lambda: &SampledWavelengths) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| rgb_to_spectrum(color, lambda.lambda[i]))
+    }
+
+    pub fn value(&self, i: usize) -> Float {
+        self.values[i]
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.values.iter().all(|v| *v == 0.0)
+    }
+}
+
+impl ops::Add<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, other: SampledSpectrum) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| self.values[i] + other.values[i])
+    }
+}
+
+impl ops::Mul<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, other: SampledSpectrum) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| self.values[i] * other.values[i])
+    }
+}
+
+impl ops::Mul<Float> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, t: Float) -> SampledSpectrum {
+        SampledSpectrum::from_fn(|i| self.values[i] * t)
+    }
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: Float) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let delta = range / N_SAMPLES as Float;
+
+        let lambda = std::array::from_fn(|i| {
+            let l = hero + delta * i as Float;
+            if l > LAMBDA_MAX {
+                l - range
+            } else {
+                l
+            }
+        });
+
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; N_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> Float {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> Float {
+        self.lambda[i]
+    }
+
+    // wavelength dependent events (dispersion) split the paths apart, so
+    // only the hero wavelength keeps going and carries all of the weight
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= N_SAMPLES as Float;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf.iter().skip(1).all(|pdf| *pdf == 0.0)
+    }
+}
+
+// piecewise gaussian used by the CIE fit below
+fn gaussian(x: Float, mu: Float, sigma_low: Float, sigma_high: Float) -> Float {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// analytic multi-lobe fit of the CIE 1931 2 degree matching functions
+// (Wyman, Sloan and Shirley 2013)
+pub fn cie_x(lambda: Float) -> Float {
+    1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(lambda: Float) -> Float {
+    0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(lambda: Float) -> Float {
+    1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+// smooth bands that partition the visible range, an rgb triple is
+// upsampled as the matching weighted sum so that reflectances stay in [0,1]
+fn blue_band(lambda: Float) -> Float {
+    1.0 - logistic((lambda - 490.0) / 8.0)
+}
+
+fn red_band(lambda: Float) -> Float {
+    logistic((lambda - 585.0) / 8.0)
+}
+
+fn green_band(lambda: Float) -> Float {
+    1.0 - blue_band(lambda) - red_band(lambda)
+}
+
+fn logistic(x: Float) -> Float {
+    1.0 / (1.0 + (-x).exp())
+}
+
+pub fn rgb_to_spectrum(color: Color, lambda: Float) -> Float {
+    color.r() * red_band(lambda) + color.g() * green_band(lambda) + color.b() * blue_band(lambda)
+}
+
+type Matrix3 = [[Float; 3]; 3];
+
+const XYZ_TO_SRGB: Matrix3 = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+struct SpectralConversion {
+    // 1 / integral of the y matching function
+    y_norm: Float,
+    // XYZ -> linear rgb, calibrated so the rgb upsampling round trips exactly
+    xyz_to_rgb: Matrix3,
+}
+
+fn conversion() -> &'static SpectralConversion {
+    static CONVERSION: OnceLock<SpectralConversion> = OnceLock::new();
+
+    CONVERSION.get_or_init(|| {
+        const STEPS: usize = 4700;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as Float;
+
+        let mut y_integral = 0.0;
+        let mut band_xyz = [[0.0; 3]; 3];
+        for i in 0..STEPS {
+            let lambda = LAMBDA_MIN + (i as Float + 0.5) * step;
+            let cmf = [cie_x(lambda), cie_y(lambda), cie_z(lambda)];
+            let bands = [red_band(lambda), green_band(lambda), blue_band(lambda)];
+
+            y_integral += cmf[1] * step;
+            for (row, c) in cmf.iter().enumerate() {
+                for (col, b) in bands.iter().enumerate() {
+                    band_xyz[row][col] += c * b * step;
+                }
+            }
+        }
+
+        let y_norm = 1.0 / y_integral;
+        for row in band_xyz.iter_mut() {
+            for v in row.iter_mut() {
+                *v *= y_norm;
+            }
+        }
+
+        // columns of band_rgb are the rgb colors the pure bands map to
+        let band_rgb = mat_mul(&XYZ_TO_SRGB, &band_xyz);
+        SpectralConversion {
+            y_norm,
+            xyz_to_rgb: mat_mul(&mat_inverse(&band_rgb), &XYZ_TO_SRGB),
+        }
+    })
+}
+
+// Monte Carlo estimate of the XYZ tristimulus values of a sampled spectrum
+pub fn to_xyz(s: &SampledSpectrum, lambda: &SampledWavelengths) -> [Float; 3] {
+    let y_norm = conversion().y_norm;
+    let mut xyz = [0.0; 3];
+
+    for i in 0..N_SAMPLES {
+        if lambda.pdf[i] == 0.0 {
+            continue;
+        }
+        let l = lambda.lambda[i];
+        let w = s.values[i] / lambda.pdf[i] / N_SAMPLES as Float;
+        xyz[0] += cie_x(l) * w;
+        xyz[1] += cie_y(l) * w;
+        xyz[2] += cie_z(l) * w;
+    }
+
+    xyz.map(|v| v * y_norm)
+}
+
+pub fn to_rgb(s: &SampledSpectrum, lambda: &SampledWavelengths) -> Color {
+    let xyz = to_xyz(s, lambda);
+    let m = &conversion().xyz_to_rgb;
+    let row = |r: usize| m[r][0] * xyz[0] + m[r][1] * xyz[1] + m[r][2] * xyz[2];
+
+    Color::new(row(0), row(1), row(2))
+}
+
+// linear rgb of a blackbody at `kelvin`, scaled to unit luminance so only
+// the tint comes from the temperature
+pub fn blackbody_rgb(kelvin: Float) -> Color {
+    const STEPS: usize = 470;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as Float;
+
+    let mut xyz = [0.0; 3];
+    for i in 0..STEPS {
+        let lambda = LAMBDA_MIN + (i as Float + 0.5) * step;
+        // Planck's law without its constant factor, lambda in micrometers
+        let micrometers = lambda / 1000.0;
+        let radiance =
+            1.0 / (micrometers.powi(5) * ((14_387.77 / (micrometers * kelvin)).exp() - 1.0));
+        xyz[0] += cie_x(lambda) * radiance;
+        xyz[1] += cie_y(lambda) * radiance;
+        xyz[2] += cie_z(lambda) * radiance;
+    }
+    if !(xyz[1] > 0.0 && xyz[1].is_finite()) {
+        return Color::black();
+    }
+
+    let m = &XYZ_TO_SRGB;
+    let row = |r: usize| (m[r][0] * xyz[0] + m[r][1] * xyz[1] + m[r][2] * xyz[2]) / xyz[1];
+    Color::new(row(0), row(1), row(2))
+}
+
+fn mat_mul(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    std::array::from_fn(|r| std::array::from_fn(|c| (0..3).map(|k| a[r][k] * b[k][c]).sum()))
+}
+
+fn mat_inverse(m: &Matrix3) -> Matrix3 {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adj = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+
+    adj.map(|row| row.map(|v| v / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrate(color: Color) -> Color {
+        // deterministic stratified sweep over the hero wavelength
+        let n = 2000;
+        let mut sum = Color::black();
+        for i in 0..n {
+            let lambda = SampledWavelengths::sample_uniform((i as Float + 0.5) / n as Float);
+            let s = SampledSpectrum::from_rgb(color, &lambda);
+            sum += to_rgb(&s, &lambda);
+        }
+        sum / n as Float
+    }
+
+    #[test]
+    fn white_round_trip() {
+        let white = integrate(Color::new(1.0, 1.0, 1.0));
+
+        assert!((white.r() - 1.0).abs() < 1.0e-2);
+        assert!((white.g() - 1.0).abs() < 1.0e-2);
+        assert!((white.b() - 1.0).abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn color_round_trip() {
+        let color = integrate(Color::new(0.8, 0.3, 0.1));
+
+        assert!((color.r() - 0.8).abs() < 1.0e-2);
+        assert!((color.g() - 0.3).abs() < 1.0e-2);
+        assert!((color.b() - 0.1).abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn blackbody_tints() {
+        let warm = blackbody_rgb(3000.0);
+        let daylight = blackbody_rgb(6500.0);
+        let cool = blackbody_rgb(10000.0);
+
+        assert!(warm.r() > warm.g() && warm.g() > warm.b());
+        assert!(cool.b() > cool.r());
+        for c in daylight.to_array() {
+            assert!((c - 1.0).abs() < 0.15, "{c}");
+        }
+    }
+
+    #[test]
+    fn terminate_secondary() {
+        let mut lambda = SampledWavelengths::sample_uniform(0.5);
+        lambda.terminate_secondary();
+
+        assert!(lambda.secondary_terminated());
+        // the hero wavelength alone still gives an unbiased estimate
+        let s = SampledSpectrum::new(1.0);
+        let rgb = to_rgb(&s, &lambda);
+        assert!(rgb.g() > 0.0);
+    }
+
+    #[test]
+    fn wavelengths_in_range() {
+        let lambda = SampledWavelengths::sample_uniform(0.99);
+        for i in 0..N_SAMPLES {
+            assert!(lambda.lambda(i) >= LAMBDA_MIN && lambda.lambda(i) <= LAMBDA_MAX);
+        }
+    }
+}
@@ -1,58 +1,293 @@
-use crate::hittable::{HitRecord, Hittable};
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
 use crate::interval::Interval;
 use crate::material::Scatter;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vec3::{dot, Point3};
+use crate::utils::{gamma, Float, PI};
+use crate::vec3::{dot, Normal3, Point3, Vec3};
 
 use std::sync::Arc;
 
 pub struct Sphere {
     center: Point3,
-    radius: f32,
+    radius: Float,
     material: Arc<dyn Scatter>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f32, material: Arc<dyn Scatter>) -> Sphere {
+    pub fn new(center: Point3, radius: Float, material: Arc<dyn Scatter>) -> Sphere {
         Sphere {
             center,
             radius,
             material,
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord> {
-        let mut oc = r.origin() - self.center;
-        let a = r.direction().length_squared();
-        let half_b = dot(oc, r.direction());
+    // p: a given point on the sphere of radius one, centered at the origin
+    // u: returned value [0,1] of angle around the Y axis from X=-1
+    // v: returned value [0,1] of angle from Y=-1 to Y=+1
+    fn get_sphere_uv(p: Normal3) -> (Float, Float) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // partial derivatives of the point along u and v for the mapping above,
+    // n is the outward unit normal
+    fn get_sphere_dpduv(n: Normal3, radius: Float) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        if sin_theta < 1.0e-4 {
+            // u is degenerate at the poles, any tangent frame will do
+            let onb = Onb::build_from_w(n);
+            return (onb.u(), onb.v());
+        }
+
+        let dpdu = 2.0 * PI * radius * Vec3::new(n.z(), 0.0, -n.x());
+        let dpdv = PI
+            * radius
+            * Vec3::new(
+                -n.x() * n.y() / sin_theta,
+                sin_theta,
+                -n.z() * n.y() / sin_theta,
+            );
+        (dpdu, dpdv)
+    }
+
+    // point of r at t projected back onto the surface, which bounds its
+    // error by the distance from the center alone
+    fn surface_point(&self, r: Ray, t: Float) -> Point3 {
+        let mut local = r.at(t) - self.center;
+        local *= self.radius / local.length();
+        self.center + local
+    }
+
+    // both distances at which the line of r crosses the surface, in order
+    fn roots(&self, r: Ray) -> Option<(Float, Float)> {
+        let oc = r.origin() - self.center;
+        let d = r.direction();
+        let a = d.length_squared();
+        let half_b = dot(oc, d);
         let c = oc.length_squared() - self.radius.powi(2);
 
-        let discriminant = half_b.powi(2) - a * c;
+        // b^2 - ac written around the point on the ray closest to the
+        // center, which stays accurate when the origin is far away
+        let closest = oc - (half_b / a) * d;
+        let length = closest.length();
+        let discriminant = a * (self.radius - length) * (self.radius + length);
         if discriminant < 0.0 {
             return None;
         }
 
+        // avoid the cancellation in -half_b + sqrtd for the second root
         let sqrtd = discriminant.sqrt();
-        let root = (-half_b - sqrtd) / a;
+        let q = if half_b > 0.0 {
+            -half_b - sqrtd
+        } else {
+            -half_b + sqrtd
+        };
+        let (t0, t1) = (q / a, c / q);
+        Some((t0.min(t1), t0.max(t1)))
+    }
+}
 
-        if !ray_t.surrounds(root) {
-            return None;
+impl Hittable for Sphere {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        let (t0, t1) = self.roots(r)?;
+
+        // the far root is still a valid hit when the ray starts inside
+        // the sphere or the near one is cut away by the material's alpha
+        for root in [t0, t1] {
+            if !ray_t.surrounds(root) {
+                continue;
+            }
+            let surface = || {
+                let p = self.surface_point(r, root);
+                let (u, v) = Sphere::get_sphere_uv(Normal3::from((p - self.center) / self.radius));
+                (u, v, p)
+            };
+            if alpha_test(&*self.material, surface) {
+                return Some(Hit::new(root, self));
+            }
         }
 
-        let p = r.at(root);
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - rvec, self.center + rvec)
+    }
+}
+
+impl Primitive for Sphere {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let p = self.surface_point(r, hit.t);
+        let local = p - self.center;
+        let p_error = gamma(5) * local.abs() + gamma(1) * Vec3::from(p).abs();
+
+        let outward_normal = Normal3::from(local / self.radius);
+        let (u, v) = Sphere::get_sphere_uv(outward_normal);
+        let (dpdu, dpdv) = Sphere::get_sphere_dpduv(outward_normal, self.radius);
         let mut rec = HitRecord {
-            t: root,
+            t: hit.t,
             p,
-            material: self.material.clone(),
+            p_error,
+            material: &*self.material,
+            u,
+            v,
+            dpdu,
+            dpdv,
             front_face: false,
-            normal: (p - self.center) / self.radius,
+            normal: outward_normal,
+            vertex_color: None,
         };
 
-        let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+impl Solid for Sphere {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        match self.roots(r) {
+            Some((t0, t1)) => vec![Span {
+                enter: Hit::new(t0, self),
+                exit: Hit::new(t1, self),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha_mask::AlphaMask;
+    use crate::color::Color;
+    use crate::material::tests::gray;
+    use crate::texture::{SolidColor, Texture};
+
+    // opaque only on the half of the sphere away from the -z side
+    struct BackHalf;
+
+    impl Texture for BackHalf {
+        fn value(&self, _u: Float, _v: Float, p: Point3) -> Color {
+            let opacity = if p.z() > 0.0 { 1.0 } else { 0.0 };
+            Color::new(opacity, opacity, opacity)
+        }
+    }
+
+    // inverse of get_sphere_uv
+    fn point_at(u: Float, v: Float, radius: Float) -> Point3 {
+        let phi = 2.0 * PI * u;
+        let theta = PI * v;
+        radius
+            * Point3::new(
+                -phi.cos() * theta.sin(),
+                -theta.cos(),
+                phi.sin() * theta.sin(),
+            )
+    }
+
+    #[test]
+    fn uv_round_trip() {
+        let p = Vec3::from(point_at(0.3, 0.6, 1.0));
+        let (u, v) = Sphere::get_sphere_uv(Normal3::from(p));
+
+        assert!((u - 0.3).abs() < 1.0e-5);
+        assert!((v - 0.6).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn partial_derivatives() {
+        let (u, v, radius, h) = (0.3, 0.6, 2.0, 1.0e-3);
+        let p = point_at(u, v, radius);
+        let n = Normal3::from(Vec3::from(p) / radius);
+        let (dpdu, dpdv) = Sphere::get_sphere_dpduv(n, radius);
+
+        let du = (point_at(u + h, v, radius) - p) / h - dpdu;
+        let dv = (point_at(u, v + h, radius) - p) / h - dpdv;
+
+        assert!(du.length() < 0.05);
+        assert!(dv.length() < 0.05);
+    }
+
+    #[test]
+    fn cutout_skips_to_far_side() {
+        let lambertian = gray();
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        let opaque = Sphere::new(Point3::origin(), 1.0, lambertian.clone());
+        let rec = opaque
+            .hit(r, Interval::new(0.001, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 4.0).abs() < 1.0e-5);
+
+        // the near side is cut away, the ray goes on to the far one
+        let mask = Arc::new(AlphaMask::cutout(
+            lambertian.clone(),
+            Arc::new(BackHalf),
+            0.5,
+        ));
+        let back = Sphere::new(Point3::origin(), 1.0, mask);
+        let rec = back.hit(r, Interval::new(0.001, Float::INFINITY)).unwrap();
+        assert!((rec.t - 6.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+
+        // everything is cut away
+        let opacity = Arc::new(SolidColor::from_value(0.2));
+        let mask = Arc::new(AlphaMask::cutout(lambertian, opacity, 0.5));
+        let masked = Sphere::new(Point3::origin(), 1.0, mask);
+        assert!(masked
+            .hit(r, Interval::new(0.001, Float::INFINITY))
+            .is_none());
+        assert!(opaque.occluded(r, Interval::new(0.001, Float::INFINITY)));
+        assert!(!masked.occluded(r, Interval::new(0.001, Float::INFINITY)));
+    }
+
+    #[test]
+    fn hit_from_inside() {
+        let sphere = Sphere::new(Point3::origin(), 1.0, gray());
+        let r = Ray::new(Point3::origin(), Vec3::new(0.0, 0.0, 1.0));
+
+        let rec = sphere
+            .hit(r, Interval::new(0.001, Float::INFINITY))
+            .unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-5);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn grazing_hit() {
+        let sphere = Sphere::new(Point3::origin(), 1.0, gray());
+        let r = Ray::new(Point3::new(-5.0, 0.999, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let rec = sphere.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert!((Vec3::from(rec.p).length() - 1.0).abs() < 1.0e-5);
+        assert!(rec.t < 5.0);
+    }
+
+    #[test]
+    fn spawned_rays_leave_the_surface() {
+        let ground = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, gray());
+        let r = Ray::new(Point3::new(13.0, 2.0, 3.0), Vec3::new(-13.0, -2.0, -2.9));
+        let rec = ground.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+
+        // nearly tangent reflection must not hit the surface it left
+        let outgoing = rec.spawn_ray(Vec3::new(1.0, 1.0e-4, 0.0));
+        assert!(ground
+            .hit(outgoing, Interval::new(0.0, Float::INFINITY))
+            .is_none());
 
-        Some(rec)
+        // a transmitted ray starts below the surface and reaches the far side
+        let transmitted = rec.spawn_ray(Vec3::new(0.0, -1.0, 0.0));
+        let far = ground
+            .hit(transmitted, Interval::new(0.0, Float::INFINITY))
+            .unwrap();
+        assert!(far.t > 1000.0);
+        assert!(!far.front_face);
     }
 }
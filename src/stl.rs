@@ -0,0 +1,157 @@
+// STL meshes, ascii or binary. STL stores every triangle with its own
+// corners, identical corners are merged again so the result is a
+// connected mesh that smooth normals and subdivision can work on. The
+// facet normals are ignored, the winding decides the outside.
+
+use crate::mesh::TriangleMesh;
+use crate::utils::Float;
+use crate::vec3::Point3;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub fn load_stl(path: impl AsRef<Path>) -> io::Result<TriangleMesh> {
+    parse_stl(&fs::read(path)?)
+}
+
+pub fn parse_stl(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    // ascii files start with "solid", but so do some binary headers, the
+    // triangle count settles it
+    let binary = bytes.len() >= 84 && {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
+        bytes.len() as u64 == 84 + 50 * count as u64
+    };
+    let corners = if binary {
+        binary_corners(bytes)
+    } else if bytes.trim_ascii_start().starts_with(b"solid") {
+        ascii_corners(bytes)?
+    } else {
+        return Err(invalid("not an stl file"));
+    };
+
+    let mut merged: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut positions = Vec::new();
+    let indices = corners
+        .chunks_exact(3)
+        .map(|triangle| {
+            [0, 1, 2].map(|k| {
+                let corner = triangle[k];
+                // + 0.0 makes -0 and 0 the same
+                *merged
+                    .entry(corner.map(|x| (x + 0.0).to_bits()))
+                    .or_insert_with(|| {
+                        let [x, y, z] = corner.map(|x| x as Float);
+                        positions.push(Point3::new(x, y, z));
+                        (positions.len() - 1) as u32
+                    })
+            })
+        })
+        .collect();
+    Ok(TriangleMesh::new(positions, indices))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// 80 byte header, triangle count, then per triangle a normal, three
+// corners and two attribute bytes, all little endian
+fn binary_corners(bytes: &[u8]) -> Vec<[f32; 3]> {
+    bytes[84..]
+        .chunks_exact(50)
+        .flat_map(|facet| {
+            (0..3).map(move |k| {
+                [0, 1, 2].map(|axis| {
+                    let at = 12 + 12 * k + 4 * axis;
+                    f32::from_le_bytes(facet[at..at + 4].try_into().unwrap())
+                })
+            })
+        })
+        .collect()
+}
+
+fn ascii_corners(bytes: &[u8]) -> io::Result<Vec<[f32; 3]>> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("ascii stl isn't text"))?;
+    let mut corners = Vec::new();
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        if word != "vertex" {
+            continue;
+        }
+        let mut corner = [0.0; 3];
+        for x in corner.iter_mut() {
+            *x = words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| invalid("bad stl vertex"))?;
+        }
+        corners.push(corner);
+    }
+    if corners.len() % 3 != 0 {
+        return Err(invalid("stl facet without three vertices"));
+    }
+    Ok(corners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two triangles sharing an edge
+    const ASCII: &str = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+
+    fn binary() -> Vec<u8> {
+        // a header that starts like an ascii file, as some exporters write
+        let mut bytes = b"solid exported".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        for triangle in [
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+            [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        ] {
+            for x in [0.0f32, 0.0, 1.0] {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+            for corner in triangle {
+                for x in corner {
+                    bytes.extend_from_slice(&(x as f32).to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        let ascii = parse_stl(ASCII.as_bytes()).unwrap();
+        // the shared corners are merged
+        assert_eq!(ascii.positions.len(), 4);
+        assert_eq!(ascii.indices, vec![[0, 1, 2], [0, 2, 3]]);
+
+        let binary = parse_stl(&binary()).unwrap();
+        assert_eq!(binary.positions, ascii.positions);
+        assert_eq!(binary.indices, ascii.indices);
+
+        assert!(parse_stl(b"solid broken\n vertex 0 0\n").is_err());
+        assert!(parse_stl(b"not a mesh").is_err());
+    }
+}
@@ -0,0 +1,480 @@
+// Subdivision surfaces, refined at load time into smooth triangle meshes:
+// Catmull-Clark for quads (any polygon works, one level turns everything
+// into quads) and Loop for triangles. Creases follow the semi-sharp rules
+// of DeRose et al., an edge of sharpness s is refined with the sharp rules
+// for s levels and blended into the smooth ones for a fraction of a level.
+// Boundary edges are treated as infinitely sharp.
+
+use crate::mesh::{PolyMesh, TriangleMesh};
+use crate::utils::{Float, PI};
+use crate::vec3::{Point3, Vec3};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    CatmullClark,
+    Loop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subdivision {
+    pub scheme: Scheme,
+    pub levels: u32,
+}
+
+impl Subdivision {
+    pub fn new(scheme: Scheme, levels: u32) -> Subdivision {
+        Subdivision { scheme, levels }
+    }
+
+    // the refined mesh, still as polygons
+    pub fn refine(&self, mesh: &PolyMesh) -> PolyMesh {
+        let mut mesh = mesh.clone();
+        for _ in 0..self.levels {
+            mesh = match self.scheme {
+                Scheme::CatmullClark => catmull_clark(&mesh),
+                Scheme::Loop => loop_subdivide(&mesh),
+            };
+        }
+        mesh
+    }
+
+    // the refined mesh with smooth normals, sharp along the creases left
+    pub fn apply(&self, mesh: &PolyMesh) -> TriangleMesh {
+        self.refine(mesh).to_triangle_mesh()
+    }
+}
+
+struct Edge {
+    vertices: [u32; 2],
+    faces: Vec<usize>,
+    sharpness: Float,
+}
+
+// edges and what touches them, enough for one level of either scheme
+struct Topology {
+    edges: Vec<Edge>,
+    index: HashMap<[u32; 2], usize>,
+    // edges around each vertex
+    around: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(mesh: &PolyMesh) -> Topology {
+        let mut topology = Topology {
+            edges: Vec::new(),
+            index: HashMap::new(),
+            around: vec![Vec::new(); mesh.positions.len()],
+        };
+        for (f, face) in mesh.faces.iter().enumerate() {
+            for k in 0..face.len() {
+                let e = topology.edge(face[k], face[(k + 1) % face.len()]);
+                topology.edges[e].faces.push(f);
+            }
+        }
+        for &([a, b], sharpness) in &mesh.creases {
+            if let Some(&e) = topology.index.get(&[a.min(b), a.max(b)]) {
+                let edge = &mut topology.edges[e];
+                edge.sharpness = edge.sharpness.max(sharpness);
+            }
+        }
+        for edge in topology.edges.iter_mut() {
+            if edge.faces.len() != 2 {
+                edge.sharpness = Float::INFINITY;
+            }
+        }
+        topology
+    }
+
+    fn edge(&mut self, a: u32, b: u32) -> usize {
+        let key = [a.min(b), a.max(b)];
+        if let Some(&e) = self.index.get(&key) {
+            return e;
+        }
+        let e = self.edges.len();
+        self.edges.push(Edge {
+            vertices: key,
+            faces: Vec::new(),
+            sharpness: 0.0,
+        });
+        self.index.insert(key, e);
+        self.around[a as usize].push(e);
+        self.around[b as usize].push(e);
+        e
+    }
+
+    fn find(&self, a: u32, b: u32) -> usize {
+        self.index[&[a.min(b), a.max(b)]]
+    }
+
+    // the new position of an edge's midpoint from its smooth rule
+    fn edge_point(&self, positions: &[Point3], e: usize, smooth: impl FnOnce() -> Vec3) -> Point3 {
+        let edge = &self.edges[e];
+        let [a, b] = edge.vertices.map(|i| Vec3::from(positions[i as usize]));
+        let sharp = 0.5 * (a + b);
+        Point3::from(if edge.sharpness >= 1.0 {
+            sharp
+        } else {
+            lerp(smooth(), sharp, edge.sharpness)
+        })
+    }
+
+    // the new position of an old vertex from its smooth rule, which
+    // creases through it override
+    fn vertex_point(
+        &self,
+        positions: &[Point3],
+        v: usize,
+        smooth: impl FnOnce() -> Vec3,
+    ) -> Point3 {
+        let p = Vec3::from(positions[v]);
+        let creases: Vec<&Edge> = self.around[v]
+            .iter()
+            .map(|&e| &self.edges[e])
+            .filter(|edge| edge.sharpness > 0.0)
+            .collect();
+        if creases.len() < 2 {
+            return Point3::from(smooth());
+        }
+
+        let sharp = if creases.len() == 2 {
+            let other = |edge: &Edge| {
+                let [a, b] = edge.vertices;
+                Vec3::from(positions[if a as usize == v { b } else { a } as usize])
+            };
+            (other(creases[0]) + 6.0 * p + other(creases[1])) / 8.0
+        } else {
+            p
+        };
+        let sharpness =
+            creases.iter().map(|edge| edge.sharpness).sum::<Float>() / creases.len() as Float;
+        Point3::from(if sharpness >= 1.0 {
+            sharp
+        } else {
+            lerp(smooth(), sharp, sharpness)
+        })
+    }
+
+    // creases one level down, split at the new edge points
+    fn child_creases(&self, first_edge_point: usize) -> Vec<([u32; 2], Float)> {
+        let mut creases = Vec::new();
+        for (e, edge) in self.edges.iter().enumerate() {
+            if edge.faces.len() == 2 && edge.sharpness > 1.0 {
+                let mid = (first_edge_point + e) as u32;
+                let [a, b] = edge.vertices;
+                creases.push(([a, mid], edge.sharpness - 1.0));
+                creases.push(([mid, b], edge.sharpness - 1.0));
+            }
+        }
+        creases
+    }
+}
+
+fn lerp(a: Vec3, b: Vec3, t: Float) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+// uvs aren't smoothed, a new point takes the average of the uvs of the
+// points it is made from and old vertices keep theirs
+fn average_uv(uvs: &[[Float; 2]], vertices: &[u32]) -> [Float; 2] {
+    let sum = vertices.iter().fold([0.0, 0.0], |[u, v], &i| {
+        let [du, dv] = uvs[i as usize];
+        [u + du, v + dv]
+    });
+    sum.map(|x| x / vertices.len() as Float)
+}
+
+fn centroid(positions: &[Point3], vertices: impl Iterator<Item = u32>) -> Vec3 {
+    let (sum, n) = vertices.fold((Vec3::zero(), 0), |(sum, n), i| {
+        (sum + Vec3::from(positions[i as usize]), n + 1)
+    });
+    sum / n as Float
+}
+
+// one level of Catmull-Clark, every face of n sides becomes n quads
+pub fn catmull_clark(mesh: &PolyMesh) -> PolyMesh {
+    let topology = Topology::new(mesh);
+    let positions = &mesh.positions;
+    let face_points: Vec<Vec3> = mesh
+        .faces
+        .iter()
+        .map(|face| centroid(positions, face.iter().copied()))
+        .collect();
+
+    let mut refined =
+        Vec::with_capacity(positions.len() + topology.edges.len() + face_points.len());
+    for v in 0..positions.len() {
+        refined.push(topology.vertex_point(positions, v, || {
+            let around = &topology.around[v];
+            let faces: Vec<usize> = around
+                .iter()
+                .flat_map(|&e| topology.edges[e].faces.iter().copied())
+                .collect();
+            // each face around v is counted by both of its edges at v
+            let q = faces
+                .iter()
+                .map(|&f| face_points[f])
+                .fold(Vec3::zero(), |a, b| a + b)
+                / faces.len() as Float;
+            let r = around
+                .iter()
+                .map(|&e| centroid(positions, topology.edges[e].vertices.into_iter()))
+                .fold(Vec3::zero(), |a, b| a + b)
+                / around.len() as Float;
+            let n = around.len() as Float;
+            (q + 2.0 * r + (n - 3.0) * Vec3::from(positions[v])) / n
+        }));
+    }
+    for (e, edge) in topology.edges.iter().enumerate() {
+        refined.push(topology.edge_point(positions, e, || {
+            let [a, b] = edge.vertices.map(|i| Vec3::from(positions[i as usize]));
+            (a + b + face_points[edge.faces[0]] + face_points[edge.faces[1]]) / 4.0
+        }));
+    }
+    refined.extend(face_points.iter().map(|&p| Point3::from(p)));
+
+    let mut uvs = Vec::new();
+    if !mesh.uvs.is_empty() {
+        uvs.extend_from_slice(&mesh.uvs);
+        uvs.extend(
+            topology
+                .edges
+                .iter()
+                .map(|edge| average_uv(&mesh.uvs, &edge.vertices)),
+        );
+        uvs.extend(mesh.faces.iter().map(|face| average_uv(&mesh.uvs, face)));
+    }
+
+    let first_edge_point = positions.len();
+    let first_face_point = first_edge_point + topology.edges.len();
+    let mut faces = Vec::new();
+    for (f, face) in mesh.faces.iter().enumerate() {
+        let n = face.len();
+        let edge_point = |a: u32, b: u32| (first_edge_point + topology.find(a, b)) as u32;
+        for k in 0..n {
+            let (prev, v, next) = (face[(k + n - 1) % n], face[k], face[(k + 1) % n]);
+            faces.push(vec![
+                v,
+                edge_point(v, next),
+                (first_face_point + f) as u32,
+                edge_point(prev, v),
+            ]);
+        }
+    }
+
+    PolyMesh {
+        positions: refined,
+        faces,
+        uvs,
+        creases: topology.child_creases(first_edge_point),
+    }
+}
+
+// one level of Loop, polygons are fan triangulated first and every
+// triangle becomes four
+pub fn loop_subdivide(mesh: &PolyMesh) -> PolyMesh {
+    let triangles = PolyMesh {
+        faces: mesh
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(|i| vec![face[0], face[i], face[i + 1]]))
+            .collect(),
+        ..mesh.clone()
+    };
+    let topology = Topology::new(&triangles);
+    let positions = &mesh.positions;
+
+    let mut refined = Vec::with_capacity(positions.len() + topology.edges.len());
+    for v in 0..positions.len() {
+        refined.push(topology.vertex_point(positions, v, || {
+            let around = &topology.around[v];
+            let n = around.len() as Float;
+            let ring = around
+                .iter()
+                .map(|&e| {
+                    let [a, b] = topology.edges[e].vertices;
+                    Vec3::from(positions[if a as usize == v { b } else { a } as usize])
+                })
+                .fold(Vec3::zero(), |a, b| a + b);
+            let c = 3.0 / 8.0 + 0.25 * (2.0 * PI / n).cos();
+            let beta = (5.0 / 8.0 - c * c) / n;
+            (1.0 - n * beta) * Vec3::from(positions[v]) + beta * ring
+        }));
+    }
+    for (e, edge) in topology.edges.iter().enumerate() {
+        refined.push(topology.edge_point(positions, e, || {
+            // the corners across the edge in the two triangles
+            let opposite = edge
+                .faces
+                .iter()
+                .flat_map(|&f| triangles.faces[f].iter())
+                .filter(|i| !edge.vertices.contains(i))
+                .map(|&i| Vec3::from(positions[i as usize]))
+                .fold(Vec3::zero(), |a, b| a + b);
+            let [a, b] = edge.vertices.map(|i| Vec3::from(positions[i as usize]));
+            3.0 / 8.0 * (a + b) + opposite / 8.0
+        }));
+    }
+
+    let mut uvs = Vec::new();
+    if !mesh.uvs.is_empty() {
+        uvs.extend_from_slice(&mesh.uvs);
+        uvs.extend(
+            topology
+                .edges
+                .iter()
+                .map(|edge| average_uv(&mesh.uvs, &edge.vertices)),
+        );
+    }
+
+    let first_edge_point = positions.len();
+    let mut faces = Vec::new();
+    for triangle in &triangles.faces {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        let edge_point = |a: u32, b: u32| (first_edge_point + topology.find(a, b)) as u32;
+        let (ab, bc, ca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+        faces.push(vec![a, ab, ca]);
+        faces.push(vec![b, bc, ab]);
+        faces.push(vec![c, ca, bc]);
+        faces.push(vec![ab, bc, ca]);
+    }
+
+    PolyMesh {
+        positions: refined,
+        faces,
+        uvs,
+        creases: topology.child_creases(first_edge_point),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn cube() -> PolyMesh {
+        let positions = (0..8)
+            .map(|i| {
+                let s = |bit: u32| if i & bit != 0 { 1.0 } else { -1.0 };
+                Point3::new(s(1), s(2), s(4))
+            })
+            .collect();
+        let faces = vec![
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+        ];
+        PolyMesh::new(positions, faces)
+    }
+
+    fn octahedron() -> PolyMesh {
+        let positions = vec![
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(-1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, -1.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+        ];
+        let faces = vec![
+            vec![0, 2, 4],
+            vec![2, 1, 4],
+            vec![1, 3, 4],
+            vec![3, 0, 4],
+            vec![2, 0, 5],
+            vec![1, 2, 5],
+            vec![3, 1, 5],
+            vec![0, 3, 5],
+        ];
+        PolyMesh::new(positions, faces)
+    }
+
+    #[test]
+    fn catmull_clark_cube() {
+        let refined = catmull_clark(&cube());
+        assert_eq!(refined.positions.len(), 8 + 12 + 6);
+        assert_eq!(refined.faces.len(), 24);
+        // (q + 2 r) / 3 with q = 1/3 and r = 2/3 along each axis
+        let corner = Vec3::from(refined.positions[7]);
+        assert!((corner - Vec3::new(5.0, 5.0, 5.0) / 9.0).length() < 1.0e-5);
+
+        // with every edge sharp it stays a cube
+        let mut sharp = cube();
+        for face in sharp.faces.clone() {
+            for k in 0..4 {
+                sharp = sharp.with_crease(face[k], face[(k + 1) % 4], Float::INFINITY);
+            }
+        }
+        let refined = Subdivision::new(Scheme::CatmullClark, 3).refine(&sharp);
+        assert_eq!(refined.faces.len(), 6 * 64);
+        for p in &refined.positions {
+            let extent = p
+                .to_array()
+                .map(Float::abs)
+                .into_iter()
+                .fold(0.0, Float::max);
+            assert!((extent - 1.0).abs() < 1.0e-5);
+        }
+
+        // a crease of sharpness 1 only holds for the first level
+        let mut soft = cube();
+        for face in soft.faces.clone() {
+            for k in 0..4 {
+                soft = soft.with_crease(face[k], face[(k + 1) % 4], 1.0);
+            }
+        }
+        let refined = Subdivision::new(Scheme::CatmullClark, 2).refine(&soft);
+        assert!(refined.positions[7].x() < 1.0 - 1.0e-3);
+    }
+
+    #[test]
+    fn uvs_follow_the_refinement() {
+        // a single quad mapped onto the unit square
+        let quad = PolyMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            vec![vec![0, 1, 2, 3]],
+        )
+        .with_uvs(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+
+        for scheme in [Scheme::CatmullClark, Scheme::Loop] {
+            let mesh = Subdivision::new(scheme, 2).refine(&quad);
+            assert_eq!(mesh.uvs.len(), mesh.positions.len());
+            assert_eq!(mesh.uvs[..4], quad.uvs[..]);
+            assert!(mesh.uvs.iter().flatten().all(|&x| (0.0..=1.0).contains(&x)));
+        }
+        // the face point sits at the middle of the square
+        let mesh = Subdivision::new(Scheme::CatmullClark, 1).refine(&quad);
+        assert_eq!(mesh.uvs.last(), Some(&[0.5, 0.5]));
+        assert!(Subdivision::new(Scheme::Loop, 1)
+            .apply(&cube())
+            .uvs
+            .is_empty());
+    }
+
+    #[test]
+    fn loop_octahedron() {
+        let refined = loop_subdivide(&octahedron());
+        assert_eq!(refined.positions.len(), 6 + 12);
+        assert_eq!(refined.faces.len(), 32);
+        // the ring cancels, leaving (1 - 4 beta) with beta = 31 / 256
+        assert!((refined.positions[0].x() - 132.0 / 256.0).abs() < 1.0e-5);
+        // 3/8 of each end plus 1/8 of the two across
+        let mid = Vec3::from(refined.positions[6]);
+        assert!((mid - Vec3::new(0.375, 0.375, 0.0)).length() < 1.0e-5);
+
+        // smooth normals point away from the center
+        let mesh = Subdivision::new(Scheme::Loop, 2).apply(&octahedron());
+        assert_eq!(mesh.indices.len(), 8 * 16);
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!(crate::vec3::dot(Vec3::from(*p), *n) > 0.0);
+        }
+    }
+}
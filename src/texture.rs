@@ -0,0 +1,113 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::perlin::Perlin;
+use crate::utils::Float;
+use crate::vec3::Point3;
+
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: Float, v: Float, p: Point3) -> Color;
+
+    // the value at a hit, for textures that need more of it than the uv
+    // and the point, like vertex colors
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, rec.p)
+    }
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> SolidColor {
+        SolidColor { albedo }
+    }
+
+    // scalar parameters (roughness, metallic, ...) are read
+    // from the first channel, so fill all three with the value
+    pub fn from_value(value: Float) -> SolidColor {
+        SolidColor::new(Color::new(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _p: Point3) -> Color {
+        self.albedo
+    }
+}
+
+// 3d checkerboard of unit `scale` cubes, alternating between two textures
+pub struct CheckerTexture {
+    inv_scale: Float,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: Float, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: Float, even: Color, odd: Color) -> CheckerTexture {
+        CheckerTexture::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: Float, v: Float, p: Point3) -> Color {
+        let [x, y, z] = p.to_array().map(|c| (self.inv_scale * c).floor() as i64);
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// marble like veins, turbulence shifting the phase of a sine along z
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: Float,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: Float) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: Float, _v: Float, p: Point3) -> Color {
+        let phase = self.scale * p.z() + 10.0 * self.noise.turb(p, 7);
+        Color::new(0.5, 0.5, 0.5) * (1.0 + phase.sin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let checker = CheckerTexture::from_colors(0.5, white, Color::black());
+        let at = |x, y, z| checker.value(0.0, 0.0, Point3::new(x, y, z)).r();
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.0);
+        assert_eq!(at(-0.1, -0.1, 0.1), 1.0);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
+use crate::hittable::{alpha_test, Hit, HitRecord, Hittable, Primitive};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::polynomial::{solve_quadratic, solve_quartic};
+use crate::ray::Ray;
+use crate::utils::{azimuth, degrees_to_radians, gamma, Float, PI};
+use crate::vec3::{dot, Normal3, Point3, Vec3};
+
+use std::sync::Arc;
+
+// Torus lying in the xz plane around `center`, a tube of `minor_radius`
+// swept around the y axis at `major_radius`. u goes around the axis from
+// +x, v around the tube starting from its outer equator and going up.
+pub struct Torus {
+    center: Point3,
+    major_radius: Float,
+    minor_radius: Float,
+    phi_max: Float,
+    material: Arc<dyn Scatter>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        major_radius: Float,
+        minor_radius: Float,
+        material: Arc<dyn Scatter>,
+    ) -> Torus {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            phi_max: 2.0 * PI,
+            material,
+        }
+    }
+
+    // only the part swept from +x towards +z up to `degrees`
+    pub fn with_phi_max(self, degrees: Float) -> Torus {
+        Torus {
+            phi_max: degrees_to_radians(degrees.clamp(0.0, 360.0)),
+            ..self
+        }
+    }
+
+    fn in_sweep(&self, p: Point3) -> bool {
+        let local = p - self.center;
+        self.phi_max >= 2.0 * PI || azimuth(local.x(), local.z()) <= self.phi_max
+    }
+
+    // every distance at which the line of r crosses the surface, in order
+    #[allow(clippy::unnecessary_cast)] // Float may already be f64
+    fn roots(&self, r: Ray) -> Vec<Float> {
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+        // a bounding sphere first, which also moves the origin close to the
+        // torus so the quartic's coefficients stay small
+        let oc = r.origin() - self.center;
+        let d = r.direction();
+        let a = d.length_squared();
+        let bound = self.major_radius + self.minor_radius;
+        let Some((near, _)) = solve_quadratic(a, dot(oc, d), oc.length_squared() - bound * bound)
+        else {
+            return Vec::new();
+        };
+
+        let [ox, oy, oz] = (oc + near * d).to_array().map(|x| x as f64);
+        let [dx, dy, dz] = d.to_array().map(|x| x as f64);
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) along the line
+        let a = dx * dx + dy * dy + dz * dz;
+        let b = 2.0 * (ox * dx + oy * dy + oz * dz);
+        let c = ox * ox + oy * oy + oz * oz + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        solve_quartic([
+            c * c - four_r2 * (ox * ox + oz * oz),
+            2.0 * b * c - 2.0 * four_r2 * (ox * dx + oz * dz),
+            b * b + 2.0 * a * c - four_r2 * (dx * dx + dz * dz),
+            2.0 * a * b,
+            a * a,
+        ])
+        .into_iter()
+        .map(|t| t as Float + near)
+        .collect()
+    }
+}
+
+impl Hittable for Torus {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        self.roots(r)
+            .into_iter()
+            .filter(|&t| ray_t.surrounds(t) && self.in_sweep(r.at(t)))
+            .map(|t| Hit::new(t, self))
+            .find(|hit| {
+                alpha_test(&*self.material, || {
+                    let rec = self.interaction(r, hit);
+                    (rec.u, rec.v, rec.p)
+                })
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = self.major_radius + self.minor_radius;
+        let corner = Vec3::new(extent, self.minor_radius, extent);
+        Aabb::from_points(self.center - corner, self.center + corner)
+    }
+}
+
+impl Primitive for Torus {
+    fn interaction(&self, r: Ray, hit: &Hit) -> HitRecord<'_> {
+        let [x, y, z] = (r.at(hit.t) - self.center).to_array();
+        let phi = azimuth(x, z);
+        let theta = azimuth((x * x + z * z).sqrt() - self.major_radius, y);
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+
+        // reprojected onto the surface from the two angles
+        let outward = Vec3::new(cos_theta * cos_phi, sin_theta, cos_theta * sin_phi);
+        let ring = self.major_radius * Vec3::new(cos_phi, 0.0, sin_phi);
+        let local = ring + self.minor_radius * outward;
+        let p = self.center + local;
+
+        let dpdu = self.phi_max * Vec3::new(-local.z(), 0.0, local.x());
+        let dpdv = 2.0
+            * PI
+            * self.minor_radius
+            * Vec3::new(-sin_theta * cos_phi, cos_theta, -sin_theta * sin_phi);
+
+        let outward_normal = Normal3::from(outward);
+        let mut rec = HitRecord {
+            t: hit.t,
+            p,
+            p_error: gamma(7) * (ring.abs() + self.minor_radius * outward.abs())
+                + gamma(1) * Vec3::from(p).abs(),
+            material: &*self.material,
+            u: phi / self.phi_max,
+            v: theta / (2.0 * PI),
+            dpdu,
+            dpdv,
+            front_face: false,
+            normal: outward_normal,
+            vertex_color: None,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+}
+
+impl Solid for Torus {
+    fn spans(&self, r: Ray) -> Vec<Span<'_>> {
+        let roots = self.roots(r);
+        // an odd count is a tangent root lost to rounding, not a crossing
+        if roots.len() % 2 != 0 {
+            return Vec::new();
+        }
+        roots
+            .chunks(2)
+            .map(|pair| Span {
+                enter: Hit::new(pair[0], self),
+                exit: Hit::new(pair[1], self),
+            })
+            .collect()
+    }
+
+    // the sweep leaves a gap with no surface across it
+    fn is_closed(&self) -> bool {
+        self.phi_max >= 2.0 * PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::tests::gray;
+
+    fn torus() -> Torus {
+        Torus::new(Point3::origin(), 2.0, 0.5, gray())
+    }
+
+    #[test]
+    fn through_both_sides() {
+        let torus = torus();
+        let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let spans = torus.spans(r);
+        assert_eq!(spans.len(), 2);
+        for (span, (enter, exit)) in spans.iter().zip([(2.5, 3.5), (6.5, 7.5)]) {
+            assert!((span.enter.t - enter).abs() < 1.0e-4);
+            assert!((span.exit.t - exit).abs() < 1.0e-4);
+        }
+
+        let rec = torus.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert!((rec.t - 2.5).abs() < 1.0e-4);
+        assert!(rec.front_face);
+        assert!((Vec3::from(rec.normal) - Vec3::new(-1.0, 0.0, 0.0)).length() < 1.0e-4);
+        assert!((rec.u - 0.5).abs() < 1.0e-4 && rec.v.abs() < 1.0e-4);
+
+        // down through the hole in the middle
+        let r = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(r, Interval::new(0.0, Float::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn top_of_the_tube() {
+        let torus = torus();
+        let r = Ray::new(Point3::new(0.0, 5.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = torus.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert!((rec.t - 4.5).abs() < 1.0e-4);
+        assert!((rec.v - 0.25).abs() < 1.0e-4);
+        assert!((rec.p - Point3::new(0.0, 0.5, 2.0)).length() < 1.0e-4);
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let half = torus().with_phi_max(180.0);
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = half.hit(r, Interval::new(0.0, Float::INFINITY)).unwrap();
+        assert!((rec.t - 6.5).abs() < 1.0e-4);
+        assert!((rec.u - 0.5).abs() < 1.0e-4);
+        // and csg won't take it
+        assert!(torus().is_closed() && !half.is_closed());
+    }
+}
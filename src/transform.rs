@@ -0,0 +1,288 @@
+use std::ops;
+
+use crate::aabb::Aabb;
+use crate::utils::{degrees_to_radians, gamma, Float};
+use crate::vec3::{cross, unit_vector, Normal3, Point3, Vec3};
+
+type Matrix = [[Float; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+// Affine transform, kept together with its inverse so normals and rays
+// going the other way don't need to invert anything. Matrices are row
+// major and act on column vectors, `a * b` applies b first.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    // the last row of m is ignored and taken as (0, 0, 0, 1), None if the
+    // matrix is singular
+    pub fn from_matrix(m: Matrix) -> Option<Transform> {
+        let a = |i: usize, j: usize| m[i][j];
+        let cofactor = |i: usize, j: usize| {
+            let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+            let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+            a(r0, c0) * a(r1, c1) - a(r0, c1) * a(r1, c0)
+        };
+
+        let det = (0..3).map(|j| a(0, j) * cofactor(0, j)).sum::<Float>();
+        if det.abs() < Float::MIN_POSITIVE {
+            return None;
+        }
+
+        // inverse of the linear part is the transposed cofactors over the
+        // determinant, the translation is undone after it
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().take(3).enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                *value = cofactor(j, i) / det;
+            }
+        }
+        for row in inv.iter_mut().take(3) {
+            row[3] = -(0..3).map(|k| row[k] * m[k][3]).sum::<Float>();
+        }
+
+        let mut m = m;
+        m[3] = IDENTITY[3];
+        Some(Transform { m, inv })
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for (i, d) in offset.to_array().into_iter().enumerate() {
+            m[i][3] = d;
+            inv[i][3] = -d;
+        }
+        Transform { m, inv }
+    }
+
+    pub fn scale(x: Float, y: Float, z: Float) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for (i, s) in [x, y, z].into_iter().enumerate() {
+            m[i][i] = s;
+            inv[i][i] = 1.0 / s;
+        }
+        Transform { m, inv }
+    }
+
+    // counter clockwise rotation around axis, looking down the axis
+    pub fn rotate(degrees: Float, axis: Vec3) -> Transform {
+        let [x, y, z] = unit_vector(axis).to_array();
+        let theta = degrees_to_radians(degrees);
+        let (sin, cos) = theta.sin_cos();
+
+        let mut m = IDENTITY;
+        m[0][0] = x * x + (1.0 - x * x) * cos;
+        m[0][1] = x * y * (1.0 - cos) - z * sin;
+        m[0][2] = x * z * (1.0 - cos) + y * sin;
+        m[1][0] = x * y * (1.0 - cos) + z * sin;
+        m[1][1] = y * y + (1.0 - y * y) * cos;
+        m[1][2] = y * z * (1.0 - cos) - x * sin;
+        m[2][0] = x * z * (1.0 - cos) - y * sin;
+        m[2][1] = y * z * (1.0 - cos) + x * sin;
+        m[2][2] = z * z + (1.0 - z * z) * cos;
+
+        // rotations are orthogonal, the inverse is the transpose
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().take(3).enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                *value = m[j][i];
+            }
+        }
+        Transform { m, inv }
+    }
+
+    // camera to world for a camera at eye looking at target, with +z
+    // forward, +y up and +x as up cross forward. None when the view is
+    // degenerate.
+    pub fn look_at(eye: Point3, target: Point3, up: Vec3) -> Option<Transform> {
+        if (target - eye).near_zero() {
+            return None;
+        }
+        let dir = unit_vector(target - eye);
+        let side = cross(up, dir);
+        if side.near_zero() {
+            return None;
+        }
+        let side = unit_vector(side);
+        let up = cross(dir, side);
+        Transform::from_matrix([
+            [side.x(), up.x(), dir.x(), eye.x()],
+            [side.y(), up.y(), dir.y(), eye.y()],
+            [side.z(), up.z(), dir.z(), eye.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix {
+        self.m
+    }
+
+    // true for reflections, which turn the winding of triangles around
+    pub fn swaps_handedness(&self) -> bool {
+        let m = &self.m;
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        det < 0.0
+    }
+
+    fn apply(m: &Matrix, v: [Float; 3], w: Float) -> [Float; 3] {
+        let row = |i: usize| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2] + m[i][3] * w;
+        [row(0), row(1), row(2)]
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        let [x, y, z] = Transform::apply(&self.m, p.to_array(), 1.0);
+        Point3::new(x, y, z)
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let [x, y, z] = Transform::apply(&self.m, v.to_array(), 0.0);
+        Vec3::new(x, y, z)
+    }
+
+    // normals go through the inverse transpose, the result is not
+    // normalized
+    pub fn normal(&self, n: Normal3) -> Normal3 {
+        let [x, y, z] = n.to_array();
+        let inv = &self.inv;
+        Normal3::new(
+            inv[0][0] * x + inv[1][0] * y + inv[2][0] * z,
+            inv[0][1] * x + inv[1][1] * y + inv[2][1] * z,
+            inv[0][2] * x + inv[1][2] * y + inv[2][2] * z,
+        )
+    }
+
+    // transforms a point with an absolute error bound, returning the new
+    // point and a bound that also covers the rounding of the transform
+    pub fn point_with_error(&self, p: Point3, p_error: Vec3) -> (Point3, Vec3) {
+        let abs = self.m.map(|row| row.map(Float::abs));
+        let [px, py, pz] = Vec3::from(p).abs().to_array();
+        let [ex, ey, ez] = p_error.to_array();
+        let bound = |i: usize| {
+            gamma(3) * (abs[i][0] * px + abs[i][1] * py + abs[i][2] * pz + abs[i][3])
+                + (gamma(3) + 1.0) * (abs[i][0] * ex + abs[i][1] * ey + abs[i][2] * ez)
+        };
+        (self.point(p), Vec3::new(bound(0), bound(1), bound(2)))
+    }
+
+    // box around the transformed corners of bbox
+    pub fn bounding_box(&self, bbox: Aabb) -> Aabb {
+        if bbox.is_empty() {
+            return bbox;
+        }
+        (0..8).fold(Aabb::EMPTY, |out, corner| {
+            let pick = |axis: usize| {
+                let slab = bbox.axis(axis);
+                if corner & (1 << axis) == 0 {
+                    slab.min
+                } else {
+                    slab.max
+                }
+            };
+            let p = self.point(Point3::new(pick(0), pick(1), pick(2)));
+            Aabb::enclosing(out, Aabb::from_points(p, p))
+        })
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl ops::Mul<Transform> for Transform {
+    type Output = Transform;
+
+    fn mul(self, o: Transform) -> Transform {
+        Transform {
+            m: multiply(&self.m, &o.m),
+            inv: multiply(&o.inv, &self.inv),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::dot;
+
+    fn assert_close(a: Point3, b: Point3) {
+        assert!((a - b).length() < 1.0e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn compose_and_invert() {
+        let t = Transform::translate(Vec3::new(1.0, 2.0, 3.0))
+            * Transform::rotate(90.0, Vec3::new(0.0, 1.0, 0.0))
+            * Transform::scale(2.0, 2.0, 2.0);
+        let p = Point3::new(1.0, 0.0, 0.0);
+
+        // scaled to x = 2, rotated onto -z, then moved
+        assert_close(t.point(p), Point3::new(1.0, 2.0, 1.0));
+        assert_close(t.inverse().point(t.point(p)), p);
+
+        let general = Transform::from_matrix(t.matrix()).unwrap();
+        assert_close(general.inverse().point(t.point(p)), p);
+        assert!(Transform::from_matrix([[0.0; 4]; 4]).is_none());
+    }
+
+    #[test]
+    fn look_at_and_handedness() {
+        let eye = Point3::new(1.0, 2.0, 3.0);
+        let t =
+            Transform::look_at(eye, Point3::new(1.0, 2.0, 5.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert_close(t.point(Point3::origin()), eye);
+        assert_close(
+            t.point(Point3::new(0.0, 0.0, 2.0)),
+            Point3::new(1.0, 2.0, 5.0),
+        );
+        assert!(!t.swaps_handedness());
+        assert!((t * Transform::scale(-1.0, 1.0, 1.0)).swaps_handedness());
+        assert!(Transform::look_at(eye, eye, Vec3::new(0.0, 1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::scale(1.0, 4.0, 1.0) * Transform::rotate(30.0, Vec3::new(1.0, 1.0, 0.0));
+        let (tangent, normal) = (Vec3::new(1.0, -1.0, 0.0), Normal3::new(1.0, 1.0, 0.0));
+
+        assert!(dot(t.vector(tangent), t.normal(normal)).abs() < 1.0e-5);
+    }
+}
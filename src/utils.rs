@@ -1,13 +1,63 @@
-pub const PI: f32 = 3.14;
+// floating point type used for geometry, shading and colors. The `f64`
+// feature trades speed for precision, e.g. for very large scenes.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
 
-pub fn degrees_to_radians(degrees: f32) -> f32 {
+#[cfg(not(feature = "f64"))]
+pub const PI: Float = std::f32::consts::PI;
+#[cfg(feature = "f64")]
+pub const PI: Float = std::f64::consts::PI;
+
+pub fn degrees_to_radians(degrees: Float) -> Float {
     degrees * PI / 180.0
 }
 
-pub fn random_double() -> f32 {
-    rand::random::<f32>()
+// angle of (x, z) around the y axis, from +x towards +z, in [0, 2 pi)
+pub fn azimuth(x: Float, z: Float) -> Float {
+    let phi = z.atan2(x);
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+pub fn random_double() -> Float {
+    rand::random::<Float>()
 }
 
-pub fn random_double_bounded(min: f32, max: f32) -> f32 {
+pub fn random_double_bounded(min: Float, max: Float) -> Float {
     min + (max - min) * random_double()
 }
+
+// half the distance between 1.0 and the next representable float,
+// the bound on relative rounding error of a single operation
+pub const MACHINE_EPSILON: Float = Float::EPSILON * 0.5;
+
+// conservative bound on the relative error after n rounded operations
+pub fn gamma(n: i32) -> Float {
+    let n = n as Float * MACHINE_EPSILON;
+    n / (1.0 - n)
+}
+
+// smallest representable float strictly greater than v
+pub fn next_float_up(v: Float) -> Float {
+    if v.is_infinite() && v > 0.0 {
+        return v;
+    }
+    // skip -0.0 so the step below moves away from zero
+    let v = if v == 0.0 { 0.0 } else { v };
+    let bits = v.to_bits();
+    if v >= 0.0 {
+        Float::from_bits(bits + 1)
+    } else {
+        Float::from_bits(bits - 1)
+    }
+}
+
+// largest representable float strictly less than v
+pub fn next_float_down(v: Float) -> Float {
+    -next_float_up(-v)
+}
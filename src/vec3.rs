@@ -1,41 +1,217 @@
 use std::fmt;
 use std::ops;
 
-use crate::utils::{random_double, random_double_bounded};
+use crate::simd::Float4;
+use crate::utils::{random_double, random_double_bounded, Float, PI};
+
+// Points, directions, normals and colors are all three floats but they
+// transform and combine differently, so each gets its own type and only
+// the operations that make sense for it. They share the storage and the
+// basics generated here; the fourth simd lane is always kept at zero.
+macro_rules! tuple3 {
+    ($name:ident) => {
+        #[derive(Debug, Default, Clone, Copy)]
+        pub struct $name {
+            e: Float4,
+        }
+
+        impl $name {
+            #[inline]
+            pub fn new(x: Float, y: Float, z: Float) -> $name {
+                $name {
+                    e: Float4::new(x, y, z, 0.0),
+                }
+            }
+
+            #[inline]
+            pub(crate) fn from_simd(e: Float4) -> $name {
+                $name { e }
+            }
+
+            #[inline]
+            pub(crate) fn simd(self) -> Float4 {
+                self.e
+            }
+
+            #[inline]
+            pub fn to_array(self) -> [Float; 3] {
+                let [x, y, z, _] = self.e.to_array();
+                [x, y, z]
+            }
+
+            #[inline]
+            pub fn abs(self) -> $name {
+                let [x, y, z] = self.to_array();
+                $name::new(x.abs(), y.abs(), z.abs())
+            }
+
+            // component wise min / max, used by bounding boxes
+            #[inline]
+            pub fn min(self, other: $name) -> $name {
+                $name::from_simd(self.e.min(other.e))
+            }
+
+            #[inline]
+            pub fn max(self, other: $name) -> $name {
+                $name::from_simd(self.e.max(other.e))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let [x, y, z] = self.to_array();
+                write!(f, "{} {} {}", x, y, z)
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.e == other.e
+            }
+        }
+
+        impl ops::Mul<Float> for $name {
+            type Output = $name;
+
+            #[inline]
+            fn mul(self, t: Float) -> $name {
+                $name::from_simd(self.e * Float4::splat(t))
+            }
+        }
+
+        impl ops::Mul<$name> for Float {
+            type Output = $name;
+
+            #[inline]
+            fn mul(self, v: $name) -> $name {
+                v * self
+            }
+        }
+
+        impl ops::Div<Float> for $name {
+            type Output = $name;
+
+            #[inline]
+            fn div(self, t: Float) -> $name {
+                self * (1.0 / t)
+            }
+        }
 
-#[derive(Debug, Default, Clone, Copy)]
-pub struct Vec3 {
-    points: [f32; 3],
+        impl ops::MulAssign<Float> for $name {
+            #[inline]
+            fn mul_assign(&mut self, t: Float) {
+                *self = *self * t;
+            }
+        }
+
+        impl ops::DivAssign<Float> for $name {
+            #[inline]
+            fn div_assign(&mut self, t: Float) {
+                *self = *self / t;
+            }
+        }
+    };
 }
 
-impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
-        Vec3 { points: [x, y, z] }
-    }
+// `$lhs op $rhs -> $out` for element wise operators
+macro_rules! tuple3_op {
+    ($trait:ident, $fn:ident, $op:tt, $lhs:ident, $rhs:ident, $out:ident) => {
+        impl ops::$trait<$rhs> for $lhs {
+            type Output = $out;
+
+            #[inline]
+            fn $fn(self, other: $rhs) -> $out {
+                $out::from_simd(self.simd() $op other.simd())
+            }
+        }
+    };
+}
 
-    pub fn origin() -> Vec3 {
-        Vec3 {
-            points: [0.0, 0.0, 0.0],
+macro_rules! tuple3_assign_op {
+    ($trait:ident, $fn:ident, $op:tt, $lhs:ident, $rhs:ident) => {
+        impl ops::$trait<$rhs> for $lhs {
+            #[inline]
+            fn $fn(&mut self, other: $rhs) {
+                *self = $lhs::from_simd(self.simd() $op other.simd());
+            }
         }
+    };
+}
+
+macro_rules! tuple3_neg {
+    ($name:ident) => {
+        impl ops::Neg for $name {
+            type Output = $name;
+
+            #[inline]
+            fn neg(self) -> $name {
+                $name::from_simd(Float4::default() - self.simd())
+            }
+        }
+    };
+}
+
+pub(crate) use {tuple3, tuple3_assign_op, tuple3_op};
+
+tuple3!(Vec3);
+tuple3!(Point3);
+tuple3!(Normal3);
+
+tuple3_op!(Add, add, +, Vec3, Vec3, Vec3);
+tuple3_op!(Sub, sub, -, Vec3, Vec3, Vec3);
+tuple3_op!(Mul, mul, *, Vec3, Vec3, Vec3);
+tuple3_assign_op!(AddAssign, add_assign, +, Vec3, Vec3);
+tuple3_assign_op!(SubAssign, sub_assign, -, Vec3, Vec3);
+tuple3_neg!(Vec3);
+
+tuple3_op!(Add, add, +, Point3, Vec3, Point3);
+tuple3_op!(Sub, sub, -, Point3, Vec3, Point3);
+tuple3_op!(Sub, sub, -, Point3, Point3, Vec3);
+// only meaningful for weighted sums such as barycentric interpolation
+tuple3_op!(Add, add, +, Point3, Point3, Point3);
+tuple3_assign_op!(AddAssign, add_assign, +, Point3, Vec3);
+
+tuple3_op!(Add, add, +, Normal3, Normal3, Normal3);
+tuple3_neg!(Normal3);
+
+impl ops::Div<Vec3> for Vec3 {
+    type Output = Vec3;
+
+    #[inline]
+    fn div(self, other: Vec3) -> Vec3 {
+        // keep the unused lane at 0 instead of 0 / 0
+        let divisor = other.simd() + Float4::new(0.0, 0.0, 0.0, 1.0);
+        Vec3::from_simd(self.simd() / divisor)
+    }
+}
+
+impl Vec3 {
+    pub fn zero() -> Vec3 {
+        Vec3::default()
     }
 
-    pub fn x(&mut self) -> f32 {
-        self.points[0]
+    #[inline]
+    pub fn x(&self) -> Float {
+        self.e.x()
     }
 
-    pub fn y(&mut self) -> f32 {
-        self.points[1]
+    #[inline]
+    pub fn y(&self) -> Float {
+        self.e.y()
     }
 
-    pub fn z(&mut self) -> f32 {
-        self.points[2]
+    #[inline]
+    pub fn z(&self) -> Float {
+        self.e.z()
     }
 
-    pub fn length_squared(&mut self) -> f32 {
-        self.x() * self.x() + self.y() * self.y() + self.z() * self.z()
+    #[inline]
+    pub fn length_squared(&self) -> Float {
+        dot(*self, *self)
     }
 
-    pub fn length(&mut self) -> f32 {
+    #[inline]
+    pub fn length(&self) -> Float {
         self.length_squared().sqrt()
     }
 
@@ -43,7 +219,7 @@ impl Vec3 {
         Vec3::new(random_double(), random_double(), random_double())
     }
 
-    pub fn random_bounded(min: f32, max: f32) -> Vec3 {
+    pub fn random_bounded(min: Float, max: Float) -> Vec3 {
         Vec3::new(
             random_double_bounded(min, max),
             random_double_bounded(min, max),
@@ -51,218 +227,174 @@ impl Vec3 {
         )
     }
 
-    pub fn reflect(self, n: Vec3) -> Vec3 {
+    pub fn reflect(self, n: impl Direction) -> Vec3 {
+        let n = n.to_vec3();
         self - 2.0 * dot(self, n) * n
     }
 
-    pub fn refract(self, n: Vec3, etai_over_etat: f32) -> Vec3 {
-        let cos_theta = dot((-1.0) * self, n).min(1.0);
-        let mut r_out_perp = etai_over_etat * (self + cos_theta * n);
-        let r_out_parallel = -(1.0 - r_out_perp.length().powi(2)).abs().sqrt() * n;
+    pub fn refract(self, n: impl Direction, etai_over_etat: Float) -> Vec3 {
+        let n = n.to_vec3();
+        let cos_theta = dot(-self, n).min(1.0);
+        let r_out_perp = etai_over_etat * (self + cos_theta * n);
+        let r_out_parallel = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * n;
         r_out_perp + r_out_parallel
     }
 
-    pub fn near_zero(&mut self) -> bool {
-        const EPS: f32 = 1.0e-8;
+    // mirror `w` about this vector, both pointing away from the surface
+    pub fn reflect_about(self, w: Vec3) -> Vec3 {
+        2.0 * dot(w, self) * self - w
+    }
+
+    pub fn near_zero(&self) -> bool {
+        const EPS: Float = 1.0e-8;
         self.x().abs() < EPS && self.y().abs() < EPS && self.z().abs() < EPS
     }
 }
 
-pub fn dot(u: Vec3, v: Vec3) -> f32 {
-    u.points[0] * v.points[0] + u.points[1] * v.points[1] + u.points[2] * v.points[2]
-}
+impl Point3 {
+    pub fn origin() -> Point3 {
+        Point3::default()
+    }
 
-pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
-    Vec3 {
-        points: [
-            u.points[1] * v.points[2] - u.points[2] * v.points[1],
-            u.points[2] * v.points[0] - u.points[0] * v.points[2],
-            u.points[0] * v.points[1] - u.points[1] * v.points[0],
-        ],
+    #[inline]
+    pub fn x(&self) -> Float {
+        self.e.x()
     }
-}
 
-pub fn unit_vector(u: &mut Vec3) -> Vec3 {
-    let length = u.length();
-    Vec3 {
-        points: [
-            u.points[0] / length,
-            u.points[1] / length,
-            u.points[2] / length,
-        ],
+    #[inline]
+    pub fn y(&self) -> Float {
+        self.e.y()
     }
-}
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let mut p = Vec3::new(
-            random_double_bounded(-1.0, 1.0),
-            random_double_bounded(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+    #[inline]
+    pub fn z(&self) -> Float {
+        self.e.z()
     }
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let mut p = Vec3::random_bounded(-1.0, 1.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+impl Normal3 {
+    #[inline]
+    pub fn x(&self) -> Float {
+        self.e.x()
     }
-}
 
-pub type Point3 = Vec3;
+    #[inline]
+    pub fn y(&self) -> Float {
+        self.e.y()
+    }
 
-impl fmt::Display for Vec3 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} {} {}",
-            self.points[0], self.points[1], self.points[2]
-        )
+    #[inline]
+    pub fn z(&self) -> Float {
+        self.e.z()
     }
-}
 
-impl ops::Add<Vec3> for Vec3 {
-    type Output = Vec3;
+    pub fn length(&self) -> Float {
+        dot(*self, *self).sqrt()
+    }
 
-    fn add(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            points: [
-                self.points[0] + other.points[0],
-                self.points[1] + other.points[1],
-                self.points[2] + other.points[2],
-            ],
+    // flipped, if needed, to lie in the same hemisphere as `v`
+    pub fn face_forward(self, v: impl Direction) -> Normal3 {
+        if dot(self, v) < 0.0 {
+            -self
+        } else {
+            self
         }
     }
 }
 
-impl ops::Sub<Vec3> for Vec3 {
-    type Output = Vec3;
-
-    fn sub(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            points: [
-                self.points[0] - other.points[0],
-                self.points[1] - other.points[1],
-                self.points[2] - other.points[2],
-            ],
-        }
+// position vector of a point, i.e. the point relative to the origin
+impl From<Point3> for Vec3 {
+    fn from(p: Point3) -> Vec3 {
+        Vec3::from_simd(p.simd())
     }
 }
 
-impl ops::Mul<Vec3> for Vec3 {
-    type Output = Vec3;
-
-    fn mul(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            points: [
-                self.points[0] * other.points[0],
-                self.points[1] * other.points[1],
-                self.points[2] * other.points[2],
-            ],
-        }
+impl From<Vec3> for Point3 {
+    fn from(v: Vec3) -> Point3 {
+        Point3::from_simd(v.simd())
     }
 }
 
-impl ops::Mul<f32> for Vec3 {
-    type Output = Vec3;
-
-    fn mul(self, t: f32) -> Vec3 {
-        Vec3 {
-            points: [self.points[0] * t, self.points[1] * t, self.points[2] * t],
-        }
+impl From<Normal3> for Vec3 {
+    fn from(n: Normal3) -> Vec3 {
+        Vec3::from_simd(n.simd())
     }
 }
 
-impl ops::Mul<Vec3> for f32 {
-    type Output = Vec3;
-
-    fn mul(self, v: Vec3) -> Vec3 {
-        Vec3 {
-            points: [self * v.points[0], self * v.points[1], self * v.points[2]],
-        }
+impl From<Vec3> for Normal3 {
+    fn from(v: Vec3) -> Normal3 {
+        Normal3::from_simd(v.simd())
     }
 }
 
-impl ops::Div<Vec3> for Vec3 {
-    type Output = Vec3;
+// directions that can take part in dot and cross products. Points
+// deliberately don't, they have to be turned into vectors first.
+pub trait Direction: Copy {
+    fn to_vec3(self) -> Vec3;
+}
 
-    fn div(self, other: Vec3) -> Vec3 {
-        Vec3 {
-            points: [
-                self.points[0] / other.points[0],
-                self.points[1] / other.points[1],
-                self.points[2] / other.points[2],
-            ],
-        }
+impl Direction for Vec3 {
+    #[inline]
+    fn to_vec3(self) -> Vec3 {
+        self
     }
 }
 
-impl ops::Div<f32> for Vec3 {
-    type Output = Vec3;
-
-    fn div(self, t: f32) -> Vec3 {
-        Vec3 {
-            points: [self.points[0] / t, self.points[1] / t, self.points[2] / t],
-        }
+impl Direction for Normal3 {
+    #[inline]
+    fn to_vec3(self) -> Vec3 {
+        Vec3::from(self)
     }
 }
 
-impl ops::Div<Vec3> for f32 {
-    type Output = Vec3;
+#[inline]
+pub fn dot(u: impl Direction, v: impl Direction) -> Float {
+    (u.to_vec3().simd() * v.to_vec3().simd()).sum3()
+}
 
-    fn div(self, t: Vec3) -> Vec3 {
-        Vec3 {
-            points: [t.points[0] / self, t.points[1] / self, t.points[2] / self],
-        }
-    }
+#[inline]
+pub fn cross(u: impl Direction, v: impl Direction) -> Vec3 {
+    Vec3::from_simd(u.to_vec3().simd().cross3(v.to_vec3().simd()))
 }
 
-impl ops::Neg for Vec3 {
-    type Output = Vec3;
+#[inline]
+pub fn unit_vector(u: Vec3) -> Vec3 {
+    u / u.length()
+}
 
-    fn neg(self) -> Vec3 {
-        Vec3 {
-            points: [-self.points[0], -self.points[1], -self.points[2]],
+pub fn random_in_unit_disk() -> Vec3 {
+    loop {
+        let p = Vec3::new(
+            random_double_bounded(-1.0, 1.0),
+            random_double_bounded(-1.0, 1.0),
+            0.0,
+        );
+        if p.length_squared() < 1.0 {
+            return p;
         }
     }
 }
 
-impl ops::AddAssign<Vec3> for Vec3 {
-    fn add_assign(&mut self, other: Self) {
-        self.points[0] += other.points[0];
-        self.points[1] += other.points[1];
-        self.points[2] += other.points[2];
+pub fn random_in_unit_sphere() -> Vec3 {
+    loop {
+        let p = Vec3::random_bounded(-1.0, 1.0);
+        if p.length_squared() < 1.0 {
+            return p;
+        }
     }
 }
 
-impl ops::MulAssign<f32> for Vec3 {
-    fn mul_assign(&mut self, t: f32) {
-        self.points[0] *= t;
-        self.points[1] *= t;
-        self.points[2] *= t;
-    }
-}
+// cosine weighted direction about +z, pdf is cos(theta) / pi
+pub fn random_cosine_direction() -> Vec3 {
+    let r1 = random_double();
+    let r2 = random_double();
 
-impl ops::DivAssign<f32> for Vec3 {
-    fn div_assign(&mut self, t: f32) {
-        self.points[0] /= t;
-        self.points[1] /= t;
-        self.points[2] /= t;
-    }
-}
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
 
-impl PartialEq for Vec3 {
-    fn eq(&self, other: &Self) -> bool {
-        self.points[0] == other.points[0]
-            && self.points[1] == other.points[1]
-            && self.points[2] == other.points[2]
-    }
+    Vec3::new(x, y, z)
 }
 
 #[cfg(test)]
@@ -272,9 +404,10 @@ mod tests {
     #[test]
     fn constructor() {
         let new = Vec3::new(0.0, 0.0, 0.0);
-        let origin = Vec3::origin();
+        let zero = Vec3::zero();
 
-        assert_eq!(new, origin);
+        assert_eq!(new, zero);
+        assert_eq!(Point3::new(0.0, 0.0, 0.0), Point3::origin());
     }
 
     #[test]
@@ -284,9 +417,9 @@ mod tests {
 
         let added = foo + other;
 
-        assert_eq!(added.points[0], 5.0);
-        assert_eq!(added.points[1], 7.0);
-        assert_eq!(added.points[2], 9.0);
+        assert_eq!(added.x(), 5.0);
+        assert_eq!(added.y(), 7.0);
+        assert_eq!(added.z(), 9.0);
     }
 
     #[test]
@@ -296,9 +429,9 @@ mod tests {
 
         let subbed = other - foo;
 
-        assert_eq!(subbed.points[0], 3.0);
-        assert_eq!(subbed.points[1], 3.0);
-        assert_eq!(subbed.points[2], 3.0);
+        assert_eq!(subbed.x(), 3.0);
+        assert_eq!(subbed.y(), 3.0);
+        assert_eq!(subbed.z(), 3.0);
     }
 
     #[test]
@@ -308,16 +441,16 @@ mod tests {
 
         let mul = other * foo;
 
-        assert_eq!(mul.points[0], 4.0);
-        assert_eq!(mul.points[1], 10.0);
-        assert_eq!(mul.points[2], 18.0);
+        assert_eq!(mul.x(), 4.0);
+        assert_eq!(mul.y(), 10.0);
+        assert_eq!(mul.z(), 18.0);
 
         let bar = Vec3::new(2.0, 4.0, 8.0);
         let mul = bar * 2.0;
 
-        assert_eq!(mul.points[0], 4.0);
-        assert_eq!(mul.points[1], 8.0);
-        assert_eq!(mul.points[2], 16.0);
+        assert_eq!(mul.x(), 4.0);
+        assert_eq!(mul.y(), 8.0);
+        assert_eq!(mul.z(), 16.0);
     }
 
     #[test]
@@ -325,9 +458,12 @@ mod tests {
         let foo = Vec3::new(1.0, 2.0, 3.0);
         let other = foo / 2.0;
 
-        assert_eq!(other.points[0], 0.5);
-        assert_eq!(other.points[1], 1.0);
-        assert_eq!(other.points[2], 1.5);
+        assert_eq!(other.x(), 0.5);
+        assert_eq!(other.y(), 1.0);
+        assert_eq!(other.z(), 1.5);
+
+        let by_vec = foo / Vec3::new(2.0, 4.0, 6.0);
+        assert_eq!(by_vec, Vec3::new(0.5, 0.5, 0.5));
     }
 
     #[test]
@@ -367,21 +503,41 @@ mod tests {
         let vec = Vec3::new(1.0, 2.0, 3.0);
         let neg = -vec;
 
-        assert_eq!(neg.points[0], -1.0);
-        assert_eq!(neg.points[1], -2.0);
-        assert_eq!(neg.points[2], -3.0);
+        assert_eq!(neg.x(), -1.0);
+        assert_eq!(neg.y(), -2.0);
+        assert_eq!(neg.z(), -3.0);
     }
 
     #[test]
     fn length_squared() {
-        let mut vec = Vec3::new(1.0, 2.0, 3.0);
+        let vec = Vec3::new(1.0, 2.0, 3.0);
         assert_eq!(vec.length_squared(), 14.0);
     }
 
     #[test]
     fn length() {
-        let mut vec = Vec3::new(2.0, 2.0, 2.0);
-        let expected = 12 as f32;
+        let vec = Vec3::new(2.0, 2.0, 2.0);
+        let expected: Float = 12.0;
         assert_eq!(vec.length(), expected.sqrt());
     }
+
+    #[test]
+    fn dot_and_cross() {
+        let u = Vec3::new(1.0, 2.0, 3.0);
+        let v = Vec3::new(4.0, 5.0, 6.0);
+
+        assert_eq!(dot(u, v), 32.0);
+        assert_eq!(cross(u, v), Vec3::new(-3.0, 6.0, -3.0));
+    }
+
+    #[test]
+    fn points_and_vectors() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let q = Point3::new(4.0, 6.0, 3.0);
+
+        let d: Vec3 = q - p;
+        assert_eq!(d, Vec3::new(3.0, 4.0, 0.0));
+        assert_eq!(p + d, q);
+        assert_eq!(d.length(), 5.0);
+    }
 }
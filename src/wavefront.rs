@@ -0,0 +1,269 @@
+// Wavefront path tracing. Instead of following one path at a time, the
+// camera rays of a tile move through the pipeline as a batch: every bounce
+// intersects the whole queue, shades the hits grouped by material and
+// queues the scattered rays for the next bounce.
+
+use crate::camera::{Background, Camera};
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Scatter;
+use crate::ray::Ray;
+use crate::utils::Float;
+use crate::vec3::{Point3, Vec3};
+
+// tiles are square, edge length in pixels
+pub const TILE_SIZE: usize = 16;
+
+// Structure of arrays ray queue, so a stage that only reads part of a ray
+// streams through contiguous memory. Each ray carries the throughput of
+// its path so far and the pixel it contributes to.
+#[derive(Default)]
+pub struct RayBuffer {
+    origin_x: Vec<Float>,
+    origin_y: Vec<Float>,
+    origin_z: Vec<Float>,
+    direction_x: Vec<Float>,
+    direction_y: Vec<Float>,
+    direction_z: Vec<Float>,
+    throughput_r: Vec<Float>,
+    throughput_g: Vec<Float>,
+    throughput_b: Vec<Float>,
+    pixel: Vec<usize>,
+}
+
+impl RayBuffer {
+    pub fn len(&self) -> usize {
+        self.pixel.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixel.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.origin_x.clear();
+        self.origin_y.clear();
+        self.origin_z.clear();
+        self.direction_x.clear();
+        self.direction_y.clear();
+        self.direction_z.clear();
+        self.throughput_r.clear();
+        self.throughput_g.clear();
+        self.throughput_b.clear();
+        self.pixel.clear();
+    }
+
+    pub fn push(&mut self, r: Ray, throughput: Color, pixel: usize) {
+        let (origin, direction) = (r.origin(), r.direction());
+        self.origin_x.push(origin.x());
+        self.origin_y.push(origin.y());
+        self.origin_z.push(origin.z());
+        self.direction_x.push(direction.x());
+        self.direction_y.push(direction.y());
+        self.direction_z.push(direction.z());
+        self.throughput_r.push(throughput.r());
+        self.throughput_g.push(throughput.g());
+        self.throughput_b.push(throughput.b());
+        self.pixel.push(pixel);
+    }
+
+    pub fn ray(&self, i: usize) -> Ray {
+        Ray::new(
+            Point3::new(self.origin_x[i], self.origin_y[i], self.origin_z[i]),
+            Vec3::new(
+                self.direction_x[i],
+                self.direction_y[i],
+                self.direction_z[i],
+            ),
+        )
+    }
+
+    pub fn throughput(&self, i: usize) -> Color {
+        Color::new(
+            self.throughput_r[i],
+            self.throughput_g[i],
+            self.throughput_b[i],
+        )
+    }
+
+    pub fn pixel(&self, i: usize) -> usize {
+        self.pixel[i]
+    }
+}
+
+// renders the same image as Camera::render_pixels, tile by tile
+pub(crate) fn render(camera: &Camera, world: &dyn Hittable) -> Vec<Color> {
+    let width = camera.image_width as usize;
+    let height = camera.image_height() as usize;
+    let mut pixels = vec![Color::black(); width * height];
+
+    // reused across tiles and bounces to keep allocations out of the loop
+    let mut queue = RayBuffer::default();
+    let mut next = RayBuffer::default();
+    let mut hits = Vec::new();
+    let mut order = Vec::new();
+
+    for tile_y in (0..height).step_by(TILE_SIZE) {
+        for tile_x in (0..width).step_by(TILE_SIZE) {
+            generate(camera, (tile_x, tile_y), (width, height), &mut queue);
+
+            for _ in 0..camera.max_depth {
+                if queue.is_empty() {
+                    break;
+                }
+                intersect(world, &queue, &mut hits);
+                shade(
+                    &camera.background,
+                    &queue,
+                    &hits,
+                    &mut order,
+                    &mut pixels,
+                    &mut next,
+                );
+
+                std::mem::swap(&mut queue, &mut next);
+                next.clear();
+            }
+            // paths still alive at the maximum depth contribute nothing
+            queue.clear();
+        }
+    }
+    pixels
+}
+
+// queues every camera sample of the tile starting at `tile`
+fn generate(camera: &Camera, tile: (usize, usize), size: (usize, usize), queue: &mut RayBuffer) {
+    let (width, height) = size;
+    for row in tile.1..(tile.1 + TILE_SIZE).min(height) {
+        for col in tile.0..(tile.0 + TILE_SIZE).min(width) {
+            for _ in 0..camera.samples_per_pixel {
+                let r = camera.get_ray(col as i32, row as i32);
+                queue.push(r, Color::new(1.0, 1.0, 1.0), row * width + col);
+            }
+        }
+    }
+}
+
+// closest hit for every queued ray, this is where packet traversal goes
+fn intersect<'a>(
+    world: &'a dyn Hittable,
+    queue: &RayBuffer,
+    hits: &mut Vec<Option<HitRecord<'a>>>,
+) {
+    hits.clear();
+    hits.extend(
+        (0..queue.len()).map(|i| world.hit(queue.ray(i), Interval::new(0.0, Float::INFINITY))),
+    );
+}
+
+// identifies the material instance so hits can be grouped by it
+fn material_key(rec: &HitRecord) -> usize {
+    rec.material as *const dyn Scatter as *const () as usize
+}
+
+// adds emitted and background light to the pixels and queues the
+// scattered rays, running each material's code over all of its hits
+// in one go
+fn shade(
+    background: &Background,
+    queue: &RayBuffer,
+    hits: &[Option<HitRecord>],
+    order: &mut Vec<usize>,
+    pixels: &mut [Color],
+    next: &mut RayBuffer,
+) {
+    order.clear();
+    for (i, hit) in hits.iter().enumerate() {
+        match hit {
+            Some(_) => order.push(i),
+            None => {
+                let radiance = background.radiance(queue.ray(i).direction());
+                pixels[queue.pixel(i)] += queue.throughput(i) * radiance;
+            }
+        }
+    }
+    order.sort_by_key(|&i| hits[i].as_ref().map(material_key));
+
+    for &i in order.iter() {
+        let Some(rec) = &hits[i] else {
+            continue;
+        };
+        let (r, throughput, pixel) = (queue.ray(i), queue.throughput(i), queue.pixel(i));
+
+        pixels[pixel] += throughput * rec.material.emitted(rec.u, rec.v, rec.p);
+        if let Some((attenuation, scattered)) = rec.material.scatter(&r, rec) {
+            next.push(scattered, throughput * attenuation, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::material::tests::gray;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    use std::sync::Arc;
+
+    fn camera(wavefront: bool) -> Camera {
+        let mut camera = Camera::default();
+        camera.aspect_ratio = 2.0;
+        camera.image_width = 40.0;
+        camera.samples_per_pixel = 16;
+        camera.max_depth = 8;
+        camera.vfov = 40.0;
+        camera.lookfrom = Point3::new(0.0, 1.0, 5.0);
+        camera.lookat = Point3::origin();
+        camera.vup = Vec3::new(0.0, 1.0, 0.0);
+        camera.focus_dist = 5.0;
+        camera.wavefront = wavefront;
+        camera
+    }
+
+    fn mean(pixels: &[Color]) -> Color {
+        let sum = pixels.iter().fold(Color::black(), |acc, &p| acc + p);
+        (1.0 / pixels.len() as Float) * sum
+    }
+
+    #[test]
+    fn buffer_round_trip() {
+        let mut buffer = RayBuffer::default();
+        let r = Ray::new(Point3::new(1.0, 2.0, 3.0), Vec3::new(4.0, 5.0, 6.0));
+        buffer.push(r, Color::new(0.1, 0.2, 0.3), 7);
+
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.ray(0).origin(), r.origin());
+        assert_eq!(buffer.ray(0).direction(), r.direction());
+        assert_eq!(buffer.throughput(0), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(buffer.pixel(0), 7);
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn matches_recursive_renderer() {
+        let mut world = HittableList::default();
+        let red = Arc::new(Lambertian::new(Color::new(0.7, 0.2, 0.2)));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -100.5, 0.0),
+            100.0,
+            gray(),
+        )));
+        world.add(Arc::new(Sphere::new(Point3::origin(), 0.5, red)));
+
+        let recursive = camera(false).render_pixels(&world);
+        let wavefront = camera(true).render_pixels(&world);
+        assert_eq!(recursive.len(), wavefront.len());
+
+        // same estimator, so only the noise differs
+        let difference = mean(&recursive) - mean(&wavefront);
+        let reference = mean(&recursive);
+        assert!(difference.r().abs() < 0.02 * reference.r());
+        assert!(difference.g().abs() < 0.02 * reference.g());
+        assert!(difference.b().abs() < 0.02 * reference.b());
+    }
+}
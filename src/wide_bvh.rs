@@ -0,0 +1,345 @@
+// Wide bounding volume hierarchy, the binary build collapsed into nodes of
+// 4 or 8 children. Child bounds are stored as structure of arrays in groups
+// of four, so one slab test covers a whole group of children.
+
+use crate::aabb::Aabb;
+use crate::bvh::BuildNode;
+use crate::hittable::{Hit, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::simd::Float4;
+use crate::utils::Float;
+
+use std::sync::Arc;
+
+pub type Bvh4 = WideBvh<1>;
+pub type Bvh8 = WideBvh<2>;
+
+// the binary build splits at the median, so no tree of a realistic size
+// gets deeper than this, and each level leaves at most 7 children of an
+// 8-wide node on the traversal stack
+const MAX_DEPTH: usize = 32;
+const STACK_SIZE: usize = MAX_DEPTH * 8;
+
+#[derive(Clone, Copy)]
+enum Child {
+    Empty,
+    Node(usize),
+    // index into the objects
+    Leaf(usize),
+}
+
+// four children with their bounds laid out lane by lane
+#[derive(Clone, Copy)]
+struct Group {
+    min: [Float4; 3],
+    max: [Float4; 3],
+    children: [Child; 4],
+    // bit i is set when lane i holds a child, the slab test alone lets
+    // rays through the inverted bounds of an empty lane
+    occupied: u32,
+}
+
+impl Group {
+    fn empty() -> Group {
+        Group {
+            min: [Float4::splat(Float::INFINITY); 3],
+            max: [Float4::splat(-Float::INFINITY); 3],
+            children: [Child::Empty; 4],
+            occupied: 0,
+        }
+    }
+
+    fn set(&mut self, lane: usize, bbox: Aabb, child: Child) {
+        for axis in 0..3 {
+            let mut min = self.min[axis].to_array();
+            let mut max = self.max[axis].to_array();
+            min[lane] = bbox.axis(axis).min;
+            max[lane] = bbox.axis(axis).max;
+            self.min[axis] = Float4::new(min[0], min[1], min[2], min[3]);
+            self.max[axis] = Float4::new(max[0], max[1], max[2], max[3]);
+        }
+        self.children[lane] = child;
+        self.occupied |= 1 << lane;
+    }
+
+    // slab test of all four children, returns the mask of children hit
+    // and the distance at which the ray enters each of them
+    #[inline]
+    fn hit(&self, origin: &[Float4; 3], inv_d: &[Float4; 3], ray_t: Interval) -> (u32, [Float; 4]) {
+        let mut near = Float4::splat(ray_t.min);
+        let mut far = Float4::splat(ray_t.max);
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_d[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_d[axis];
+            // NaNs from 0 * inf pass through to the current bound
+            near = t0.min(t1).max(near);
+            far = t0.max(t1).min(far);
+        }
+        (near.le_mask(far) & self.occupied, near.to_array())
+    }
+}
+
+// children waiting to be visited with their entry distance, kept on the
+// call stack so a traversal doesn't allocate
+struct Stack {
+    entries: [(Child, Float); STACK_SIZE],
+    len: usize,
+}
+
+impl Stack {
+    fn new(root: Child, t: Float) -> Stack {
+        let mut stack = Stack {
+            entries: [(Child::Empty, 0.0); STACK_SIZE],
+            len: 0,
+        };
+        stack.push((root, t));
+        stack
+    }
+
+    #[inline]
+    fn push(&mut self, entry: (Child, Float)) {
+        self.entries[self.len] = entry;
+        self.len += 1;
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<(Child, Float)> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.entries[self.len])
+    }
+
+    // puts the nearest of the entries pushed since `start` on top
+    #[inline]
+    fn sort_from(&mut self, start: usize) {
+        self.entries[start..self.len].sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+    }
+}
+
+// GROUPS groups of four children per node, see the Bvh4 and Bvh8 aliases
+pub struct WideBvh<const GROUPS: usize> {
+    nodes: Vec<[Group; GROUPS]>,
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb,
+    has_instances: bool,
+}
+
+impl<const GROUPS: usize> WideBvh<GROUPS> {
+    const WIDTH: usize = 4 * GROUPS;
+
+    pub fn new(list: &HittableList) -> WideBvh<GROUPS> {
+        let mut bvh = WideBvh {
+            nodes: Vec::new(),
+            objects: Vec::new(),
+            bbox: list.bounding_box(),
+            has_instances: list.has_instances(),
+        };
+
+        let mut objects = list.objects().to_vec();
+        if !objects.is_empty() {
+            bvh.collapse(BuildNode::new(&mut objects), 1);
+        }
+        bvh
+    }
+
+    // turns the binary subtree into a node with up to WIDTH children by
+    // repeatedly opening the child with the largest surface area, then
+    // does the same for the children. Returns the index of the node.
+    fn collapse(&mut self, root: BuildNode, depth: usize) -> usize {
+        assert!(
+            depth <= MAX_DEPTH,
+            "wide bvh deeper than its traversal stack"
+        );
+
+        let mut children = vec![root];
+        while children.len() < Self::WIDTH {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| matches!(child, BuildNode::Interior { .. }))
+                .max_by(|(_, a), (_, b)| {
+                    let (a, b) = (a.bounding_box(), b.bounding_box());
+                    a.surface_area().total_cmp(&b.surface_area())
+                })
+                .map(|(i, _)| i);
+            let Some(i) = largest else {
+                break;
+            };
+            if let BuildNode::Interior { children: pair, .. } = children.swap_remove(i) {
+                children.extend(*pair);
+            }
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push([Group::empty(); GROUPS]);
+        for (slot, child) in children.into_iter().enumerate() {
+            let bbox = child.bounding_box();
+            let child = match child {
+                BuildNode::Leaf(object) => {
+                    self.objects.push(object);
+                    Child::Leaf(self.objects.len() - 1)
+                }
+                interior => Child::Node(self.collapse(interior, depth + 1)),
+            };
+            self.nodes[index][slot / 4].set(slot % 4, bbox, child);
+        }
+        index
+    }
+
+    // ray origin and reciprocal direction broadcast to all lanes
+    fn ray_lanes(r: Ray) -> ([Float4; 3], [Float4; 3]) {
+        let origin = r.origin().to_array().map(Float4::splat);
+        let inv_d = r.direction().to_array().map(|d| Float4::splat(1.0 / d));
+        (origin, inv_d)
+    }
+}
+
+impl<const GROUPS: usize> Hittable for WideBvh<GROUPS> {
+    fn intersect(&self, r: Ray, ray_t: Interval) -> Option<Hit<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let (origin, inv_d) = Self::ray_lanes(r);
+        let mut closest = None;
+        let mut closest_t = ray_t.max;
+
+        // the nearest of a node's children is always on top
+        let mut stack = Stack::new(Child::Node(0), ray_t.min);
+        while let Some((child, t_near)) = stack.pop() {
+            if t_near > closest_t {
+                continue;
+            }
+
+            match child {
+                Child::Empty => {}
+                Child::Leaf(i) => {
+                    let object_t = Interval::new(ray_t.min, closest_t);
+                    if let Some(hit) = self.objects[i].intersect(r, object_t) {
+                        closest_t = hit.t;
+                        closest = Some(hit);
+                    }
+                }
+                Child::Node(node) => {
+                    let pushed = stack.len;
+                    let node_t = Interval::new(ray_t.min, closest_t);
+                    for group in self.nodes[node].iter() {
+                        let (mask, near) = group.hit(&origin, &inv_d, node_t);
+                        for (lane, entry) in group.children.into_iter().zip(near).enumerate() {
+                            if mask & (1 << lane) != 0 {
+                                stack.push(entry);
+                            }
+                        }
+                    }
+                    stack.sort_from(pushed);
+                }
+            }
+        }
+        closest
+    }
+
+    // any hit ends the search, so children are visited in whatever order
+    fn occluded(&self, r: Ray, ray_t: Interval) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let (origin, inv_d) = Self::ray_lanes(r);
+        let mut stack = Stack::new(Child::Node(0), ray_t.min);
+        while let Some((child, _)) = stack.pop() {
+            match child {
+                Child::Empty => {}
+                Child::Leaf(i) => {
+                    if self.objects[i].occluded(r, ray_t) {
+                        return true;
+                    }
+                }
+                Child::Node(node) => {
+                    for group in self.nodes[node].iter() {
+                        let (mask, near) = group.hit(&origin, &inv_d, ray_t);
+                        for (lane, entry) in group.children.into_iter().zip(near).enumerate() {
+                            if mask & (1 << lane) != 0 {
+                                stack.push(entry);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn has_instances(&self) -> bool {
+        self.has_instances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::tests::{assert_same_hits, random_spheres};
+    use crate::material::tests::gray;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn bvh4_matches_list() {
+        let list = random_spheres(200);
+        assert_same_hits(&list, &Bvh4::new(&list));
+    }
+
+    #[test]
+    fn bvh8_matches_list() {
+        let list = random_spheres(200);
+        assert_same_hits(&list, &Bvh8::new(&list));
+    }
+
+    #[test]
+    fn empty_lanes_are_never_hit() {
+        let mut group = Group::empty();
+        let bbox = Aabb::from_points(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 2.0, 2.0));
+        group.set(2, bbox, Child::Leaf(0));
+        let lanes = |r: Ray| {
+            let origin = r.origin().to_array().map(Float4::splat);
+            let inv_d = r.direction().to_array().map(|d| Float4::splat(1.0 / d));
+            group
+                .hit(&origin, &inv_d, Interval::new(0.0, Float::INFINITY))
+                .0
+        };
+
+        assert_eq!(
+            lanes(Ray::new(Point3::origin(), Vec3::new(1.0, 1.0, 1.0))),
+            0b100
+        );
+        // misses the one child, the inverted bounds of the empty lanes
+        // don't reject it on any axis
+        assert_eq!(
+            lanes(Ray::new(Point3::origin(), Vec3::new(-1.0, -1.0, -1.0))),
+            0
+        );
+    }
+
+    #[test]
+    fn collapses_to_full_nodes() {
+        // a 4x4x4 grid, since random spheres can make the binary tree
+        // lopsided enough to need more nodes
+        let material = gray();
+        let mut list = HittableList::default();
+        for i in 0..64 {
+            let center = Point3::new((i % 4) as Float, (i / 4 % 4) as Float, (i / 16) as Float);
+            list.add(Arc::new(Sphere::new(3.0 * center, 1.0, material.clone())));
+        }
+        let bvh = Bvh4::new(&list);
+
+        assert_eq!(bvh.objects.len(), 64);
+        // the binary tree needs 63 interior nodes
+        assert!(bvh.nodes.len() < 32, "{} nodes", bvh.nodes.len());
+
+        let single = random_spheres(1);
+        assert_same_hits(&single, &Bvh8::new(&single));
+    }
+}
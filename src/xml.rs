@@ -0,0 +1,230 @@
+// Small xml reader for the scene formats built on xml. Only elements and
+// their attributes are kept, text, comments and declarations are skipped.
+
+use std::io;
+
+// elements inside elements, far more than a scene needs but few enough
+// that the recursive parser can't run out of stack
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    // in file order, with entities already decoded
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    // the document's root element
+    pub fn parse(text: &str) -> io::Result<Element> {
+        let mut parser = Parser { text, pos: 0 };
+        parser.skip_markup()?;
+        let root = parser.element(0)?;
+        parser.skip_markup()?;
+        if parser.pos != text.len() {
+            return Err(invalid("content after the xml root element"));
+        }
+        Ok(root)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn decode_entities(raw: &str) -> io::Result<String> {
+    let mut out = String::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| invalid("unterminated xml entity"))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32)
+                .ok_or_else(|| invalid("unknown xml entity"))?,
+        };
+        out.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // skips past `end`, which has to come up
+    fn skip_past(&mut self, end: &str) -> io::Result<()> {
+        let at = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| invalid("unterminated xml markup"))?;
+        self.pos += at + end.len();
+        Ok(())
+    }
+
+    // whitespace, comments, processing instructions and doctypes
+    fn skip_markup(&mut self) -> io::Result<()> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> io::Result<()> {
+        if !self.rest().starts_with(token) {
+            return Err(invalid(&format!("expected {token} in xml")));
+        }
+        self.pos += token.len();
+        Ok(())
+    }
+
+    fn name(&mut self) -> io::Result<String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_-:.".contains(c)))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(invalid("expected an xml name"));
+        }
+        let name = rest[..end].to_string();
+        self.pos += end;
+        Ok(name)
+    }
+
+    fn element(&mut self, depth: usize) -> io::Result<Element> {
+        if depth >= MAX_DEPTH {
+            return Err(invalid("xml nested too deeply"));
+        }
+        self.expect("<")?;
+        let mut element = Element {
+            name: self.name()?,
+            ..Element::default()
+        };
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(invalid("unquoted xml attribute")),
+            };
+            self.pos += 1;
+            let end = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| invalid("unterminated xml attribute"))?;
+            let value = decode_entities(&self.rest()[..end])?;
+            self.pos += end + 1;
+            element.attributes.push((key, value));
+        }
+
+        loop {
+            // text between the tags isn't kept
+            let text = self
+                .rest()
+                .find('<')
+                .ok_or_else(|| invalid("unclosed xml element"))?;
+            self.pos += text;
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                if self.name()? != element.name {
+                    return Err(invalid("mismatched xml closing tag"));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                self.skip_past("]]>")?;
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                self.skip_markup()?;
+            } else {
+                element.children.push(self.element(depth + 1)?);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_elements() {
+        let root = Element::parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- a comment -->
+<scene version='3.0.0'>
+    <float name="a&amp;b" value="&#x31;.5"/>
+    text is <![CDATA[ <skipped> ]]> ignored
+    <shape type="sphere"><!-- inner --><float name="radius" value="2" /></shape>
+</scene>
+"#,
+        )
+        .unwrap();
+        assert_eq!(root.name, "scene");
+        assert_eq!(root.attribute("version"), Some("3.0.0"));
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].attribute("name"), Some("a&b"));
+        assert_eq!(root.children[0].attribute("value"), Some("1.5"));
+        assert_eq!(root.children[1].children[0].attribute("value"), Some("2"));
+
+        assert!(Element::parse("<a><b></a>").is_err());
+        assert!(Element::parse("<a x=1/>").is_err());
+        assert!(Element::parse("<a/><b/>").is_err());
+        assert!(Element::parse(&"<a>".repeat(200_000)).is_err());
+    }
+}